@@ -0,0 +1,14 @@
+/// Options of anvil given on the command line, shared by all backends
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// Bind the EGL display to the wayland display, advertising the legacy `wl_drm` global
+    ///
+    /// Disabled by `--no-wl-drm`, hardware-accelerated clients then need to use dmabuf.
+    pub wl_drm: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config { wl_drm: true }
+    }
+}
@@ -21,6 +21,7 @@ use smithay::{
             get_role, with_states, with_surface_tree_upward, Damage, SubsurfaceCachedState,
             SurfaceAttributes, TraversalAction,
         },
+        data_device::DnDIconAttributes,
         seat::CursorImageAttributes,
         shell::wlr_layer::Layer,
     },
@@ -46,7 +47,7 @@ pub fn draw_cursor<R, E, F, T>(
     frame: &mut F,
     surface: &wl_surface::WlSurface,
     location: Point<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     log: &Logger,
 ) -> Result<(), SwapBuffersError>
 where
@@ -85,7 +86,7 @@ fn draw_surface_tree<R, E, F, T>(
     frame: &mut F,
     root: &wl_surface::WlSurface,
     location: Point<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     log: &Logger,
 ) -> Result<(), SwapBuffersError>
 where
@@ -181,9 +182,9 @@ where
                     }
                     if let Err(err) = frame.render_texture_at(
                         &texture.texture,
-                        location.to_f64().to_physical(output_scale as f64).to_i32_round(),
+                        location.to_f64().to_physical(output_scale).to_i32_round(),
                         buffer_scale,
-                        output_scale as f64,
+                        output_scale,
                         buffer_transform,
                         &[Rectangle::from_loc_and_size((0, 0), (i32::MAX, i32::MAX))],
                         1.0,
@@ -204,7 +205,7 @@ pub fn draw_windows<R, E, F, T>(
     frame: &mut F,
     window_map: &WindowMap,
     output_rect: Rectangle<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     log: &::slog::Logger,
 ) -> Result<(), SwapBuffersError>
 where
@@ -256,7 +257,7 @@ pub fn draw_layers<R, E, F, T>(
     window_map: &WindowMap,
     layer: Layer,
     output_rect: Rectangle<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     log: &::slog::Logger,
 ) -> Result<(), SwapBuffersError>
 where
@@ -308,7 +309,7 @@ pub fn draw_dnd_icon<R, E, F, T>(
     frame: &mut F,
     surface: &wl_surface::WlSurface,
     location: Point<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     log: &::slog::Logger,
 ) -> Result<(), SwapBuffersError>
 where
@@ -323,7 +324,16 @@ where
             "Trying to display as a dnd icon a surface that does not have the DndIcon role."
         );
     }
-    draw_surface_tree(renderer, frame, surface, location, output_scale, log)
+    let offset = with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<DnDIconAttributes>>()
+            .map(|attributes| attributes.lock().unwrap().offset)
+    })
+    .ok()
+    .flatten()
+    .unwrap_or_default();
+    draw_surface_tree(renderer, frame, surface, location + offset, output_scale, log)
 }
 
 #[cfg(feature = "debug")]
@@ -172,7 +172,7 @@ impl<Backend> AnvilState<Backend> {
 
                     self.output_map
                         .borrow_mut()
-                        .update_scale_by_name(current_scale + 0.25f32, output_name);
+                        .update_scale_by_name(current_scale + 0.25, output_name);
                 }
 
                 KeyAction::ScaleDown => {
@@ -186,7 +186,7 @@ impl<Backend> AnvilState<Backend> {
 
                     self.output_map
                         .borrow_mut()
-                        .update_scale_by_name(f32::max(1.0f32, current_scale - 0.25f32), output_name);
+                        .update_scale_by_name(f64::max(1.0, current_scale - 0.25), output_name);
                 }
 
                 action => match action {
@@ -271,7 +271,7 @@ impl AnvilState<UdevData> {
 
                         output_map.update_scale_by_name(new_scale, name);
 
-                        let rescale = scale as f64 / new_scale as f64;
+                        let rescale = scale / new_scale;
                         let output_location = output_location.to_f64();
                         let mut pointer_output_location = self.pointer_location - output_location;
                         pointer_output_location.x *= rescale;
@@ -291,10 +291,10 @@ impl AnvilState<UdevData> {
                         .map(|o| (o.name().to_owned(), o.location(), o.scale()));
 
                     if let Some((name, output_location, scale)) = output {
-                        let new_scale = f32::max(1.0, scale - 0.25);
+                        let new_scale = f64::max(1.0, scale - 0.25);
                         output_map.update_scale_by_name(new_scale, name);
 
-                        let rescale = scale as f64 / new_scale as f64;
+                        let rescale = scale / new_scale;
                         let output_location = output_location.to_f64();
                         let mut pointer_output_location = self.pointer_location - output_location;
                         pointer_output_location.x *= rescale;
@@ -9,6 +9,7 @@
 #[macro_use]
 extern crate slog;
 
+pub mod config;
 #[cfg(feature = "udev")]
 pub mod cursor;
 pub mod drawing;
@@ -28,4 +29,5 @@ pub mod x11;
 #[cfg(feature = "xwayland")]
 pub mod xwayland;
 
+pub use config::Config;
 pub use state::AnvilState;
@@ -9,6 +9,11 @@ static POSSIBLE_BACKENDS: &[&str] = &[
     "--x11 : Run anvil as an X11 client.",
 ];
 
+static POSSIBLE_OPTIONS: &[&str] = &[
+    #[cfg(feature = "egl")]
+    "--no-wl-drm : Do not advertise the legacy wl_drm global, EGL clients need to use dmabuf.",
+];
+
 fn main() {
     // A logger facility, here we use the terminal here
     let log = if std::env::var("ANVIL_MUTEX_LOG").is_ok() {
@@ -23,33 +28,48 @@ fn main() {
     let _guard = slog_scope::set_global_logger(log.clone());
     slog_stdlog::init().expect("Could not setup log backend");
 
-    let arg = ::std::env::args().nth(1);
+    let mut config = anvil::Config::default();
+    let mut arg = None;
+    for a in ::std::env::args().skip(1) {
+        match &a[..] {
+            #[cfg(feature = "egl")]
+            "--no-wl-drm" => config.wl_drm = false,
+            _ => arg = arg.or(Some(a)),
+        }
+    }
     match arg.as_ref().map(|s| &s[..]) {
         #[cfg(feature = "winit")]
         Some("--winit") => {
             slog::info!(log, "Starting anvil with winit backend");
-            anvil::winit::run_winit(log);
+            anvil::winit::run_winit(config, log);
         }
         #[cfg(feature = "udev")]
         Some("--tty-udev") => {
             slog::info!(log, "Starting anvil on a tty using udev");
-            anvil::udev::run_udev(log);
+            anvil::udev::run_udev(config, log);
         }
         #[cfg(feature = "x11")]
         Some("--x11") => {
             slog::info!(log, "Starting anvil with x11 backend");
-            anvil::x11::run_x11(log);
+            anvil::x11::run_x11(config, log);
         }
         Some(other) => {
             crit!(log, "Unknown backend: {}", other);
         }
         None => {
-            println!("USAGE: anvil --backend");
+            println!("USAGE: anvil --backend [options]");
             println!();
             println!("Possible backends are:");
             for b in POSSIBLE_BACKENDS {
                 println!("\t{}", b);
             }
+            if !POSSIBLE_OPTIONS.is_empty() {
+                println!();
+                println!("Possible options are:");
+                for o in POSSIBLE_OPTIONS {
+                    println!("\t{}", o);
+                }
+            }
         }
     }
 }
@@ -28,7 +28,7 @@ pub struct Output {
     surfaces: Vec<WlSurface>,
     layer_surfaces: RefCell<Vec<wl_surface::WlSurface>>,
     current_mode: Mode,
-    scale: f32,
+    scale: f64,
     output_scale: i32,
     location: Point<i32, Logical>,
     userdata: UserDataMap,
@@ -50,7 +50,7 @@ impl Output {
 
         let scale = std::env::var(format!("ANVIL_SCALE_{}", name.as_ref()))
             .ok()
-            .and_then(|s| s.parse::<f32>().ok())
+            .and_then(|s| s.parse::<f64>().ok())
             .unwrap_or(1.0)
             .max(1.0);
 
@@ -88,7 +88,7 @@ impl Output {
         self.current_mode
             .size
             .to_f64()
-            .to_logical(self.scale as f64)
+            .to_logical(self.scale)
             .to_i32_round()
     }
 
@@ -96,7 +96,7 @@ impl Output {
         self.location
     }
 
-    pub fn scale(&self) -> f32 {
+    pub fn scale(&self) -> f64 {
         self.scale
     }
 
@@ -339,7 +339,7 @@ impl OutputMap {
         self.outputs.get(index)
     }
 
-    pub fn update<F>(&mut self, mode: Option<Mode>, scale: Option<f32>, mut f: F)
+    pub fn update<F>(&mut self, mode: Option<Mode>, scale: Option<f64>, mut f: F)
     where
         F: FnMut(&Output) -> bool,
     {
@@ -375,8 +375,8 @@ impl OutputMap {
                                 if output_geometry.contains(location) {
                                     let mut toplevel_output_location =
                                         (location - output_geometry.loc).to_f64();
-                                    toplevel_output_location.x *= rescale as f64;
-                                    toplevel_output_location.y *= rescale as f64;
+                                    toplevel_output_location.x *= rescale;
+                                    toplevel_output_location.y *= rescale;
                                     window_map.set_location(
                                         &toplevel,
                                         output_geometry.loc + toplevel_output_location.to_i32_round(),
@@ -405,11 +405,11 @@ impl OutputMap {
         self.arrange();
     }
 
-    pub fn update_by_name<N: AsRef<str>>(&mut self, mode: Option<Mode>, scale: Option<f32>, name: N) {
+    pub fn update_by_name<N: AsRef<str>>(&mut self, mode: Option<Mode>, scale: Option<f64>, name: N) {
         self.update(mode, scale, |o| o.name() == name.as_ref())
     }
 
-    pub fn update_scale_by_name<N: AsRef<str>>(&mut self, scale: f32, name: N) {
+    pub fn update_scale_by_name<N: AsRef<str>>(&mut self, scale: f64, name: N) {
         self.update_by_name(None, Some(scale), name)
     }
 
@@ -605,14 +605,18 @@ impl DrmRenderer {
                 {
                     // reset the cursor if the surface is no longer alive
                     let mut reset = false;
-                    if let CursorImageStatus::Image(ref surface) = *cursor_status {
+                    if let CursorImageStatus::Image { ref surface, .. } = *cursor_status {
                         reset = !surface.as_ref().is_alive();
                     }
                     if reset {
                         *cursor_status = CursorImageStatus::Default;
                     }
 
-                    if let CursorImageStatus::Image(ref wl_surface) = *cursor_status {
+                    if let CursorImageStatus::Image {
+                        surface: ref wl_surface,
+                        ..
+                    } = *cursor_status
+                    {
                         draw_cursor(
                             renderer,
                             frame,
@@ -21,7 +21,7 @@ pub fn render_layers_and_windows(
     frame: &mut Gles2Frame,
     window_map: &WindowMap,
     output_geometry: Rectangle<i32, Logical>,
-    output_scale: f32,
+    output_scale: f64,
     logger: &Logger,
 ) -> Result<(), SwapBuffersError> {
     frame.clear(
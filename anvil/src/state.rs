@@ -14,7 +14,9 @@ use smithay::{
     },
     utils::{Logical, Point},
     wayland::{
-        data_device::{default_action_chooser, init_data_device, set_data_device_focus, DataDeviceEvent},
+        data_device::{
+            default_action_chooser, init_data_device, set_data_device_focus, DataDeviceEvent, ResourceLimits,
+        },
         output::xdg::init_xdg_output_manager,
         seat::{CursorImageStatus, KeyboardHandle, PointerHandle, Seat, XkbConfig},
         shell::xdg::decoration::init_xdg_decoration_manager,
@@ -154,6 +156,7 @@ impl<BackendData: Backend + 'static> AnvilState<BackendData> {
                 _ => {}
             },
             default_action_chooser,
+            ResourceLimits::default(),
             log.clone(),
         );
 
@@ -67,7 +67,7 @@ use smithay::{
     wayland::dmabuf::init_dmabuf_global,
 };
 
-use crate::{drawing::*, window_map::WindowMap};
+use crate::{config::Config, drawing::*, window_map::WindowMap};
 use crate::{
     render::render_layers_and_windows,
     state::{AnvilState, Backend},
@@ -91,6 +91,8 @@ pub struct UdevData {
     pub session: AutoSession,
     #[cfg(feature = "egl")]
     primary_gpu: Option<PathBuf>,
+    #[cfg(feature = "egl")]
+    wl_drm: bool,
     backends: HashMap<dev_t, BackendData>,
     signaler: Signaler<SessionSignal>,
     pointer_image: crate::cursor::Cursor,
@@ -103,7 +105,8 @@ impl Backend for UdevData {
     }
 }
 
-pub fn run_udev(log: Logger) {
+#[cfg_attr(not(feature = "egl"), allow(unused_variables))]
+pub fn run_udev(config: Config, log: Logger) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Rc::new(RefCell::new(Display::new()));
 
@@ -132,6 +135,8 @@ pub fn run_udev(log: Logger) {
         session,
         #[cfg(feature = "egl")]
         primary_gpu,
+        #[cfg(feature = "egl")]
+        wl_drm: config.wl_drm,
         backends: HashMap::new(),
         signaler: session_signal.clone(),
         pointer_image: crate::cursor::Cursor::load(&log),
@@ -464,7 +469,7 @@ impl AnvilState<UdevData> {
             }));
 
             #[cfg(feature = "egl")]
-            if path.canonicalize().ok() == self.backend_data.primary_gpu {
+            if path.canonicalize().ok() == self.backend_data.primary_gpu && self.backend_data.wl_drm {
                 info!(self.log, "Initializing EGL Hardware Acceleration via {:?}", path);
                 if renderer
                     .borrow_mut()
@@ -764,14 +769,18 @@ fn render_surface(
                 {
                     // reset the cursor if the surface is no longer alive
                     let mut reset = false;
-                    if let CursorImageStatus::Image(ref surface) = *cursor_status {
+                    if let CursorImageStatus::Image { ref surface, .. } = *cursor_status {
                         reset = !surface.as_ref().is_alive();
                     }
                     if reset {
                         *cursor_status = CursorImageStatus::Default;
                     }
 
-                    if let CursorImageStatus::Image(ref wl_surface) = *cursor_status {
+                    if let CursorImageStatus::Image {
+                        surface: ref wl_surface,
+                        ..
+                    } = *cursor_status
+                    {
                         draw_cursor(
                             renderer,
                             frame,
@@ -785,10 +794,10 @@ fn render_surface(
                             pointer_image,
                             relative_ptr_location
                                 .to_f64()
-                                .to_physical(output_scale as f64)
+                                .to_physical(output_scale)
                                 .to_i32_round(),
                             1,
-                            output_scale as f64,
+                            output_scale,
                             Transform::Normal,
                             &[Rectangle::from_loc_and_size((0, 0), (i32::MAX, i32::MAX))],
                             1.0,
@@ -802,7 +811,7 @@ fn render_surface(
                         renderer,
                         frame,
                         fps_texture,
-                        output_scale as f64,
+                        output_scale,
                         surface.fps.avg().round() as u32,
                     )?;
 
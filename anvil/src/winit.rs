@@ -1,4 +1,4 @@
-use std::{cell::RefCell, rc::Rc, sync::atomic::Ordering, time::Duration};
+use std::{cell::RefCell, rc::Rc, sync::atomic::Ordering, time::Instant};
 
 #[cfg(feature = "debug")]
 use smithay::backend::renderer::gles2::Gles2Texture;
@@ -17,7 +17,7 @@ use smithay::{
         calloop::EventLoop,
         wayland_server::{protocol::wl_output, Display},
     },
-    utils::Transform,
+    utils::{frame_clock::FrameClock, Transform},
     wayland::{
         output::{Mode, PhysicalProperties},
         seat::CursorImageStatus,
@@ -27,7 +27,7 @@ use smithay::{
 use slog::Logger;
 
 use crate::state::{AnvilState, Backend};
-use crate::{drawing::*, render::render_layers_and_windows};
+use crate::{config::Config, drawing::*, render::render_layers_and_windows};
 
 pub const OUTPUT_NAME: &str = "winit";
 
@@ -44,7 +44,8 @@ impl Backend for WinitData {
     }
 }
 
-pub fn run_winit(log: Logger) {
+#[cfg_attr(not(feature = "egl"), allow(unused_variables))]
+pub fn run_winit(config: Config, log: Logger) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Rc::new(RefCell::new(Display::new()));
 
@@ -58,13 +59,17 @@ pub fn run_winit(log: Logger) {
     let backend = Rc::new(RefCell::new(backend));
 
     #[cfg(feature = "egl")]
-    if backend
-        .borrow_mut()
-        .renderer()
-        .bind_wl_display(&display.borrow())
-        .is_ok()
     {
-        info!(log, "EGL hardware-acceleration enabled");
+        if !config.wl_drm {
+            info!(log, "Legacy wl_drm disabled, EGL clients need to use dmabuf");
+        } else if backend
+            .borrow_mut()
+            .renderer()
+            .bind_wl_display(&display.borrow())
+            .is_ok()
+        {
+            info!(log, "EGL hardware-acceleration enabled");
+        }
         let dmabuf_formats = backend
             .borrow_mut()
             .renderer()
@@ -78,7 +83,7 @@ pub fn run_winit(log: Logger) {
             move |buffer, _| backend.borrow_mut().renderer().import_dmabuf(buffer).is_ok(),
             log.clone(),
         );
-    };
+    }
 
     let size = backend.borrow().window_size().physical_size;
 
@@ -118,6 +123,7 @@ pub fn run_winit(log: Logger) {
     );
 
     let start_time = std::time::Instant::now();
+    let mut frame_clock = FrameClock::new(mode.refresh);
     let mut cursor_visible = true;
 
     #[cfg(feature = "xwayland")]
@@ -173,7 +179,7 @@ pub fn run_winit(log: Logger) {
                             output_geometry
                                 .size
                                 .to_f64()
-                                .to_physical(output_scale as f64)
+                                .to_physical(output_scale)
                                 .to_i32_round(),
                             Transform::Flipped180,
                             |renderer, frame| {
@@ -209,7 +215,7 @@ pub fn run_winit(log: Logger) {
                                     let mut guard = state.cursor_status.lock().unwrap();
                                     // reset the cursor if the surface is no longer alive
                                     let mut reset = false;
-                                    if let CursorImageStatus::Image(ref surface) = *guard {
+                                    if let CursorImageStatus::Image { ref surface, .. } = *guard {
                                         reset = !surface.as_ref().is_alive();
                                     }
                                     if reset {
@@ -217,7 +223,7 @@ pub fn run_winit(log: Logger) {
                                     }
 
                                     // draw as relevant
-                                    if let CursorImageStatus::Image(ref surface) = *guard {
+                                    if let CursorImageStatus::Image { ref surface, .. } = *guard {
                                         cursor_visible = false;
                                         draw_cursor(
                                             renderer,
@@ -240,7 +246,7 @@ pub fn run_winit(log: Logger) {
                                         renderer,
                                         frame,
                                         &state.backend_data.fps_texture,
-                                        output_scale as f64,
+                                        output_scale,
                                         fps,
                                     )?;
                                 }
@@ -254,6 +260,7 @@ pub fn run_winit(log: Logger) {
                 .and_then(|_| backend.submit(None, 1.0));
 
             backend.window().set_cursor_visible(cursor_visible);
+            frame_clock.presented(Instant::now());
 
             if let Err(SwapBuffersError::ContextLost(err)) = result {
                 error!(log, "Critical Rendering Error: {}", err);
@@ -269,7 +276,10 @@ pub fn run_winit(log: Logger) {
         display.borrow_mut().flush_clients(&mut state);
 
         if event_loop
-            .dispatch(Some(Duration::from_millis(16)), &mut state)
+            .dispatch(
+                Some(frame_clock.time_to_next_presentation(Instant::now())),
+                &mut state,
+            )
             .is_err()
         {
             state.running.store(false, Ordering::SeqCst);
@@ -28,6 +28,7 @@ use smithay::{
 };
 
 use crate::{
+    config::Config,
     drawing::{draw_cursor, draw_dnd_icon},
     render::render_layers_and_windows,
     state::Backend,
@@ -56,7 +57,7 @@ impl Backend for X11Data {
     }
 }
 
-pub fn run_x11(log: Logger) {
+pub fn run_x11(config: Config, log: Logger) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Rc::new(RefCell::new(Display::new()));
 
@@ -100,21 +101,23 @@ pub fn run_x11(log: Logger) {
 
     #[cfg(feature = "egl")]
     {
-        if renderer.borrow_mut().bind_wl_display(&*display.borrow()).is_ok() {
+        if !config.wl_drm {
+            info!(log, "Legacy wl_drm disabled, EGL clients need to use dmabuf");
+        } else if renderer.borrow_mut().bind_wl_display(&*display.borrow()).is_ok() {
             info!(log, "EGL hardware-acceleration enabled");
-            let dmabuf_formats = renderer
-                .borrow_mut()
-                .dmabuf_formats()
-                .cloned()
-                .collect::<Vec<_>>();
-            let renderer = renderer.clone();
-            init_dmabuf_global(
-                &mut *display.borrow_mut(),
-                dmabuf_formats,
-                move |buffer, _| renderer.borrow_mut().import_dmabuf(buffer).is_ok(),
-                log.clone(),
-            );
         }
+        let dmabuf_formats = renderer
+            .borrow_mut()
+            .dmabuf_formats()
+            .cloned()
+            .collect::<Vec<_>>();
+        let renderer = renderer.clone();
+        init_dmabuf_global(
+            &mut *display.borrow_mut(),
+            dmabuf_formats,
+            move |buffer, _| renderer.borrow_mut().import_dmabuf(buffer).is_ok(),
+            log.clone(),
+        );
     }
 
     let size = {
@@ -274,7 +277,7 @@ pub fn run_x11(log: Logger) {
                         // reset the cursor if the surface is no longer alive
                         let mut reset = false;
 
-                        if let CursorImageStatus::Image(ref surface) = *guard {
+                        if let CursorImageStatus::Image { ref surface, .. } = *guard {
                             reset = !surface.as_ref().is_alive();
                         }
 
@@ -283,7 +286,7 @@ pub fn run_x11(log: Logger) {
                         }
 
                         // draw as relevant
-                        if let CursorImageStatus::Image(ref surface) = *guard {
+                        if let CursorImageStatus::Image { ref surface, .. } = *guard {
                             cursor_visible = false;
                             draw_cursor(
                                 renderer,
@@ -302,7 +305,7 @@ pub fn run_x11(log: Logger) {
                     {
                         use crate::drawing::draw_fps;
 
-                        draw_fps(renderer, frame, fps_texture, output_scale as f64, fps)?;
+                        draw_fps(renderer, frame, fps_texture, output_scale, fps)?;
                     }
 
                     Ok(())
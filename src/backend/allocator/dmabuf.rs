@@ -10,9 +10,10 @@
 //! This can be especially useful in resources where other parts of the stack should decide upon
 //! the lifetime of the buffer. E.g. when you are only caching associated resources for a dmabuf.
 
-use super::{Buffer, Format, Fourcc, Modifier};
+use super::{Allocator, Buffer, Format, Fourcc, Modifier};
 use crate::utils::{Buffer as BufferCoords, Size};
 use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 use std::os::unix::io::{IntoRawFd, RawFd};
 use std::sync::{Arc, Weak};
 
@@ -251,3 +252,69 @@ impl AsDmabuf for Dmabuf {
         Ok(self.clone())
     }
 }
+
+/// Wrapper for an [`Allocator`], that exports all of its buffers as [`Dmabuf`]s.
+///
+/// This allows to allocate buffers on one device and to use them on another,
+/// e.g. rendering into them by binding them to a renderer of a different gpu
+/// or importing them via [`ImportDma`](crate::backend::renderer::ImportDma).
+#[derive(Debug)]
+pub struct DmabufAllocator<A, B> {
+    allocator: A,
+    _buffer: PhantomData<B>,
+}
+
+impl<A, B> DmabufAllocator<A, B>
+where
+    A: Allocator<B>,
+    B: Buffer + AsDmabuf,
+{
+    /// Wrap an existing [`Allocator`]
+    pub fn new(allocator: A) -> Self {
+        DmabufAllocator {
+            allocator,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying [`Allocator`]
+    pub fn allocator(&self) -> &A {
+        &self.allocator
+    }
+}
+
+/// Errors thrown by the [`DmabufAllocator`]
+#[derive(Debug, thiserror::Error)]
+pub enum DmabufAllocatorError<A: std::error::Error + 'static, E: std::error::Error + 'static> {
+    /// The underlying allocator failed to create a buffer
+    #[error("Failed to allocate buffer: {0}")]
+    Allocation(#[source] A),
+    /// The allocated buffer could not be exported as a dmabuf
+    #[error("Failed to export buffer as dmabuf: {0}")]
+    Export(#[source] E),
+}
+
+impl<A, B> Allocator<Dmabuf> for DmabufAllocator<A, B>
+where
+    A: Allocator<B>,
+    A::Error: 'static,
+    B: Buffer + AsDmabuf,
+    <B as AsDmabuf>::Error: std::error::Error + 'static,
+{
+    type Error = DmabufAllocatorError<A::Error, <B as AsDmabuf>::Error>;
+
+    fn create_buffer(
+        &mut self,
+        width: u32,
+        height: u32,
+        fourcc: Fourcc,
+        modifiers: &[Modifier],
+    ) -> Result<Dmabuf, Self::Error> {
+        let buffer = self
+            .allocator
+            .create_buffer(width, height, fourcc, modifiers)
+            .map_err(DmabufAllocatorError::Allocation)?;
+        // the exported dmabuf keeps the memory alive
+        buffer.export().map_err(DmabufAllocatorError::Export)
+    }
+}
@@ -14,6 +14,8 @@
 //!
 //! Helpers:
 //! - [`Swapchain`] to help with buffer management for framebuffers
+//! - [`DmabufAllocator`](dmabuf::DmabufAllocator) to share buffers of any exportable allocator
+//!   with other devices
 
 pub mod dmabuf;
 #[cfg(feature = "backend_drm")]
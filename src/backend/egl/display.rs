@@ -440,6 +440,16 @@ impl EGLDisplay {
             dmabuf.format().code as u32 as i32,
         ]);
 
+        if is_yuv_format(dmabuf.format().code) {
+            // the sampler does the conversion to rgb, assume the most common encoding
+            out.extend(&[
+                ffi::egl::YUV_COLOR_SPACE_HINT_EXT as i32,
+                ffi::egl::ITU_REC601_EXT as i32,
+                ffi::egl::SAMPLE_RANGE_HINT_EXT as i32,
+                ffi::egl::YUV_NARROW_RANGE_EXT as i32,
+            ]);
+        }
+
         let names = [
             [
                 ffi::egl::DMA_BUF_PLANE0_FD_EXT,
@@ -551,6 +561,25 @@ impl EGLDisplay {
     }
 }
 
+/// Returns whether the given format is a (possibly multi-planar) YUV format.
+///
+/// Dmabufs of these formats can only be sampled as external textures,
+/// which convert their contents to RGB.
+pub fn is_yuv_format(fourcc: Fourcc) -> bool {
+    matches!(
+        fourcc,
+        Fourcc::Nv12
+            | Fourcc::Nv21
+            | Fourcc::Nv16
+            | Fourcc::Nv61
+            | Fourcc::P010
+            | Fourcc::Yuv420
+            | Fourcc::Yvu420
+            | Fourcc::Yuyv
+            | Fourcc::Uyvy
+    )
+}
+
 fn get_dmabuf_formats(
     display: &ffi::egl::types::EGLDisplay,
     extensions: &[String],
@@ -871,6 +900,39 @@ impl EGLBufferReader {
 
         Some((width, height).into())
     }
+
+    /// Try to receive the format of a given [`WlBuffer`].
+    ///
+    /// In case the buffer is not managed by EGL (but e.g. the [`wayland::shm` module](crate::wayland::shm)) or the
+    /// context has been lost, `None` is returned.
+    pub fn egl_buffer_format(&self, buffer: &WlBuffer) -> Option<Format> {
+        if !buffer.as_ref().is_alive() {
+            debug!(self.logger, "Suplied buffer is no longer alive");
+            return None;
+        }
+
+        let mut format: i32 = 0;
+        if unsafe {
+            ffi::egl::QueryWaylandBufferWL(
+                **self.display,
+                buffer.as_ref().c_ptr() as _,
+                ffi::egl::EGL_TEXTURE_FORMAT,
+                &mut format,
+            ) == 0
+        } {
+            return None;
+        }
+
+        match format {
+            x if x == ffi::egl::TEXTURE_RGB as i32 => Some(Format::RGB),
+            x if x == ffi::egl::TEXTURE_RGBA as i32 => Some(Format::RGBA),
+            ffi::egl::TEXTURE_EXTERNAL_WL => Some(Format::External),
+            ffi::egl::TEXTURE_Y_UV_WL => Some(Format::Y_UV),
+            ffi::egl::TEXTURE_Y_U_V_WL => Some(Format::Y_U_V),
+            ffi::egl::TEXTURE_Y_XUXV_WL => Some(Format::Y_XUXV),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(feature = "use_system_lib")]
@@ -0,0 +1,342 @@
+//! Renderer recording draw calls instead of executing them
+//!
+//! The [`DummyRenderer`] does not need any graphics hardware, all of its operations succeed and are
+//! recorded as [`DrawCommand`]s. This allows to run rendering code, like
+//! [`Space::render_output`](crate::desktop::Space::render_output), in tests and to assert what
+//! was drawn where.
+
+use std::convert::Infallible;
+
+#[cfg(feature = "wayland_frontend")]
+use wayland_server::protocol::wl_buffer;
+
+use super::{Bind, Frame, Offscreen, Renderer, Texture, TextureFilter, Unbind};
+#[cfg(all(
+    feature = "wayland_frontend",
+    feature = "backend_egl",
+    feature = "use_system_lib"
+))]
+use super::{EGLBufferReader, EglError, ImportEgl};
+#[cfg(feature = "wayland_frontend")]
+use super::{ImportDma, ImportShm};
+#[cfg(feature = "wayland_frontend")]
+use crate::backend::allocator::{dmabuf::Dmabuf, Buffer as _};
+use crate::utils::{Buffer, Physical, Rectangle, Size, Transform};
+#[cfg(feature = "wayland_frontend")]
+use crate::wayland::color_management::ColorMetadata;
+
+/// A draw call recorded by the [`DummyRenderer`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    /// A new frame was started with [`Renderer::render`]
+    Render {
+        /// Size of the frame
+        size: Size<i32, Physical>,
+        /// Transformation of the frame
+        transform: Transform,
+    },
+    /// Parts of the frame were cleared with [`Frame::clear`]
+    Clear {
+        /// The color used to clear the frame
+        color: [f32; 4],
+        /// The cleared rectangles
+        at: Vec<Rectangle<i32, Physical>>,
+    },
+    /// A texture was rendered with [`Frame::render_texture_from_to`]
+    RenderTexture {
+        /// The rendered texture
+        texture: DummyTexture,
+        /// The rendered part of the texture
+        src: Rectangle<i32, Buffer>,
+        /// Where the texture was rendered
+        dst: Rectangle<f64, Physical>,
+        /// Damaged parts of the texture
+        damage: Vec<Rectangle<i32, Buffer>>,
+        /// Transformation of the texture
+        src_transform: Transform,
+        /// Alpha value the texture was rendered with
+        alpha: f32,
+    },
+    /// The color metadata of the following textures was changed with [`Frame::set_color_metadata`]
+    ///
+    /// Only recorded if it differs from the metadata set before, a frame starts with `None`.
+    #[cfg(feature = "wayland_frontend")]
+    ColorMetadata(Option<ColorMetadata>),
+}
+
+/// Texture of the [`DummyRenderer`]
+///
+/// It holds no pixel data, only the properties of the imported buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DummyTexture {
+    id: usize,
+    size: Size<i32, Buffer>,
+    #[cfg(feature = "wayland_frontend")]
+    buffer: Option<wl_buffer::WlBuffer>,
+}
+
+impl DummyTexture {
+    /// Unique id of this texture, in the order the textures were imported
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The buffer this texture was imported from, if any
+    #[cfg(feature = "wayland_frontend")]
+    pub fn buffer(&self) -> Option<&wl_buffer::WlBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Texture for DummyTexture {
+    fn size(&self) -> Size<i32, Buffer> {
+        self.size
+    }
+
+    fn width(&self) -> u32 {
+        self.size.w as u32
+    }
+
+    fn height(&self) -> u32 {
+        self.size.h as u32
+    }
+}
+
+/// Renderer recording all draw calls, see the [module-level documentation](self)
+#[derive(Debug, Default)]
+pub struct DummyRenderer {
+    commands: Vec<DrawCommand>,
+    next_texture_id: usize,
+}
+
+impl DummyRenderer {
+    /// Create a new renderer without any recorded commands
+    pub fn new() -> DummyRenderer {
+        DummyRenderer::default()
+    }
+
+    /// The commands recorded so far, in the order they were issued
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    /// Take the commands recorded so far, clearing the recording
+    pub fn take_commands(&mut self) -> Vec<DrawCommand> {
+        std::mem::take(&mut self.commands)
+    }
+
+    /// Create a texture of the given size, as if it was imported
+    pub fn create_texture(&mut self, size: Size<i32, Buffer>) -> DummyTexture {
+        let id = self.next_texture_id;
+        self.next_texture_id += 1;
+        DummyTexture {
+            id,
+            size,
+            #[cfg(feature = "wayland_frontend")]
+            buffer: None,
+        }
+    }
+
+    #[cfg(feature = "wayland_frontend")]
+    fn import(&mut self, buffer: &wl_buffer::WlBuffer, size: Size<i32, Buffer>) -> DummyTexture {
+        let mut texture = self.create_texture(size);
+        texture.buffer = Some(buffer.clone());
+        texture
+    }
+}
+
+/// Frame of the [`DummyRenderer`]
+#[derive(Debug)]
+pub struct DummyFrame {
+    transform: Transform,
+    commands: Vec<DrawCommand>,
+    #[cfg(feature = "wayland_frontend")]
+    color_metadata: Option<ColorMetadata>,
+}
+
+impl Frame for DummyFrame {
+    type Error = Infallible;
+    type TextureId = DummyTexture;
+
+    fn clear(&mut self, color: [f32; 4], at: &[Rectangle<i32, Physical>]) -> Result<(), Self::Error> {
+        self.commands.push(DrawCommand::Clear {
+            color,
+            at: at.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn render_texture_from_to(
+        &mut self,
+        texture: &Self::TextureId,
+        src: Rectangle<i32, Buffer>,
+        dst: Rectangle<f64, Physical>,
+        damage: &[Rectangle<i32, Buffer>],
+        src_transform: Transform,
+        alpha: f32,
+    ) -> Result<(), Self::Error> {
+        self.commands.push(DrawCommand::RenderTexture {
+            texture: texture.clone(),
+            src,
+            dst,
+            damage: damage.to_vec(),
+            src_transform,
+            alpha,
+        });
+        Ok(())
+    }
+
+    fn transformation(&self) -> Transform {
+        self.transform
+    }
+
+    #[cfg(feature = "wayland_frontend")]
+    fn set_color_metadata(&mut self, metadata: Option<ColorMetadata>) -> Result<(), Self::Error> {
+        if self.color_metadata != metadata {
+            self.color_metadata = metadata;
+            self.commands.push(DrawCommand::ColorMetadata(metadata));
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for DummyRenderer {
+    type Error = Infallible;
+    type TextureId = DummyTexture;
+    type Frame = DummyFrame;
+
+    fn downscale_filter(&mut self, _filter: TextureFilter) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn upscale_filter(&mut self, _filter: TextureFilter) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn render<F, R>(
+        &mut self,
+        size: Size<i32, Physical>,
+        dst_transform: Transform,
+        rendering: F,
+    ) -> Result<R, Self::Error>
+    where
+        F: FnOnce(&mut Self, &mut Self::Frame) -> R,
+    {
+        self.commands.push(DrawCommand::Render {
+            size,
+            transform: dst_transform,
+        });
+        let mut frame = DummyFrame {
+            transform: dst_transform,
+            commands: Vec::new(),
+            #[cfg(feature = "wayland_frontend")]
+            color_metadata: None,
+        };
+        let result = rendering(self, &mut frame);
+        self.commands.append(&mut frame.commands);
+        Ok(result)
+    }
+}
+
+impl Bind<DummyTexture> for DummyRenderer {
+    fn bind(&mut self, _target: DummyTexture) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl Unbind for DummyRenderer {
+    fn unbind(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl Offscreen<DummyTexture> for DummyRenderer {
+    fn create_buffer(&mut self, size: Size<i32, Buffer>) -> Result<DummyTexture, Infallible> {
+        Ok(self.create_texture(size))
+    }
+}
+
+#[cfg(feature = "wayland_frontend")]
+impl ImportShm for DummyRenderer {
+    fn import_shm_buffer(
+        &mut self,
+        buffer: &wl_buffer::WlBuffer,
+        _surface: Option<&crate::wayland::compositor::SurfaceData>,
+        _damage: &[Rectangle<i32, Buffer>],
+    ) -> Result<DummyTexture, Infallible> {
+        let size = super::buffer_dimensions(buffer).unwrap_or_default();
+        Ok(self.import(buffer, size))
+    }
+}
+
+#[cfg(all(
+    feature = "wayland_frontend",
+    feature = "backend_egl",
+    feature = "use_system_lib"
+))]
+impl ImportEgl for DummyRenderer {
+    fn bind_wl_display(&mut self, _display: &wayland_server::Display) -> Result<(), EglError> {
+        Err(EglError::DisplayNotSupported)
+    }
+
+    fn unbind_wl_display(&mut self) {}
+
+    fn egl_reader(&self) -> Option<&EGLBufferReader> {
+        None
+    }
+
+    fn import_egl_buffer(&mut self, buffer: &wl_buffer::WlBuffer) -> Result<DummyTexture, Infallible> {
+        let size = super::buffer_dimensions(buffer).unwrap_or_default();
+        Ok(self.import(buffer, size))
+    }
+}
+
+#[cfg(feature = "wayland_frontend")]
+impl ImportDma for DummyRenderer {
+    fn import_dma_buffer(&mut self, buffer: &wl_buffer::WlBuffer) -> Result<DummyTexture, Infallible> {
+        let size = super::buffer_dimensions(buffer).unwrap_or_default();
+        Ok(self.import(buffer, size))
+    }
+
+    fn import_dmabuf(&mut self, dmabuf: &Dmabuf) -> Result<DummyTexture, Infallible> {
+        Ok(self.create_texture(dmabuf.size()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_calls_are_recorded() {
+        let mut renderer = DummyRenderer::new();
+        let texture = renderer.create_texture((10, 10).into());
+        let dst = Rectangle::from_loc_and_size((5.0, 5.0), (10.0, 10.0));
+
+        renderer
+            .render((100, 100).into(), Transform::Normal, |_, frame| {
+                frame.clear(
+                    [0.0, 0.0, 0.0, 1.0],
+                    &[Rectangle::from_loc_and_size((0, 0), (100, 100))],
+                )?;
+                frame.render_texture_from_to(
+                    &texture,
+                    Rectangle::from_loc_and_size((0, 0), (10, 10)),
+                    dst,
+                    &[],
+                    Transform::Normal,
+                    1.0,
+                )
+            })
+            .unwrap()
+            .unwrap();
+
+        let commands = renderer.take_commands();
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(commands[0], DrawCommand::Render { size, .. } if size == (100, 100).into()));
+        assert!(
+            matches!(commands[2], DrawCommand::RenderTexture { ref texture, dst: d, .. } if texture.id() == 0 && d == dst)
+        );
+        assert!(renderer.commands().is_empty());
+    }
+}
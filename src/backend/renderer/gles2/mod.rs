@@ -13,7 +13,7 @@ use cgmath::{prelude::*, Matrix3, Vector2, Vector3};
 mod shaders;
 mod version;
 
-use super::{Bind, Frame, Renderer, Texture, TextureFilter, Unbind};
+use super::{Bind, Frame, Offscreen, ReadPixels, Renderer, Texture, TextureFilter, Unbind};
 use crate::backend::allocator::{
     dmabuf::{Dmabuf, WeakDmabuf},
     Format,
@@ -29,6 +29,8 @@ use crate::utils::{Buffer, Physical, Rectangle, Size, Transform};
 use super::ImportEgl;
 #[cfg(feature = "wayland_frontend")]
 use super::{ImportDma, ImportShm};
+#[cfg(feature = "wayland_frontend")]
+use crate::backend::egl::display::is_yuv_format;
 #[cfg(all(feature = "wayland_frontend", feature = "use_system_lib"))]
 use crate::backend::egl::{display::EGLBufferReader, Format as EGLFormat};
 #[cfg(feature = "wayland_frontend")]
@@ -164,6 +166,8 @@ pub struct Gles2Renderer {
     buffers: Vec<WeakGles2Buffer>,
     target_buffer: Option<Gles2Buffer>,
     target_surface: Option<Rc<EGLSurface>>,
+    // offscreen texture and the framebuffer it is attached to
+    target_texture: Option<(Gles2Texture, ffi::types::GLuint)>,
     extensions: Vec<String>,
     tex_programs: [Gles2TexProgram; shaders::FRAGMENT_COUNT],
     solid_program: Gles2SolidProgram,
@@ -217,6 +221,7 @@ impl fmt::Debug for Gles2Renderer {
             .field("buffers", &self.buffers)
             .field("target_buffer", &self.target_buffer)
             .field("target_surface", &self.target_surface)
+            .field("target_texture", &self.target_texture)
             .field("extensions", &self.extensions)
             .field("tex_programs", &self.tex_programs)
             .field("solid_program", &self.solid_program)
@@ -268,6 +273,9 @@ pub enum Gles2Error {
     /// This rendering operation was called without a previous `begin`-call
     #[error("Call begin before doing any rendering operations")]
     UnconstraintRenderingOperation,
+    /// A shared egl context could not be created
+    #[error("Failed to create shared egl context")]
+    ContextCreationError(#[source] crate::backend::egl::Error),
 }
 
 impl From<Gles2Error> for SwapBuffersError {
@@ -278,7 +286,8 @@ impl From<Gles2Error> for SwapBuffersError {
             | x @ Gles2Error::ProgramLinkError
             | x @ Gles2Error::GLFunctionLoaderError
             | x @ Gles2Error::GLExtensionNotSupported(_)
-            | x @ Gles2Error::UnconstraintRenderingOperation => SwapBuffersError::ContextLost(Box::new(x)),
+            | x @ Gles2Error::UnconstraintRenderingOperation
+            | x @ Gles2Error::ContextCreationError(_) => SwapBuffersError::ContextLost(Box::new(x)),
             Gles2Error::ContextActivationError(err) => err.into(),
             x @ Gles2Error::FramebufferBindingError
             | x @ Gles2Error::BindBufferEGLError(_)
@@ -294,7 +303,8 @@ impl From<Gles2Error> for SwapBuffersError {
             | x @ Gles2Error::ProgramLinkError
             | x @ Gles2Error::GLFunctionLoaderError
             | x @ Gles2Error::GLExtensionNotSupported(_)
-            | x @ Gles2Error::UnconstraintRenderingOperation => SwapBuffersError::ContextLost(Box::new(x)),
+            | x @ Gles2Error::UnconstraintRenderingOperation
+            | x @ Gles2Error::ContextCreationError(_) => SwapBuffersError::ContextLost(Box::new(x)),
             Gles2Error::ContextActivationError(err) => err.into(),
             x @ Gles2Error::FramebufferBindingError | x @ Gles2Error::BindBufferEGLError(_) => {
                 SwapBuffersError::TemporaryFailure(Box::new(x))
@@ -540,6 +550,7 @@ impl Gles2Renderer {
             solid_program,
             target_buffer: None,
             target_surface: None,
+            target_texture: None,
             buffers: Vec::new(),
             #[cfg(feature = "wayland_frontend")]
             dmabuf_cache: std::collections::HashMap::new(),
@@ -570,7 +581,7 @@ impl Gles2Renderer {
     fn cleanup(&mut self) -> Result<(), Gles2Error> {
         self.make_current()?;
         #[cfg(feature = "wayland_frontend")]
-        self.dmabuf_cache.retain(|entry, _tex| entry.upgrade().is_some());
+        prune_dmabuf_cache(&mut self.dmabuf_cache);
         for resource in self.destruction_callback.try_iter() {
             match resource {
                 CleanupResource::Texture(texture) => unsafe {
@@ -585,6 +596,12 @@ impl Gles2Renderer {
     }
 }
 
+// drops cached entries of dmabufs, that were already destroyed
+#[cfg(feature = "wayland_frontend")]
+fn prune_dmabuf_cache<T>(cache: &mut std::collections::HashMap<WeakDmabuf, T>) {
+    cache.retain(|entry, _| entry.upgrade().is_some());
+}
+
 #[cfg(feature = "wayland_frontend")]
 impl ImportShm for Gles2Renderer {
     fn import_shm_buffer(
@@ -781,7 +798,9 @@ impl ImportDma for Gles2Renderer {
         }
 
         self.existing_dmabuf_texture(buffer)?.map(Ok).unwrap_or_else(|| {
-            let is_external = !self.egl.dmabuf_render_formats().contains(&buffer.format());
+            // yuv formats need to be sampled as external textures to be converted to rgb
+            let is_external = is_yuv_format(buffer.format().code)
+                || !self.egl.dmabuf_render_formats().contains(&buffer.format());
 
             self.make_current()?;
             let image = self
@@ -983,12 +1002,69 @@ impl Bind<Dmabuf> for Gles2Renderer {
     }
 }
 
+impl Bind<Gles2Texture> for Gles2Renderer {
+    fn bind(&mut self, texture: Gles2Texture) -> Result<(), Gles2Error> {
+        self.unbind()?;
+        self.make_current()?;
+
+        unsafe {
+            let mut fbo = 0;
+            self.gl.GenFramebuffers(1, &mut fbo as *mut _);
+            self.gl.BindFramebuffer(ffi::FRAMEBUFFER, fbo);
+            self.gl.FramebufferTexture2D(
+                ffi::FRAMEBUFFER,
+                ffi::COLOR_ATTACHMENT0,
+                ffi::TEXTURE_2D,
+                texture.0.texture,
+                0,
+            );
+            let status = self.gl.CheckFramebufferStatus(ffi::FRAMEBUFFER);
+            if status != ffi::FRAMEBUFFER_COMPLETE {
+                self.gl.BindFramebuffer(ffi::FRAMEBUFFER, 0);
+                self.gl.DeleteFramebuffers(1, &fbo as *const _);
+                return Err(Gles2Error::FramebufferBindingError);
+            }
+            self.target_texture = Some((texture, fbo));
+        }
+
+        Ok(())
+    }
+}
+
+impl Offscreen<Gles2Texture> for Gles2Renderer {
+    fn create_buffer(&mut self, size: Size<i32, Buffer>) -> Result<Gles2Texture, Gles2Error> {
+        self.make_current()?;
+        let tex = unsafe {
+            let mut tex = 0;
+            self.gl.GenTextures(1, &mut tex);
+            self.gl.BindTexture(ffi::TEXTURE_2D, tex);
+            self.gl.TexImage2D(
+                ffi::TEXTURE_2D,
+                0,
+                ffi::RGBA as i32,
+                size.w,
+                size.h,
+                0,
+                ffi::RGBA,
+                ffi::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            self.gl.BindTexture(ffi::TEXTURE_2D, 0);
+            tex
+        };
+        Ok(unsafe { Gles2Texture::from_raw(self, tex, size) })
+    }
+}
+
 impl Unbind for Gles2Renderer {
     fn unbind(&mut self) -> Result<(), <Self as Renderer>::Error> {
         unsafe {
             self.egl.make_current()?;
         }
         unsafe { self.gl.BindFramebuffer(ffi::FRAMEBUFFER, 0) };
+        if let Some((_, fbo)) = self.target_texture.take() {
+            unsafe { self.gl.DeleteFramebuffers(1, &fbo as *const _) };
+        }
         self.target_buffer = None;
         self.target_surface = None;
         self.egl.unbind()?;
@@ -996,6 +1072,34 @@ impl Unbind for Gles2Renderer {
     }
 }
 
+impl ReadPixels for Gles2Renderer {
+    fn read_pixels(&mut self, region: Rectangle<i32, Physical>) -> Result<Vec<u8>, Gles2Error> {
+        if self.target_buffer.is_none() && self.target_surface.is_none() && self.target_texture.is_none() {
+            return Err(Gles2Error::FramebufferBindingError);
+        }
+        self.make_current()?;
+
+        let mut pixels = vec![0u8; (region.size.w.max(0) * region.size.h.max(0) * 4) as usize];
+        unsafe {
+            // rows are tightly packed
+            self.gl.PixelStorei(ffi::PACK_ALIGNMENT, 1);
+            // The projection used during `render` flips the y-axis to account for OpenGLs
+            // coordinate system, so the rows are already in top to bottom order.
+            self.gl.ReadPixels(
+                region.loc.x,
+                region.loc.y,
+                region.size.w,
+                region.size.h,
+                ffi::RGBA,
+                ffi::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+
+        Ok(pixels)
+    }
+}
+
 impl Drop for Gles2Renderer {
     fn drop(&mut self) {
         unsafe {
@@ -1035,6 +1139,27 @@ impl Gles2Renderer {
         &self.egl
     }
 
+    /// Creates a new [`Gles2Renderer`] with an [`EGLContext`] sharing resources with the context of this renderer.
+    ///
+    /// Textures created by either renderer are valid for both of them, which avoids re-importing
+    /// client buffers for every renderer, e.g. when rendering thumbnails or multiple outputs.
+    ///
+    /// # Safety
+    ///
+    /// The same constraints as for [`Gles2Renderer::new`] apply. Each renderer may only be used
+    /// by one thread at a time and the resulting renderer starts out with its context being current
+    /// on the calling thread. Both renderers are typically used from the same thread,
+    /// as their contexts are made current on every operation.
+    pub unsafe fn new_shared<L>(&self, logger: L) -> Result<Gles2Renderer, Gles2Error>
+    where
+        L: Into<Option<::slog::Logger>>,
+    {
+        let logger = logger.into().unwrap_or_else(|| self.logger.clone());
+        let context = EGLContext::new_shared(&self.egl.display, &self.egl, logger.clone())
+            .map_err(Gles2Error::ContextCreationError)?;
+        Gles2Renderer::new(context, logger)
+    }
+
     /// Run custom code in the GL context owned by this renderer.
     ///
     /// The OpenGL state of the renderer is considered an implementation detail
@@ -1067,6 +1192,10 @@ impl Renderer for Gles2Renderer {
         Ok(())
     }
 
+    fn cleanup_textures(&mut self) -> Result<(), Self::Error> {
+        self.cleanup()
+    }
+
     fn render<F, R>(
         &mut self,
         size: Size<i32, Physical>,
@@ -1446,3 +1575,38 @@ impl Gles2Frame {
         self.current_projection.as_ref()
     }
 }
+
+#[cfg(all(test, feature = "wayland_frontend"))]
+mod tests {
+    use super::prune_dmabuf_cache;
+    use crate::backend::allocator::{
+        dmabuf::{Dmabuf, DmabufFlags},
+        Fourcc, Modifier,
+    };
+    use std::{collections::HashMap, fs::File, os::unix::io::IntoRawFd};
+
+    fn dmabuf() -> Dmabuf {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let mut builder = Dmabuf::builder((64, 64), Fourcc::Argb8888, DmabufFlags::empty());
+        builder.add_plane(fd, 0, 0, 256, Modifier::Linear);
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn cycling_buffers_does_not_grow_cache() {
+        let mut cache = HashMap::new();
+        let mut buffers = vec![dmabuf(), dmabuf()];
+
+        for i in 0..100 {
+            // a client double-buffering, destroying and replacing one of its buffers every frame
+            cache.insert(buffers[i % 2].weak(), i);
+            buffers[(i + 1) % 2] = dmabuf();
+            prune_dmabuf_cache(&mut cache);
+            assert!(cache.len() <= 2);
+        }
+
+        drop(buffers);
+        prune_dmabuf_cache(&mut cache);
+        assert!(cache.is_empty());
+    }
+}
@@ -13,15 +13,16 @@ use std::error::Error;
 use crate::utils::{Buffer, Physical, Point, Rectangle, Size, Transform};
 
 #[cfg(feature = "wayland_frontend")]
-use crate::wayland::compositor::SurfaceData;
+use crate::wayland::{color_management::ColorMetadata, compositor::SurfaceData};
 use cgmath::Matrix3;
 #[cfg(feature = "wayland_frontend")]
 use wayland_server::protocol::{wl_buffer, wl_shm};
 
+pub mod dummy;
 #[cfg(feature = "renderer_gl")]
 pub mod gles2;
 #[cfg(feature = "wayland_frontend")]
-use crate::backend::allocator::{dmabuf::Dmabuf, Format};
+use crate::backend::allocator::{dmabuf::Dmabuf, Format, Fourcc};
 #[cfg(all(
     feature = "wayland_frontend",
     feature = "backend_egl",
@@ -100,6 +101,15 @@ pub trait Unbind: Renderer {
     fn unbind(&mut self) -> Result<(), <Self as Renderer>::Error>;
 }
 
+/// Functionality to create offscreen rendering targets
+///
+/// Combined with [`Bind`] this allows to render into buffers not shown on any output,
+/// for example to capture the contents of a window.
+pub trait Offscreen<Target>: Renderer + Bind<Target> {
+    /// Create a new instance of a framebuffer of the given size, that can be bound for rendering.
+    fn create_buffer(&mut self, size: Size<i32, Buffer>) -> Result<Target, <Self as Renderer>::Error>;
+}
+
 /// A two dimensional texture
 pub trait Texture {
     /// Size of the texture plane
@@ -175,6 +185,18 @@ pub trait Frame {
 
     /// Output transformation that is applied to this frame
     fn transformation(&self) -> Transform;
+
+    /// Set the color metadata of the textures rendered next, `None` for sRGB contents.
+    ///
+    /// [`draw_surface_tree`](utils::draw_surface_tree) calls this with the
+    /// [color metadata](crate::wayland::color_management) of every surface before rendering it.
+    /// Renderers driving wide-color or HDR outputs may use it to tag the output or to tone-map
+    /// the following textures. The default implementation ignores the metadata.
+    #[cfg(feature = "wayland_frontend")]
+    fn set_color_metadata(&mut self, metadata: Option<ColorMetadata>) -> Result<(), Self::Error> {
+        let _ = metadata;
+        Ok(())
+    }
 }
 
 /// Abstraction of commonly used rendering operations for compositors.
@@ -191,6 +213,18 @@ pub trait Renderer {
     /// Set the filter method to be used when rendering a texture into a larger area than its size
     fn upscale_filter(&mut self, filter: TextureFilter) -> Result<(), Self::Error>;
 
+    /// Free resources of textures, that are not in use anymore.
+    ///
+    /// This includes cached textures of imported client buffers, that were already destroyed.
+    /// Renderers usually do this lazily at the start of [`Renderer::render`], but compositors
+    /// not rendering for longer periods of time may call this to avoid accumulating
+    /// stale resources.
+    ///
+    /// The default implementation does nothing.
+    fn cleanup_textures(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// Initialize a rendering context on the current rendering target with given dimensions and transformation.
     ///
     /// This function *may* error, if:
@@ -208,6 +242,34 @@ pub trait Renderer {
         F: FnOnce(&mut Self, &mut Self::Frame) -> R;
 }
 
+/// Trait for Renderers supporting reading back the contents of the current rendering target.
+pub trait ReadPixels: Renderer {
+    /// Read the pixels of a region of the currently bound rendering target.
+    ///
+    /// The result contains tightly packed RGBA8 values, row by row, starting at the top-left
+    /// corner of the region. Coordinates are in the same coordinate system used during
+    /// [`Renderer::render`] with [`Transform::Normal`], meaning `(0, 0)` is the top-left corner
+    /// of the target.
+    ///
+    /// This is mostly useful for testing the rendering results (see [`pixels_match`]) or
+    /// for taking screenshots. It should be called after rendering into the target and
+    /// before the target is presented or unbound.
+    fn read_pixels(&mut self, region: Rectangle<i32, Physical>) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Compare two RGBA8 images, as returned by [`ReadPixels::read_pixels`], allowing for
+/// a given difference per color channel.
+///
+/// Returns `false` if the images have a different length or if any channel of
+/// any pixel differs by more than `tolerance`.
+pub fn pixels_match(expected: &[u8], actual: &[u8], tolerance: u8) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(actual.iter())
+            .all(|(a, b)| (*a as i16 - *b as i16).abs() <= tolerance as i16)
+}
+
 #[cfg(feature = "wayland_frontend")]
 /// Trait for Renderers supporting importing shm-based buffers.
 pub trait ImportShm: Renderer {
@@ -254,6 +316,14 @@ pub trait ImportEgl: Renderer {
     /// This will allow clients to utilize EGL to create hardware-accelerated
     /// surfaces. This renderer will thus be able to handle wl_drm-based buffers.
     ///
+    /// Binding advertises the legacy `wl_drm` global of the EGL implementation to clients.
+    /// Buffers of hardware-accelerated clients can also be shared through the `zwp_linux_dmabuf_v1`
+    /// global (see [`init_dmabuf_global`](crate::wayland::dmabuf::init_dmabuf_global)), which only
+    /// requires [`ImportDma`]. However clients using Mesa still need `wl_drm` to discover the device
+    /// to render with, unless the compositor supports the default feedback of version 4 of
+    /// `zwp_linux_dmabuf_v1`, which smithay does not provide yet. Compositors should therefore
+    /// keep calling this function, unless they do not need to support such clients.
+    ///
     /// ## Errors
     ///
     /// This might return [`EglExtensionNotSupported`](super::egl::Error::EglExtensionNotSupported)
@@ -483,3 +553,64 @@ pub fn buffer_dimensions(buffer: &wl_buffer::WlBuffer) -> Option<Size<i32, Buffe
 
     crate::wayland::shm::with_buffer_contents(buffer, |_, data| (data.width, data.height).into()).ok()
 }
+
+/// Returns the pixel format of a wl_buffer
+///
+/// EGL-based buffers only expose a coarse format, which is mapped to the closest
+/// matching fourcc code. `None` is returned for external EGL buffers.
+///
+/// *Note*: This will only return formats for buffer types known to smithay (see [`buffer_type`])
+#[cfg(feature = "wayland_frontend")]
+pub fn buffer_format(buffer: &wl_buffer::WlBuffer) -> Option<Fourcc> {
+    use crate::backend::allocator::Buffer;
+    use std::convert::TryFrom;
+
+    if let Some(buf) = buffer.as_ref().user_data().get::<Dmabuf>() {
+        return Some(buf.format().code);
+    }
+
+    #[cfg(all(feature = "backend_egl", feature = "use_system_lib"))]
+    if let Some(format) = BUFFER_READER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|x| x.upgrade())
+        .and_then(|x| x.egl_buffer_format(buffer))
+    {
+        use crate::backend::egl::Format as EGLFormat;
+        return match format {
+            EGLFormat::RGB => Some(Fourcc::Xrgb8888),
+            EGLFormat::RGBA => Some(Fourcc::Argb8888),
+            EGLFormat::Y_UV => Some(Fourcc::Nv12),
+            EGLFormat::Y_U_V => Some(Fourcc::Yuv420),
+            EGLFormat::External | EGLFormat::Y_XUXV => None,
+        };
+    }
+
+    crate::wayland::shm::with_buffer_contents(buffer, |_, data| match data.format {
+        // the only two formats, which do not match their fourcc code
+        wl_shm::Format::Argb8888 => Some(Fourcc::Argb8888),
+        wl_shm::Format::Xrgb8888 => Some(Fourcc::Xrgb8888),
+        format => Fourcc::try_from(format.to_raw()).ok(),
+    })
+    .ok()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pixels_match;
+
+    #[test]
+    fn pixels_match_within_tolerance() {
+        let expected = [255, 0, 0, 255, 0, 128, 0, 255];
+        let actual = [253, 1, 0, 255, 0, 126, 2, 255];
+        assert!(pixels_match(&expected, &actual, 2));
+        assert!(!pixels_match(&expected, &actual, 1));
+    }
+
+    #[test]
+    fn pixels_match_different_size() {
+        assert!(!pixels_match(&[0, 0, 0, 255], &[0, 0, 0, 255, 0, 0, 0, 255], 255));
+    }
+}
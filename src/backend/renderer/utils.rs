@@ -1,16 +1,21 @@
 //! Utility module for helpers around drawing [`WlSurface`]s with [`Renderer`]s.
 
 use crate::{
-    backend::renderer::{buffer_dimensions, Frame, ImportAll, Renderer, Texture},
-    utils::{Buffer, Logical, Point, Rectangle, Size, Transform},
-    wayland::compositor::{
-        is_sync_subsurface, with_surface_tree_upward, BufferAssignment, Damage, SubsurfaceCachedState,
-        SurfaceAttributes, TraversalAction,
+    backend::renderer::{buffer_dimensions, buffer_type, BufferType, Frame, ImportAll, Renderer, Texture},
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Size, Transform},
+    wayland::{
+        color_management::color_metadata,
+        compositor::{
+            is_sync_subsurface, with_states, with_surface_tree_downward, with_surface_tree_upward,
+            BufferAssignment, Damage, RectangleKind, RegionAttributes, SubsurfaceCachedState,
+            SurfaceAttributes, TraversalAction,
+        },
+        seat::CursorImageAttributes,
     },
 };
-use std::cell::RefCell;
 #[cfg(feature = "desktop")]
 use std::collections::HashSet;
+use std::{cell::RefCell, sync::Mutex};
 use wayland_server::protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface};
 
 #[derive(Default)]
@@ -18,14 +23,29 @@ pub(crate) struct SurfaceState {
     pub(crate) buffer_dimensions: Option<Size<i32, Buffer>>,
     pub(crate) buffer_scale: i32,
     pub(crate) buffer_transform: Transform,
+    // offset requested with `wl_surface.offset`, not yet taken by the compositor
+    pub(crate) buffer_delta: Option<Point<i32, Logical>>,
     pub(crate) buffer: Option<WlBuffer>,
+    // replaced buffers are only released once the frames using them were presented
+    pub(crate) delay_release: bool,
+    // the current buffer was drawn in a frame, that was not presented yet
+    pub(crate) buffer_in_use: bool,
+    // replaced buffers still used by a frame, that was not presented yet
+    pub(crate) pending_release: Vec<WlBuffer>,
     pub(crate) texture: Option<Box<dyn std::any::Any + 'static>>,
+    // importing the current buffer failed, do not retry until a new buffer is attached
+    pub(crate) import_failed: bool,
+    // opaque region as non-overlapping rectangles, clamped to the surface size
+    pub(crate) opaque_regions: Vec<Rectangle<i32, Logical>>,
     #[cfg(feature = "desktop")]
     pub(crate) damage_seen: HashSet<crate::desktop::space::SpaceOutputHash>,
 }
 
 impl SurfaceState {
     pub fn update_buffer(&mut self, attrs: &mut SurfaceAttributes) {
+        if let Some(delta) = attrs.buffer_delta.take() {
+            self.buffer_delta = Some(self.buffer_delta.unwrap_or_default() + delta);
+        }
         match attrs.buffer.take() {
             Some(BufferAssignment::NewBuffer { buffer, .. }) => {
                 // new contents
@@ -34,10 +54,12 @@ impl SurfaceState {
                 self.buffer_transform = attrs.buffer_transform.into();
                 if let Some(old_buffer) = std::mem::replace(&mut self.buffer, Some(buffer)) {
                     if &old_buffer != self.buffer.as_ref().unwrap() {
-                        old_buffer.release();
+                        self.release_buffer(old_buffer);
+                        self.buffer_in_use = false;
                     }
                 }
                 self.texture = None;
+                self.import_failed = false;
                 #[cfg(feature = "desktop")]
                 self.damage_seen.clear();
             }
@@ -45,14 +67,40 @@ impl SurfaceState {
                 // remove the contents
                 self.buffer_dimensions = None;
                 if let Some(buffer) = self.buffer.take() {
-                    buffer.release();
+                    self.release_buffer(buffer);
                 };
+                self.buffer_in_use = false;
                 self.texture = None;
+                self.import_failed = false;
                 #[cfg(feature = "desktop")]
                 self.damage_seen.clear();
             }
             None => {}
         }
+
+        self.opaque_regions = match (attrs.opaque_region.as_ref(), self.surface_size()) {
+            (Some(region), Some(size)) => opaque_regions(region, size),
+            _ => Vec::new(),
+        };
+    }
+
+    // release a replaced buffer, once no pending frame uses it anymore
+    fn release_buffer(&mut self, buffer: WlBuffer) {
+        // shm contents are copied when imported, the renderer never reads from the buffer itself
+        let is_shm = matches!(buffer_type(&buffer), Some(BufferType::Shm));
+        if self.delay_release && self.buffer_in_use && !is_shm {
+            self.pending_release.push(buffer);
+        } else {
+            buffer.release();
+        }
+    }
+
+    // the last frame drawn was presented, buffers replaced since are not needed anymore
+    fn frame_presented(&mut self) {
+        self.buffer_in_use = false;
+        for buffer in self.pending_release.drain(..) {
+            buffer.release();
+        }
     }
 
     /// Returns the size of the surface.
@@ -63,6 +111,72 @@ impl SurfaceState {
     }
 }
 
+/// Takes the offset requested by the client with `wl_surface.offset` since the last call
+///
+/// The offset moves the top-left corner of the surface contents relative to the previous ones,
+/// the compositor is expected to move the surface accordingly: a window by changing its location,
+/// a cursor or drag'n'drop icon by moving its hotspot. Offsets of subsurfaces are applied to
+/// their position by [`on_commit_buffer_handler`] directly, so drawing, input and damage all
+/// follow them.
+///
+/// Offsets requested by several commits accumulate until they are taken. Returns `None` if no
+/// offset was requested or if the surface is not handled by [`on_commit_buffer_handler`].
+pub fn take_buffer_delta(surface: &WlSurface) -> Option<Point<i32, Logical>> {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<RefCell<SurfaceState>>()
+            .and_then(|data| data.borrow_mut().buffer_delta.take())
+    })
+    .unwrap_or(None)
+}
+
+/// Behaviour of [`draw_surface_tree_with_failure_mode`] for buffers, that cannot be imported
+/// by the renderer
+///
+/// By default a placeholder is drawn in place of such surfaces, so a misbehaving client
+/// cannot fail the rendering of a whole output. Failed imports are only logged once
+/// per buffer and not retried until a new buffer is attached.
+///
+/// Use [`ImportFailureMode::Error`] to restore hard failures for debugging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFailureMode {
+    /// Skip drawing the surface and its subsurfaces
+    Skip,
+    /// Draw a solid rectangle of the given color in place of the surface
+    Placeholder([f32; 4]),
+    /// Return the import error, failing the whole rendering operation
+    Error,
+}
+
+impl Default for ImportFailureMode {
+    fn default() -> Self {
+        ImportFailureMode::Placeholder([0.3, 0.3, 0.3, 1.0])
+    }
+}
+
+// Resolve a region into a list of non-overlapping rectangles inside the surface bounds
+fn opaque_regions(region: &RegionAttributes, size: Size<i32, Logical>) -> Vec<Rectangle<i32, Logical>> {
+    let surface = Rectangle::from_loc_and_size((0, 0), size);
+    region
+        .rects
+        .iter()
+        .fold(
+            Vec::new(),
+            |rects: Vec<Rectangle<i32, Logical>>, (kind, rect)| match kind {
+                RectangleKind::Add => {
+                    let added = rect.subtract_rects(rects.iter().copied());
+                    rects.into_iter().chain(added).collect()
+                }
+                RectangleKind::Subtract => rects.into_iter().flat_map(|r| r.subtract_rect(*rect)).collect(),
+            },
+        )
+        .into_iter()
+        .flat_map(|rect| rect.intersection(surface))
+        .filter(|rect| rect.size.w > 0 && rect.size.h > 0)
+        .collect()
+}
+
 /// Handler to let smithay take over buffer management.
 ///
 /// Needs to be called first on the commit-callback of
@@ -72,7 +186,29 @@ impl SurfaceState {
 /// not be accessible anymore, but [`draw_surface_tree`] and other
 /// `draw_*` helpers of the [desktop module](`crate::desktop`) will
 /// become usable for surfaces handled this way.
+///
+/// Buffers replaced by a commit are released right away, see
+/// [`on_commit_buffer_handler_with_delayed_release`] to keep them until the frames drawn
+/// from them were presented.
 pub fn on_commit_buffer_handler(surface: &WlSurface) {
+    commit_buffer_handler(surface, false);
+}
+
+/// Handler to let smithay take over buffer management, releasing buffers only once they are
+/// not used by the renderer anymore.
+///
+/// Behaves like [`on_commit_buffer_handler`], except for buffers replaced by a commit while a
+/// frame drawn from them by [`draw_surface_tree`] was not presented yet: as the renderer may
+/// still read from them, their release is delayed until [`release_presented_buffers`] is called
+/// for the surface. Compositors using this handler need to call it once their frames were
+/// presented, or their clients will run out of buffers.
+///
+/// Shm buffers are always released right away, their contents are copied when imported.
+pub fn on_commit_buffer_handler_with_delayed_release(surface: &WlSurface) {
+    commit_buffer_handler(surface, true);
+}
+
+fn commit_buffer_handler(surface: &WlSurface, delay_release: bool) {
     if !is_sync_subsurface(surface) {
         with_surface_tree_upward(
             surface,
@@ -87,22 +223,59 @@ pub fn on_commit_buffer_handler(surface: &WlSurface) {
                     .get::<RefCell<SurfaceState>>()
                     .unwrap()
                     .borrow_mut();
+                data.delay_release = delay_release;
                 data.update_buffer(&mut *states.cached_state.current::<SurfaceAttributes>());
+                // subsurfaces are positioned by their client, so move them right away
+                if states.role == Some("subsurface") {
+                    if let Some(delta) = data.buffer_delta.take() {
+                        states.cached_state.current::<SubsurfaceCachedState>().location += delta;
+                        states.cached_state.pending::<SubsurfaceCachedState>().location += delta;
+                    }
+                }
             },
             |_, _, _| true,
         );
     }
 }
 
+/// Release the buffers of a surface and its subsurfaces, that are not needed anymore after a frame
+/// was presented.
+///
+/// When a client commits a new buffer, the previous one is only released right away if it was not
+/// drawn by [`draw_surface_tree`] since the last call to this function. Otherwise the renderer might
+/// still read from it for a frame not yet presented, and its release is delayed until this
+/// function is called. Call it once the frame showing the surface was presented, e.g. on the vblank
+/// event of the backend. [`Space::send_frames_for_output`](crate::desktop::Space::send_frames_for_output)
+/// does so for the surfaces shown on the presented output.
+///
+/// Note: This only handles buffers managed by [`on_commit_buffer_handler_with_delayed_release`].
+pub fn release_presented_buffers(surface: &WlSurface) {
+    with_surface_tree_upward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |_, states, _| {
+            if let Some(data) = states.data_map.get::<RefCell<SurfaceState>>() {
+                data.borrow_mut().frame_presented();
+            }
+        },
+        |_, _, _| true,
+    );
+}
+
 /// Draws a surface and its subsurfaces using a given [`Renderer`] and [`Frame`].
 ///
 /// - `scale` needs to be equivalent to the fractional scale the rendered result should have.
 /// - `location` is the position the surface should be drawn at.
 /// - `damage` is the set of regions of the surface that should be drawn.
+/// - `alpha` is the opacity the surface and its subsurfaces are drawn with.
+///
+/// Buffers, that cannot be imported, are handled with the default [`ImportFailureMode`].
 ///
 /// Note: This element will render nothing, if you are not using
 /// [`crate::backend::renderer::utils::on_commit_buffer_handler`]
 /// to let smithay handle buffer management.
+#[allow(clippy::too_many_arguments)]
 pub fn draw_surface_tree<R, E, F, T>(
     renderer: &mut R,
     frame: &mut F,
@@ -110,6 +283,40 @@ pub fn draw_surface_tree<R, E, F, T>(
     scale: f64,
     location: Point<i32, Logical>,
     damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+{
+    draw_surface_tree_with_failure_mode(
+        renderer,
+        frame,
+        surface,
+        scale,
+        location,
+        damage,
+        alpha,
+        ImportFailureMode::default(),
+        log,
+    )
+}
+
+/// Draws a surface and its subsurfaces like [`draw_surface_tree`], handling buffers, that
+/// cannot be imported, according to `failure_mode`.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_surface_tree_with_failure_mode<R, E, F, T>(
+    renderer: &mut R,
+    frame: &mut F,
+    surface: &WlSurface,
+    scale: f64,
+    location: Point<i32, Logical>,
+    damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    failure_mode: ImportFailureMode,
     log: &slog::Logger,
 ) -> Result<(), R::Error>
 where
@@ -118,6 +325,52 @@ where
     E: std::error::Error,
     T: Texture + 'static,
 {
+    draw_surface_tree_scaled(
+        renderer,
+        frame,
+        surface,
+        (scale, scale),
+        location,
+        damage,
+        alpha,
+        failure_mode,
+        log,
+    )
+}
+
+// scale a logical rectangle into physical space, with separate horizontal and vertical factors
+fn to_physical_scaled(rect: Rectangle<f64, Logical>, scale: (f64, f64)) -> Rectangle<f64, Physical> {
+    Rectangle::from_loc_and_size(
+        (rect.loc.x * scale.0, rect.loc.y * scale.1),
+        (rect.size.w * scale.0, rect.size.h * scale.1),
+    )
+}
+
+/// Draws a surface and its subsurfaces like [`draw_surface_tree_with_failure_mode`], with separate
+/// horizontal and vertical scale factors, e.g. to stretch the surface to a given size.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_surface_tree_scaled<R, E, F, T>(
+    renderer: &mut R,
+    frame: &mut F,
+    surface: &WlSurface,
+    scale: (f64, f64),
+    location: Point<i32, Logical>,
+    damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    failure_mode: ImportFailureMode,
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+{
+    let placeholder = match failure_mode {
+        ImportFailureMode::Placeholder(color) => Some(color),
+        _ => None,
+    };
+    let mut import_result = Ok(());
     let mut result = Ok(());
     with_surface_tree_upward(
         surface,
@@ -128,16 +381,17 @@ where
                 let mut data = data.borrow_mut();
                 let attributes = states.cached_state.current::<SurfaceAttributes>();
                 // Import a new buffer if necessary
-                if data.texture.is_none() {
+                if data.texture.is_none() && !data.import_failed {
                     if let Some(buffer) = data.buffer.as_ref() {
+                        // use the scale and transform committed together with the buffer
                         let buffer_damage = attributes
                             .damage
                             .iter()
                             .map(|dmg| match dmg {
                                 Damage::Buffer(rect) => *rect,
                                 Damage::Surface(rect) => rect.to_buffer(
-                                    attributes.buffer_scale,
-                                    attributes.buffer_transform.into(),
+                                    data.buffer_scale,
+                                    data.buffer_transform,
                                     &data.surface_size().unwrap(),
                                 ),
                             })
@@ -149,15 +403,22 @@ where
                             }
                             Some(Err(err)) => {
                                 slog::warn!(log, "Error loading buffer: {}", err);
+                                data.import_failed = true;
+                                if failure_mode == ImportFailureMode::Error {
+                                    import_result = Err(err);
+                                }
                             }
                             None => {
                                 slog::error!(log, "Unknown buffer format for: {:?}", buffer);
+                                data.import_failed = true;
                             }
                         }
                     }
                 }
                 // Now, should we be drawn ?
-                if data.texture.is_some() {
+                if data.texture.is_some()
+                    || (data.import_failed && placeholder.is_some() && data.surface_size().is_some())
+                {
                     // if yes, also process the children
                     if states.role == Some("subsurface") {
                         let current = states.cached_state.current::<SubsurfaceCachedState>();
@@ -173,24 +434,25 @@ where
                 TraversalAction::SkipChildren
             }
         },
-        |_surface, states, location| {
+        |surface, states, location| {
             let mut location = *location;
             if let Some(data) = states.data_map.get::<RefCell<SurfaceState>>() {
                 let mut data = data.borrow_mut();
                 let dimensions = data.surface_size();
                 let buffer_scale = data.buffer_scale;
                 let buffer_transform = data.buffer_transform;
-                let attributes = states.cached_state.current::<SurfaceAttributes>();
+                // we need to re-extract the subsurface offset, as the previous closure
+                // only passes it to our children
+                let mut surface_offset = (0, 0).into();
+                if states.role == Some("subsurface") {
+                    let current = states.cached_state.current::<SubsurfaceCachedState>();
+                    surface_offset = current.location;
+                }
+                // the renderer reads from the buffer until the frame is presented
+                data.buffer_in_use |= data.texture.is_some();
                 if let Some(texture) = data.texture.as_mut().and_then(|x| x.downcast_mut::<T>()) {
                     let dimensions = dimensions.unwrap();
-                    // we need to re-extract the subsurface offset, as the previous closure
-                    // only passes it to our children
-                    let mut surface_offset = (0, 0).into();
-                    if states.role == Some("subsurface") {
-                        let current = states.cached_state.current::<SubsurfaceCachedState>();
-                        surface_offset = current.location;
-                        location += current.location;
-                    }
+                    location += surface_offset;
 
                     let damage = damage
                         .iter()
@@ -208,15 +470,47 @@ where
                         .collect::<Vec<_>>();
 
                     // TODO: Take wp_viewporter into account
-                    if let Err(err) = frame.render_texture_at(
-                        texture,
-                        location.to_f64().to_physical(scale).to_i32_round(),
-                        buffer_scale,
+                    // the buffer covers the logical surface size, which accounts for
+                    // the buffer scale and transform committed by the client
+                    let dst = to_physical_scaled(
+                        Rectangle::from_loc_and_size(location, dimensions).to_f64(),
                         scale,
-                        attributes.buffer_transform.into(),
-                        &damage,
-                        1.0,
-                    ) {
+                    );
+                    let dst = Rectangle::from_loc_and_size(dst.loc.to_i32_round::<i32>().to_f64(), dst.size);
+                    if let Err(err) = frame.set_color_metadata(color_metadata(surface)).and_then(|_| {
+                        frame.render_texture_from_to(
+                            texture,
+                            Rectangle::from_loc_and_size((0, 0), texture.size()),
+                            dst,
+                            &damage,
+                            buffer_transform,
+                            alpha,
+                        )
+                    }) {
+                        result = Err(err);
+                    }
+                } else if let (true, Some(color), Some(dimensions)) =
+                    (data.import_failed, placeholder, dimensions)
+                {
+                    location += surface_offset;
+
+                    let damage = damage
+                        .iter()
+                        .cloned()
+                        .map(|mut geo| {
+                            geo.loc -= surface_offset;
+                            geo
+                        })
+                        .flat_map(|geo| geo.intersection(Rectangle::from_loc_and_size((0, 0), dimensions)))
+                        // move it to the drawing location and into physical space
+                        .map(|mut geo| {
+                            geo.loc += location;
+                            to_physical_scaled(geo.to_f64(), scale).to_i32_round()
+                        })
+                        .filter(|geo| geo.size.w > 0 && geo.size.h > 0)
+                        .collect::<Vec<_>>();
+
+                    if let Err(err) = frame.clear([color[0], color[1], color[2], color[3] * alpha], &damage) {
                         result = Err(err);
                     }
                 }
@@ -225,5 +519,227 @@ where
         |_, _, _| true,
     );
 
-    result
+    import_result.and(result)
+}
+
+/// Draws only the damaged regions of a surface and its subsurfaces using a given [`Renderer`] and [`Frame`].
+///
+/// - `scale` needs to be equivalent to the fractional scale the rendered result should have.
+/// - `location` is the position the surface should be drawn at.
+/// - `damage` is the set of regions of the bound buffer, that should be redrawn.
+///   Everything outside of these regions is left untouched.
+///
+/// In contrast to [`draw_surface_tree`] the damage is given in physical coordinates
+/// relative to the bound buffer, as usually tracked by buffer-age based damage tracking.
+///
+/// Note: This function will render nothing, if you are not using
+/// [`crate::backend::renderer::utils::on_commit_buffer_handler`]
+/// to let smithay handle buffer management.
+pub fn draw_surface_tree_damaged<R, E, F, T>(
+    renderer: &mut R,
+    frame: &mut F,
+    surface: &WlSurface,
+    scale: f64,
+    location: Point<i32, Logical>,
+    damage: &[Rectangle<i32, Physical>],
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+{
+    let damage = damage
+        .iter()
+        // smallest logical rectangle covering the physical damage
+        .map(|rect| rect.to_f64().to_logical(scale).to_i32_up())
+        // make it relative to the surface
+        .map(|mut rect: Rectangle<i32, Logical>| {
+            rect.loc -= location;
+            rect
+        })
+        .collect::<Vec<_>>();
+    if damage.is_empty() {
+        return Ok(());
+    }
+
+    draw_surface_tree(renderer, frame, surface, scale, location, &damage, 1.0, log)
+}
+
+// bounding box of a surface and its mapped subsurfaces, offset by `location`
+pub(crate) fn surface_tree_bbox(
+    surface: &WlSurface,
+    location: Point<i32, Logical>,
+) -> Rectangle<i32, Logical> {
+    let mut bounding_box = Rectangle::from_loc_and_size(location, (0, 0));
+    with_surface_tree_downward(
+        surface,
+        location,
+        |_, states, loc: &Point<i32, Logical>| {
+            let mut loc = *loc;
+            let data = states.data_map.get::<RefCell<SurfaceState>>();
+
+            if let Some(size) = data.and_then(|d| d.borrow().surface_size()) {
+                if states.role == Some("subsurface") {
+                    let current = states.cached_state.current::<SubsurfaceCachedState>();
+                    loc += current.location;
+                }
+
+                // Update the bounding box.
+                bounding_box = bounding_box.merge(Rectangle::from_loc_and_size(loc, size));
+
+                TraversalAction::DoChildren(loc)
+            } else {
+                // If the parent surface is unmapped, then the child surfaces are hidden as
+                // well, no need to consider them here.
+                TraversalAction::SkipChildren
+            }
+        },
+        |_, _, _| {},
+        |_, _, _| true,
+    );
+    bounding_box
+}
+
+// marks surfaces drawn as a cursor without having the cursor image role, to only warn once
+struct MissingCursorRole;
+
+/// Draws a cursor surface and its subsurfaces using a given [`Renderer`] and [`Frame`].
+///
+/// - `location` is the position of the pointer relative to the output. It will be
+///   clamped to the output bounds given by `output_size`, so the cursor always stays on-screen.
+/// - `scale` needs to be equivalent to the fractional scale the rendered result should have.
+/// - `default_cursor` is drawn instead of `surface`, if it does not have the cursor image role,
+///   with its top-left corner at the pointer location. Nothing is drawn in that case if it is `None`.
+///
+/// The hotspot of the cursor is read from the [`CursorImageAttributes`] of the surface
+/// and subtracted from `location`. A warning is logged the first time a surface without
+/// the cursor image role is drawn.
+///
+/// Note: This function will render nothing, if you are not using
+/// [`crate::backend::renderer::utils::on_commit_buffer_handler`]
+/// to let smithay handle buffer management.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_cursor<R, E, F, T>(
+    renderer: &mut R,
+    frame: &mut F,
+    surface: &WlSurface,
+    location: Point<i32, Logical>,
+    output_size: Size<i32, Logical>,
+    scale: f64,
+    default_cursor: Option<&T>,
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+{
+    let location = Point::<i32, Logical>::from((
+        location.x.max(0).min((output_size.w - 1).max(0)),
+        location.y.max(0).min((output_size.h - 1).max(0)),
+    ));
+
+    let hotspot = with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<CursorImageAttributes>>()
+            .map(|attrs| attrs.lock().unwrap().hotspot)
+    })
+    .ok()
+    .flatten();
+    let hotspot = match hotspot {
+        Some(hotspot) => hotspot,
+        None => {
+            let first_time = with_states(surface, |states| {
+                states.data_map.insert_if_missing(|| MissingCursorRole)
+            })
+            .unwrap_or(false);
+            if first_time {
+                slog::warn!(
+                    log,
+                    "Trying to display as a cursor a surface that does not have the CursorImage role, drawing the default cursor instead."
+                );
+            }
+            return match default_cursor {
+                Some(texture) => frame.set_color_metadata(None).and_then(|_| {
+                    frame.render_texture_at(
+                        texture,
+                        location.to_f64().to_physical(scale),
+                        1,
+                        scale,
+                        Transform::Normal,
+                        &[Rectangle::from_loc_and_size((0, 0), texture.size())],
+                        1.0,
+                    )
+                }),
+                None => Ok(()),
+            };
+        }
+    };
+
+    let location = location - hotspot;
+    // the whole cursor is redrawn, including subsurfaces at negative offsets
+    let damage = [surface_tree_bbox(surface, (0, 0).into())];
+    draw_surface_tree(renderer, frame, surface, scale, location, &damage, 1.0, log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SurfaceState;
+    use crate::{
+        utils::{Point, Size, Transform},
+        wayland::compositor::SurfaceAttributes,
+    };
+
+    #[test]
+    fn scaled_buffer_surface_size() {
+        let state = SurfaceState {
+            buffer_dimensions: Some(Size::from((200, 100))),
+            buffer_scale: 2,
+            buffer_transform: Transform::Normal,
+            ..Default::default()
+        };
+
+        assert_eq!(Some(Size::from((100, 50))), state.surface_size());
+    }
+
+    #[test]
+    fn scaled_rotated_buffer_surface_size() {
+        let state = SurfaceState {
+            buffer_dimensions: Some(Size::from((200, 100))),
+            buffer_scale: 2,
+            buffer_transform: Transform::_90,
+            ..Default::default()
+        };
+
+        assert_eq!(Some(Size::from((50, 100))), state.surface_size());
+    }
+
+    #[test]
+    fn buffer_deltas_accumulate_until_taken() {
+        let mut state = SurfaceState::default();
+        let mut attributes = SurfaceAttributes {
+            buffer_delta: Some((3, -4).into()),
+            ..Default::default()
+        };
+        state.update_buffer(&mut attributes);
+        assert_eq!(attributes.buffer_delta, None);
+        assert_eq!(state.buffer_delta, Some(Point::from((3, -4))));
+
+        // processing the same state again does not apply the offset twice
+        state.update_buffer(&mut attributes);
+        assert_eq!(state.buffer_delta, Some(Point::from((3, -4))));
+
+        attributes.buffer_delta = Some((2, 2).into());
+        state.update_buffer(&mut attributes);
+        assert_eq!(state.buffer_delta.take(), Some(Point::from((5, -2))));
+
+        // once taken, only new offsets are reported
+        attributes.buffer_delta = Some((1, 0).into());
+        state.update_buffer(&mut attributes);
+        assert_eq!(state.buffer_delta, Some(Point::from((1, 0))));
+    }
 }
@@ -0,0 +1,87 @@
+use crate::{
+    desktop::{Space, Window},
+    wayland::{
+        seat::{KeyboardHandle, PointerHandle},
+        Serial,
+    },
+};
+
+/// Keyboard focus policy moving the focus to the [`Window`] under the pointer
+///
+/// Call [`FocusFollowsMouse::update`] after every pointer motion. If a delay is configured,
+/// the focus only moves once the pointer rested on the same window for that long, so you
+/// also need to call [`FocusFollowsMouse::update`] periodically (e.g. once per frame) for
+/// the pending focus change to be applied.
+///
+/// The focus stays on the last window if the pointer moves to an area without any window
+/// and is never changed while the pointer or the keyboard is grabbed, which means that
+/// drag'n'drop operations and popup grabs cannot lose their focus to another window.
+#[derive(Debug)]
+pub struct FocusFollowsMouse {
+    delay: u32,
+    pending: Option<(Window, u32)>,
+}
+
+impl FocusFollowsMouse {
+    /// Create a new focus policy
+    ///
+    /// `delay` is the time, in milliseconds, the pointer needs to stay on a window before
+    /// it gets focused. `0` focuses windows as soon as the pointer enters them.
+    pub fn new(delay: u32) -> FocusFollowsMouse {
+        FocusFollowsMouse { delay, pending: None }
+    }
+
+    /// The delay before a window under the pointer gets focused, in milliseconds
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    /// Change the delay before a window under the pointer gets focused, in milliseconds
+    pub fn set_delay(&mut self, delay: u32) {
+        self.delay = delay;
+    }
+
+    /// Move the keyboard focus to the window under the pointer, if appropriate
+    ///
+    /// The focused window also becomes the [active window](Space::active_window) of the space.
+    /// `time` needs to use the same clock as the time of your input events.
+    ///
+    /// Returns the window that got focused, if the focus changed.
+    pub fn update(
+        &mut self,
+        space: &mut Space,
+        pointer: &PointerHandle,
+        keyboard: &KeyboardHandle,
+        serial: Serial,
+        time: u32,
+    ) -> Option<Window> {
+        if pointer.is_grabbed() || keyboard.is_grabbed() {
+            self.pending = None;
+            return None;
+        }
+
+        let window = match space.window_under(pointer.current_location()) {
+            Some(window) if space.active_window().as_ref() != Some(window) => window.clone(),
+            _ => {
+                self.pending = None;
+                return None;
+            }
+        };
+
+        // restart the delay whenever the pointer enters another window
+        let since = match self.pending {
+            Some((ref pending, since)) if *pending == window => since,
+            _ => time,
+        };
+        if time.wrapping_sub(since) < self.delay {
+            self.pending = Some((window, since));
+            return None;
+        }
+
+        self.pending = None;
+        let surface = window.toplevel().get_surface().cloned();
+        keyboard.set_focus(surface.as_ref(), serial);
+        space.set_active_window(Some(&window));
+        Some(window)
+    }
+}
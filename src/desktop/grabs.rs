@@ -0,0 +1,494 @@
+use std::{cell::RefCell, rc::Rc, sync::Mutex};
+
+use wayland_protocols::xdg_shell::server::xdg_toplevel;
+use wayland_server::protocol::{wl_pointer::ButtonState, wl_shell_surface, wl_surface::WlSurface};
+
+use crate::{
+    desktop::{Kind, Space, Window, WindowSurfaceType},
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::{
+        compositor::with_states,
+        seat::{AxisFrame, PointerGrab, PointerGrabStartData, PointerInnerHandle},
+        shell::xdg::{SurfaceCachedState, XdgToplevelSurfaceRoleAttributes},
+        Serial,
+    },
+};
+
+// the surface of the space under a given location, in the form expected by `PointerHandle::motion`
+fn surface_under(space: &Space, location: Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)> {
+    let window = space.window_under(location)?;
+    let window_location = space.window_geometry(window)?.loc;
+    window
+        .surface_under(location - window_location.to_f64(), WindowSurfaceType::ALL)
+        .map(|(surface, location)| (surface, location + window_location))
+}
+
+/// [`PointerGrab`] implementing the interactive move of a [`Window`] of a [`Space`]
+///
+/// This is typically started in response to an `xdg_toplevel.move` request, using
+/// [`PointerHandle::start_grab_with_current_focus`](crate::wayland::seat::PointerHandle::start_grab_with_current_focus)
+/// or the start data of the current grab. The window follows the pointer until all buttons
+/// are released, no client has the pointer focus in the meantime.
+///
+/// Once released, the pointer focus is given to the window under the pointer. Layer surfaces
+/// are not considered, if you have layer surfaces above the windows, call
+/// [`PointerHandle::reevaluate_focus`](crate::wayland::seat::PointerHandle::reevaluate_focus)
+/// once the grab ended.
+#[derive(Debug)]
+pub struct MoveGrab {
+    start_data: PointerGrabStartData,
+    space: Rc<RefCell<Space>>,
+    window: Window,
+    initial_location: Point<i32, Logical>,
+    bounds: Option<Rectangle<i32, Logical>>,
+}
+
+impl MoveGrab {
+    /// Create a grab moving a window mapped in the given space
+    pub fn new(start_data: PointerGrabStartData, space: Rc<RefCell<Space>>, window: Window) -> MoveGrab {
+        let initial_location = space
+            .borrow()
+            .window_geometry(&window)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+        MoveGrab {
+            start_data,
+            space,
+            window,
+            initial_location,
+            bounds: None,
+        }
+    }
+
+    /// Keep the location of the window inside of `bounds`, e.g. to keep it reachable on the outputs
+    pub fn with_bounds(mut self, bounds: Rectangle<i32, Logical>) -> MoveGrab {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// The window being moved
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+}
+
+impl PointerGrab for MoveGrab {
+    fn motion(
+        &mut self,
+        handle: &mut PointerInnerHandle<'_>,
+        location: Point<f64, Logical>,
+        _focus: Option<(WlSurface, Point<i32, Logical>)>,
+        serial: Serial,
+        time: u32,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(location, None, serial, time);
+
+        let delta = location - self.start_data.location;
+        let mut new_location = (self.initial_location.to_f64() + delta).to_i32_round();
+        if let Some(bounds) = self.bounds {
+            new_location.x = new_location.x.max(bounds.loc.x).min(bounds.loc.x + bounds.size.w);
+            new_location.y = new_location.y.max(bounds.loc.y).min(bounds.loc.y + bounds.size.h);
+        }
+
+        let mut space = self.space.borrow_mut();
+        if space.window_geometry(&self.window).is_some() {
+            space.map_window(&self.window, new_location, false);
+        }
+    }
+
+    fn button(
+        &mut self,
+        handle: &mut PointerInnerHandle<'_>,
+        button: u32,
+        state: ButtonState,
+        serial: Serial,
+        time: u32,
+    ) {
+        handle.button(button, state, serial, time);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab and give the focus back
+            handle.unset_grab(serial, time);
+            let location = handle.current_location();
+            let focus = surface_under(&self.space.borrow(), location);
+            handle.motion(location, focus, serial, time);
+        }
+    }
+
+    fn axis(&mut self, handle: &mut PointerInnerHandle<'_>, details: AxisFrame) {
+        handle.axis(details)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData {
+        &self.start_data
+    }
+}
+
+bitflags::bitflags! {
+    /// Edges of a [`Window`] being resized by a [`ResizeGrab`]
+    pub struct ResizeEdge: u32 {
+        /// The top edge
+        const TOP = 1;
+        /// The bottom edge
+        const BOTTOM = 2;
+        /// The left edge
+        const LEFT = 4;
+        /// The top left corner
+        const TOP_LEFT = Self::TOP.bits | Self::LEFT.bits;
+        /// The bottom left corner
+        const BOTTOM_LEFT = Self::BOTTOM.bits | Self::LEFT.bits;
+        /// The right edge
+        const RIGHT = 8;
+        /// The top right corner
+        const TOP_RIGHT = Self::TOP.bits | Self::RIGHT.bits;
+        /// The bottom right corner
+        const BOTTOM_RIGHT = Self::BOTTOM.bits | Self::RIGHT.bits;
+    }
+}
+
+impl From<xdg_toplevel::ResizeEdge> for ResizeEdge {
+    fn from(edge: xdg_toplevel::ResizeEdge) -> ResizeEdge {
+        ResizeEdge::from_bits_truncate(edge.to_raw())
+    }
+}
+
+impl From<wl_shell_surface::Resize> for ResizeEdge {
+    fn from(edge: wl_shell_surface::Resize) -> ResizeEdge {
+        ResizeEdge::from_bits_truncate(edge.bits())
+    }
+}
+
+impl From<ResizeEdge> for wl_shell_surface::Resize {
+    fn from(edge: ResizeEdge) -> wl_shell_surface::Resize {
+        wl_shell_surface::Resize::from_bits_truncate(edge.bits())
+    }
+}
+
+// state of a resize, stored in the user data of the window until the final size is committed
+#[derive(Debug, Clone, Copy)]
+struct ResizeData {
+    edges: ResizeEdge,
+    initial_location: Point<i32, Logical>,
+    initial_size: Size<i32, Logical>,
+    state: ResizeState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResizeState {
+    // the grab is active
+    Resizing,
+    // the grab was released, the client has to ack the configure with the given serial
+    WaitingForFinalAck(Serial),
+    // the grab was released, the next commit has the final size
+    WaitingForCommit,
+}
+
+impl ResizeData {
+    // location keeping the edges opposite to the resized ones in place
+    fn anchored_location(&self, size: Size<i32, Logical>) -> Point<i32, Logical> {
+        let mut location = self.initial_location;
+        if self.edges.intersects(ResizeEdge::LEFT) {
+            location.x += self.initial_size.w - size.w;
+        }
+        if self.edges.intersects(ResizeEdge::TOP) {
+            location.y += self.initial_size.h - size.h;
+        }
+        location
+    }
+}
+
+// size of a window resized from the given edges with the pointer moved by `delta`
+fn resized(
+    initial_size: Size<i32, Logical>,
+    edges: ResizeEdge,
+    delta: Point<f64, Logical>,
+) -> Size<i32, Logical> {
+    let (mut dx, mut dy) = delta.into();
+    let mut new_size = initial_size;
+    if edges.intersects(ResizeEdge::LEFT | ResizeEdge::RIGHT) {
+        if edges.intersects(ResizeEdge::LEFT) {
+            dx = -dx;
+        }
+        new_size.w = (initial_size.w as f64 + dx) as i32;
+    }
+    if edges.intersects(ResizeEdge::TOP | ResizeEdge::BOTTOM) {
+        if edges.intersects(ResizeEdge::TOP) {
+            dy = -dy;
+        }
+        new_size.h = (initial_size.h as f64 + dy) as i32;
+    }
+    new_size
+}
+
+// clamp a size to the size hints of a toplevel
+fn clamp_size(
+    size: Size<i32, Logical>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+) -> Size<i32, Logical> {
+    // a maximum size of 0 means there is no maximum
+    let max_w = if max_size.w == 0 { i32::MAX } else { max_size.w };
+    let max_h = if max_size.h == 0 { i32::MAX } else { max_size.h };
+    (
+        size.w.max(min_size.w.max(1)).min(max_w),
+        size.h.max(min_size.h.max(1)).min(max_h),
+    )
+        .into()
+}
+
+type ResizeUserData = RefCell<Option<ResizeData>>;
+
+/// [`PointerGrab`] implementing the interactive resize of a [`Window`] of a [`Space`]
+///
+/// This is typically started in response to an `xdg_toplevel.resize` request. On every motion,
+/// a new size respecting the minimum and maximum size of the toplevel is suggested to the client
+/// with a configure. Once all buttons are released, the final size is sent and the grab ends.
+///
+/// Resizing from the top or left edges also moves the window, which can only be done once the
+/// client committed a buffer of its new size. For this, [`ResizeGrab::handle_commit`] needs to be
+/// called on every commit of the toplevel surface.
+#[derive(Debug)]
+pub struct ResizeGrab {
+    start_data: PointerGrabStartData,
+    window: Window,
+    edges: ResizeEdge,
+    initial_size: Size<i32, Logical>,
+    last_size: Size<i32, Logical>,
+}
+
+impl ResizeGrab {
+    /// Create a grab resizing a window mapped in the given space from the given edges
+    pub fn new(
+        start_data: PointerGrabStartData,
+        space: &Space,
+        window: Window,
+        edges: ResizeEdge,
+    ) -> ResizeGrab {
+        let initial_size = window.geometry().size;
+        let initial_location = space
+            .window_geometry(&window)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+        window.user_data().insert_if_missing(ResizeUserData::default);
+        *window.user_data().get::<ResizeUserData>().unwrap().borrow_mut() = Some(ResizeData {
+            edges,
+            initial_location,
+            initial_size,
+            state: ResizeState::Resizing,
+        });
+        ResizeGrab {
+            start_data,
+            window,
+            edges,
+            initial_size,
+            last_size: initial_size,
+        }
+    }
+
+    /// The window being resized
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Keep a window resized from its top or left edges anchored at its bottom or right edges
+    ///
+    /// Call this after every commit of the toplevel surface of a window, once the window was
+    /// [refreshed](Window::refresh). Does nothing if the window is not being resized.
+    pub fn handle_commit(space: &mut Space, window: &Window) {
+        let data = match window.user_data().get::<ResizeUserData>() {
+            Some(data) => data,
+            None => return,
+        };
+        let resize = match *data.borrow() {
+            Some(resize) => resize,
+            None => return,
+        };
+
+        let location = resize.anchored_location(window.geometry().size);
+        if space
+            .window_geometry(window)
+            .map(|geometry| geometry.loc != location)
+            .unwrap_or(false)
+        {
+            space.map_window(window, location, false);
+        }
+
+        let done = match resize.state {
+            ResizeState::Resizing => false,
+            ResizeState::WaitingForFinalAck(serial) => {
+                // the commit following the ack of the final configure has the final size
+                let acked = window.toplevel().get_surface().and_then(|surface| {
+                    with_states(surface, |states| {
+                        states
+                            .data_map
+                            .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                            .and_then(|attributes| attributes.lock().unwrap().configure_serial)
+                    })
+                    .ok()
+                    .flatten()
+                });
+                acked.map(|acked| acked >= serial).unwrap_or(false)
+            }
+            ResizeState::WaitingForCommit => true,
+        };
+        if done {
+            *data.borrow_mut() = None;
+        }
+    }
+
+    // the state of the resize once the final size was sent
+    fn final_state(&self) -> ResizeState {
+        let serial = match self.window.toplevel() {
+            Kind::Xdg(xdg) => xdg.get_surface().and_then(|surface| {
+                with_states(surface, |states| {
+                    states
+                        .data_map
+                        .get::<Mutex<XdgToplevelSurfaceRoleAttributes>>()
+                        .and_then(|attributes| attributes.lock().unwrap().pending_configure_serial())
+                })
+                .ok()
+                .flatten()
+            }),
+            // there is no configure to ack
+            _ => None,
+        };
+        serial
+            .map(ResizeState::WaitingForFinalAck)
+            .unwrap_or(ResizeState::WaitingForCommit)
+    }
+
+    fn send_size(&self, resizing: bool) {
+        match self.window.toplevel() {
+            Kind::Xdg(xdg) => {
+                let size = self.last_size;
+                let ret = xdg.with_pending_state(|state| {
+                    if resizing {
+                        state.states.set(xdg_toplevel::State::Resizing);
+                    } else {
+                        state.states.unset(xdg_toplevel::State::Resizing);
+                    }
+                    state.size = Some(size);
+                });
+                if ret.is_ok() {
+                    xdg.send_configure();
+                }
+            }
+            Kind::Wl(wl) => wl.send_configure(self.last_size, self.edges.into()),
+            #[cfg(feature = "xwayland")]
+            Kind::X11(_) => {}
+        }
+    }
+}
+
+impl PointerGrab for ResizeGrab {
+    fn motion(
+        &mut self,
+        handle: &mut PointerInnerHandle<'_>,
+        location: Point<f64, Logical>,
+        _focus: Option<(WlSurface, Point<i32, Logical>)>,
+        serial: Serial,
+        time: u32,
+    ) {
+        // the size hints of a dead toplevel are gone
+        let surface = match self.window.toplevel().get_surface() {
+            Some(surface) => surface,
+            None => {
+                handle.unset_grab(serial, time);
+                return;
+            }
+        };
+
+        // While the grab is active, no client has pointer focus
+        handle.motion(location, None, serial, time);
+
+        let new_size = resized(self.initial_size, self.edges, location - self.start_data.location);
+        let (min_size, max_size) = with_states(surface, |states| {
+            let data = states.cached_state.current::<SurfaceCachedState>();
+            (data.min_size, data.max_size)
+        })
+        .unwrap();
+        let new_size = clamp_size(new_size, min_size, max_size);
+
+        if new_size != self.last_size {
+            self.last_size = new_size;
+            self.send_size(true);
+        }
+    }
+
+    fn button(
+        &mut self,
+        handle: &mut PointerInnerHandle<'_>,
+        button: u32,
+        state: ButtonState,
+        serial: Serial,
+        time: u32,
+    ) {
+        handle.button(button, state, serial, time);
+        if handle.current_pressed().is_empty() {
+            // No more buttons are pressed, release the grab
+            handle.unset_grab(serial, time);
+            if !self.window.toplevel().alive() {
+                return;
+            }
+            self.send_size(false);
+            let state = self.final_state();
+            if let Some(data) = self.window.user_data().get::<ResizeUserData>() {
+                if let Some(ref mut resize) = *data.borrow_mut() {
+                    resize.state = state;
+                }
+            }
+        }
+    }
+
+    fn axis(&mut self, handle: &mut PointerInnerHandle<'_>, details: AxisFrame) {
+        handle.axis(details)
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData {
+        &self.start_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_is_clamped_to_size_hints() {
+        let min = (100, 50).into();
+        let max = (400, 300).into();
+        let size = resized(
+            (200, 200).into(),
+            ResizeEdge::BOTTOM_RIGHT,
+            (-150.0, 150.0).into(),
+        );
+        assert_eq!(clamp_size(size, min, max), (100, 300).into());
+
+        // no hints only keep the size positive
+        let size = resized((200, 200).into(), ResizeEdge::RIGHT, (-300.0, 0.0).into());
+        assert_eq!(clamp_size(size, (0, 0).into(), (0, 0).into()), (1, 200).into());
+    }
+
+    #[test]
+    fn top_left_resize_keeps_bottom_right_anchored() {
+        let resize = ResizeData {
+            edges: ResizeEdge::TOP_LEFT,
+            initial_location: (100, 100).into(),
+            initial_size: (200, 200).into(),
+            state: ResizeState::Resizing,
+        };
+        // moving the top left corner up and left grows the window
+        let size = resized(resize.initial_size, resize.edges, (-20.0, -30.0).into());
+        assert_eq!(size, (220, 230).into());
+        let location = resize.anchored_location(size);
+        assert_eq!(location, (80, 70).into());
+        assert_eq!(location + size.to_point(), (300, 300).into());
+
+        // the bottom right edges do not move the window
+        let resize = ResizeData {
+            edges: ResizeEdge::BOTTOM_RIGHT,
+            ..resize
+        };
+        assert_eq!(resize.anchored_location(size), (100, 100).into());
+    }
+}
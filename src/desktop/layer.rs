@@ -1,18 +1,23 @@
 use crate::{
-    backend::renderer::{utils::draw_surface_tree, Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{
+        utils::{draw_surface_tree_with_failure_mode, ImportFailureMode},
+        Frame, ImportAll, Renderer, Texture,
+    },
     desktop::{utils::*, PopupManager, Space},
     utils::{user_data::UserDataMap, Logical, Point, Rectangle},
     wayland::{
         compositor::{with_states, with_surface_tree_downward, TraversalAction},
         output::{Inner as OutputInner, Output},
+        seat::{KeyboardGrab, KeyboardGrabStartData, KeyboardHandle, KeyboardInnerHandle},
         shell::wlr_layer::{
             Anchor, ExclusiveZone, KeyboardInteractivity, Layer as WlrLayer, LayerSurface as WlrLayerSurface,
             LayerSurfaceCachedState,
         },
+        Serial,
     },
 };
 use indexmap::IndexSet;
-use wayland_server::protocol::wl_surface::WlSurface;
+use wayland_server::protocol::{wl_keyboard::KeyState, wl_surface::WlSurface};
 
 use std::{
     cell::{RefCell, RefMut},
@@ -33,6 +38,8 @@ pub struct LayerMap {
     zone: Rectangle<i32, Logical>,
     // surfaces for tracking enter and leave events
     surfaces: Vec<WlSurface>,
+    // layer surface currently holding an exclusive keyboard grab
+    keyboard_grab: Option<(LayerSurface, FocusRestore)>,
     logger: ::slog::Logger,
 }
 
@@ -59,6 +66,7 @@ pub fn layer_map_for_output(o: &Output) -> RefMut<'_, LayerMap> {
                     .unwrap_or_else(|| (0, 0).into()),
             ),
             surfaces: Vec::new(),
+            keyboard_grab: None,
             logger: (*o.inner.0.lock().unwrap())
                 .log
                 .new(slog::o!("smithay_module" => "layer_map")),
@@ -149,6 +157,9 @@ impl LayerMap {
     }
 
     /// Returns a [`LayerSurface`] under a given point and on a given layer, if any.
+    ///
+    /// The input regions of the surfaces are honored, points outside of them fall
+    /// through to the layer surfaces below.
     pub fn layer_under<P: Into<Point<f64, Logical>>>(
         &self,
         layer: WlrLayer,
@@ -158,6 +169,8 @@ impl LayerMap {
         self.layers_on(layer).rev().find(|l| {
             let bbox = self.layer_geometry(l).unwrap();
             bbox.to_f64().contains(point)
+                && l.surface_under(point - layer_state(l).location.to_f64(), WindowSurfaceType::ALL)
+                    .is_some()
         })
     }
 
@@ -184,6 +197,76 @@ impl LayerMap {
             .find(|w| w.get_surface().map(|x| x == surface).unwrap_or(false))
     }
 
+    /// Returns the topmost [`LayerSurface`] requesting exclusive keyboard focus, if any.
+    ///
+    /// See [`LayerSurface::requests_exclusive_keyboard_focus`].
+    pub fn exclusive_keyboard_layer(&self) -> Option<&LayerSurface> {
+        self.layers_on(WlrLayer::Overlay)
+            .rev()
+            .chain(self.layers_on(WlrLayer::Top).rev())
+            .find(|l| l.requests_exclusive_keyboard_focus())
+    }
+
+    /// Update the keyboard focus according to the keyboard interactivity of the mapped layer surfaces.
+    ///
+    /// If a [`LayerSurface`] requests exclusive keyboard focus, a grab is set on the keyboard keeping the
+    /// focus on it for as long as it is mapped. Focus changes requested through [`KeyboardHandle::set_focus`]
+    /// in the meantime are remembered and applied once the layer surface is unmapped or gives up its
+    /// exclusive keyboard interactivity.
+    ///
+    /// This should be called after mapping or unmapping layer surfaces and on commits of mapped layer surfaces.
+    pub fn update_keyboard_focus(&mut self, keyboard: &KeyboardHandle, serial: Serial) {
+        let exclusive = self.exclusive_keyboard_layer().cloned();
+        let mut restore = None;
+        if let Some((layer, focus_restore)) = self.keyboard_grab.take() {
+            if exclusive.as_ref() == Some(&layer) && layer.alive() {
+                self.keyboard_grab = Some((layer, focus_restore));
+                return;
+            }
+            restore = focus_restore.borrow_mut().take();
+            if restore.is_some()
+                && keyboard
+                    .grab_start_data()
+                    .map(|data| data.focus.as_ref() == layer.get_surface())
+                    .unwrap_or(false)
+            {
+                keyboard.unset_grab();
+            }
+        }
+
+        match exclusive
+            .as_ref()
+            .and_then(|layer| Some((layer, layer.get_surface()?)))
+        {
+            Some((layer, surface)) => {
+                let focus_restore = Rc::new(RefCell::new(Some(
+                    restore.unwrap_or_else(|| keyboard.current_focus()),
+                )));
+                keyboard.set_grab(
+                    LayerKeyboardGrab {
+                        layer: layer.clone(),
+                        focus_restore: focus_restore.clone(),
+                        start_data: KeyboardGrabStartData {
+                            focus: Some(surface.clone()),
+                        },
+                    },
+                    serial,
+                );
+                keyboard.set_focus(Some(surface), serial);
+                self.keyboard_grab = Some((layer.clone(), focus_restore));
+            }
+            None => {
+                if let Some(focus) = restore {
+                    slog::trace!(
+                        self.logger,
+                        "Restoring keyboard focus after exclusive layer surface"
+                    );
+                    keyboard.set_focus(focus.as_ref(), serial);
+                }
+            }
+        }
+    }
+
     /// Force re-arranging the layer surfaces, e.g. when the output size changes.
     ///
     /// Note: Mapping or unmapping a layer surface will automatically cause a re-arrangement.
@@ -199,13 +282,23 @@ impl LayerMap {
             let mut zone = output_rect;
             slog::trace!(self.logger, "Arranging layers into {:?}", output_rect.size);
 
-            for layer in self.layers.iter() {
-                let surface = if let Some(surface) = layer.get_surface() {
-                    surface
-                } else {
-                    continue;
-                };
+            let mut layers = self
+                .layers
+                .iter()
+                .filter_map(|layer| {
+                    let surface = layer.get_surface()?;
+                    let data = with_states(surface, |states| {
+                        *states.cached_state.current::<LayerSurfaceCachedState>()
+                    })
+                    .unwrap();
+                    Some((layer, surface, data))
+                })
+                .collect::<Vec<_>>();
+            // Surfaces claiming an exclusive zone need to be arranged first,
+            // so that the remaining surfaces can be placed in the area left over.
+            layers.sort_by_key(|(_, _, data)| !matches!(data.exclusive_zone, ExclusiveZone::Exclusive(_)));
 
+            for (layer, surface, data) in layers {
                 let logger_ref = &self.logger;
                 let surfaces_ref = &mut self.surfaces;
                 with_surface_tree_downward(
@@ -235,65 +328,9 @@ impl LayerMap {
                     }
                 }
 
-                let data = with_states(surface, |states| {
-                    *states.cached_state.current::<LayerSurfaceCachedState>()
-                })
-                .unwrap();
-
-                let source = match data.exclusive_zone {
-                    ExclusiveZone::Neutral | ExclusiveZone::Exclusive(_) => &zone,
-                    ExclusiveZone::DontCare => &output_rect,
-                };
-
-                let mut size = data.size;
-                if size.w == 0 {
-                    size.w = source.size.w / 2;
-                }
-                if size.h == 0 {
-                    size.h = source.size.h / 2;
-                }
-                if data.anchor.anchored_horizontally() {
-                    size.w = source.size.w;
-                }
-                if data.anchor.anchored_vertically() {
-                    size.h = source.size.h;
-                }
-
-                let x = if data.anchor.contains(Anchor::LEFT) {
-                    source.loc.x + data.margin.left
-                } else if data.anchor.contains(Anchor::RIGHT) {
-                    source.loc.x + (source.size.w - size.w) - data.margin.right
-                } else {
-                    source.loc.x + ((source.size.w / 2) - (size.w / 2))
-                };
-
-                let y = if data.anchor.contains(Anchor::TOP) {
-                    source.loc.y + data.margin.top
-                } else if data.anchor.contains(Anchor::BOTTOM) {
-                    source.loc.y + (source.size.h - size.h) - data.margin.bottom
-                } else {
-                    source.loc.y + ((source.size.h / 2) - (size.h / 2))
-                };
-
-                let location: Point<i32, Logical> = (x, y).into();
-
-                if let ExclusiveZone::Exclusive(amount) = data.exclusive_zone {
-                    match data.anchor {
-                        x if x.contains(Anchor::LEFT) && !x.contains(Anchor::RIGHT) => {
-                            zone.loc.x += amount as i32 + data.margin.left + data.margin.right
-                        }
-                        x if x.contains(Anchor::TOP) && !x.contains(Anchor::BOTTOM) => {
-                            zone.loc.y += amount as i32 + data.margin.top + data.margin.bottom
-                        }
-                        x if x.contains(Anchor::RIGHT) && !x.contains(Anchor::LEFT) => {
-                            zone.size.w -= amount as i32 + data.margin.left + data.margin.right
-                        }
-                        x if x.contains(Anchor::BOTTOM) && !x.contains(Anchor::TOP) => {
-                            zone.size.h -= amount as i32 + data.margin.top + data.margin.top
-                        }
-                        _ => {}
-                    }
-                }
+                let geometry = arrange_layer(output_rect, &mut zone, &data);
+                let location = geometry.loc;
+                let size = geometry.size;
 
                 slog::trace!(
                     self.logger,
@@ -340,6 +377,154 @@ impl LayerMap {
     }
 }
 
+/// Computes the geometry of a layer surface on an output from its cached protocol state.
+///
+/// `output` is the full area of the output, while `zone` is the area that is not yet claimed
+/// by the exclusive zone of another layer surface. If the surface sets an exclusive zone itself,
+/// `zone` is shrunk accordingly.
+fn arrange_layer(
+    output: Rectangle<i32, Logical>,
+    zone: &mut Rectangle<i32, Logical>,
+    data: &LayerSurfaceCachedState,
+) -> Rectangle<i32, Logical> {
+    let source = match data.exclusive_zone {
+        ExclusiveZone::Neutral | ExclusiveZone::Exclusive(_) => *zone,
+        ExclusiveZone::DontCare => output,
+    };
+
+    let mut size = data.size;
+    if data.anchor.anchored_horizontally() {
+        size.w = source.size.w - data.margin.left - data.margin.right;
+    } else if size.w == 0 {
+        size.w = source.size.w / 2;
+    }
+    if data.anchor.anchored_vertically() {
+        size.h = source.size.h - data.margin.top - data.margin.bottom;
+    } else if size.h == 0 {
+        size.h = source.size.h / 2;
+    }
+    size.w = size.w.max(0);
+    size.h = size.h.max(0);
+
+    let x = if data.anchor.contains(Anchor::LEFT) {
+        source.loc.x + data.margin.left
+    } else if data.anchor.contains(Anchor::RIGHT) {
+        source.loc.x + (source.size.w - size.w) - data.margin.right
+    } else {
+        source.loc.x + ((source.size.w / 2) - (size.w / 2))
+    };
+
+    let y = if data.anchor.contains(Anchor::TOP) {
+        source.loc.y + data.margin.top
+    } else if data.anchor.contains(Anchor::BOTTOM) {
+        source.loc.y + (source.size.h - size.h) - data.margin.bottom
+    } else {
+        source.loc.y + ((source.size.h / 2) - (size.h / 2))
+    };
+
+    if let ExclusiveZone::Exclusive(amount) = data.exclusive_zone {
+        let amount = amount as i32;
+        // The exclusive zone is only meaningful if the surface is anchored to a single edge,
+        // or to an edge and both perpendicular edges.
+        let free_horizontally =
+            data.anchor.anchored_horizontally() || !data.anchor.intersects(Anchor::LEFT | Anchor::RIGHT);
+        let free_vertically =
+            data.anchor.anchored_vertically() || !data.anchor.intersects(Anchor::TOP | Anchor::BOTTOM);
+        match data.anchor {
+            x if free_vertically && x.contains(Anchor::LEFT) && !x.contains(Anchor::RIGHT) => {
+                let amount = amount + data.margin.left;
+                zone.loc.x += amount;
+                zone.size.w -= amount;
+            }
+            x if free_horizontally && x.contains(Anchor::TOP) && !x.contains(Anchor::BOTTOM) => {
+                let amount = amount + data.margin.top;
+                zone.loc.y += amount;
+                zone.size.h -= amount;
+            }
+            x if free_vertically && x.contains(Anchor::RIGHT) && !x.contains(Anchor::LEFT) => {
+                zone.size.w -= amount + data.margin.right
+            }
+            x if free_horizontally && x.contains(Anchor::BOTTOM) && !x.contains(Anchor::TOP) => {
+                zone.size.h -= amount + data.margin.bottom
+            }
+            _ => {}
+        }
+        zone.size.w = zone.size.w.max(0);
+        zone.size.h = zone.size.h.max(0);
+    }
+
+    Rectangle::from_loc_and_size((x, y), size)
+}
+
+// Focus to restore once an exclusive layer surface gives up the keyboard focus,
+// `None` once it has been restored.
+type FocusRestore = Rc<RefCell<Option<Option<WlSurface>>>>;
+
+// Keeps the keyboard focus on a layer surface with exclusive keyboard interactivity,
+// see `LayerMap::update_keyboard_focus`
+#[derive(Debug)]
+struct LayerKeyboardGrab {
+    layer: LayerSurface,
+    focus_restore: FocusRestore,
+    start_data: KeyboardGrabStartData,
+}
+
+impl LayerKeyboardGrab {
+    fn is_active(&self) -> bool {
+        self.layer.alive()
+            && self
+                .layer
+                .user_data()
+                .get::<LayerUserdata>()
+                .map(|s| s.borrow().is_some())
+                .unwrap_or(false)
+            && self.layer.requests_exclusive_keyboard_focus()
+    }
+
+    fn end(&mut self, handle: &mut KeyboardInnerHandle<'_>, serial: Serial) {
+        handle.unset_grab(serial, false);
+        if let Some(focus) = self.focus_restore.borrow_mut().take() {
+            handle.set_focus(focus.as_ref(), serial);
+        }
+    }
+}
+
+impl KeyboardGrab for LayerKeyboardGrab {
+    fn input(
+        &mut self,
+        handle: &mut KeyboardInnerHandle<'_>,
+        keycode: u32,
+        key_state: KeyState,
+        modifiers: Option<(u32, u32, u32, u32)>,
+        serial: Serial,
+        time: u32,
+    ) {
+        if !self.is_active() {
+            self.end(handle, serial);
+        }
+        handle.input(keycode, key_state, modifiers, serial, time)
+    }
+
+    fn set_focus(&mut self, handle: &mut KeyboardInnerHandle<'_>, focus: Option<&WlSurface>, serial: Serial) {
+        if focus == self.layer.get_surface() {
+            handle.set_focus(focus, serial);
+            return;
+        }
+
+        // remember the requested focus to restore it once the grab ends
+        if let Some(restore) = self.focus_restore.borrow_mut().as_mut() {
+            *restore = focus.cloned();
+        }
+        if !self.is_active() {
+            self.end(handle, serial);
+        }
+    }
+
+    fn start_data(&self) -> &KeyboardGrabStartData {
+        &self.start_data
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct LayerState {
     pub location: Point<i32, Logical>,
@@ -446,6 +631,36 @@ impl LayerSurface {
             .unwrap_or(false)
     }
 
+    /// Returns true, if the surface requested exclusive keyboard focus.
+    ///
+    /// Exclusive keyboard interactivity is only honored for surfaces on the top and overlay layers,
+    /// surfaces on other layers use normal focus semantics.
+    pub fn requests_exclusive_keyboard_focus(&self) -> bool {
+        self.cached_state()
+            .map(|state| {
+                state.keyboard_interactivity == KeyboardInteractivity::Exclusive
+                    && matches!(state.layer, WlrLayer::Top | WlrLayer::Overlay)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Give the keyboard focus to this surface in response to a user interaction, e.g. a click.
+    ///
+    /// The focus is only set if the surface indicated, that it is able to process keyboard events
+    /// (see [`LayerSurface::can_receive_keyboard_focus`]). Returns whether the focus was requested.
+    ///
+    /// While another layer surface holds the keyboard focus exclusively, the new focus is only applied
+    /// once the exclusive surface is unmapped.
+    pub fn focus_on_demand(&self, keyboard: &KeyboardHandle, serial: Serial) -> bool {
+        match self.get_surface() {
+            Some(surface) if self.can_receive_keyboard_focus() => {
+                keyboard.set_focus(Some(surface), serial);
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Returns the layer this surface resides on, if any yet.
     pub fn layer(&self) -> Option<WlrLayer> {
         self.0.surface.get_surface().map(|surface| {
@@ -579,10 +794,12 @@ impl LayerSurface {
 /// - `scale` needs to be equivalent to the fractional scale the rendered result should have.
 /// - `location` is the position the layer surface should be drawn at.
 /// - `damage` is the set of regions of the layer surface that should be drawn.
+/// - `alpha` is the opacity the layer surface and its popups are drawn with.
 ///
 /// Note: This function will render nothing, if you are not using
 /// [`crate::backend::renderer::utils::on_commit_buffer_handler`]
 /// to let smithay handle buffer management.
+#[allow(clippy::too_many_arguments)]
 pub fn draw_layer_surface<R, E, F, T, P>(
     renderer: &mut R,
     frame: &mut F,
@@ -590,6 +807,39 @@ pub fn draw_layer_surface<R, E, F, T, P>(
     scale: f64,
     location: P,
     damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+    P: Into<Point<i32, Logical>>,
+{
+    draw_layer_surface_with_failure_mode(
+        renderer,
+        frame,
+        layer,
+        scale,
+        location,
+        damage,
+        alpha,
+        ImportFailureMode::default(),
+        log,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_layer_surface_with_failure_mode<R, E, F, T, P>(
+    renderer: &mut R,
+    frame: &mut F,
+    layer: &LayerSurface,
+    scale: f64,
+    location: P,
+    damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    failure_mode: ImportFailureMode,
     log: &slog::Logger,
 ) -> Result<(), R::Error>
 where
@@ -601,7 +851,17 @@ where
 {
     let location = location.into();
     if let Some(surface) = layer.get_surface() {
-        draw_surface_tree(renderer, frame, surface, scale, location, damage, log)?;
+        draw_surface_tree_with_failure_mode(
+            renderer,
+            frame,
+            surface,
+            scale,
+            location,
+            damage,
+            alpha,
+            failure_mode,
+            log,
+        )?;
         for (popup, p_location) in PopupManager::popups_for_surface(surface)
             .ok()
             .into_iter()
@@ -616,13 +876,15 @@ where
                         geo
                     })
                     .collect::<Vec<_>>();
-                draw_surface_tree(
+                draw_surface_tree_with_failure_mode(
                     renderer,
                     frame,
                     surface,
                     scale,
                     location + p_location,
                     &damage,
+                    alpha,
+                    failure_mode,
                     log,
                 )?;
             }
@@ -630,3 +892,78 @@ where
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wayland::shell::wlr_layer::Margins;
+
+    fn output() -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((0, 0), (1920, 1080))
+    }
+
+    #[test]
+    fn exclusive_top_panel_shrinks_zone() {
+        let mut zone = output();
+        let data = LayerSurfaceCachedState {
+            size: (0, 30).into(),
+            anchor: Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            exclusive_zone: ExclusiveZone::Exclusive(30),
+            ..Default::default()
+        };
+
+        let geo = arrange_layer(output(), &mut zone, &data);
+        assert_eq!(geo, Rectangle::from_loc_and_size((0, 0), (1920, 30)));
+        assert_eq!(zone, Rectangle::from_loc_and_size((0, 30), (1920, 1050)));
+    }
+
+    #[test]
+    fn margins_are_applied_to_anchored_edges() {
+        let mut zone = output();
+        let data = LayerSurfaceCachedState {
+            size: (0, 40).into(),
+            anchor: Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+            exclusive_zone: ExclusiveZone::Exclusive(40),
+            margin: Margins {
+                top: 0,
+                right: 10,
+                bottom: 5,
+                left: 10,
+            },
+            ..Default::default()
+        };
+
+        let geo = arrange_layer(output(), &mut zone, &data);
+        assert_eq!(geo, Rectangle::from_loc_and_size((10, 1035), (1900, 40)));
+        assert_eq!(zone, Rectangle::from_loc_and_size((0, 0), (1920, 1035)));
+    }
+
+    #[test]
+    fn corner_anchored_surface_has_no_exclusive_zone() {
+        let mut zone = output();
+        let data = LayerSurfaceCachedState {
+            size: (100, 100).into(),
+            anchor: Anchor::TOP | Anchor::RIGHT,
+            exclusive_zone: ExclusiveZone::Exclusive(100),
+            ..Default::default()
+        };
+
+        let geo = arrange_layer(output(), &mut zone, &data);
+        assert_eq!(geo, Rectangle::from_loc_and_size((1820, 0), (100, 100)));
+        assert_eq!(zone, output());
+    }
+
+    #[test]
+    fn dont_care_ignores_exclusive_zones() {
+        let mut zone = Rectangle::from_loc_and_size((0, 30), (1920, 1050));
+        let data = LayerSurfaceCachedState {
+            anchor: Anchor::all(),
+            exclusive_zone: ExclusiveZone::DontCare,
+            ..Default::default()
+        };
+
+        let geo = arrange_layer(output(), &mut zone, &data);
+        assert_eq!(geo, output());
+        assert_eq!(zone, Rectangle::from_loc_and_size((0, 30), (1920, 1050)));
+    }
+}
@@ -36,6 +36,8 @@
 //! Each [`Output`](crate::wayland::output::Output) can be associated a [`LayerMap`] by calling [`layer_map_for_output`],
 //! which [`LayerSurface`]s can be mapped upon. Associated layer maps are automatically rendered by [`Space::render_output`],
 //! but a [draw function](`draw_layer_surface`) is also provided for manual layer-surface management.
+//! A [`LayerMap`] can also keep the keyboard focus on layer surfaces requesting exclusive keyboard interactivity
+//! through [`LayerMap::update_keyboard_focus`].
 //!
 //! ### Popups
 //!
@@ -43,18 +45,33 @@
 //! relations to one-another. Popups are then automatically rendered with their matching toplevel surfaces,
 //! when either [`draw_window`], [`draw_layer_surface`] or [`Space::render_output`] is called.
 //!
+//! ### Focus
+//!
+//! [`FocusFollowsMouse`] implements a keyboard focus policy giving the focus to the [`Window`]
+//! under the pointer, optionally after a delay.
+//!
+//! ### Grabs
+//!
+//! [`MoveGrab`] is a pointer grab implementing the interactive move of a [`Window`] inside a [`Space`],
+//! as requested by clients with `xdg_toplevel.move`.
+//! [`ResizeGrab`] likewise implements the interactive resize requested with `xdg_toplevel.resize`.
+//!
 //! ## Remarks
 //!
 //! Note that the desktop abstractions are concerned with easing rendering different clients and therefore need to be able
 //! to manage client buffers to do so. If you plan to use the provided drawing functions, you need to use
 //! [`on_commit_buffer_handler`](crate::backend::renderer::utils::on_commit_buffer_handler).
 
+mod focus;
+mod grabs;
 pub(crate) mod layer;
 mod popup;
 pub mod space;
 pub mod utils;
 mod window;
 
+pub use self::focus::FocusFollowsMouse;
+pub use self::grabs::{MoveGrab, ResizeEdge, ResizeGrab};
 pub use self::layer::{draw_layer_surface, layer_map_for_output, LayerMap, LayerSurface};
 pub use self::popup::*;
 pub use self::space::Space;
@@ -35,6 +35,9 @@ pub enum PopupGrabError {
     /// The client tried to grab a popup which is not the topmost
     #[error("popup was not created on the topmost popup")]
     NotTheTopmostPopup,
+    /// The client tried to grab a popup with a serial not belonging to a recent button or key press
+    #[error("the serial does not belong to a recent button or key press")]
+    InvalidSerial,
 }
 
 /// Defines the possibly strategies
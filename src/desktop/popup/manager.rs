@@ -3,7 +3,10 @@ use crate::{
     wayland::{
         compositor::{get_role, with_states},
         seat::Seat,
-        shell::xdg::{XdgPopupSurfaceRoleAttributes, XDG_POPUP_ROLE},
+        shell::{
+            legacy,
+            xdg::{XdgPopupSurfaceRoleAttributes, XDG_POPUP_ROLE},
+        },
         Serial,
     },
 };
@@ -94,6 +97,14 @@ impl PopupManager {
                     return Err(PopupGrabError::InvalidGrab);
                 }
             }
+            // wl_shell popups are grabbed when they are created
+            PopupKind::Wl(_) => {}
+        }
+
+        if !seat.serials().validate(serial, |event| event.is_press()) {
+            // the protocol requires us to dismiss the popup in this case
+            popup.send_done();
+            return Err(PopupGrabError::InvalidSerial);
         }
 
         // The primary store for the grab is the seat, additional we store it
@@ -211,6 +222,9 @@ impl PopupManager {
 
 fn find_popup_root_surface(popup: &PopupKind) -> Result<WlSurface, DeadResource> {
     let mut parent = popup.parent().ok_or(DeadResource)?;
+    while let Some((wl_parent, _)) = legacy::popup_placement(&parent) {
+        parent = wl_parent;
+    }
     while get_role(&parent) == Some(XDG_POPUP_ROLE) {
         parent = with_states(&parent, |states| {
             states
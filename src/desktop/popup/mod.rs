@@ -11,7 +11,10 @@ use crate::{
     utils::{Logical, Point, Rectangle},
     wayland::{
         compositor::with_states,
-        shell::xdg::{PopupSurface, SurfaceCachedState, XdgPopupSurfaceRoleAttributes},
+        shell::{
+            legacy::ShellSurface,
+            xdg::{PopupSurface, SurfaceCachedState, XdgPopupSurfaceRoleAttributes},
+        },
     },
 };
 
@@ -20,12 +23,15 @@ use crate::{
 pub enum PopupKind {
     /// xdg-shell [`PopupSurface`]
     Xdg(PopupSurface),
+    /// wl_shell [`ShellSurface`] set as a popup
+    Wl(ShellSurface),
 }
 
 impl PopupKind {
     fn alive(&self) -> bool {
         match *self {
             PopupKind::Xdg(ref t) => t.alive(),
+            PopupKind::Wl(ref t) => t.alive(),
         }
     }
 
@@ -33,12 +39,14 @@ impl PopupKind {
     pub fn get_surface(&self) -> Option<&WlSurface> {
         match *self {
             PopupKind::Xdg(ref t) => t.get_surface(),
+            PopupKind::Wl(ref t) => t.get_surface(),
         }
     }
 
     fn parent(&self) -> Option<WlSurface> {
         match *self {
             PopupKind::Xdg(ref t) => t.get_parent_surface(),
+            PopupKind::Wl(ref t) => t.popup_placement().map(|(parent, _)| parent),
         }
     }
 
@@ -66,10 +74,17 @@ impl PopupKind {
 
         match *self {
             PopupKind::Xdg(ref t) => t.send_popup_done(),
+            PopupKind::Wl(ref t) => t.send_popup_done(),
         }
     }
 
     fn location(&self) -> Point<i32, Logical> {
+        if let PopupKind::Wl(ref t) = *self {
+            return t
+                .popup_placement()
+                .map(|(_, location)| location)
+                .unwrap_or_default();
+        }
         let wl_surface = match self.get_surface() {
             Some(s) => s,
             None => return (0, 0).into(),
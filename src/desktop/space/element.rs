@@ -1,8 +1,11 @@
 use crate::{
-    backend::renderer::{Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{utils::ImportFailureMode, Frame, ImportAll, Renderer, Texture},
     desktop::{space::*, utils::*},
     utils::{Logical, Point, Rectangle},
-    wayland::output::Output,
+    wayland::{
+        output::Output,
+        shell::fullscreen::{presented_surface, PresentMethod},
+    },
 };
 use std::{
     any::{Any, TypeId},
@@ -106,6 +109,10 @@ where
     }
     fn geometry(&self, space_id: usize) -> Rectangle<i32, Logical>;
     fn accumulated_damage(&self, for_values: Option<(&Space, &Output)>) -> Vec<Rectangle<i32, Logical>>;
+    // regions fully covering anything below the element, in space coordinates
+    fn opaque_regions(&self, _space_id: usize) -> Vec<Rectangle<i32, Logical>> {
+        Vec::new()
+    }
     #[allow(clippy::too_many_arguments)]
     fn draw(
         &self,
@@ -115,6 +122,7 @@ where
         scale: f64,
         location: Point<i32, Logical>,
         damage: &[Rectangle<i32, Logical>],
+        failure_mode: ImportFailureMode,
         log: &slog::Logger,
     ) -> Result<(), R::Error>;
     fn z_index(&self) -> u8;
@@ -147,6 +155,8 @@ where
         scale: f64,
         location: Point<i32, Logical>,
         damage: &[Rectangle<i32, Logical>],
+        // custom elements handle their own import failures
+        _failure_mode: ImportFailureMode,
         log: &slog::Logger,
     ) -> Result<(), R::Error> {
         (&**self as &dyn RenderElement<R, F, E, T>).draw(renderer, frame, scale, location, damage, log)
@@ -213,11 +223,192 @@ where
             scale,
             location,
             damage,
+            1.0,
             log,
         )
     }
 }
 
+/// Image of a cursor, resolved from a [`CursorImageStatus`](crate::wayland::seat::CursorImageStatus)
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorImage {
+    /// No cursor is displayed
+    ///
+    /// The client requested to hide the cursor or its cursor surface has no contents.
+    Hidden,
+    /// The compositor should display its own default cursor
+    ///
+    /// This is also used, if the cursor surface of the client was destroyed.
+    Default,
+    /// The cursor is displayed using a client surface
+    Surface {
+        /// Surface of the cursor image
+        surface: WlSurface,
+        /// Location of the hotspot in the surface
+        hotspot: Point<i32, Logical>,
+    },
+}
+
+/// Cursor displayed on an [`Output`], as returned by [`Space::cursor_for_output`]
+///
+/// Backends supporting hardware cursor planes can assign the image to such a plane at
+/// [`OutputCursor::location`] minus the hotspot. Otherwise the cursor needs to be composited,
+/// for surfaces by passing [`OutputCursor::surface_element`] to [`Space::render_output`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputCursor {
+    /// Image of the cursor
+    pub image: CursorImage,
+    /// Location of the pointer relative to the output
+    pub location: Point<i32, Logical>,
+    // location of the output in the space
+    output_location: Point<i32, Logical>,
+}
+
+impl OutputCursor {
+    pub(super) fn new(
+        image: CursorImage,
+        location: Point<i32, Logical>,
+        output_location: Point<i32, Logical>,
+    ) -> OutputCursor {
+        OutputCursor {
+            image,
+            location,
+            output_location,
+        }
+    }
+
+    /// Returns an element drawing the cursor surface, to be composited when no cursor plane is used
+    ///
+    /// Returns `None`, if the cursor is not displayed using a client surface.
+    pub fn surface_element(&self) -> Option<SurfaceTree> {
+        match self.image {
+            CursorImage::Surface { ref surface, hotspot } => Some(SurfaceTree {
+                surface: surface.clone(),
+                position: self.output_location + self.location - hotspot,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Element drawing the surface presented on an [`Output`] via the
+/// [`fullscreen`](crate::wayland::shell::fullscreen) shell.
+///
+/// The surface is placed on the output according to its [`PresentMethod`] and cropped to the output.
+#[derive(Debug)]
+pub struct FullscreenShellElement {
+    surface: WlSurface,
+    method: PresentMethod,
+    output_geometry: Rectangle<i32, Logical>,
+}
+
+impl FullscreenShellElement {
+    /// Creates an element for the surface currently presented on the given output
+    ///
+    /// Returns `None` if no surface is presented on the output or the output is not mapped in the space.
+    pub fn for_output(space: &Space, output: &Output) -> Option<Self> {
+        let (surface, method) = presented_surface(output)?;
+        let output_geometry = space.output_geometry(output)?;
+        Some(FullscreenShellElement {
+            surface,
+            method,
+            output_geometry,
+        })
+    }
+
+    // location of the surface in space coordinates, its scale factors and the visible area
+    fn placement(&self) -> (Point<i32, Logical>, (f64, f64), Rectangle<i32, Logical>) {
+        let size = bbox_from_surface_tree(&self.surface, (0, 0)).size;
+        let (location, factor) = self.method.placement(size, self.output_geometry.size);
+        let location = self.output_geometry.loc + location;
+        let bbox = scale_rect(Rectangle::from_loc_and_size((0, 0), size), factor);
+        let geometry = Rectangle::from_loc_and_size(location, bbox.size)
+            .intersection(self.output_geometry)
+            .unwrap_or_else(|| Rectangle::from_loc_and_size(self.output_geometry.loc, (0, 0)));
+        (location, factor, geometry)
+    }
+}
+
+fn scale_rect(rect: Rectangle<i32, Logical>, factor: (f64, f64)) -> Rectangle<i32, Logical> {
+    Rectangle::<f64, Logical>::from_loc_and_size(
+        (rect.loc.x as f64 * factor.0, rect.loc.y as f64 * factor.1),
+        (rect.size.w as f64 * factor.0, rect.size.h as f64 * factor.1),
+    )
+    .to_i32_up()
+}
+
+impl<R, F, E, T> RenderElement<R, F, E, T> for FullscreenShellElement
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+{
+    fn id(&self) -> usize {
+        self.surface.as_ref().id() as usize
+    }
+
+    fn geometry(&self) -> Rectangle<i32, Logical> {
+        self.placement().2
+    }
+
+    fn accumulated_damage(
+        &self,
+        for_values: Option<SpaceOutputTuple<'_, '_>>,
+    ) -> Vec<Rectangle<i32, Logical>> {
+        let (location, factor, geometry) = self.placement();
+        damage_from_surface_tree(&self.surface, (0, 0), for_values.map(|x| (x.0, x.1)))
+            .into_iter()
+            .map(|rect| {
+                // scale and make relative to the visible area
+                let mut rect = scale_rect(rect, factor);
+                rect.loc += location - geometry.loc;
+                rect
+            })
+            .flat_map(|rect| rect.intersection(Rectangle::from_loc_and_size((0, 0), geometry.size)))
+            .collect()
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut R,
+        frame: &mut F,
+        scale: f64,
+        location: Point<i32, Logical>,
+        damage: &[Rectangle<i32, Logical>],
+        log: &slog::Logger,
+    ) -> Result<(), R::Error> {
+        let (surface_location, factor, geometry) = self.placement();
+        let offset = geometry.loc - surface_location;
+        let damage = damage
+            .iter()
+            .map(|rect| {
+                let rect = Rectangle::from_loc_and_size(rect.loc + offset, rect.size);
+                scale_rect(rect, (1.0 / factor.0, 1.0 / factor.1))
+            })
+            .collect::<Vec<_>>();
+        // the tree is drawn with the combined scale, so its location needs to be scaled inversely
+        let location = (location - offset).to_f64();
+        let location =
+            Point::<f64, Logical>::from((location.x / factor.0, location.y / factor.1)).to_i32_round();
+        crate::backend::renderer::utils::draw_surface_tree_scaled(
+            renderer,
+            frame,
+            &self.surface,
+            (scale * factor.0, scale * factor.1),
+            location,
+            &damage,
+            1.0,
+            ImportFailureMode::default(),
+            log,
+        )
+    }
+
+    fn z_index(&self) -> u8 {
+        RenderZindex::Shell as u8
+    }
+}
+
 /// Newtype for (&Space, &Output) to provide a `Hash` implementation for damage tracking
 #[derive(Debug, PartialEq)]
 pub struct SpaceOutputTuple<'a, 'b>(pub &'a Space, pub &'b Output);
@@ -1,8 +1,9 @@
 use crate::{
-    backend::renderer::{Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{utils::ImportFailureMode, Frame, ImportAll, Renderer, Texture},
     desktop::{
         layer::{layer_state as output_layer_state, *},
         space::{Space, SpaceElement},
+        utils::opaque_regions_from_surface_tree,
     },
     utils::{Logical, Point, Rectangle},
     wayland::{output::Output, shell::wlr_layer::Layer},
@@ -55,6 +56,13 @@ where
         self.accumulated_damage(for_values)
     }
 
+    fn opaque_regions(&self, space_id: usize) -> Vec<Rectangle<i32, Logical>> {
+        let loc = SpaceElement::<R, F, E, T>::location(self, space_id);
+        self.get_surface()
+            .map(|surface| opaque_regions_from_surface_tree(surface, loc))
+            .unwrap_or_default()
+    }
+
     fn draw(
         &self,
         space_id: usize,
@@ -63,9 +71,20 @@ where
         scale: f64,
         location: Point<i32, Logical>,
         damage: &[Rectangle<i32, Logical>],
+        failure_mode: ImportFailureMode,
         log: &slog::Logger,
     ) -> Result<(), R::Error> {
-        let res = draw_layer_surface(renderer, frame, self, scale, location, damage, log);
+        let res = draw_layer_surface_with_failure_mode(
+            renderer,
+            frame,
+            self,
+            scale,
+            location,
+            damage,
+            1.0,
+            failure_mode,
+            log,
+        );
         if res.is_ok() {
             layer_state(space_id, self).drawn = true;
         }
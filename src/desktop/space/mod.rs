@@ -2,22 +2,29 @@
 //! rendering helpers to add custom elements or different clients to a space.
 
 use crate::{
-    backend::renderer::{Frame, ImportAll, Renderer},
+    backend::renderer::{
+        buffer_type,
+        utils::{release_presented_buffers, take_buffer_delta, ImportFailureMode, SurfaceState},
+        Bind, BufferType, Frame, ImportAll, Offscreen, Renderer, Unbind,
+    },
     desktop::{
-        layer::{layer_map_for_output, LayerSurface},
+        layer::{layer_map_for_output, layer_state as output_layer_state, LayerSurface},
         popup::PopupManager,
-        utils::{output_leave, output_update},
-        window::Window,
+        utils::{bbox_from_surface_tree, output_leave, output_update},
+        window::{draw_window_with_failure_mode, Window, WindowSurfaceType},
     },
-    utils::{Logical, Point, Rectangle, Transform},
+    utils::{Logical, Point, Rectangle, Size, Transform},
     wayland::{
-        compositor::{get_parent, is_sync_subsurface},
+        color_management::{color_metadata, ColorMetadata},
+        compositor::{get_children, get_parent, is_sync_subsurface, with_states, SurfaceData},
         output::Output,
+        seat::{CursorImageAttributes, CursorImageStatus},
+        shell::wlr_layer::Layer,
     },
 };
 use indexmap::{IndexMap, IndexSet};
-use std::{collections::VecDeque, fmt};
-use wayland_server::protocol::wl_surface::WlSurface;
+use std::{cell::RefCell, collections::VecDeque, fmt, sync::Mutex};
+use wayland_server::protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface};
 
 mod element;
 mod layer;
@@ -38,7 +45,11 @@ pub struct Space {
     pub(super) id: usize,
     // in z-order, back to front
     windows: IndexSet<Window>,
+    active_window: Option<Window>,
     outputs: Vec<Output>,
+    occlusion_culling: bool,
+    debug_damage: Option<[f32; 4]>,
+    import_failure_mode: ImportFailureMode,
     logger: ::slog::Logger,
 }
 
@@ -63,7 +74,11 @@ impl Space {
         Space {
             id: next_space_id(),
             windows: IndexSet::new(),
+            active_window: None,
             outputs: Vec::new(),
+            occlusion_culling: true,
+            debug_damage: None,
+            import_failure_mode: ImportFailureMode::default(),
             logger: crate::slog_or_fallback(log),
         }
     }
@@ -76,9 +91,32 @@ impl Space {
     /// If activate is true it will set the new windows state
     /// to be activate and removes that state from every
     /// other mapped window.
+    ///
+    /// The outputs the window now overlaps with are entered right away,
+    /// without waiting for the next [`Space::refresh`].
     pub fn map_window<P: Into<Point<i32, Logical>>>(&mut self, window: &Window, location: P, activate: bool) {
+        let old_bbox = if self.windows.contains(window) {
+            Some(window_rect(window, &self.id))
+        } else {
+            None
+        };
+        let on_top = self.windows.last() == Some(window);
         self.insert_window(window, activate);
         window_state(self.id, window).location = location.into();
+
+        if let Some(old_bbox) = old_bbox {
+            let new_bbox = window_rect(window, &self.id);
+            if old_bbox != new_bbox {
+                // a moved window damages its old and new position
+                self.add_damage(&[old_bbox, new_bbox]);
+            } else if !on_top {
+                // a raised window may now cover other windows
+                self.add_damage(&[window_rect_with_popups(window, &self.id)]);
+            }
+        } else {
+            self.add_damage(&[window_rect_with_popups(window, &self.id)]);
+        }
+        self.update_window_outputs(window);
     }
 
     /// Moves an already mapped [`Window`] to top of the stack
@@ -89,12 +127,19 @@ impl Space {
     /// to be activate and removes that state from every
     /// other mapped window.
     pub fn raise_window(&mut self, window: &Window, activate: bool) {
-        if self.windows.shift_remove(window) {
+        if self.windows.contains(window) {
+            let on_top = self.windows.last() == Some(window);
             self.insert_window(window, activate);
+            if !on_top {
+                // a raised window may now cover other windows
+                self.add_damage(&[window_rect_with_popups(window, &self.id)]);
+            }
         }
     }
 
     fn insert_window(&mut self, window: &Window, activate: bool) {
+        // re-inserting moves the window to the top of the stack
+        self.windows.shift_remove(window);
         self.windows.insert(window.clone());
 
         if activate {
@@ -104,30 +149,84 @@ impl Space {
                     w.set_activated(false);
                 }
             }
+            self.active_window = Some(window.clone());
         }
     }
 
     /// Unmap a [`Window`] from this space.
     ///
+    /// The surfaces of the window leave all outputs of the space and the area
+    /// it covered is damaged.
+    ///
     /// This function does nothing for already unmapped windows
     pub fn unmap_window(&mut self, window: &Window) {
+        if self.windows.contains(window) {
+            self.add_damage(&[window_rect_with_popups(window, &self.id)]);
+            if let Some(surface) = window.toplevel().get_surface() {
+                let popups = PopupManager::popups_for_surface(surface)
+                    .ok()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(popup, _)| popup.get_surface().cloned());
+                for surface in std::iter::once(surface.clone()).chain(popups) {
+                    for output in &self.outputs {
+                        let mut state = output_state(self.id, output);
+                        output_leave(output, &mut state.surfaces, &surface, &self.logger);
+                    }
+                }
+            }
+        }
         if let Some(map) = window.user_data().get::<WindowUserdata>() {
             map.borrow_mut().remove(&self.id);
         }
         self.windows.shift_remove(window);
+        if self.active_window.as_ref() == Some(window) {
+            self.active_window = None;
+        }
+    }
+
+    /// Returns the active [`Window`], if any
+    ///
+    /// This is the window last activated by [`Space::map_window`], [`Space::raise_window`]
+    /// or [`Space::set_active_window`], as long as it stays mapped.
+    pub fn active_window(&self) -> Option<Window> {
+        self.active_window.clone().filter(|w| self.windows.contains(w))
+    }
+
+    /// Sets the active [`Window`], usually the one holding the keyboard focus
+    ///
+    /// The activated state of all mapped windows is updated accordingly, `None` deactivates
+    /// all of them, e.g. when the keyboard focus moves to a layer surface or to no surface at all.
+    /// [`Space::window_for_surface`] can be used to find the window of the focused surface.
+    ///
+    /// Unmapped windows cannot be activated and are treated as `None`.
+    pub fn set_active_window(&mut self, window: Option<&Window>) {
+        let window = window.filter(|w| self.windows.contains(*w));
+        for w in self.windows.iter() {
+            w.set_activated(Some(w) == window);
+        }
+        self.active_window = window.cloned();
     }
 
     /// Iterate window in z-order back to front
+    ///
+    /// This is the order windows are rendered in by [`Space::render_output`].
     pub fn windows(&self) -> impl DoubleEndedIterator<Item = &Window> {
         self.windows.iter()
     }
 
     /// Get a reference to the window under a given point, if any
+    ///
+    /// If multiple windows overlap at the given point, the top-most one is returned.
+    /// The input regions of the surfaces are honored, points outside of them fall
+    /// through to the windows below.
     pub fn window_under<P: Into<Point<f64, Logical>>>(&self, point: P) -> Option<&Window> {
         let point = point.into();
         self.windows.iter().rev().find(|w| {
             let bbox = window_rect(w, &self.id);
             bbox.to_f64().contains(point)
+                && w.surface_under(point - window_loc(w, &self.id).to_f64(), WindowSurfaceType::ALL)
+                    .is_some()
         })
     }
 
@@ -162,6 +261,33 @@ impl Space {
         })
     }
 
+    /// Sets the opacity a mapped [`Window`] and its popups are rendered with.
+    ///
+    /// `alpha` is clamped to the range `0.0..=1.0`. Translucent windows
+    /// are never used to hide elements below them.
+    ///
+    /// This function does nothing for unmapped windows.
+    pub fn set_element_alpha(&mut self, window: &Window, alpha: f32) {
+        if !self.windows.contains(window) {
+            return;
+        }
+
+        let alpha = alpha.max(0.0).min(1.0);
+        let old_alpha = std::mem::replace(&mut window_state(self.id, window).alpha, alpha);
+        if (old_alpha - alpha).abs() > f32::EPSILON {
+            self.add_damage(&[window_rect_with_popups(window, &self.id)]);
+        }
+    }
+
+    /// Returns the opacity of a mapped [`Window`], see [`Space::set_element_alpha`].
+    pub fn element_alpha(&self, window: &Window) -> Option<f32> {
+        if !self.windows.contains(window) {
+            return None;
+        }
+
+        Some(window_state(self.id, window).alpha)
+    }
+
     /// Returns the geometry of a [`Window`] including its relative position inside the Space.
     pub fn window_geometry(&self, w: &Window) -> Option<Rectangle<i32, Logical>> {
         if !self.windows.contains(w) {
@@ -180,6 +306,21 @@ impl Space {
         Some(window_rect(w, &self.id))
     }
 
+    /// Calls `processor` for every mapped surface of a [`Window`], with its location inside the Space.
+    ///
+    /// Does nothing if the window is not mapped in this space. See [`Window::with_surfaces`] for details.
+    pub fn with_window_surfaces<F>(&self, w: &Window, mut processor: F)
+    where
+        F: FnMut(&WlSurface, &SurfaceData, Point<i32, Logical>),
+    {
+        if !self.windows.contains(w) {
+            return;
+        }
+
+        let window_location = window_loc(w, &self.id);
+        w.with_surfaces(|surface, states, location| processor(surface, states, window_location + location));
+    }
+
     /// Maps an [`Output`] inside the space.
     ///
     /// Can be safely called on an already mapped
@@ -198,6 +339,7 @@ impl Space {
             // keep surfaces, we still need to inform them of leaving,
             // if they don't overlap anymore during refresh.
             surfaces: state.surfaces.drain(..).collect::<Vec<_>>(),
+            clear_color: state.clear_color,
             // resets last_seen and old_damage, if remapped
             ..Default::default()
         };
@@ -206,6 +348,34 @@ impl Space {
         }
     }
 
+    /// Sets the color used to clear unoccupied regions of a mapped [`Output`] in [`Space::render_output`].
+    ///
+    /// If `None` the color passed to [`Space::render_output`] is used.
+    /// A fully transparent color (`[0.0, 0.0, 0.0, 0.0]`) may be used for nested or embedded compositors.
+    ///
+    /// This function does nothing for unmapped outputs.
+    pub fn set_output_clear_color(&mut self, output: &Output, color: Option<[f32; 4]>) {
+        if !self.outputs.contains(output) {
+            return;
+        }
+
+        let mut state = output_state(self.id, output);
+        if state.clear_color != color {
+            state.clear_color = color;
+            // everything needs to be cleared again
+            state.old_damage = VecDeque::new();
+        }
+    }
+
+    /// Returns the clear color set for a mapped [`Output`], see [`Space::set_output_clear_color`].
+    pub fn output_clear_color(&self, output: &Output) -> Option<[f32; 4]> {
+        if !self.outputs.contains(output) {
+            return None;
+        }
+
+        output_state(self.id, output).clear_color
+    }
+
     /// Iterate over all mapped [`Output`]s of this space.
     pub fn outputs(&self) -> impl Iterator<Item = &Output> {
         self.outputs.iter()
@@ -247,6 +417,65 @@ impl Space {
         })
     }
 
+    /// Returns the cursor to display on a mapped output
+    ///
+    /// `status` is the cursor image requested by the client with the pointer focus and `location`
+    /// the location of the pointer in the space. This resolves the image to display separately from
+    /// [`Space::render_output`], so that backends can put it on a hardware cursor plane. The hotspot
+    /// of cursor surfaces is read from their [`CursorImageAttributes`].
+    ///
+    /// Returns `None`, if the output is not mapped or the cursor is not visible on it. Cursor
+    /// surfaces are visible as long as their bounding box overlaps the output, which may be the
+    /// case while the pointer is on a neighbouring output. Other cursor images are only visible
+    /// on the output the pointer is on.
+    pub fn cursor_for_output(
+        &self,
+        output: &Output,
+        status: &CursorImageStatus,
+        location: Point<f64, Logical>,
+    ) -> Option<OutputCursor> {
+        let output_geometry = self.output_geometry(output)?;
+        let image = match status {
+            CursorImageStatus::Hidden => CursorImage::Hidden,
+            CursorImageStatus::Default => CursorImage::Default,
+            CursorImageStatus::Image { surface, hotspot } => with_states(surface, |states| {
+                let mapped = states
+                    .data_map
+                    .get::<RefCell<SurfaceState>>()
+                    .map(|state| state.borrow().buffer.is_some())
+                    .unwrap_or(false);
+                if mapped {
+                    let hotspot = states
+                        .data_map
+                        .get::<Mutex<CursorImageAttributes>>()
+                        .map(|attrs| attrs.lock().unwrap().hotspot)
+                        .unwrap_or(*hotspot);
+                    CursorImage::Surface {
+                        surface: surface.clone(),
+                        hotspot,
+                    }
+                } else {
+                    CursorImage::Hidden
+                }
+            })
+            .unwrap_or(CursorImage::Default),
+        };
+        let visible = match image {
+            CursorImage::Surface { ref surface, hotspot } => {
+                bbox_from_surface_tree(surface, location.to_i32_round() - hotspot).overlaps(output_geometry)
+            }
+            _ => output_geometry.to_f64().contains(location),
+        };
+        if !visible {
+            return None;
+        }
+        Some(OutputCursor::new(
+            image,
+            location.to_i32_round() - output_geometry.loc,
+            output_geometry.loc,
+        ))
+    }
+
     /// Returns the reder scale of a mapped output.
     ///
     /// If the output was not previously mapped to the `Space`
@@ -290,6 +519,31 @@ impl Space {
         outputs
     }
 
+    /// Returns the smallest rectangle containing all mapped [`Window`]s, the layers
+    /// of the mapped [`Output`]s and the outputs themselves.
+    ///
+    /// Returns an empty rectangle at the origin if nothing is mapped.
+    pub fn bounding_box(&self) -> Rectangle<i32, Logical> {
+        let windows = self.windows.iter().map(|w| window_rect(w, &self.id));
+        let outputs = self.outputs.iter().flat_map(|o| {
+            let output_loc = output_state(self.id, o).location;
+            let map = layer_map_for_output(o);
+            let layers = map
+                .layers()
+                .map(|l| {
+                    let mut bbox = l.bbox();
+                    bbox.loc += output_loc + output_layer_state(l).location;
+                    bbox
+                })
+                .collect::<Vec<_>>();
+            self.output_geometry(o).into_iter().chain(layers)
+        });
+        windows
+            .chain(outputs)
+            .reduce(|bbox, rect| bbox.merge(rect))
+            .unwrap_or_default()
+    }
+
     /// Refresh some internal values and update client state,
     /// meaning this will handle output enter and leave events
     /// for mapped outputs and windows based on their position.
@@ -306,52 +560,57 @@ impl Space {
         }
 
         for window in &self.windows {
-            let bbox = window_rect(window, &self.id);
-            let kind = window.toplevel();
+            self.update_window_outputs(window);
+        }
+    }
 
-            for output in &self.outputs {
-                let output_geometry = self
-                    .output_geometry(output)
-                    .unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), (0, 0)));
-                let mut output_state = output_state(self.id, output);
-
-                // Check if the bounding box of the toplevel intersects with
-                // the output, if not no surface in the tree can intersect with
-                // the output.
-                if !output_geometry.overlaps(bbox) {
-                    if let Some(surface) = kind.get_surface() {
-                        output_leave(output, &mut output_state.surfaces, surface, &self.logger);
-                    }
-                    continue;
+    // send output enter and leave events to the surfaces of a window based on its position
+    fn update_window_outputs(&self, window: &Window) {
+        let bbox = window_rect(window, &self.id);
+        let kind = window.toplevel();
+
+        for output in &self.outputs {
+            let output_geometry = self
+                .output_geometry(output)
+                .unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), (0, 0)));
+            let mut output_state = output_state(self.id, output);
+
+            // Check if the bounding box of the toplevel intersects with
+            // the output, if not no surface in the tree can intersect with
+            // the output.
+            if !output_geometry.overlaps(bbox) {
+                if let Some(surface) = kind.get_surface() {
+                    output_leave(output, &mut output_state.surfaces, surface, &self.logger);
                 }
+                continue;
+            }
 
-                if let Some(surface) = kind.get_surface() {
-                    output_update(
-                        output,
-                        output_geometry,
-                        &mut output_state.surfaces,
-                        surface,
-                        window_loc(window, &self.id),
-                        &self.logger,
-                    );
-
-                    for (popup, location) in PopupManager::popups_for_surface(surface)
-                        .ok()
-                        .into_iter()
-                        .flatten()
-                    {
-                        if let Some(surface) = popup.get_surface() {
-                            let location = window_loc(window, &self.id) + window.geometry().loc + location
-                                - popup.geometry().loc;
-                            output_update(
-                                output,
-                                output_geometry,
-                                &mut output_state.surfaces,
-                                surface,
-                                location,
-                                &self.logger,
-                            );
-                        }
+            if let Some(surface) = kind.get_surface() {
+                output_update(
+                    output,
+                    output_geometry,
+                    &mut output_state.surfaces,
+                    surface,
+                    window_loc(window, &self.id),
+                    &self.logger,
+                );
+
+                for (popup, location) in PopupManager::popups_for_surface(surface)
+                    .ok()
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some(surface) = popup.get_surface() {
+                        let location = window_loc(window, &self.id) + window.geometry().loc + location
+                            - popup.geometry().loc;
+                        output_update(
+                            output,
+                            output_geometry,
+                            &mut output_state.surfaces,
+                            surface,
+                            location,
+                            &self.logger,
+                        );
                     }
                 }
             }
@@ -360,6 +619,13 @@ impl Space {
 
     /// Should be called on commit to let the space automatically call [`Window::refresh`]
     /// for the window that belongs to the given surface, if managed by this space.
+    ///
+    /// This also records the damage of the committed surface tree for every overlapping output,
+    /// see [`Space::damage_for_output`]. If the bounding box of a window changed, both
+    /// its old and new bounding box are damaged.
+    ///
+    /// A window, whose client requested an offset with `wl_surface.offset`, is moved by it
+    /// (see [`take_buffer_delta`](crate::backend::renderer::utils::take_buffer_delta)).
     pub fn commit(&self, surface: &WlSurface) {
         if is_sync_subsurface(surface) {
             return;
@@ -369,15 +635,152 @@ impl Space {
             root = parent;
         }
         if let Some(window) = self.windows().find(|w| w.toplevel().get_surface() == Some(&root)) {
+            let old_bbox = window_rect(window, &self.id);
             window.refresh();
+            if let Some(delta) = take_buffer_delta(&root) {
+                window_state(self.id, window).location += delta;
+                self.update_window_outputs(window);
+            }
+            let new_bbox = window_rect(window, &self.id);
+
+            let loc = window_loc(window, &self.id);
+            let mut damage = window
+                .accumulated_damage(None)
+                .into_iter()
+                .map(|mut rect| {
+                    rect.loc += loc;
+                    rect
+                })
+                .collect::<Vec<_>>();
+            if old_bbox != new_bbox {
+                damage.push(old_bbox);
+                damage.push(new_bbox);
+            }
+            self.add_damage(&damage);
+        } else {
+            for output in &self.outputs {
+                let map = layer_map_for_output(output);
+                if let Some(layer) = map.layer_for_surface(&root) {
+                    let loc = output_state(self.id, output).location + output_layer_state(layer).location;
+                    let damage = layer
+                        .accumulated_damage(None)
+                        .into_iter()
+                        .map(|mut rect| {
+                            rect.loc += loc;
+                            rect
+                        })
+                        .collect::<Vec<_>>();
+                    std::mem::drop(map);
+                    self.add_damage(&damage);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns the damage reported by commits (see [`Space::commit`]) and window moves
+    /// since the last call to [`Space::render_output`] for the given [`Output`].
+    ///
+    /// The returned regions are relative to the output.
+    /// Returns `None`, if the output is not mapped to this space.
+    pub fn damage_for_output(&self, output: &Output) -> Option<Vec<Rectangle<i32, Logical>>> {
+        if !self.outputs.contains(output) {
+            return None;
         }
+
+        let state = output_state(self.id, output);
+        Some(
+            state
+                .pending_damage
+                .iter()
+                .map(|rect| Rectangle::from_loc_and_size(rect.loc - state.location, rect.size))
+                .collect(),
+        )
+    }
+
+    /// Damages the whole area of the given [`Output`]
+    ///
+    /// The next [`Space::render_output`] call for this output repaints it completely, regardless
+    /// of the age of the buffer. Use this when the rendering changed without any client commit,
+    /// e.g. because of a different theme or clear color.
+    ///
+    /// Does nothing, if the output is not mapped to this space.
+    pub fn damage_all(&self, output: &Output) {
+        if let Some(output_geo) = self.output_geometry(output) {
+            // the full damage supersedes any other pending damage
+            let mut state = output_state(self.id, output);
+            state.pending_damage.clear();
+            state.pending_damage.push(output_geo);
+        }
+    }
+
+    // Store damage given in space coordinates for every overlapping output
+    fn add_damage(&self, damage: &[Rectangle<i32, Logical>]) {
+        for output in &self.outputs {
+            let output_geo = match self.output_geometry(output) {
+                Some(geo) => geo,
+                None => continue,
+            };
+            output_state(self.id, output).pending_damage.extend(
+                damage
+                    .iter()
+                    .filter_map(|rect| rect.intersection(output_geo))
+                    .filter(|rect| rect.size.w > 0 && rect.size.h > 0),
+            );
+        }
+    }
+
+    /// Enables or disables occlusion culling during [`Space::render_output`].
+    ///
+    /// If enabled (the default), regions of elements covered by the opaque region
+    /// of an element above them are not drawn. Disabling this can be useful for debugging.
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        self.occlusion_culling = enabled;
+    }
+
+    /// Returns whether occlusion culling is enabled, see [`Space::set_occlusion_culling`].
+    pub fn occlusion_culling(&self) -> bool {
+        self.occlusion_culling
+    }
+
+    /// Enables or disables the visualization of damage during [`Space::render_output`].
+    ///
+    /// If set, the regions redrawn by each call to [`Space::render_output`] are overlaid with
+    /// the given color on top of their contents, which makes it easy to spot regions that are
+    /// redrawn needlessly or not at all. The color is filled using [`Frame::clear`] after all
+    /// elements have been drawn, and should be translucent and premultiplied for the contents
+    /// to remain visible, e.g. `[0.3, 0.0, 0.0, 0.3]`.
+    ///
+    /// The overlay is part of the rendered buffer: it is only removed once the region is
+    /// redrawn, so it lingers on outputs that are not damaged any further.
+    pub fn set_debug_damage(&mut self, color: Option<[f32; 4]>) {
+        self.debug_damage = color;
+    }
+
+    /// Returns the color damage is visualized with, see [`Space::set_debug_damage`].
+    pub fn debug_damage(&self) -> Option<[f32; 4]> {
+        self.debug_damage
+    }
+
+    /// Sets how the windows and layer surfaces of this space handle buffers, that cannot be
+    /// imported by the renderer, see [`ImportFailureMode`].
+    ///
+    /// Custom [`RenderElement`]s are drawn by themselves and are not affected.
+    pub fn set_import_failure_mode(&mut self, mode: ImportFailureMode) {
+        self.import_failure_mode = mode;
+    }
+
+    /// Returns how buffers, that cannot be imported, are handled, see [`Space::set_import_failure_mode`].
+    pub fn import_failure_mode(&self) -> ImportFailureMode {
+        self.import_failure_mode
     }
 
     /// Render a given [`Output`] using a given [`Renderer`].
     ///
     /// [`Space`] will render all mapped [`Window`]s, mapped [`LayerSurface`](super::LayerSurface)s
     /// of the given [`Output`] and their popups (if tracked by a [`PopupManager`](super::PopupManager)).
-    /// `clear_color` will be used to fill all unoccupied regions.
+    /// `clear_color` will be used to fill all unoccupied regions, unless a different
+    /// color was set for the output via [`Space::set_output_clear_color`].
     ///
     /// Rendering using this function will automatically apply damage-tracking.
     /// To facilitate this you need to provide age values of the buffers bound to
@@ -466,6 +869,8 @@ impl Space {
             slog::trace!(self.logger, "Removing toplevel at: {:?}", old_toplevel);
             damage.push(old_toplevel);
         }
+        // Then add the damage reported by commits since the last render
+        damage.extend(state.pending_damage.drain(..));
 
         // lets iterate front to back and figure out, what new windows or unmoved windows we have
         for element in &render_elements {
@@ -522,6 +927,36 @@ impl Space {
             return Ok(None);
         }
 
+        // Figure out the damage to draw per element, going top to bottom
+        // and hiding everything covered by opaque regions of elements above.
+        let mut occlusion = Vec::<Rectangle<i32, Logical>>::new();
+        let mut element_damage = render_elements
+            .iter()
+            .rev()
+            .map(|element| {
+                let geo = element.geometry(self.id);
+                let element_damage = damage
+                    .iter()
+                    .flat_map(|d| d.intersection(geo))
+                    .flat_map(|d| d.subtract_rects(occlusion.iter().copied()))
+                    .filter(|d| d.size.w > 0 && d.size.h > 0)
+                    .collect::<Vec<_>>();
+                if self.occlusion_culling {
+                    occlusion.extend(element.opaque_regions(self.id));
+                }
+                element_damage
+            })
+            .collect::<Vec<_>>();
+        element_damage.reverse();
+
+        let clear_color = state.clear_color.unwrap_or(clear_color);
+        let physical_damage = damage
+            .iter()
+            // Map from global space to output space
+            .map(|geo| Rectangle::from_loc_and_size(geo.loc - output_geo.loc, geo.size))
+            // Map from logical to physical
+            .map(|geo| geo.to_f64().to_physical(state.render_scale).to_i32_round())
+            .collect::<Vec<_>>();
         let output_transform: Transform = output.current_transform().into();
         let res = renderer.render(
             output_transform
@@ -533,25 +968,15 @@ impl Space {
             |renderer, frame| {
                 // First clear all damaged regions
                 slog::trace!(self.logger, "Clearing at {:#?}", damage);
-                frame.clear(
-                    clear_color,
-                    &damage
-                        .iter()
-                        // Map from global space to output space
-                        .map(|geo| Rectangle::from_loc_and_size(geo.loc - output_geo.loc, geo.size))
-                        // Map from logical to physical
-                        .map(|geo| geo.to_f64().to_physical(state.render_scale).to_i32_round())
-                        .collect::<Vec<_>>(),
-                )?;
+                frame.clear(clear_color, &physical_damage)?;
                 // Then re-draw all windows & layers overlapping with a damage rect.
 
-                for element in &render_elements {
+                for (element, damage) in render_elements.iter().zip(element_damage.iter()) {
                     let geo = element.geometry(self.id);
-                    if damage.iter().any(|d| d.overlaps(geo)) {
+                    if !damage.is_empty() {
                         let loc = element.location(self.id);
                         let damage = damage
                             .iter()
-                            .flat_map(|d| d.intersection(geo))
                             // Map from output space to surface-relative coordinates
                             .map(|geo| Rectangle::from_loc_and_size(geo.loc - loc, geo.size))
                             .collect::<Vec<_>>();
@@ -568,11 +993,17 @@ impl Space {
                             state.render_scale,
                             loc - output_geo.loc,
                             &damage,
+                            self.import_failure_mode,
                             &self.logger,
                         )?;
                     }
                 }
 
+                // Finally highlight what was redrawn, if requested
+                if let Some(color) = self.debug_damage {
+                    frame.clear(color, &physical_damage)?;
+                }
+
                 Result::<(), R::Error>::Ok(())
             },
         );
@@ -594,6 +1025,7 @@ impl Space {
             })
             .collect();
         state.old_damage.push_front(new_damage.clone());
+        state.presented = true;
 
         Ok(Some(
             new_damage
@@ -606,22 +1038,201 @@ impl Space {
         ))
     }
 
+    /// Renders the contents of a mapped [`Window`] into a newly created texture
+    ///
+    /// This can be used to create thumbnails of windows, e.g. for window switchers or overviews.
+    /// The texture has the size of the window geometry at the given `scale` and contains the
+    /// toplevel surface tree and its popups, clipped to the window geometry. Unoccupied regions
+    /// are left transparent.
+    ///
+    /// The renderer is unbound afterwards, you need to bind your own target again before
+    /// rendering anything else.
+    pub fn render_window_to_texture<R>(
+        &self,
+        renderer: &mut R,
+        window: &Window,
+        scale: f64,
+    ) -> Result<R::TextureId, RenderError<R>>
+    where
+        R: Renderer + ImportAll + Offscreen<<R as Renderer>::TextureId>,
+        R::TextureId: Clone + 'static,
+    {
+        if !self.windows.contains(window) {
+            return Err(RenderError::UnmappedWindow);
+        }
+
+        let geometry = window.geometry();
+        let size = geometry
+            .size
+            .to_f64()
+            .to_buffer(scale, Transform::Normal)
+            .to_i32_round();
+        let texture = renderer.create_buffer(size).map_err(RenderError::Rendering)?;
+        renderer.bind(texture.clone()).map_err(RenderError::Rendering)?;
+        let res = renderer.render(
+            Size::from((size.w, size.h)),
+            Transform::Normal,
+            |renderer, frame| {
+                frame.clear(
+                    [0.0, 0.0, 0.0, 0.0],
+                    &[Rectangle::from_loc_and_size((0, 0), (size.w, size.h))],
+                )?;
+                // the window geometry is relative to the toplevel surface, which needs to be
+                // moved for the geometry to start at the origin of the texture
+                draw_window_with_failure_mode(
+                    renderer,
+                    frame,
+                    window,
+                    scale,
+                    (-geometry.loc.x, -geometry.loc.y),
+                    &[geometry],
+                    1.0,
+                    self.import_failure_mode,
+                    &self.logger,
+                )
+            },
+        );
+        renderer.unbind().map_err(RenderError::Rendering)?;
+        res.and_then(|res| res)
+            .map(|_| texture)
+            .map_err(RenderError::Rendering)
+    }
+
+    /// Returns a surface and its buffer, that may be directly scanned out on the given [`Output`].
+    ///
+    /// A surface is only considered a candidate, if it is the toplevel surface of the
+    /// top-most [`Window`] on the output, covers the whole output with a fully opaque
+    /// dmabuf matching the size of the current mode and nothing else is visible on top of it.
+    /// Custom elements rendered via [`Space::render_output`] are not known to the space
+    /// and need to be considered by the caller.
+    ///
+    /// The backend may then try to assign the buffer to a plane directly and
+    /// fall back to [`Space::render_output`], if that fails.
+    pub fn scanout_candidate(&self, output: &Output) -> Option<(WlSurface, WlBuffer)> {
+        let output_geo = self.output_geometry(output)?;
+        let mode_size = output.current_mode()?.size;
+
+        let layer_map = layer_map_for_output(output);
+        if layer_map
+            .layers_on(Layer::Top)
+            .chain(layer_map.layers_on(Layer::Overlay))
+            .any(|layer| layer.bbox().size.w > 0 && layer.bbox().size.h > 0)
+        {
+            return None;
+        }
+
+        let window = self
+            .windows
+            .iter()
+            .rev()
+            .find(|w| window_rect_with_popups(w, &self.id).overlaps(output_geo))?;
+        if window_state(self.id, window).alpha < 1.0
+            || window_rect_with_popups(window, &self.id) != output_geo
+        {
+            return None;
+        }
+
+        let surface = window.toplevel().get_surface()?;
+        if !get_children(surface).is_empty() {
+            return None;
+        }
+        with_states(surface, |states| {
+            let data = states.data_map.get::<RefCell<SurfaceState>>()?.borrow();
+            let size = data.surface_size()?;
+            let buffer_size = data.buffer_dimensions?;
+            let surface_rect = Rectangle::from_loc_and_size((0, 0), size);
+            let opaque = surface_rect
+                .subtract_rects(data.opaque_regions.iter().copied())
+                .is_empty();
+
+            let buffer = data.buffer.clone()?;
+            if opaque
+                && (buffer_size.w, buffer_size.h) == (mode_size.w, mode_size.h)
+                && matches!(buffer_type(&buffer), Some(BufferType::Dma))
+            {
+                Some((surface.clone(), buffer))
+            } else {
+                None
+            }
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Returns the color metadata of the contents covering the whole given [`Output`].
+    ///
+    /// This is the metadata set on the toplevel surface of the top-most [`Window`] on the output,
+    /// if it covers the whole output and is not translucent, e.g. a fullscreen video player.
+    /// It may be used to tag the output with the color space and mastering metadata of the contents.
+    ///
+    /// Returns `None` if no such window exists or if it contains sRGB contents.
+    pub fn output_color_metadata(&self, output: &Output) -> Option<ColorMetadata> {
+        let output_geo = self.output_geometry(output)?;
+        let window = self
+            .windows
+            .iter()
+            .rev()
+            .find(|w| window_rect_with_popups(w, &self.id).overlaps(output_geo))?;
+        if window_state(self.id, window).alpha < 1.0
+            || !window_rect_with_popups(window, &self.id).contains_rect(output_geo)
+        {
+            return None;
+        }
+        color_metadata(window.toplevel().get_surface()?)
+    }
+
     /// Sends the frame callback to mapped [`Window`]s and [`LayerSurface`]s.
     ///
-    /// If `all` is set this will be send to `all` mapped surfaces.
+    /// If `all` is set this will be send to `all` mapped surfaces,
+    /// which is useful to force the first frame.
+    ///
     /// Otherwise only windows and layers previously drawn during the
-    /// previous frame will be send frame events.
+    /// previous frame will be send frame events and only if they are visible on
+    /// an output, that was rendered since frame events were last send for it.
+    /// Outputs not rendered for longer than their refresh interval are still
+    /// considered to avoid stalling clients. `time` is expected in milliseconds.
     pub fn send_frames(&self, all: bool, time: u32) {
+        let ready_outputs = self
+            .outputs
+            .iter()
+            .filter(|output| {
+                let refresh_interval = output
+                    .current_mode()
+                    .and_then(|mode| mode.refresh_rate())
+                    .map(|refresh| 1_000_000 / refresh as u32)
+                    .unwrap_or(0);
+                let mut state = output_state(self.id, output);
+                let ready = all
+                    || state.presented
+                    || state
+                        .last_frame_time
+                        .map(|last| time.wrapping_sub(last) >= refresh_interval)
+                        .unwrap_or(true);
+                if ready {
+                    state.presented = false;
+                    state.last_frame_time = Some(time);
+                }
+                ready
+            })
+            .collect::<Vec<_>>();
+        let ready_geometries = ready_outputs
+            .iter()
+            .flat_map(|output| self.output_geometry(output))
+            .collect::<Vec<_>>();
+
         for window in self.windows.iter().filter(|w| {
             all || {
-                let mut state = window_state(self.id, w);
-                std::mem::replace(&mut state.drawn, false)
+                let bbox = window_rect_with_popups(w, &self.id);
+                ready_geometries.iter().any(|geo| geo.overlaps(bbox)) && {
+                    let mut state = window_state(self.id, w);
+                    std::mem::replace(&mut state.drawn, false)
+                }
             }
         }) {
             window.send_frame(time);
         }
 
-        for output in self.outputs.iter() {
+        for output in ready_outputs {
             let map = layer_map_for_output(output);
             for layer in map.layers().filter(|l| {
                 all || {
@@ -633,9 +1244,72 @@ impl Space {
             }
         }
     }
+
+    /// Sends the frame callback to the surfaces shown on a given [`Output`].
+    ///
+    /// Use this instead of [`Space::send_frames`] once the contents of `output` were actually
+    /// presented, e.g. on a vblank event of its backend. Only windows and layers drawn during
+    /// the previous frame and visible on `output` are send frame events, surfaces exclusively
+    /// shown on other outputs are left alone, so that they are throttled to the refresh
+    /// rate of their own outputs. `time` is expected in milliseconds.
+    ///
+    /// The buffers of the windows and layers visible on `output`, whose release was delayed
+    /// because they were still used for drawing, are released as well (see
+    /// [`on_commit_buffer_handler_with_delayed_release`](crate::backend::renderer::utils::on_commit_buffer_handler_with_delayed_release)).
+    /// A window spanning several outputs must therefore not be drawn into frames of other
+    /// outputs, which are still pending, when calling this.
+    ///
+    /// Does nothing if the output is not mapped in this space.
+    pub fn send_frames_for_output(&self, output: &Output, time: u32) {
+        let output_geo = match self.output_geometry(output) {
+            Some(geo) => geo,
+            None => return,
+        };
+        {
+            let mut state = output_state(self.id, output);
+            state.presented = false;
+            state.last_frame_time = Some(time);
+        }
+
+        for window in self
+            .windows
+            .iter()
+            .filter(|w| window_rect_with_popups(w, &self.id).overlaps(output_geo))
+        {
+            if let Some(surface) = window.toplevel().get_surface() {
+                release_presented_buffers_with_popups(surface);
+            }
+            if std::mem::replace(&mut window_state(self.id, window).drawn, false) {
+                window.send_frame(time);
+            }
+        }
+
+        let map = layer_map_for_output(output);
+        for layer in map.layers() {
+            if let Some(surface) = layer.get_surface() {
+                release_presented_buffers_with_popups(surface);
+            }
+            if std::mem::replace(&mut layer_state(self.id, layer).drawn, false) {
+                layer.send_frame(time);
+            }
+        }
+    }
+}
+
+fn release_presented_buffers_with_popups(surface: &WlSurface) {
+    release_presented_buffers(surface);
+    for (popup, _) in PopupManager::popups_for_surface(surface)
+        .ok()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(surface) = popup.get_surface() {
+            release_presented_buffers(surface);
+        }
+    }
 }
 
-/// Errors thrown by [`Space::render_output`]
+/// Errors thrown by [`Space::render_output`] and [`Space::render_window_to_texture`]
 #[derive(thiserror::Error)]
 pub enum RenderError<R: Renderer> {
     /// The provided [`Renderer`] did return an error during an operation
@@ -647,6 +1321,9 @@ pub enum RenderError<R: Renderer> {
     /// The given [`Output`] is not mapped to this [`Space`].
     #[error("Output was not mapped to this space")]
     UnmappedOutput,
+    /// The given [`Window`] is not mapped to this [`Space`].
+    #[error("Window was not mapped to this space")]
+    UnmappedWindow,
 }
 
 impl<R: Renderer> fmt::Debug for RenderError<R> {
@@ -655,6 +1332,161 @@ impl<R: Renderer> fmt::Debug for RenderError<R> {
             RenderError::Rendering(err) => fmt::Debug::fmt(err, f),
             RenderError::OutputNoMode => f.write_str("Output has no active move"),
             RenderError::UnmappedOutput => f.write_str("Output was not mapped to this space"),
+            RenderError::UnmappedWindow => f.write_str("Window was not mapped to this space"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::renderer::dummy::{DrawCommand, DummyRenderer},
+        wayland::output::{Mode, PhysicalProperties},
+    };
+    use wayland_server::{protocol::wl_output::Subpixel, Display};
+
+    // an output with a single mode of the given size
+    fn test_output(display: &mut Display, size: (i32, i32)) -> Output {
+        let physical = PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Smithay".into(),
+            model: "Test".into(),
+        };
+        let (output, _global) = Output::new(display, "test".into(), physical, None);
+        let mode = Mode {
+            size: size.into(),
+            refresh: 60_000,
+        };
+        output.change_current_state(Some(mode), None, None, None);
+        output
+    }
+
+    #[test]
+    fn fractional_output_scale() {
+        let mut display = Display::new();
+        let output = test_output(&mut display, (1920, 1080));
+
+        let mut space = Space::new(None);
+        space.map_output(&output, 1.5, (100, 0));
+        let geometry = Rectangle::from_loc_and_size((100, 0), (1280, 720));
+        assert_eq!(space.output_scale(&output), Some(1.5));
+        assert_eq!(space.output_geometry(&output), Some(geometry));
+        assert_eq!(space.bounding_box(), geometry);
+
+        // the whole output is cleared in physical coordinates
+        let mut renderer = DummyRenderer::new();
+        space
+            .render_output(&mut renderer, &output, 0, [0.0; 4], &[])
+            .unwrap();
+        let commands = renderer.take_commands();
+        assert_eq!(
+            commands[0],
+            DrawCommand::Render {
+                size: (1920, 1080).into(),
+                transform: Transform::Normal,
+            }
+        );
+        assert_eq!(
+            commands[1],
+            DrawCommand::Clear {
+                color: [0.0; 4],
+                at: vec![Rectangle::from_loc_and_size((0, 0), (1920, 1080))],
+            }
+        );
+    }
+
+    #[test]
+    fn debug_damage_is_overlaid() {
+        let mut display = Display::new();
+        let output = test_output(&mut display, (800, 600));
+
+        let mut space = Space::new(None);
+        space.map_output(&output, 1.0, (0, 0));
+        let color = [0.3, 0.0, 0.0, 0.3];
+        space.set_debug_damage(Some(color));
+        assert_eq!(space.debug_damage(), Some(color));
+
+        let mut renderer = DummyRenderer::new();
+        space
+            .render_output(&mut renderer, &output, 0, [0.0; 4], &[])
+            .unwrap();
+        let damage = vec![Rectangle::from_loc_and_size((0, 0), (800, 600))];
+        assert_eq!(
+            renderer.take_commands()[1..],
+            [
+                DrawCommand::Clear {
+                    color: [0.0; 4],
+                    at: damage.clone(),
+                },
+                DrawCommand::Clear { color, at: damage },
+            ]
+        );
+    }
+
+    #[test]
+    fn damage_all_repaints_the_output() {
+        let mut display = Display::new();
+        let output = test_output(&mut display, (800, 600));
+
+        let mut space = Space::new(None);
+        space.map_output(&output, 1.0, (0, 0));
+        let mut renderer = DummyRenderer::new();
+        space
+            .render_output(&mut renderer, &output, 0, [0.0; 4], &[])
+            .unwrap();
+        // nothing changed since the last frame
+        assert_eq!(
+            space
+                .render_output(&mut renderer, &output, 1, [0.0; 4], &[])
+                .unwrap(),
+            None
+        );
+
+        space.damage_all(&output);
+        space.damage_all(&output);
+        assert_eq!(
+            space.damage_for_output(&output),
+            Some(vec![Rectangle::from_loc_and_size((0, 0), (800, 600))])
+        );
+        assert_eq!(
+            space
+                .render_output(&mut renderer, &output, 1, [0.0; 4], &[])
+                .unwrap(),
+            Some(vec![Rectangle::from_loc_and_size((0, 0), (800, 600))])
+        );
+    }
+
+    #[test]
+    fn cursor_is_resolved_per_output() {
+        let mut display = Display::new();
+        let output = test_output(&mut display, (800, 600));
+
+        let mut space = Space::new(None);
+        assert_eq!(
+            space.cursor_for_output(&output, &CursorImageStatus::Default, (10.0, 10.0).into()),
+            None
+        );
+        space.map_output(&output, 1.0, (800, 0));
+
+        // the location is relative to the output
+        let cursor = space
+            .cursor_for_output(&output, &CursorImageStatus::Default, (810.0, 20.0).into())
+            .unwrap();
+        assert_eq!(cursor.image, CursorImage::Default);
+        assert_eq!(cursor.location, Point::from((10, 20)));
+        assert!(cursor.surface_element().is_none());
+
+        let cursor = space
+            .cursor_for_output(&output, &CursorImageStatus::Hidden, (810.0, 20.0).into())
+            .unwrap();
+        assert_eq!(cursor.image, CursorImage::Hidden);
+
+        // the pointer is on another output
+        assert_eq!(
+            space.cursor_for_output(&output, &CursorImageStatus::Default, (10.0, 20.0).into()),
+            None
+        );
+    }
+}
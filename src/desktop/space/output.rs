@@ -38,10 +38,17 @@ where
 pub struct OutputState {
     pub location: Point<i32, Logical>,
     pub render_scale: f64,
+    pub clear_color: Option<[f32; 4]>,
 
     // damage and last_state are in space coordinate space
     pub old_damage: VecDeque<Vec<Rectangle<i32, Logical>>>,
     pub last_state: IndexMap<ToplevelId, Rectangle<i32, Logical>>,
+    // damage reported by commits since the last render
+    pub pending_damage: Vec<Rectangle<i32, Logical>>,
+
+    // frame callback throttling, set if rendered since frames were last sent
+    pub presented: bool,
+    pub last_frame_time: Option<u32>,
 
     // surfaces for tracking enter and leave events
     pub surfaces: Vec<WlSurface>,
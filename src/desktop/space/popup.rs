@@ -1,5 +1,5 @@
 use crate::{
-    backend::renderer::{Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{utils::ImportFailureMode, Frame, ImportAll, Renderer, Texture},
     desktop::{
         layer::LayerSurface,
         popup::{PopupKind, PopupManager},
@@ -136,6 +136,7 @@ where
         _scale: f64,
         _location: Point<i32, Logical>,
         _damage: &[Rectangle<i32, Logical>],
+        _failure_mode: ImportFailureMode,
         _log: &slog::Logger,
     ) -> Result<(), R::Error> {
         // popups are special, we track them, but they render with their parents
@@ -1,8 +1,9 @@
 use crate::{
-    backend::renderer::{Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{utils::ImportFailureMode, Frame, ImportAll, Renderer, Texture},
     desktop::{
         space::{Space, SpaceElement},
-        window::{draw_window, Window},
+        utils::opaque_regions_from_surface_tree,
+        window::{draw_window_with_failure_mode, Window},
     },
     utils::{Logical, Point, Rectangle},
     wayland::output::Output,
@@ -15,10 +16,20 @@ use std::{
 
 use super::RenderZindex;
 
-#[derive(Default)]
 pub struct WindowState {
     pub location: Point<i32, Logical>,
     pub drawn: bool,
+    pub alpha: f32,
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        WindowState {
+            location: Point::default(),
+            drawn: false,
+            alpha: 1.0,
+        }
+    }
 }
 
 pub type WindowUserdata = RefCell<HashMap<usize, WindowState>>;
@@ -89,6 +100,17 @@ where
         self.accumulated_damage(for_values)
     }
 
+    fn opaque_regions(&self, space_id: usize) -> Vec<Rectangle<i32, Logical>> {
+        // translucent windows do not hide anything below them
+        if window_state(space_id, self).alpha < 1.0 {
+            return Vec::new();
+        }
+        self.toplevel()
+            .get_surface()
+            .map(|surface| opaque_regions_from_surface_tree(surface, window_loc(self, &space_id)))
+            .unwrap_or_default()
+    }
+
     fn draw(
         &self,
         space_id: usize,
@@ -97,9 +119,21 @@ where
         scale: f64,
         location: Point<i32, Logical>,
         damage: &[Rectangle<i32, Logical>],
+        failure_mode: ImportFailureMode,
         log: &slog::Logger,
     ) -> Result<(), R::Error> {
-        let res = draw_window(renderer, frame, self, scale, location, damage, log);
+        let alpha = window_state(space_id, self).alpha;
+        let res = draw_window_with_failure_mode(
+            renderer,
+            frame,
+            self,
+            scale,
+            location,
+            damage,
+            alpha,
+            failure_mode,
+            log,
+        );
         if res.is_ok() {
             window_state(space_id, self).drawn = true;
         }
@@ -6,8 +6,8 @@ use crate::{
     utils::{Logical, Point, Rectangle},
     wayland::{
         compositor::{
-            with_surface_tree_downward, with_surface_tree_upward, Damage, SubsurfaceCachedState,
-            SurfaceAttributes, TraversalAction,
+            get_parent, with_states, with_surface_tree_downward, with_surface_tree_upward, Damage,
+            SubsurfaceCachedState, SurfaceAttributes, SurfaceData, TraversalAction,
         },
         output::Output,
     },
@@ -58,23 +58,85 @@ pub fn bbox_from_surface_tree<P>(surface: &wl_surface::WlSurface, location: P) -
 where
     P: Into<Point<i32, Logical>>,
 {
-    let location = location.into();
-    let mut bounding_box = Rectangle::from_loc_and_size(location, (0, 0));
+    crate::backend::renderer::utils::surface_tree_bbox(surface, location.into())
+}
+
+/// Calls `processor` for a given surface and all its mapped subsurfaces, with their location.
+///
+/// - `location` is the location of the given surface, the location of the subsurfaces is offset by it.
+///
+/// The surfaces are visited in the order they are drawn, from back to front. Unmapped surfaces and
+/// their children are skipped. The state of the surfaces is locked while `processor` runs, use the
+/// provided [`SurfaceData`] instead of [`with_states`] to access it.
+pub fn with_surfaces_surface_tree<P, F>(surface: &wl_surface::WlSurface, location: P, mut processor: F)
+where
+    P: Into<Point<i32, Logical>>,
+    F: FnMut(&wl_surface::WlSurface, &SurfaceData, Point<i32, Logical>),
+{
+    fn is_mapped(states: &SurfaceData) -> bool {
+        states
+            .data_map
+            .get::<RefCell<SurfaceState>>()
+            .map(|data| data.borrow().surface_size().is_some())
+            .unwrap_or(false)
+    }
+    fn surface_location(states: &SurfaceData, mut location: Point<i32, Logical>) -> Point<i32, Logical> {
+        if states.role == Some("subsurface") {
+            location += states.cached_state.current::<SubsurfaceCachedState>().location;
+        }
+        location
+    }
+
+    with_surface_tree_upward(
+        surface,
+        location.into(),
+        |_, states, location| {
+            if is_mapped(states) {
+                TraversalAction::DoChildren(surface_location(states, *location))
+            } else {
+                // If the parent surface is unmapped, then the child surfaces are hidden as
+                // well, no need to consider them here.
+                TraversalAction::SkipChildren
+            }
+        },
+        |surface, states, location| {
+            if is_mapped(states) {
+                processor(surface, states, surface_location(states, *location));
+            }
+        },
+        |_, _, _| true,
+    );
+}
+
+/// Returns the opaque regions of a given surface and all its subsurfaces.
+///
+/// - `location` can be set to offset the returned regions.
+pub fn opaque_regions_from_surface_tree<P>(
+    surface: &wl_surface::WlSurface,
+    location: P,
+) -> Vec<Rectangle<i32, Logical>>
+where
+    P: Into<Point<i32, Logical>>,
+{
+    let mut regions = Vec::new();
     with_surface_tree_downward(
         surface,
-        location,
+        location.into(),
         |_, states, loc: &Point<i32, Logical>| {
             let mut loc = *loc;
             let data = states.data_map.get::<RefCell<SurfaceState>>();
 
-            if let Some(size) = data.and_then(|d| d.borrow().surface_size()) {
+            if let Some(data) = data.map(|d| d.borrow()).filter(|d| d.surface_size().is_some()) {
                 if states.role == Some("subsurface") {
                     let current = states.cached_state.current::<SubsurfaceCachedState>();
                     loc += current.location;
                 }
 
-                // Update the bounding box.
-                bounding_box = bounding_box.merge(Rectangle::from_loc_and_size(loc, size));
+                regions.extend(data.opaque_regions.iter().map(|rect| {
+                    let mut rect = *rect;
+                    rect.loc += loc;
+                    rect
+                }));
 
                 TraversalAction::DoChildren(loc)
             } else {
@@ -86,7 +148,7 @@ where
         |_, _, _| {},
         |_, _, _| true,
     );
-    bounding_box
+    regions
 }
 
 /// Returns the damage rectangles of the current buffer for a given surface and its subsurfaces.
@@ -219,6 +281,35 @@ where
     found.into_inner()
 }
 
+/// Converts a global position into coordinates local to a given (sub-)surface.
+///
+/// - `global_point` is the position to convert, in the global compositor space.
+/// - `surface_origin` is the global location of the root of the surface tree `surface` is part of,
+///   e.g. the location of its window.
+///
+/// The offsets of all subsurfaces between the root and `surface` are taken into account.
+/// Surface-local coordinates are expressed in the logical coordinate space of the surface, so the
+/// buffer scale and transform of the surfaces involved do not affect the result.
+pub fn surface_local_coords<P>(
+    surface: &wl_surface::WlSurface,
+    global_point: Point<f64, Logical>,
+    surface_origin: P,
+) -> Point<f64, Logical>
+where
+    P: Into<Point<i32, Logical>>,
+{
+    let mut location = surface_origin.into();
+    let mut current = surface.clone();
+    while let Some(parent) = get_parent(&current) {
+        location += with_states(&current, |states| {
+            states.cached_state.current::<SubsurfaceCachedState>().location
+        })
+        .unwrap_or_default();
+        current = parent;
+    }
+    global_point - location.to_f64()
+}
+
 /// Sends frame callbacks for a surface and its subsurfaces with the given `time`.
 pub fn send_frames_surface_tree(surface: &wl_surface::WlSurface, time: u32) {
     with_surface_tree_downward(
@@ -326,3 +417,42 @@ pub(crate) fn output_leave(
         surface_list.retain(|s| s != surface);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        utils::{Size, Transform},
+        wayland::compositor::{RectangleKind, RegionAttributes},
+    };
+
+    #[test]
+    fn input_region_limits_hit_testing() {
+        let state = SurfaceState {
+            buffer_dimensions: Some(Size::from((100, 100))),
+            buffer_scale: 1,
+            buffer_transform: Transform::Normal,
+            ..Default::default()
+        };
+        let mut attrs = SurfaceAttributes::default();
+        assert!(state.contains_point(&attrs, (50.0, 50.0)));
+
+        attrs.input_region = Some(RegionAttributes {
+            rects: vec![(
+                RectangleKind::Add,
+                Rectangle::from_loc_and_size((10, 10), (20, 20)),
+            )],
+        });
+        assert!(state.contains_point(&attrs, (15.0, 15.0)));
+        // inside the buffer but outside of the input region
+        assert!(!state.contains_point(&attrs, (50.0, 50.0)));
+        // the input region is clipped to the surface
+        attrs.input_region = Some(RegionAttributes {
+            rects: vec![(
+                RectangleKind::Add,
+                Rectangle::from_loc_and_size((0, 0), (200, 200)),
+            )],
+        });
+        assert!(!state.contains_point(&attrs, (150.0, 150.0)));
+    }
+}
@@ -1,11 +1,17 @@
 use crate::{
-    backend::renderer::{utils::draw_surface_tree, Frame, ImportAll, Renderer, Texture},
+    backend::renderer::{
+        utils::{draw_surface_tree_with_failure_mode, ImportFailureMode},
+        Frame, ImportAll, Renderer, Texture,
+    },
     desktop::{utils::*, PopupManager, Space},
     utils::{Logical, Point, Rectangle},
     wayland::{
-        compositor::with_states,
+        compositor::{with_states, SurfaceData},
         output::Output,
-        shell::xdg::{SurfaceCachedState, ToplevelSurface},
+        shell::{
+            legacy::ShellSurface,
+            xdg::{SurfaceCachedState, ToplevelSurface},
+        },
     },
 };
 use std::{
@@ -24,6 +30,10 @@ crate::utils::ids::id_gen!(next_window_id, WINDOW_ID, WINDOW_IDS);
 pub enum Kind {
     /// xdg-shell [`ToplevelSurface`]
     Xdg(ToplevelSurface),
+    /// wl_shell [`ShellSurface`] of a toplevel, transient, maximized or fullscreen window
+    ///
+    /// `wl_shell` popups are handled as a [`PopupKind`](super::PopupKind) instead.
+    Wl(ShellSurface),
     /// XWayland surface (TODO)
     #[cfg(feature = "xwayland")]
     X11(X11Surface),
@@ -62,6 +72,7 @@ impl Kind {
     pub fn alive(&self) -> bool {
         match *self {
             Kind::Xdg(ref t) => t.alive(),
+            Kind::Wl(ref t) => t.alive(),
             #[cfg(feature = "xwayland")]
             Kind::X11(ref t) => t.alive(),
         }
@@ -71,6 +82,7 @@ impl Kind {
     pub fn get_surface(&self) -> Option<&wl_surface::WlSurface> {
         match *self {
             Kind::Xdg(ref t) => t.get_surface(),
+            Kind::Wl(ref t) => t.get_surface(),
             #[cfg(feature = "xwayland")]
             Kind::X11(ref t) => t.get_surface(),
         }
@@ -177,6 +189,31 @@ impl Window {
         bounding_box
     }
 
+    /// Calls `processor` for every mapped surface of this window, with its location relative
+    /// to (0,0) of the window.
+    ///
+    /// This walks the toplevel surface, its subsurfaces and the popups tracked by a
+    /// [`PopupManager`] together with their subsurfaces, in the order they are drawn.
+    /// See [`with_surfaces_surface_tree`] for details.
+    pub fn with_surfaces<F>(&self, mut processor: F)
+    where
+        F: FnMut(&wl_surface::WlSurface, &SurfaceData, Point<i32, Logical>),
+    {
+        if let Some(surface) = self.0.toplevel.get_surface() {
+            with_surfaces_surface_tree(surface, (0, 0), &mut processor);
+            for (popup, location) in PopupManager::popups_for_surface(surface)
+                .ok()
+                .into_iter()
+                .flatten()
+            {
+                if let Some(surface) = popup.get_surface() {
+                    let offset = self.geometry().loc + location - popup.geometry().loc;
+                    with_surfaces_surface_tree(surface, offset, &mut processor);
+                }
+            }
+        }
+    }
+
     /// Activate/Deactivate this window
     pub fn set_activated(&self, active: bool) -> bool {
         match self.0.toplevel {
@@ -189,6 +226,8 @@ impl Window {
                     }
                 })
                 .unwrap_or(false),
+            // wl_shell has no notion of activation
+            Kind::Wl(_) => false,
             #[cfg(feature = "xwayland")]
             Kind::X11(ref _t) => unimplemented!(),
         }
@@ -198,6 +237,8 @@ impl Window {
     pub fn configure(&self) {
         match self.0.toplevel {
             Kind::Xdg(ref t) => t.send_configure(),
+            // wl_shell has no pending state, sizes are suggested with `ShellSurface::send_configure`
+            Kind::Wl(_) => {}
             #[cfg(feature = "xwayland")]
             Kind::X11(ref _t) => unimplemented!(),
         }
@@ -296,10 +337,12 @@ impl Window {
 /// - `scale` needs to be equivalent to the fractional scale the rendered result should have.
 /// - `location` is the position the window should be drawn at.
 /// - `damage` is the set of regions of the window that should be drawn.
+/// - `alpha` is the opacity the window and its popups are drawn with.
 ///
 /// Note: This function will render nothing, if you are not using
 /// [`crate::backend::renderer::utils::on_commit_buffer_handler`]
 /// to let smithay handle buffer management.
+#[allow(clippy::too_many_arguments)]
 pub fn draw_window<R, E, F, T, P>(
     renderer: &mut R,
     frame: &mut F,
@@ -307,6 +350,39 @@ pub fn draw_window<R, E, F, T, P>(
     scale: f64,
     location: P,
     damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    log: &slog::Logger,
+) -> Result<(), R::Error>
+where
+    R: Renderer<Error = E, TextureId = T, Frame = F> + ImportAll,
+    F: Frame<Error = E, TextureId = T>,
+    E: std::error::Error,
+    T: Texture + 'static,
+    P: Into<Point<i32, Logical>>,
+{
+    draw_window_with_failure_mode(
+        renderer,
+        frame,
+        window,
+        scale,
+        location,
+        damage,
+        alpha,
+        ImportFailureMode::default(),
+        log,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_window_with_failure_mode<R, E, F, T, P>(
+    renderer: &mut R,
+    frame: &mut F,
+    window: &Window,
+    scale: f64,
+    location: P,
+    damage: &[Rectangle<i32, Logical>],
+    alpha: f32,
+    failure_mode: ImportFailureMode,
     log: &slog::Logger,
 ) -> Result<(), R::Error>
 where
@@ -318,7 +394,17 @@ where
 {
     let location = location.into();
     if let Some(surface) = window.toplevel().get_surface() {
-        draw_surface_tree(renderer, frame, surface, scale, location, damage, log)?;
+        draw_surface_tree_with_failure_mode(
+            renderer,
+            frame,
+            surface,
+            scale,
+            location,
+            damage,
+            alpha,
+            failure_mode,
+            log,
+        )?;
         for (popup, p_location) in PopupManager::popups_for_surface(surface)
             .ok()
             .into_iter()
@@ -334,7 +420,17 @@ where
                         geo
                     })
                     .collect::<Vec<_>>();
-                draw_surface_tree(renderer, frame, surface, scale, location + offset, &damage, log)?;
+                draw_surface_tree_with_failure_mode(
+                    renderer,
+                    frame,
+                    surface,
+                    scale,
+                    location + offset,
+                    &damage,
+                    alpha,
+                    failure_mode,
+                    log,
+                )?;
             }
         }
     }
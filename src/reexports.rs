@@ -23,3 +23,4 @@ pub use wayland_server;
 pub use winit;
 #[cfg(feature = "x11rb_event_source")]
 pub use x11rb;
+pub use xkbcommon;
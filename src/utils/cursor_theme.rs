@@ -0,0 +1,183 @@
+//! Loader for XCursor themes
+//!
+//! Compositors need to draw a cursor whenever no client provides one, for example when
+//! [`CursorImageStatus::Default`](crate::wayland::seat::CursorImageStatus::Default) is requested.
+//! This module loads the images of a named cursor from the installed XCursor themes.
+//!
+//! The images are provided as RGBA pixel data, which can be uploaded as textures by the renderer.
+//! Cursors can be animated, in which case [`XCursor::frame`] selects the image to display at a
+//! given time.
+
+use std::{fs, io, time::Duration};
+
+use ::xcursor::{
+    parser::{parse_xcursor, Image},
+    CursorTheme,
+};
+
+use crate::utils::{Buffer, Point, Size};
+
+/// Errors that can happen when loading a cursor
+#[derive(Debug, thiserror::Error)]
+pub enum XCursorError {
+    /// Neither the theme nor the themes it inherits from provide the cursor
+    #[error("The theme does not provide the cursor `{0}`")]
+    NotFound(String),
+    /// The cursor file could not be read
+    #[error("Error reading the cursor file: {0}")]
+    Io(#[from] io::Error),
+    /// The cursor file is not a valid XCursor file
+    #[error("Failed to parse the cursor file")]
+    Parse,
+}
+
+/// A single image of a cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorFrame {
+    /// Size of the image, in pixels
+    pub size: Size<i32, Buffer>,
+    /// Location of the hotspot in the image
+    pub hotspot: Point<i32, Buffer>,
+    /// Time this image is displayed before the next one of the animation
+    pub delay: Duration,
+    /// The pixels of the image, as tightly packed RGBA8 values
+    pub pixels_rgba: Vec<u8>,
+}
+
+impl From<&Image> for CursorFrame {
+    fn from(image: &Image) -> CursorFrame {
+        CursorFrame {
+            size: (image.width as i32, image.height as i32).into(),
+            hotspot: (image.xhot as i32, image.yhot as i32).into(),
+            delay: Duration::from_millis(image.delay as u64),
+            pixels_rgba: image.pixels_rgba.clone(),
+        }
+    }
+}
+
+/// A cursor loaded from an XCursor theme
+///
+/// A cursor file usually contains images for several nominal sizes, each of them possibly
+/// being animated.
+#[derive(Debug, Clone)]
+pub struct XCursor {
+    images: Vec<Image>,
+}
+
+impl XCursor {
+    /// Load the cursor with the given name from a theme
+    ///
+    /// The themes are searched in the usual XCursor locations, falling back to the themes
+    /// the given theme inherits from.
+    pub fn load(theme: &str, name: &str) -> Result<XCursor, XCursorError> {
+        let path = CursorTheme::load(theme)
+            .load_icon(name)
+            .ok_or_else(|| XCursorError::NotFound(name.into()))?;
+        let data = fs::read(path)?;
+        XCursor::from_bytes(&data)
+    }
+
+    /// Load the cursor with the given name from the theme set in the `XCURSOR_THEME` environment variable
+    ///
+    /// The `default` theme is used if the variable is not set.
+    pub fn load_from_env(name: &str) -> Result<XCursor, XCursorError> {
+        let theme = std::env::var("XCURSOR_THEME").unwrap_or_else(|_| "default".into());
+        XCursor::load(&theme, name)
+    }
+
+    /// Parse a cursor from the contents of an XCursor file
+    pub fn from_bytes(data: &[u8]) -> Result<XCursor, XCursorError> {
+        match parse_xcursor(data) {
+            Some(images) if !images.is_empty() => Ok(XCursor { images }),
+            _ => Err(XCursorError::Parse),
+        }
+    }
+
+    /// Returns the frames of the cursor best matching the given nominal size and scale
+    ///
+    /// The nominal size is usually taken from the `XCURSOR_SIZE` environment variable, `24` being
+    /// a common default. The frames are in the order of the animation, a cursor that is not animated
+    /// has a single frame.
+    pub fn frames(&self, size: u32, scale: u32) -> Vec<CursorFrame> {
+        self.nearest_images(size * scale).map(CursorFrame::from).collect()
+    }
+
+    /// Total duration of the animation of the cursor at the given nominal size and scale
+    ///
+    /// Returns [`Duration::ZERO`] if the cursor is not animated.
+    pub fn animation_duration(&self, size: u32, scale: u32) -> Duration {
+        if self.nearest_images(size * scale).nth(1).is_none() {
+            return Duration::ZERO;
+        }
+        let total = self
+            .nearest_images(size * scale)
+            .map(|image| image.delay as u64)
+            .sum();
+        Duration::from_millis(total)
+    }
+
+    /// Returns the frame to display at the given time of the animation
+    ///
+    /// `time` is the time elapsed since the animation started, it wraps around at the end of
+    /// the animation.
+    pub fn frame(&self, size: u32, scale: u32, time: Duration) -> CursorFrame {
+        let total = self.animation_duration(size, scale).as_millis();
+        let mut millis = if total > 0 { time.as_millis() % total } else { 0 };
+        for image in self.nearest_images(size * scale) {
+            if millis < image.delay as u128 {
+                return CursorFrame::from(image);
+            }
+            millis -= image.delay as u128;
+        }
+        // not animated or all delays are zero
+        CursorFrame::from(self.nearest_images(size * scale).next().unwrap())
+    }
+
+    // the images of the nominal size nearest to the requested one
+    fn nearest_images(&self, size: u32) -> impl Iterator<Item = &Image> {
+        let nearest = self
+            .images
+            .iter()
+            .min_by_key(|image| (size as i64 - image.size as i64).abs())
+            .map(|image| image.size)
+            .unwrap();
+        self.images.iter().filter(move |image| image.size == nearest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: u32, delay: u32) -> Image {
+        Image {
+            size,
+            width: size,
+            height: size,
+            xhot: 1,
+            yhot: 2,
+            delay,
+            pixels_rgba: vec![0; (size * size * 4) as usize],
+            pixels_argb: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn animated_frames_follow_delays() {
+        let cursor = XCursor {
+            images: vec![image(24, 100), image(24, 50), image(48, 0)],
+        };
+        assert_eq!(cursor.frames(24, 1).len(), 2);
+        assert_eq!(cursor.animation_duration(24, 1), Duration::from_millis(150));
+        assert_eq!(
+            cursor.frame(24, 1, Duration::from_millis(260)).delay,
+            Duration::from_millis(50)
+        );
+
+        // the scaled size selects the larger, static image
+        let frame = cursor.frame(24, 2, Duration::from_millis(260));
+        assert_eq!(frame.size, (48, 48).into());
+        assert_eq!(frame.hotspot, (1, 2).into());
+        assert_eq!(cursor.animation_duration(24, 2), Duration::ZERO);
+    }
+}
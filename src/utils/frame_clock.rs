@@ -0,0 +1,99 @@
+//! Helper to schedule frames according to the refresh rate of an output
+
+use std::time::{Duration, Instant};
+
+/// Refresh rate, in millihertz, used if the output does not report a valid one
+pub const FALLBACK_REFRESH: i32 = 60_000;
+
+/// Clock predicting the presentation times of an output
+///
+/// It is driven by the refresh rate of the output, as found in
+/// [`Mode::refresh`](crate::wayland::output::Mode), and by the times frames were actually
+/// presented, as reported by [`FrameClock::presented`]. From these it computes the time left until
+/// the next vblank, which can be used as the timeout when dispatching the event loop or to throttle
+/// the frame callbacks sent to clients, instead of guessing a fixed interval.
+///
+/// Until a first presentation is reported, the vblanks are assumed to be aligned with the creation
+/// of the clock.
+#[derive(Debug, Clone)]
+pub struct FrameClock {
+    interval: Duration,
+    last_presentation: Instant,
+}
+
+impl FrameClock {
+    /// Create a new clock for an output refreshing at the given rate, in millihertz
+    ///
+    /// A refresh rate of `0` or less is invalid and replaced by [`FALLBACK_REFRESH`].
+    pub fn new(refresh: i32) -> FrameClock {
+        FrameClock {
+            interval: refresh_interval(refresh),
+            last_presentation: Instant::now(),
+        }
+    }
+
+    /// Change the refresh rate of the output, in millihertz
+    ///
+    /// This should be called whenever the mode of the output changes.
+    pub fn set_refresh(&mut self, refresh: i32) {
+        self.interval = refresh_interval(refresh);
+    }
+
+    /// Duration between two vblanks of the output
+    pub fn refresh_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Report that a frame was presented at the given time
+    pub fn presented(&mut self, time: Instant) {
+        self.last_presentation = time;
+    }
+
+    /// Time of the last reported presentation
+    pub fn last_presentation(&self) -> Instant {
+        self.last_presentation
+    }
+
+    /// Predicted time of the first vblank following `now`
+    pub fn next_presentation(&self, now: Instant) -> Instant {
+        if now < self.last_presentation {
+            return self.last_presentation;
+        }
+        let interval = self.interval.as_nanos();
+        let elapsed = now.duration_since(self.last_presentation).as_nanos();
+        let frames = elapsed / interval + 1;
+        self.last_presentation + Duration::from_nanos((frames * interval) as u64)
+    }
+
+    /// Time left from `now` until the next vblank
+    pub fn time_to_next_presentation(&self, now: Instant) -> Duration {
+        self.next_presentation(now).saturating_duration_since(now)
+    }
+}
+
+fn refresh_interval(refresh: i32) -> Duration {
+    let refresh = if refresh > 0 { refresh } else { FALLBACK_REFRESH };
+    Duration::from_nanos(1_000_000_000_000 / refresh as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_presentation_follows_refresh_rate() {
+        let mut clock = FrameClock::new(50_000);
+        assert_eq!(clock.refresh_interval(), Duration::from_millis(20));
+
+        let start = Instant::now();
+        clock.presented(start);
+        assert_eq!(clock.next_presentation(start), start + Duration::from_millis(20));
+        assert_eq!(
+            clock.time_to_next_presentation(start + Duration::from_millis(45)),
+            Duration::from_millis(15)
+        );
+
+        clock.set_refresh(0);
+        assert_eq!(clock.refresh_interval(), refresh_interval(FALLBACK_REFRESH));
+    }
+}
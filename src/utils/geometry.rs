@@ -249,6 +249,24 @@ floating_point_coordinate_impl! {
     f64
 }
 
+/// Rounding strategy used when converting floating point geometry into integer coordinates
+///
+/// This is mostly relevant for conversions using non-integer scale factors,
+/// where the resulting coordinates do not necessarily land on integer values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rounding {
+    /// Round to the nearest integer, rounding half-way cases away from zero
+    Round,
+    /// Round towards negative infinity
+    ///
+    /// For rectangles this returns the largest integer rectangle contained in the unrounded one.
+    Floor,
+    /// Round towards positive infinity
+    ///
+    /// For rectangles this returns the smallest integer rectangle containing the unrounded one.
+    Ceil,
+}
+
 /*
  * Point
  */
@@ -355,6 +373,16 @@ impl<Kind> Point<f64, Kind> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    /// Convert to i32 for integer-space manipulations using the given rounding strategy
+    #[inline]
+    pub fn to_i32_rounded<N: Coordinate>(self, rounding: Rounding) -> Point<N, Kind> {
+        match rounding {
+            Rounding::Round => self.to_i32_round(),
+            Rounding::Floor => self.to_i32_floor(),
+            Rounding::Ceil => self.to_i32_ceil(),
+        }
+    }
 }
 
 impl<N: fmt::Debug> fmt::Debug for Point<N, Logical> {
@@ -414,6 +442,28 @@ impl<N: Coordinate> Point<N, Logical> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    #[inline]
+    /// Convert this logical point to physical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_physical_rounded(self, scale: f64, rounding: Rounding) -> Point<N, Physical> {
+        self.to_f64().to_physical(scale).to_i32_rounded(rounding)
+    }
+
+    #[inline]
+    /// Convert this logical point to buffer coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_buffer_rounded(
+        self,
+        scale: f64,
+        transformation: Transform,
+        area: &Size<N, Logical>,
+        rounding: Rounding,
+    ) -> Point<N, Buffer> {
+        self.to_f64()
+            .to_buffer(scale, transformation, &area.to_f64())
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Point<N, Physical> {
@@ -426,6 +476,13 @@ impl<N: Coordinate> Point<N, Physical> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    #[inline]
+    /// Convert this physical point to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_logical_rounded(self, scale: f64, rounding: Rounding) -> Point<N, Logical> {
+        self.to_f64().to_logical(scale).to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Point<N, Buffer> {
@@ -439,6 +496,21 @@ impl<N: Coordinate> Point<N, Buffer> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    #[inline]
+    /// Convert this buffer point to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_logical_rounded(
+        self,
+        scale: f64,
+        transform: Transform,
+        area: &Size<N, Buffer>,
+        rounding: Rounding,
+    ) -> Point<N, Logical> {
+        self.to_f64()
+            .to_logical(scale, transform, &area.to_f64())
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N, Kind> From<(N, N)> for Point<N, Kind> {
@@ -618,6 +690,16 @@ impl<Kind> Size<f64, Kind> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    /// Convert to i32 for integer-space manipulations using the given rounding strategy
+    #[inline]
+    pub fn to_i32_rounded<N: Coordinate>(self, rounding: Rounding) -> Size<N, Kind> {
+        match rounding {
+            Rounding::Round => self.to_i32_round(),
+            Rounding::Floor => self.to_i32_floor(),
+            Rounding::Ceil => self.to_i32_ceil(),
+        }
+    }
 }
 
 impl<N: fmt::Debug> fmt::Debug for Size<N, Logical> {
@@ -676,6 +758,27 @@ impl<N: Coordinate> Size<N, Logical> {
             _kind: std::marker::PhantomData,
         })
     }
+
+    #[inline]
+    /// Convert this logical size to physical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_physical_rounded(self, scale: f64, rounding: Rounding) -> Size<N, Physical> {
+        self.to_f64().to_physical(scale).to_i32_rounded(rounding)
+    }
+
+    #[inline]
+    /// Convert this logical size to buffer coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_buffer_rounded(
+        self,
+        scale: f64,
+        transformation: Transform,
+        rounding: Rounding,
+    ) -> Size<N, Buffer> {
+        self.to_f64()
+            .to_buffer(scale, transformation)
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Size<N, Physical> {
@@ -688,6 +791,13 @@ impl<N: Coordinate> Size<N, Physical> {
             _kind: std::marker::PhantomData,
         }
     }
+
+    #[inline]
+    /// Convert this physical size to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_logical_rounded(self, scale: f64, rounding: Rounding) -> Size<N, Logical> {
+        self.to_f64().to_logical(scale).to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Size<N, Buffer> {
@@ -700,6 +810,20 @@ impl<N: Coordinate> Size<N, Buffer> {
             _kind: std::marker::PhantomData,
         })
     }
+
+    #[inline]
+    /// Convert this buffer size to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    pub fn to_logical_rounded(
+        self,
+        scale: f64,
+        transformation: Transform,
+        rounding: Rounding,
+    ) -> Size<N, Logical> {
+        self.to_f64()
+            .to_logical(scale, transformation)
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate, Kind> From<(N, N)> for Size<N, Kind> {
@@ -857,6 +981,18 @@ impl<Kind> Rectangle<f64, Kind> {
     pub fn to_i32_up<N: Coordinate>(self) -> Rectangle<N, Kind> {
         Rectangle::from_extemities(self.loc.to_i32_floor(), (self.loc + self.size).to_i32_ceil())
     }
+
+    /// Convert to i32 for integer-space manipulations using the given rounding strategy
+    ///
+    /// See [`Rounding`] for how the strategies apply to rectangles.
+    #[inline]
+    pub fn to_i32_rounded<N: Coordinate>(self, rounding: Rounding) -> Rectangle<N, Kind> {
+        match rounding {
+            Rounding::Round => self.to_i32_round(),
+            Rounding::Floor => self.to_i32_down(),
+            Rounding::Ceil => self.to_i32_up(),
+        }
+    }
 }
 
 impl<N: Coordinate, Kind> Rectangle<N, Kind> {
@@ -957,6 +1093,53 @@ impl<N: Coordinate, Kind> Rectangle<N, Kind> {
     pub fn merge(self, other: Self) -> Self {
         Self::bounding_box([self.loc, self.loc + self.size, other.loc, other.loc + other.size])
     }
+
+    /// Subtract another [`Rectangle`] from this one
+    ///
+    /// Returns up to four non-overlapping rectangles covering the area of `self`
+    /// not contained in `other`.
+    pub fn subtract_rect(self, other: Self) -> Vec<Self> {
+        let intersection = match self.intersection(other) {
+            Some(intersection)
+                if intersection.size.w > N::default() && intersection.size.h > N::default() =>
+            {
+                intersection
+            }
+            _ => return vec![self],
+        };
+
+        let self_max = self.loc + self.size;
+        let int_max = intersection.loc + intersection.size;
+        let candidates = [
+            // above the intersection, full width
+            Rectangle::from_extemities(self.loc, (self_max.x, intersection.loc.y)),
+            // below the intersection, full width
+            Rectangle::from_extemities((self.loc.x, int_max.y), self_max),
+            // left of the intersection
+            Rectangle::from_extemities((self.loc.x, intersection.loc.y), (intersection.loc.x, int_max.y)),
+            // right of the intersection
+            Rectangle::from_extemities((int_max.x, intersection.loc.y), (self_max.x, int_max.y)),
+        ];
+
+        candidates
+            .iter()
+            .copied()
+            .filter(|rect| rect.size.w > N::default() && rect.size.h > N::default())
+            .collect()
+    }
+
+    /// Subtract a set of [`Rectangle`]s from this one
+    ///
+    /// Returns a list of non-overlapping rectangles covering the area of `self`
+    /// not contained in any of `others`.
+    pub fn subtract_rects(self, others: impl IntoIterator<Item = Self>) -> Vec<Self> {
+        others.into_iter().fold(vec![self], |remaining, other| {
+            remaining
+                .into_iter()
+                .flat_map(|rect| rect.subtract_rect(other))
+                .collect()
+        })
+    }
 }
 
 impl<N: Coordinate> Rectangle<N, Logical> {
@@ -991,6 +1174,28 @@ impl<N: Coordinate> Rectangle<N, Logical> {
             },
         }
     }
+
+    /// Convert this logical rectangle to physical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    #[inline]
+    pub fn to_physical_rounded(self, scale: f64, rounding: Rounding) -> Rectangle<N, Physical> {
+        self.to_f64().to_physical(scale).to_i32_rounded(rounding)
+    }
+
+    /// Convert this logical rectangle to buffer coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    #[inline]
+    pub fn to_buffer_rounded(
+        self,
+        scale: f64,
+        transformation: Transform,
+        area: &Size<N, Logical>,
+        rounding: Rounding,
+    ) -> Rectangle<N, Buffer> {
+        self.to_f64()
+            .to_buffer(scale, transformation, &area.to_f64())
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Rectangle<N, Physical> {
@@ -1002,6 +1207,13 @@ impl<N: Coordinate> Rectangle<N, Physical> {
             size: self.size.to_logical(scale),
         }
     }
+
+    /// Convert this physical rectangle to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    #[inline]
+    pub fn to_logical_rounded(self, scale: f64, rounding: Rounding) -> Rectangle<N, Logical> {
+        self.to_f64().to_logical(scale).to_i32_rounded(rounding)
+    }
 }
 
 impl<N: Coordinate> Rectangle<N, Buffer> {
@@ -1027,6 +1239,21 @@ impl<N: Coordinate> Rectangle<N, Buffer> {
             },
         }
     }
+
+    /// Convert this buffer rectangle to logical coordinate space according to a fractional scale factor,
+    /// rounding the result using the given strategy
+    #[inline]
+    pub fn to_logical_rounded(
+        self,
+        scale: f64,
+        transformation: Transform,
+        area: &Size<N, Buffer>,
+        rounding: Rounding,
+    ) -> Rectangle<N, Logical> {
+        self.to_f64()
+            .to_logical(scale, transformation, &area.to_f64())
+            .to_i32_rounded(rounding)
+    }
 }
 
 impl<N: fmt::Debug> fmt::Debug for Rectangle<N, Logical> {
@@ -1132,7 +1359,7 @@ impl Default for Transform {
 impl Transform {
     /// Inverts any 90-degree transformation into 270-degree transformations and vise versa.
     ///
-    /// Flipping is preserved and 180/Normal transformation are uneffected.
+    /// Flipped transformations are their own inverse and 180/Normal transformation are uneffected.
     pub fn invert(&self) -> Transform {
         match self {
             Transform::Normal => Transform::Normal,
@@ -1140,9 +1367,53 @@ impl Transform {
             Transform::_90 => Transform::_270,
             Transform::_180 => Transform::_180,
             Transform::_270 => Transform::_90,
-            Transform::Flipped90 => Transform::Flipped270,
+            Transform::Flipped90 => Transform::Flipped90,
             Transform::Flipped180 => Transform::Flipped180,
-            Transform::Flipped270 => Transform::Flipped90,
+            Transform::Flipped270 => Transform::Flipped270,
+        }
+    }
+
+    /// Composes this transformation with another one.
+    ///
+    /// The result is equivalent to applying `self` first and `other` afterwards, e.g. a buffer
+    /// transformation followed by an output transformation.
+    pub fn compose(&self, other: Transform) -> Transform {
+        let (rotation, flipped) = self.rotation_and_flip();
+        let (other_rotation, other_flipped) = other.rotation_and_flip();
+        // rotating after flipping is the same as rotating in the opposite direction before
+        let rotation = if flipped {
+            rotation + 4 - other_rotation
+        } else {
+            rotation + other_rotation
+        };
+        Transform::from_rotation_and_flip(rotation % 4, flipped != other_flipped)
+    }
+
+    // counter-clockwise quarter turns, applied before the optional flip
+    fn rotation_and_flip(&self) -> (u8, bool) {
+        match self {
+            Transform::Normal => (0, false),
+            Transform::_90 => (1, false),
+            Transform::_180 => (2, false),
+            Transform::_270 => (3, false),
+            Transform::Flipped => (0, true),
+            Transform::Flipped90 => (1, true),
+            Transform::Flipped180 => (2, true),
+            Transform::Flipped270 => (3, true),
+        }
+    }
+
+    fn from_rotation_and_flip(rotation: u8, flipped: bool) -> Transform {
+        match (rotation, flipped) {
+            (0, false) => Transform::Normal,
+            (1, false) => Transform::_90,
+            (2, false) => Transform::_180,
+            (3, false) => Transform::_270,
+            (0, true) => Transform::Flipped,
+            (1, true) => Transform::Flipped90,
+            (2, true) => Transform::Flipped180,
+            (3, true) => Transform::Flipped270,
+            _ => unreachable!(),
         }
     }
 
@@ -1210,7 +1481,105 @@ impl Transform {
 
 #[cfg(test)]
 mod tests {
-    use super::{Logical, Rectangle, Size, Transform};
+    use super::{Buffer, Logical, Physical, Point, Rectangle, Rounding, Size, Transform};
+
+    #[test]
+    fn subtract_rect_disjoint() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((0, 0), (10, 10));
+        let other = Rectangle::from_loc_and_size((20, 20), (10, 10));
+
+        assert_eq!(vec![rect], rect.subtract_rect(other))
+    }
+
+    #[test]
+    fn subtract_rect_contained() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((10, 10), (10, 10));
+        let other = Rectangle::from_loc_and_size((0, 0), (30, 30));
+
+        assert!(rect.subtract_rect(other).is_empty())
+    }
+
+    #[test]
+    fn subtract_rect_center() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((0, 0), (30, 30));
+        let other = Rectangle::from_loc_and_size((10, 10), (10, 10));
+
+        assert_eq!(
+            vec![
+                Rectangle::from_loc_and_size((0, 0), (30, 10)),
+                Rectangle::from_loc_and_size((0, 20), (30, 10)),
+                Rectangle::from_loc_and_size((0, 10), (10, 10)),
+                Rectangle::from_loc_and_size((20, 10), (10, 10)),
+            ],
+            rect.subtract_rect(other)
+        )
+    }
+
+    #[test]
+    fn subtract_rects_area() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((0, 0), (30, 30));
+        let others = vec![
+            Rectangle::from_loc_and_size((0, 0), (15, 30)),
+            Rectangle::from_loc_and_size((10, 10), (30, 10)),
+        ];
+
+        let remaining = rect.subtract_rects(others);
+        let area: i32 = remaining.iter().map(|r| r.size.w * r.size.h).sum();
+        assert_eq!(15 * 30 - 15 * 10, area);
+    }
+
+    const TRANSFORMS: [Transform; 8] = [
+        Transform::Normal,
+        Transform::_90,
+        Transform::_180,
+        Transform::_270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    #[test]
+    fn transform_compose_applies_both() {
+        let area = Size::<i32, Logical>::from((70, 30));
+        let point = Point::<i32, Logical>::from((10, 20));
+        for first in TRANSFORMS {
+            for second in TRANSFORMS {
+                let composed = first.compose(second);
+                assert_eq!(
+                    second.transform_point_in(
+                        first.transform_point_in(point, &area),
+                        &first.transform_size(area)
+                    ),
+                    composed.transform_point_in(point, &area),
+                    "{:?} composed with {:?}",
+                    first,
+                    second
+                );
+                assert_eq!(
+                    second.transform_size(first.transform_size(area)),
+                    composed.transform_size(area)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transform_invert_round_trips() {
+        let area = Size::<i32, Logical>::from((70, 30));
+        let point = Point::<i32, Logical>::from((10, 20));
+        for transform in TRANSFORMS {
+            assert_eq!(transform.compose(transform.invert()), Transform::Normal);
+            assert_eq!(transform.invert().compose(transform), Transform::Normal);
+            let transformed = transform.transform_point_in(point, &area);
+            assert_eq!(
+                transform
+                    .invert()
+                    .transform_point_in(transformed, &transform.transform_size(area)),
+                point
+            );
+        }
+    }
 
     #[test]
     fn transform_rect_ident() {
@@ -1304,4 +1673,82 @@ mod tests {
             transform.transform_rect_in(rect, &size)
         )
     }
+
+    #[test]
+    fn point_to_physical_rounded_negative() {
+        let point = Point::<i32, Logical>::from((-1, -3));
+
+        assert_eq!(
+            Point::from((-2, -5)),
+            point.to_physical_rounded(1.5, Rounding::Floor)
+        );
+        assert_eq!(
+            Point::from((-1, -4)),
+            point.to_physical_rounded(1.5, Rounding::Ceil)
+        );
+        assert_eq!(
+            Point::from((-2, -5)),
+            point.to_physical_rounded(1.5, Rounding::Round)
+        );
+    }
+
+    #[test]
+    fn rect_to_physical_rounded_negative() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((-3, -3), (5, 5));
+
+        // unrounded: (-4.5, -4.5) to (3.0, 3.0)
+        assert_eq!(
+            Rectangle::<i32, Physical>::from_loc_and_size((-4, -4), (7, 7)),
+            rect.to_physical_rounded(1.5, Rounding::Floor)
+        );
+        assert_eq!(
+            Rectangle::<i32, Physical>::from_loc_and_size((-5, -5), (8, 8)),
+            rect.to_physical_rounded(1.5, Rounding::Ceil)
+        );
+        assert_eq!(
+            Rectangle::<i32, Physical>::from_loc_and_size((-5, -5), (8, 8)),
+            rect.to_physical_rounded(1.5, Rounding::Round)
+        );
+    }
+
+    #[test]
+    fn rect_to_logical_rounded_fractional() {
+        let rect = Rectangle::<i32, Physical>::from_loc_and_size((1, 1), (3, 3));
+
+        // unrounded: (0.67, 0.67) to (2.67, 2.67)
+        assert_eq!(
+            Rectangle::<i32, Logical>::from_loc_and_size((1, 1), (1, 1)),
+            rect.to_logical_rounded(1.5, Rounding::Floor)
+        );
+        assert_eq!(
+            Rectangle::<i32, Logical>::from_loc_and_size((0, 0), (3, 3)),
+            rect.to_logical_rounded(1.5, Rounding::Ceil)
+        );
+        assert_eq!(
+            Rectangle::<i32, Logical>::from_loc_and_size((1, 1), (2, 2)),
+            rect.to_logical_rounded(1.5, Rounding::Round)
+        );
+    }
+
+    #[test]
+    fn rect_to_buffer_rounded_transformed() {
+        let rect = Rectangle::<i32, Logical>::from_loc_and_size((1, 2), (3, 4));
+        let area = Size::from((10, 20));
+
+        // transformed: (14, 1) (4, 3), unrounded: (21.0, 1.5) to (27.0, 6.0)
+        let buffer = rect.to_buffer_rounded(1.5, Transform::_90, &area, Rounding::Ceil);
+        assert_eq!(
+            Rectangle::<i32, Buffer>::from_loc_and_size((21, 1), (6, 5)),
+            buffer
+        );
+
+        let area = area.to_buffer(2, Transform::_90);
+        let logical = Rectangle::<i32, Buffer>::from_loc_and_size((28, 2), (8, 6)).to_logical_rounded(
+            2.0,
+            Transform::_90,
+            &area,
+            Rounding::Round,
+        );
+        assert_eq!(rect, logical);
+    }
 }
@@ -1,16 +1,22 @@
 //! Various utilities functions and types
 
+pub mod frame_clock;
 mod geometry;
 pub mod signaling;
 
 #[cfg(feature = "x11rb_event_source")]
 pub mod x11rb;
 
+#[cfg(feature = "xcursor")]
+pub mod cursor_theme;
+
 #[cfg(feature = "desktop")]
 pub(crate) mod ids;
 pub mod user_data;
 
-pub use self::geometry::{Buffer, Coordinate, Logical, Physical, Point, Raw, Rectangle, Size, Transform};
+pub use self::geometry::{
+    Buffer, Coordinate, Logical, Physical, Point, Raw, Rectangle, Rounding, Size, Transform,
+};
 
 /// This resource is not managed by Smithay
 #[derive(Debug)]
@@ -0,0 +1,169 @@
+//! Per-surface color metadata
+//!
+//! HDR and wide-color aware clients may describe the colorimetry of their buffer contents,
+//! e.g. the color primaries, the transfer function and the luminance of the display the content
+//! was mastered on. This module provides the per-surface state to store this metadata, so it can
+//! be used by the renderer to tag an output or to tone-map the contents. Drawing a surface passes
+//! its metadata to the renderer using [`Frame::set_color_metadata`](crate::backend::renderer::Frame::set_color_metadata).
+//!
+//! The wayland-protocols version currently used by smithay does not provide bindings for a
+//! color-management protocol yet, so this module does not provide a global. Instead a protocol
+//! implementation (or any other compositor-specific mechanism) is expected to set the pending
+//! metadata of a surface using [`set_color_metadata`]. Like other surface state it is double-buffered
+//! and only applied on the next commit of the surface.
+//!
+//! Surfaces without any metadata set are expected to contain sRGB contents and are unaffected.
+//!
+//! ```
+//! # extern crate wayland_server;
+//! # use wayland_server::protocol::wl_surface::WlSurface;
+//! use smithay::wayland::color_management::*;
+//!
+//! # fn dummy_function(surface: &WlSurface) {
+//! // while handling the client request
+//! set_color_metadata(
+//!     surface,
+//!     Some(ColorMetadata {
+//!         primaries: Primaries::Bt2020,
+//!         transfer_function: TransferFunction::Pq,
+//!         mastering_luminance: Some(MasteringLuminance { min: 0.005, max: 1000.0 }),
+//!         ..Default::default()
+//!     }),
+//! );
+//!
+//! // after the surface was committed, while rendering
+//! if let Some(_metadata) = color_metadata(surface) {
+//!     /* tag the output or tone-map the contents */
+//! }
+//! # }
+//! ```
+
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use super::compositor::{with_states, Cacheable};
+
+/// Color primaries of the contents of a surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primaries {
+    /// ITU-R BT.709 / sRGB primaries
+    Srgb,
+    /// ITU-R BT.2020 primaries
+    Bt2020,
+    /// DCI-P3 primaries with a D65 white point
+    DisplayP3,
+    /// Adobe RGB (1998) primaries
+    AdobeRgb,
+    /// Custom primaries given as CIE 1931 xy chromaticity coordinates
+    Custom {
+        /// Chromaticity of the red primary
+        red: (f64, f64),
+        /// Chromaticity of the green primary
+        green: (f64, f64),
+        /// Chromaticity of the blue primary
+        blue: (f64, f64),
+        /// Chromaticity of the white point
+        white: (f64, f64),
+    },
+}
+
+/// Transfer function of the contents of a surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransferFunction {
+    /// The sRGB transfer function
+    Srgb,
+    /// A pure power function with a gamma of 2.2
+    Gamma22,
+    /// Linear encoding
+    Linear,
+    /// SMPTE ST 2084 perceptual quantizer
+    Pq,
+    /// ARIB STD-B67 hybrid log-gamma
+    Hlg,
+}
+
+/// Luminance range of the display the contents were mastered on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasteringLuminance {
+    /// Minimum luminance in cd/m²
+    pub min: f64,
+    /// Maximum luminance in cd/m²
+    pub max: f64,
+}
+
+/// Color metadata describing the contents of a surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMetadata {
+    /// Color primaries of the contents
+    pub primaries: Primaries,
+    /// Transfer function of the contents
+    pub transfer_function: TransferFunction,
+    /// Color primaries of the display the contents were mastered on, if known
+    pub mastering_primaries: Option<Primaries>,
+    /// Luminance range of the display the contents were mastered on, if known
+    pub mastering_luminance: Option<MasteringLuminance>,
+    /// Maximum content light level in cd/m², if known
+    pub max_cll: Option<u32>,
+    /// Maximum frame-average light level in cd/m², if known
+    pub max_fall: Option<u32>,
+}
+
+impl Default for ColorMetadata {
+    fn default() -> Self {
+        ColorMetadata {
+            primaries: Primaries::Srgb,
+            transfer_function: TransferFunction::Srgb,
+            mastering_primaries: None,
+            mastering_luminance: None,
+            max_cll: None,
+            max_fall: None,
+        }
+    }
+}
+
+impl ColorMetadata {
+    /// Returns true, if the described contents use a high dynamic range transfer function
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.transfer_function,
+            TransferFunction::Pq | TransferFunction::Hlg
+        )
+    }
+}
+
+/// Double-buffered color state of a surface
+///
+/// Can be accessed through the `cached_state` of the surfaces [`SurfaceData`](super::compositor::SurfaceData).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ColorManagementState {
+    /// Color metadata of the surface, `None` for sRGB contents
+    pub metadata: Option<ColorMetadata>,
+}
+
+impl Cacheable for ColorManagementState {
+    fn commit(&mut self) -> Self {
+        *self
+    }
+    fn merge_into(self, into: &mut Self) {
+        *into = self;
+    }
+}
+
+/// Set the pending color metadata of a surface
+///
+/// It will be applied on the next commit of the surface. Passing `None` resets the surface to sRGB.
+pub fn set_color_metadata(surface: &WlSurface, metadata: Option<ColorMetadata>) {
+    let _ = with_states(surface, |states| {
+        states.cached_state.pending::<ColorManagementState>().metadata = metadata;
+    });
+}
+
+/// Returns the current color metadata of a surface
+///
+/// Returns `None` for surfaces with sRGB contents or if the surface was already destroyed.
+pub fn color_metadata(surface: &WlSurface) -> Option<ColorMetadata> {
+    with_states(surface, |states| {
+        states.cached_state.current::<ColorManagementState>().metadata
+    })
+    .ok()
+    .flatten()
+}
@@ -79,6 +79,13 @@ impl SurfaceImplem {
     ) {
         match req {
             wl_surface::Request::Attach { buffer, x, y } => {
+                if surface.as_ref().version() >= 5 && (x != 0 || y != 0) {
+                    surface.as_ref().post_error(
+                        wl_surface::Error::InvalidOffset as u32,
+                        "Passing a non-zero offset to attach is invalid, use wl_surface.offset".into(),
+                    );
+                    return;
+                }
                 PrivateSurfaceData::with_states(&surface, |states| {
                     states.cached_state.pending::<SurfaceAttributes>().buffer = Some(match buffer {
                         Some(buffer) => BufferAssignment::NewBuffer {
@@ -89,6 +96,13 @@ impl SurfaceImplem {
                     })
                 });
             }
+            wl_surface::Request::Offset { x, y } => {
+                PrivateSurfaceData::with_states(&surface, |states| {
+                    let mut attributes = states.cached_state.pending::<SurfaceAttributes>();
+                    attributes.buffer_delta =
+                        Some(attributes.buffer_delta.unwrap_or_default() + (x, y).into());
+                });
+            }
             wl_surface::Request::Damage { x, y, width, height } => {
                 PrivateSurfaceData::with_states(&surface, |states| {
                     states
@@ -136,6 +150,7 @@ impl SurfaceImplem {
                     return;
                 }
                 PrivateSurfaceData::commit(&surface);
+                PrivateSurfaceData::invoke_post_commit_hooks(&surface);
                 trace!(self.log, "Calling user implementation for wl_surface.commit");
                 (&mut *user_impl)(surface, ddata);
             }
@@ -178,6 +193,7 @@ impl Cacheable for SurfaceAttributes {
             buffer: self.buffer.take(),
             buffer_scale: self.buffer_scale,
             buffer_transform: self.buffer_transform,
+            buffer_delta: self.buffer_delta.take(),
             damage: std::mem::take(&mut self.damage),
             opaque_region: self.opaque_region.clone(),
             input_region: self.input_region.clone(),
@@ -194,6 +210,10 @@ impl Cacheable for SurfaceAttributes {
         }
         into.buffer_scale = self.buffer_scale;
         into.buffer_transform = self.buffer_transform;
+        if let Some(delta) = self.buffer_delta {
+            // offsets accumulate until they are processed
+            into.buffer_delta = Some(into.buffer_delta.unwrap_or_default() + delta);
+        }
         into.damage.extend(self.damage);
         into.opaque_region = self.opaque_region;
         into.input_region = self.input_region;
@@ -67,13 +67,28 @@
 //! 2. The pending state is either applied and made current, or cached for later application
 //!    is the surface is a synchronize subsurface. If the current state is applied, state
 //!    of the synchronized children subsurface are applied as well at this point.
-//! 3. Your user callback provided to [`compositor_init`] is invoked, so that you can access
+//! 3. Post-commit hooks registered to this surface using [`add_post_commit_hook`] are invoked.
+//!    They can react to the newly applied state, e.g. to detect a buffer change. If the surface
+//!    is a sync subsurface its state was only cached, hooks can check this with [`is_sync_subsurface`].
+//! 4. Your user callback provided to [`compositor_init`] is invoked, so that you can access
 //!    the new current state of the surface. The state of sync children subsurfaces of your
 //!    surface may have changed as well, so this is the place to check it, using functions
 //!    like [`with_surface_tree_upward`] or [`with_surface_tree_downward`]. On the other hand,
 //!    if the surface is a sync subsurface, its current state will note have changed as
 //!    the result of that commit. You can check if it is using [`is_sync_subsurface`].
 //!
+//! ### Synchronized subsurfaces
+//!
+//! Subsurfaces follow the commit semantics of `wl_subsurface`:
+//!
+//! - A subsurface is synchronized by default (or after `set_sync`). Commits of such a subsurface
+//!   only cache its pending state. The cached state is applied atomically together with the next
+//!   state of its parent surface, that gets applied.
+//! - A desynchronized subsurface (after `set_desync`) applies its state immediately on commit.
+//!   If cached state from a previous synchronized commit exists, it is applied together with it.
+//! - A subsurface is *effectively* synchronized, if it or any of its ancestors is synchronized.
+//!   In this case it behaves like a synchronized subsurface, regardless of its own mode.
+//!
 //! ### Surface roles
 //!
 //! The wayland protocol specifies that a surface needs to be assigned a role before it can
@@ -92,7 +107,7 @@ mod tree;
 pub use self::cache::{Cacheable, MultiCache};
 pub use self::handlers::SubsurfaceCachedState;
 use self::tree::PrivateSurfaceData;
-pub use self::tree::{AlreadyHasRole, TraversalAction};
+pub use self::tree::{AlreadyHasRole, TraversalAction, WrongRole};
 use crate::utils::{Buffer, DeadResource, Logical, Point, Rectangle};
 use wayland_server::{
     protocol::{
@@ -182,6 +197,12 @@ pub struct SurfaceAttributes {
     /// If it matches the one of the output displaying this surface, no change
     /// is necessary.
     pub buffer_transform: wl_output::Transform,
+    /// Offset of the new contents relative to the current ones
+    ///
+    /// Set by the client using `wl_surface.offset`, available since version 5 of `wl_surface`.
+    /// Like the `delta` of an attached buffer, it moves the top-left corner of the surface.
+    /// It is `None` if the client did not request an offset since it was last processed.
+    pub buffer_delta: Option<Point<i32, Logical>>,
     /// Region of the surface that is guaranteed to be opaque
     ///
     /// By default the whole surface is potentially transparent
@@ -219,6 +240,7 @@ impl Default for SurfaceAttributes {
             buffer: None,
             buffer_scale: 1,
             buffer_transform: wl_output::Transform::Normal,
+            buffer_delta: None,
             opaque_region: None,
             input_region: None,
             damage: Vec::new(),
@@ -227,6 +249,21 @@ impl Default for SurfaceAttributes {
     }
 }
 
+impl SurfaceAttributes {
+    // takes the offsets given with `wl_surface.attach` and `wl_surface.offset`,
+    // for roles positioning the surface relative to a point, like cursors
+    pub(crate) fn take_offset(&mut self) -> Option<Point<i32, Logical>> {
+        let attach_delta = match self.buffer {
+            Some(BufferAssignment::NewBuffer { ref mut delta, .. }) => Some(std::mem::take(delta)),
+            _ => None,
+        };
+        match (attach_delta, self.buffer_delta.take()) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
 /// Kind of a rectangle part of a region
 #[derive(Copy, Clone, Debug)]
 pub enum RectangleKind {
@@ -373,6 +410,19 @@ pub fn give_role(surface: &WlSurface, role: &'static str) -> Result<(), AlreadyH
     PrivateSurfaceData::set_role(surface, role)
 }
 
+/// Remove the given role from this surface
+///
+/// The surface can then be given a new role, for example a drag'n'drop icon can be reused once
+/// the drag'n'drop has ended.
+///
+/// Fails if the surface does not currently have this role.
+pub fn clear_role(surface: &WlSurface, role: &'static str) -> Result<(), WrongRole> {
+    if !surface.as_ref().is_alive() {
+        return Ok(());
+    }
+    PrivateSurfaceData::clear_role(surface, role)
+}
+
 /// Access the states associated to this surface
 pub fn with_states<F, T>(surface: &WlSurface, f: F) -> Result<T, DeadResource>
 where
@@ -398,11 +448,33 @@ pub fn get_region_attributes(region: &wl_region::WlRegion) -> RegionAttributes {
 /// Register a commit hook to be invoked on surface commit
 ///
 /// For its precise semantics, see module-level documentation.
-pub fn add_commit_hook(surface: &WlSurface, hook: fn(&WlSurface)) {
+///
+/// The hook is kept until the surface is destroyed, it should not hold on to the surface
+/// itself, which is passed to it on every invocation instead.
+pub fn add_commit_hook<F>(surface: &WlSurface, hook: F)
+where
+    F: Fn(&WlSurface) + 'static,
+{
+    if !surface.as_ref().is_alive() {
+        return;
+    }
+    PrivateSurfaceData::add_commit_hook(surface, Rc::new(hook))
+}
+
+/// Register a commit hook to be invoked after the state of the surface was applied on commit
+///
+/// For its precise semantics, see module-level documentation.
+///
+/// The hook is kept until the surface is destroyed, it should not hold on to the surface
+/// itself, which is passed to it on every invocation instead.
+pub fn add_post_commit_hook<F>(surface: &WlSurface, hook: F)
+where
+    F: Fn(&WlSurface) + 'static,
+{
     if !surface.as_ref().is_alive() {
         return;
     }
-    PrivateSurfaceData::add_commit_hook(surface, hook)
+    PrivateSurfaceData::add_post_commit_hook(surface, Rc::new(hook))
 }
 
 /// Create new [`wl_compositor`](wayland_server::protocol::wl_compositor)
@@ -426,7 +498,7 @@ where
     let implem = Rc::new(RefCell::new(implem));
 
     let compositor = display.create_global(
-        4,
+        5,
         Filter::new(move |(new_compositor, _version), _, _| {
             self::handlers::implement_compositor::<Impl>(new_compositor, log.clone(), implem.clone());
         }),
@@ -4,7 +4,11 @@ use super::{
     cache::MultiCache, get_children, handlers::is_effectively_sync, transaction::PendingTransaction,
     SurfaceData,
 };
-use std::sync::{atomic::Ordering, Mutex};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{atomic::Ordering, Mutex},
+};
 use wayland_server::protocol::wl_surface::WlSurface;
 
 pub(crate) static SUBSURFACE_ROLE: &str = "subsurface";
@@ -28,7 +32,15 @@ pub struct PrivateSurfaceData {
     public_data: SurfaceData,
     pending_transaction: PendingTransaction,
     current_txid: Serial,
-    commit_hooks: Vec<fn(&WlSurface)>,
+}
+
+type CommitHook = Rc<dyn Fn(&WlSurface)>;
+
+// Hooks of a surface, they are not `Send` and thus stored in its (non-threadsafe) data map
+#[derive(Default)]
+struct CommitHooks {
+    pre: Vec<CommitHook>,
+    post: Vec<CommitHook>,
 }
 
 /// An error type signifying that the surface already has a role and
@@ -47,6 +59,19 @@ impl std::fmt::Display for AlreadyHasRole {
 
 impl std::error::Error for AlreadyHasRole {}
 
+/// An error type signifying that the surface does not have the role
+/// it was expected to have
+#[derive(Debug)]
+pub struct WrongRole;
+
+impl std::fmt::Display for WrongRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Surface does not have the requested role.")
+    }
+}
+
+impl std::error::Error for WrongRole {}
+
 pub enum Location {
     Before,
     After,
@@ -75,7 +100,6 @@ impl PrivateSurfaceData {
             },
             pending_transaction: Default::default(),
             current_txid: Serial(0),
-            commit_hooks: Vec::new(),
         })
     }
 
@@ -142,6 +166,20 @@ impl PrivateSurfaceData {
         Ok(())
     }
 
+    pub fn clear_role(surface: &WlSurface, role: &'static str) -> Result<(), WrongRole> {
+        let my_data_mutex = surface
+            .as_ref()
+            .user_data()
+            .get::<Mutex<PrivateSurfaceData>>()
+            .unwrap();
+        let mut my_data = my_data_mutex.lock().unwrap();
+        if my_data.public_data.role != Some(role) {
+            return Err(WrongRole);
+        }
+        my_data.public_data.role = None;
+        Ok(())
+    }
+
     pub fn get_role(surface: &WlSurface) -> Option<&'static str> {
         let my_data_mutex = surface
             .as_ref()
@@ -162,27 +200,41 @@ impl PrivateSurfaceData {
         f(&my_data.public_data)
     }
 
-    pub fn add_commit_hook(surface: &WlSurface, hook: fn(&WlSurface)) {
+    fn with_commit_hooks<F, T>(surface: &WlSurface, f: F) -> T
+    where
+        F: FnOnce(&mut CommitHooks) -> T,
+    {
         let my_data_mutex = surface
             .as_ref()
             .user_data()
             .get::<Mutex<PrivateSurfaceData>>()
             .unwrap();
-        let mut my_data = my_data_mutex.lock().unwrap();
-        my_data.commit_hooks.push(hook);
+        let my_data = my_data_mutex.lock().unwrap();
+        let data_map = &my_data.public_data.data_map;
+        data_map.insert_if_missing(|| RefCell::new(CommitHooks::default()));
+        let mut hooks = data_map.get::<RefCell<CommitHooks>>().unwrap().borrow_mut();
+        f(&mut *hooks)
+    }
+
+    pub fn add_commit_hook(surface: &WlSurface, hook: CommitHook) {
+        Self::with_commit_hooks(surface, |hooks| hooks.pre.push(hook));
+    }
+
+    pub fn add_post_commit_hook(surface: &WlSurface, hook: CommitHook) {
+        Self::with_commit_hooks(surface, |hooks| hooks.post.push(hook));
+    }
+
+    pub fn invoke_post_commit_hooks(surface: &WlSurface) {
+        // don't hold the mutex while the hooks are invoked
+        let hooks = Self::with_commit_hooks(surface, |hooks| hooks.post.clone());
+        for hook in hooks {
+            hook(surface);
+        }
     }
 
     pub fn invoke_commit_hooks(surface: &WlSurface) {
         // don't hold the mutex while the hooks are invoked
-        let hooks = {
-            let my_data_mutex = surface
-                .as_ref()
-                .user_data()
-                .get::<Mutex<PrivateSurfaceData>>()
-                .unwrap();
-            let my_data = my_data_mutex.lock().unwrap();
-            my_data.commit_hooks.clone()
-        };
+        let hooks = Self::with_commit_hooks(surface, |hooks| hooks.pre.clone());
         for hook in hooks {
             hook(surface);
         }
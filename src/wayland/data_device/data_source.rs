@@ -1,13 +1,21 @@
-use std::{cell::RefCell, ops::Deref as _};
+use std::{
+    cell::RefCell,
+    ops::Deref as _,
+    rc::{Rc, Weak},
+};
 
 use wayland_server::{
     protocol::{
         wl_data_device_manager::DndAction,
-        wl_data_source::{Request, WlDataSource},
+        wl_data_source::{self, Request, WlDataSource},
     },
-    Main,
+    Filter, Main,
 };
 
+use crate::wayland::seat::SeatRc;
+
+use super::{DataDeviceEvent, ResourceLimits};
+
 /// The metadata describing a data source
 #[derive(Debug, Clone)]
 pub struct SourceMetadata {
@@ -17,12 +25,50 @@ pub struct SourceMetadata {
     pub dnd_action: DndAction,
 }
 
-pub(crate) fn implement_data_source(src: Main<WlDataSource>) -> WlDataSource {
-    src.quick_assign(|me, req, _| {
+/// Errors that can happen when accessing the metadata of a data source
+#[derive(Debug, thiserror::Error)]
+pub enum SourceMetadataError {
+    /// The data source has been destroyed by its client
+    #[error("The data source has been destroyed")]
+    Dead,
+    /// The data source has no metadata, it was not created by Smithay
+    #[error("The data source has no metadata")]
+    NoMetadata,
+}
+
+// The seats this data source was set as the selection of
+pub(super) struct SelectionSeats(pub(super) RefCell<Vec<Weak<SeatRc>>>);
+
+pub(crate) fn implement_data_source(
+    src: Main<WlDataSource>,
+    callback: Rc<RefCell<dyn FnMut(DataDeviceEvent) + 'static>>,
+    limits: ResourceLimits,
+) -> WlDataSource {
+    src.quick_assign(move |me, req, _| {
         let data: &RefCell<SourceMetadata> = me.as_ref().user_data().get().unwrap();
         let mut guard = data.borrow_mut();
         match req {
-            Request::Offer { mime_type } => guard.mime_types.push(mime_type),
+            Request::Offer { mime_type } => {
+                if guard.mime_types.len() >= limits.max_mime_types {
+                    me.as_ref().post_error(
+                        wl_data_source::Error::InvalidSource as u32,
+                        format!(
+                            "Too many mime types, at most {} per source.",
+                            limits.max_mime_types
+                        ),
+                    );
+                } else if mime_type.len() > limits.max_mime_type_length {
+                    me.as_ref().post_error(
+                        wl_data_source::Error::InvalidSource as u32,
+                        format!(
+                            "Mime type too long, at most {} bytes.",
+                            limits.max_mime_type_length
+                        ),
+                    );
+                } else {
+                    guard.mime_types.push(mime_type);
+                }
+            }
             Request::SetActions { dnd_actions } => {
                 guard.dnd_action = dnd_actions;
             }
@@ -36,17 +82,29 @@ pub(crate) fn implement_data_source(src: Main<WlDataSource>) -> WlDataSource {
             dnd_action: DndAction::None,
         })
     });
+    src.as_ref()
+        .user_data()
+        .set(|| SelectionSeats(RefCell::new(Vec::new())));
+    src.assign_destructor(Filter::new(move |src: WlDataSource, _, _| {
+        super::source_destroyed(&src, &callback)
+    }));
 
     src.deref().clone()
 }
 
 /// Access the metadata of a data source
+///
+/// Fails with [`SourceMetadataError::Dead`] if the source was destroyed, and with
+/// [`SourceMetadataError::NoMetadata`] if it was not created through Smithay.
 pub fn with_source_metadata<T, F: FnOnce(&SourceMetadata) -> T>(
     source: &WlDataSource,
     f: F,
-) -> Result<T, crate::utils::UnmanagedResource> {
+) -> Result<T, SourceMetadataError> {
+    if !source.as_ref().is_alive() {
+        return Err(SourceMetadataError::Dead);
+    }
     match source.as_ref().user_data().get::<RefCell<SourceMetadata>>() {
         Some(data) => Ok(f(&data.borrow())),
-        None => Err(crate::utils::UnmanagedResource),
+        None => Err(SourceMetadataError::NoMetadata),
     }
 }
@@ -8,6 +8,7 @@ use wayland_server::{
 use crate::{
     utils::{Logical, Point},
     wayland::{
+        compositor,
         seat::{AxisFrame, PointerGrab, PointerGrabStartData, PointerInnerHandle, Seat},
         Serial,
     },
@@ -48,6 +49,54 @@ impl DnDGrab {
             seat,
         }
     }
+
+    // the icon is dismissed, its surface can be given a new role
+    fn clear_icon(&mut self) {
+        if let Some(icon) = self.icon.take() {
+            let _ = compositor::clear_role(&icon, super::DND_ICON_ROLE);
+        }
+    }
+
+    // the source was destroyed during the drag, e.g. because its client died
+    fn source_is_dead(&self) -> bool {
+        self.data_source
+            .as_ref()
+            .map(|source| !source.as_ref().is_alive())
+            .unwrap_or(false)
+    }
+
+    // abandon the drag'n'drop without a drop, ending the grab
+    fn cancel(&mut self, handle: &mut PointerInnerHandle<'_>, serial: Serial, time: u32) {
+        {
+            let mut seat_data = self
+                .seat
+                .user_data()
+                .get::<RefCell<SeatData>>()
+                .unwrap()
+                .borrow_mut();
+            if let Some(surface) = self.current_focus.take() {
+                for device in seat_data.devices_for(&surface) {
+                    device.leave();
+                }
+            }
+            seat_data.dnd_leave();
+        }
+        self.pending_offers.clear();
+        if let Some(offer_data) = self.offer_data.take() {
+            offer_data.borrow_mut().active = false;
+        }
+        (&mut *self.callback.borrow_mut())(super::DataDeviceEvent::DnDDropped {
+            seat: self.seat.clone(),
+        });
+        self.clear_icon();
+        handle.unset_grab(serial, time);
+    }
+}
+
+impl Drop for DnDGrab {
+    fn drop(&mut self) {
+        self.clear_icon();
+    }
 }
 
 impl PointerGrab for DnDGrab {
@@ -59,10 +108,15 @@ impl PointerGrab for DnDGrab {
         serial: Serial,
         time: u32,
     ) {
+        if self.source_is_dead() {
+            self.cancel(handle, serial, time);
+            return;
+        }
+
         // While the grab is active, no client has pointer focus
         handle.motion(location, None, serial, time);
 
-        let seat_data = self
+        let mut seat_data = self
             .seat
             .user_data()
             .get::<RefCell<SeatData>>()
@@ -73,11 +127,10 @@ impl PointerGrab for DnDGrab {
             if let Some(surface) = self.current_focus.take() {
                 // only leave if there is a data source or we are on the original client
                 if self.data_source.is_some() || self.origin.as_ref().same_client_as(surface.as_ref()) {
-                    for device in &seat_data.known_devices {
-                        if device.as_ref().same_client_as(surface.as_ref()) {
-                            device.leave();
-                        }
+                    for device in seat_data.devices_for(&surface) {
+                        device.leave();
                     }
+                    seat_data.dnd_leave();
                     // disable the offers
                     self.pending_offers.clear();
                     if let Some(offer_data) = self.offer_data.take() {
@@ -96,17 +149,12 @@ impl PointerGrab for DnDGrab {
             if self.current_focus.is_none() {
                 // We entered a new surface, send the data offer if appropriate
                 if let Some(ref source) = self.data_source {
-                    let offer_data = Rc::new(RefCell::new(OfferData {
-                        active: true,
-                        dropped: false,
-                        accepted: true,
-                        chosen_action: DndAction::empty(),
-                    }));
-                    for device in seat_data
-                        .known_devices
-                        .iter()
-                        .filter(|d| d.as_ref().same_client_as(surface.as_ref()))
-                    {
+                    let offer_data = Rc::new(RefCell::new(OfferData::new()));
+                    let devices = seat_data.devices_for(&surface).cloned().collect::<Vec<_>>();
+                    for device in devices {
+                        if !seat_data.allow_offer(&device) {
+                            break;
+                        }
                         let action_choice = device
                             .as_ref()
                             .user_data()
@@ -122,19 +170,34 @@ impl PointerGrab for DnDGrab {
                                     offer,
                                     source.clone(),
                                     offer_data.clone(),
-                                    action_choice,
+                                    action_choice.clone(),
                                 )
                             })
                             .unwrap();
                         // advertize the offer to the client
                         device.data_offer(&offer);
-                        with_source_metadata(source, |meta| {
+                        seat_data.track_offer(&offer);
+                        // a source without metadata has nothing to offer
+                        let _ = with_source_metadata(source, |meta| {
                             for mime_type in meta.mime_types.iter().cloned() {
                                 offer.offer(mime_type);
                             }
-                            offer.source_actions(meta.dnd_action);
-                        })
-                        .unwrap();
+                        });
+                        let target_actions = if offer.as_ref().version() >= 3 {
+                            offer.source_actions(source_actions(source));
+                            DndAction::empty()
+                        } else {
+                            // clients predating the dnd actions always copy
+                            DndAction::Copy
+                        };
+                        negotiate_action(
+                            &offer,
+                            source,
+                            &mut offer_data.borrow_mut(),
+                            &action_choice,
+                            target_actions,
+                            target_actions,
+                        );
                         device.enter(serial.into(), &surface, x, y, Some(&offer));
                         self.pending_offers.push(offer);
                     }
@@ -142,22 +205,17 @@ impl PointerGrab for DnDGrab {
                 } else {
                     // only send if we are on a surface of the same client
                     if self.origin.as_ref().same_client_as(surface.as_ref()) {
-                        for device in &seat_data.known_devices {
-                            if device.as_ref().same_client_as(surface.as_ref()) {
-                                device.enter(serial.into(), &surface, x, y, None);
-                            }
+                        for device in seat_data.devices_for(&surface) {
+                            device.enter(serial.into(), &surface, x, y, None);
                         }
                     }
                 }
+                seat_data.dnd_enter(time);
                 self.current_focus = Some(surface);
             } else {
                 // make a move
                 if self.data_source.is_some() || self.origin.as_ref().same_client_as(surface.as_ref()) {
-                    for device in &seat_data.known_devices {
-                        if device.as_ref().same_client_as(surface.as_ref()) {
-                            device.motion(time, x, y);
-                        }
-                    }
+                    seat_data.dnd_motion(&surface, time, x, y);
                 }
             }
         }
@@ -171,9 +229,14 @@ impl PointerGrab for DnDGrab {
         serial: Serial,
         time: u32,
     ) {
+        if self.source_is_dead() {
+            self.cancel(handle, serial, time);
+            return;
+        }
+
         if handle.current_pressed().is_empty() {
             // the user dropped, proceed to the drop
-            let seat_data = self
+            let mut seat_data = self
                 .seat
                 .user_data()
                 .get::<RefCell<SeatData>>()
@@ -187,13 +250,13 @@ impl PointerGrab for DnDGrab {
             };
             if let Some(ref surface) = self.current_focus {
                 if self.data_source.is_some() || self.origin.as_ref().same_client_as(surface.as_ref()) {
-                    for device in &seat_data.known_devices {
-                        if device.as_ref().same_client_as(surface.as_ref()) {
-                            if validated {
-                                device.drop();
-                            } else {
-                                device.leave();
-                            }
+                    // the target needs to know the final position before the drop
+                    seat_data.flush_dnd_motion();
+                    for device in seat_data.devices_for(&surface) {
+                        if validated {
+                            device.drop();
+                        } else {
+                            device.leave();
                         }
                     }
                 }
@@ -212,10 +275,11 @@ impl PointerGrab for DnDGrab {
                     source.cancelled();
                 }
             }
+            seat_data.dnd_leave();
             (&mut *self.callback.borrow_mut())(super::DataDeviceEvent::DnDDropped {
                 seat: self.seat.clone(),
             });
-            self.icon = None;
+            self.clear_icon();
             // in all cases abandon the drop
             // no more buttons are pressed, release the grab
             handle.unset_grab(serial, time);
@@ -232,11 +296,50 @@ impl PointerGrab for DnDGrab {
     }
 }
 
-struct OfferData {
-    active: bool,
-    dropped: bool,
-    accepted: bool,
-    chosen_action: DndAction,
+// state of a drag'n'drop shared by the offers sent to all data devices of the target client
+pub(super) struct OfferData {
+    pub(super) active: bool,
+    pub(super) dropped: bool,
+    pub(super) accepted: bool,
+    pub(super) finished: bool,
+    pub(super) chosen_action: DndAction,
+}
+
+impl OfferData {
+    pub(super) fn new() -> OfferData {
+        OfferData {
+            active: true,
+            dropped: false,
+            accepted: true,
+            finished: false,
+            chosen_action: DndAction::empty(),
+        }
+    }
+
+    // Validate a finish request of one of the offers
+    //
+    // Returns `Ok(true)` if the source needs to be notified and `Ok(false)` if the drag'n'drop
+    // was already finished through the offer of another data device.
+    pub(super) fn finish(&mut self) -> Result<bool, &'static str> {
+        if self.finished {
+            return Ok(false);
+        }
+        if !self.active {
+            return Err("Cannot finish a data offer that is no longer active.");
+        }
+        if !self.accepted {
+            return Err("Cannot finish a data offer that has not been accepted.");
+        }
+        if !self.dropped {
+            return Err("Cannot finish a data offer that has not been dropped.");
+        }
+        if self.chosen_action.is_empty() {
+            return Err("Cannot finish a data offer with no valid action.");
+        }
+        self.finished = true;
+        self.active = false;
+        Ok(true)
+    }
 }
 
 fn implement_dnd_data_offer(
@@ -251,11 +354,8 @@ fn implement_dnd_data_offer(
         match req {
             Request::Accept { mime_type, .. } => {
                 if let Some(mtype) = mime_type {
-                    if let Err(crate::utils::UnmanagedResource) = with_source_metadata(&source, |meta| {
-                        data.accepted = meta.mime_types.contains(&mtype);
-                    }) {
-                        data.accepted = false;
-                    }
+                    data.accepted = with_source_metadata(&source, |meta| meta.mime_types.contains(&mtype))
+                        .unwrap_or(false);
                 } else {
                     data.accepted = false;
                 }
@@ -272,44 +372,18 @@ fn implement_dnd_data_offer(
                 let _ = ::nix::unistd::close(fd);
             }
             Request::Destroy => {}
-            Request::Finish => {
-                if !data.active {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that is no longer active.".into(),
-                    );
-                    return;
-                }
-                if !data.accepted {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that has not been accepted.".into(),
-                    );
-                    return;
-                }
-                if !data.dropped {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that has not been dropped.".into(),
-                    );
-                    return;
-                }
-                if data.chosen_action.is_empty() {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer with no valid action.".into(),
-                    );
-                    return;
-                }
-                source.dnd_finished();
-                data.active = false;
-            }
+            Request::Finish => match data.finish() {
+                Ok(true) => source.dnd_finished(),
+                // already finished through another data device of the client
+                Ok(false) => {}
+                Err(msg) => offer
+                    .as_ref()
+                    .post_error(wl_data_offer::Error::InvalidFinish as u32, msg.into()),
+            },
             Request::SetActions {
                 dnd_actions,
                 preferred_action,
             } => {
-                let preferred_action = preferred_action;
-
                 // preferred_action must only contain one bitflag at the same time
                 if ![DndAction::None, DndAction::Move, DndAction::Copy, DndAction::Ask]
                     .contains(&preferred_action)
@@ -320,17 +394,17 @@ fn implement_dnd_data_offer(
                     );
                     return;
                 }
-                let source_actions = with_source_metadata(&source, |meta| meta.dnd_action)
-                    .unwrap_or_else(|_| DndAction::empty());
-                let possible_actions = source_actions & dnd_actions;
-                data.chosen_action = (&mut *action_choice.borrow_mut())(possible_actions, preferred_action);
-                // check that the user provided callback respects that one precise action should be chosen
-                debug_assert!(
-                    [DndAction::None, DndAction::Move, DndAction::Copy, DndAction::Ask]
-                        .contains(&data.chosen_action)
+                if !data.active {
+                    return;
+                }
+                negotiate_action(
+                    &offer,
+                    &source,
+                    &mut data,
+                    &action_choice,
+                    dnd_actions,
+                    preferred_action,
                 );
-                offer.action(data.chosen_action);
-                source.action(data.chosen_action);
             }
             _ => unreachable!(),
         }
@@ -338,3 +412,56 @@ fn implement_dnd_data_offer(
 
     offer.deref().clone()
 }
+
+// The actions supported by the source, sources predating the dnd actions only support copy
+fn source_actions(source: &wl_data_source::WlDataSource) -> DndAction {
+    if source.as_ref().version() >= 3 {
+        with_source_metadata(source, |meta| meta.dnd_action).unwrap_or_else(|_| DndAction::empty())
+    } else {
+        DndAction::Copy
+    }
+}
+
+// Choose the action of the drag'n'drop from the actions of the source and of the target,
+// and notify both of them of the result
+fn negotiate_action(
+    offer: &wl_data_offer::WlDataOffer,
+    source: &wl_data_source::WlDataSource,
+    data: &mut OfferData,
+    action_choice: &RefCell<dyn FnMut(DndAction, DndAction) -> DndAction + 'static>,
+    dnd_actions: DndAction,
+    preferred_action: DndAction,
+) {
+    let possible_actions = source_actions(source) & dnd_actions;
+    data.chosen_action = (&mut *action_choice.borrow_mut())(possible_actions, preferred_action);
+    // check that the user provided callback respects that one precise action should be chosen
+    debug_assert!(
+        [DndAction::None, DndAction::Move, DndAction::Copy, DndAction::Ask].contains(&data.chosen_action)
+    );
+    if offer.as_ref().version() >= 3 {
+        offer.action(data.chosen_action);
+    }
+    if source.as_ref().version() >= 3 {
+        source.action(data.chosen_action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OfferData;
+    use wayland_server::protocol::wl_data_device_manager::DndAction;
+
+    #[test]
+    fn drop_on_client_with_two_devices_is_finished_once() {
+        // both offers sent to the data devices of the client share the same state
+        let mut data = OfferData::new();
+        data.chosen_action = DndAction::Copy;
+        assert!(data.finish().is_err());
+
+        data.dropped = true;
+        assert_eq!(data.finish(), Ok(true));
+        // the offer of the second data device does not finish the drag'n'drop a second time
+        assert_eq!(data.finish(), Ok(false));
+        assert!(!data.active);
+    }
+}
@@ -22,16 +22,19 @@
 //!   to peek into the the actions of your clients
 //! - the freestanding function [`set_data_device_selection`]
 //!   allows you to set the contents of the selection for your clients
+//! - the freestanding functions [`export_selection`] and [`import_selection`] allow you to
+//!   save a compositor-provided selection and to restore it later, e.g. across a restart
 //! - the freestanding function [`start_dnd`] allows you to initiate a drag'n'drop event from the compositor
 //!   itself and receive interactions of clients with it via an other dedicated callback.
 //!
 //! The module defines the role `"dnd_icon"` that is assigned to surfaces used as drag'n'drop icons.
+//! Their offset relative to the pointer is tracked in their [`DnDIconAttributes`].
 //!
 //! ## Initialization
 //!
 //! ```
 //! # extern crate wayland_server;
-//! use smithay::wayland::data_device::{init_data_device, default_action_chooser};
+//! use smithay::wayland::data_device::{init_data_device, default_action_chooser, ResourceLimits};
 //! # use smithay::wayland::compositor::compositor_init;
 //!
 //! # let mut display = wayland_server::Display::new();
@@ -41,11 +44,17 @@
 //!     |dnd_event| { /* a callback to react to client DnD/selection actions */ },
 //!     default_action_chooser,  // a closure to choose the DnD action depending on clients
 //!                              // negociation
+//!     ResourceLimits::default(), // limits on the resources used by each client
 //!     None                     // insert a logger here
 //! );
 //! ```
 
-use std::{cell::RefCell, ops::Deref as _, os::unix::io::RawFd, rc::Rc};
+use std::{
+    cell::RefCell,
+    ops::Deref as _,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
 use wayland_server::{
     protocol::{
@@ -56,23 +65,58 @@ use wayland_server::{
     Client, Display, Filter, Global, Main,
 };
 
-use slog::{debug, error, o};
+use calloop::LoopHandle;
+use slog::{debug, error, o, warn};
 
-use crate::wayland::{
-    compositor,
-    seat::{PointerGrabStartData, Seat},
-    Serial,
+use crate::{
+    utils::{Logical, Point},
+    wayland::{
+        compositor,
+        seat::{PointerGrabStartData, Seat},
+        Serial,
+    },
 };
 
 mod data_source;
 mod dnd_grab;
+mod selection_writer;
 mod server_dnd_grab;
 
-pub use self::data_source::{with_source_metadata, SourceMetadata};
+use self::data_source::SelectionSeats;
+pub use self::data_source::{with_source_metadata, SourceMetadata, SourceMetadataError};
+pub use self::selection_writer::SelectionWriter;
 pub use self::server_dnd_grab::ServerDndEvent;
 
 static DND_ICON_ROLE: &str = "dnd_icon";
 
+/// Data associated with a surface used as a drag'n'drop icon
+///
+/// It is stored in the `data_map` of the surface as a `Mutex<DnDIconAttributes>`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DnDIconAttributes {
+    /// Offset of the icon relative to the pointer
+    ///
+    /// It is moved by the offset of the buffers attached to the surface.
+    pub offset: Point<i32, Logical>,
+}
+
+impl DnDIconAttributes {
+    // the offset of an attached buffer moves the icon
+    fn commit_hook(surface: &wl_surface::WlSurface) {
+        let _ = compositor::with_states(surface, |states| {
+            let delta = states
+                .cached_state
+                .current::<compositor::SurfaceAttributes>()
+                .take_offset();
+            if let (Some(delta), Some(attributes)) =
+                (delta, states.data_map.get::<Mutex<DnDIconAttributes>>())
+            {
+                attributes.lock().unwrap().offset += delta;
+            }
+        });
+    }
+}
+
 /// Events that are generated by interactions of the clients with the data device
 #[derive(Debug)]
 pub enum DataDeviceEvent {
@@ -93,6 +137,9 @@ pub enum DataDeviceEvent {
     },
     /// The drag'n'drop action was finished by the user releasing the buttons
     ///
+    /// This is also generated if the drag'n'drop was cancelled because its data source
+    /// was destroyed during the drag.
+    ///
     /// At this point, any pointer icon should be removed.
     ///
     /// Note that this event will only be generated for client-initiated drag'n'drop session.
@@ -104,15 +151,34 @@ pub enum DataDeviceEvent {
     SendSelection {
         /// the requested mime type
         mime_type: String,
-        /// the fd to write into
-        fd: RawFd,
+        /// the writer to send the selection contents into
+        ///
+        /// The writer owns the fd provided by the client and can be moved out of the callback
+        /// to fulfill the request later, for example from an other thread.
+        writer: SelectionWriter,
+    },
+    /// The data source of the current selection was destroyed by its client
+    ///
+    /// The selection of the seat is now empty.
+    SelectionCleared {
+        /// The seat whose selection was cleared
+        seat: Seat,
     },
 }
 
 enum Selection {
     Empty,
     Client(wl_data_source::WlDataSource),
-    Compositor(SourceMetadata),
+    // the contents are only known to us for selections restored with `import_selection`
+    Compositor(SourceMetadata, Option<Imported>),
+}
+
+// the contents of a selection restored with `import_selection`
+#[derive(Clone)]
+struct Imported {
+    contents: Arc<[u8]>,
+    // writes the contents from the event loop given to `import_selection`
+    write: Rc<dyn Fn(SelectionWriter, Vec<u8>)>,
 }
 
 struct SeatData {
@@ -120,6 +186,53 @@ struct SeatData {
     selection: Selection,
     log: ::slog::Logger,
     current_focus: Option<Client>,
+    limits: ResourceLimits,
+    offers: Offers,
+    dnd_motion: DndMotion,
+}
+
+// data offers created for clients, to enforce `ResourceLimits::max_offers`
+#[derive(Default)]
+struct Offers(Vec<wl_data_offer::WlDataOffer>);
+
+impl Offers {
+    // check if another offer can be created for the client of the device, killing it otherwise
+    //
+    // The protocol has no error for this, and the only error of wl_data_device is about surface
+    // roles, so the client is disconnected without a protocol error.
+    fn allow(&mut self, device: &wl_data_device::WlDataDevice, max: usize, log: &::slog::Logger) -> bool {
+        self.0.retain(|offer| offer.as_ref().is_alive());
+        let count = self
+            .0
+            .iter()
+            .filter(|offer| offer.as_ref().same_client_as(device.as_ref()))
+            .count();
+        if count >= max {
+            warn!(
+                log,
+                "Killing a client holding too many data offers, at most {} per seat.", max
+            );
+            if let Some(client) = device.as_ref().client() {
+                client.kill();
+            }
+            return false;
+        }
+        true
+    }
+
+    fn track(&mut self, offer: &wl_data_offer::WlDataOffer) {
+        self.0.push(offer.clone());
+    }
+}
+
+// coalescing of the motion events sent to the target of a drag'n'drop
+#[derive(Default)]
+struct DndMotion {
+    // minimum time between two motion events, 0 disables the coalescing
+    interval: u32,
+    last_sent: Option<u32>,
+    // latest motion held back: target surface, time and surface-local position
+    pending: Option<(wl_surface::WlSurface, u32, f64, f64)>,
 }
 
 impl SeatData {
@@ -134,14 +247,39 @@ impl SeatData {
     }
 
     fn send_selection(&mut self) {
-        let client = match self.current_focus.as_ref() {
+        self.send_selection_to(None);
+    }
+
+    // Send the selection to the data devices of the focused client, or only to the given one
+    fn send_selection_to(&mut self, only: Option<&wl_data_device::WlDataDevice>) {
+        let client = match self.current_focus.clone() {
             Some(c) => c,
             None => return,
         };
+        // skip data devices not belonging to our client
+        let skip = |dd: &wl_data_device::WlDataDevice| {
+            dd.as_ref().client().map(|c| !c.equals(&client)).unwrap_or(true)
+                || only
+                    .map(|only| !only.as_ref().equals(dd.as_ref()))
+                    .unwrap_or(false)
+        };
+        let devices = self
+            .known_devices
+            .iter()
+            .filter(|dd| !skip(dd))
+            .cloned()
+            .collect::<Vec<_>>();
+        let max_offers = self.limits.max_offers;
         // first sanitize the selection, reseting it to null if the client holding
-        // it dropped it
+        // it dropped it or its metadata is unavailable
         let cleanup = if let Selection::Client(ref data_source) = self.selection {
-            !data_source.as_ref().is_alive()
+            match with_source_metadata(data_source, |_| ()) {
+                Ok(()) => false,
+                Err(err) => {
+                    debug!(self.log, "Treating the selection as empty: {}", err);
+                    true
+                }
+            }
         } else {
             false
         };
@@ -152,19 +290,16 @@ impl SeatData {
         match self.selection {
             Selection::Empty => {
                 // send an empty selection
-                for dd in &self.known_devices {
-                    // skip data devices not belonging to our client
-                    if dd.as_ref().client().map(|c| !c.equals(client)).unwrap_or(true) {
-                        continue;
-                    }
+                for dd in &devices {
                     dd.selection(None);
                 }
             }
             Selection::Client(ref data_source) => {
-                for dd in &self.known_devices {
-                    // skip data devices not belonging to our client
-                    if dd.as_ref().client().map(|c| !c.equals(client)).unwrap_or(true) {
-                        continue;
+                let mime_types =
+                    with_source_metadata(data_source, |meta| meta.mime_types.clone()).unwrap_or_default();
+                for dd in &devices {
+                    if !self.offers.allow(dd, max_offers, &self.log) {
+                        return;
                     }
                     let source = data_source.clone();
                     let log = self.log.clone();
@@ -191,23 +326,21 @@ impl SeatData {
                     });
                     // advertize the offer to the client
                     dd.data_offer(&offer);
-                    with_source_metadata(data_source, |meta| {
-                        for mime_type in meta.mime_types.iter().cloned() {
-                            offer.offer(mime_type);
-                        }
-                    })
-                    .unwrap();
+                    self.offers.track(&offer);
+                    for mime_type in mime_types.iter().cloned() {
+                        offer.offer(mime_type);
+                    }
                     dd.selection(Some(&offer));
                 }
             }
-            Selection::Compositor(ref meta) => {
-                for dd in &self.known_devices {
-                    // skip data devices not belonging to our client
-                    if dd.as_ref().client().map(|c| !c.equals(client)).unwrap_or(true) {
-                        continue;
+            Selection::Compositor(ref meta, ref contents) => {
+                for dd in &devices {
+                    if !self.offers.allow(dd, max_offers, &self.log) {
+                        return;
                     }
                     let log = self.log.clone();
                     let offer_meta = meta.clone();
+                    let contents = contents.clone();
                     let callback = dd
                         .as_ref()
                         .user_data()
@@ -227,16 +360,19 @@ impl SeatData {
                                 // deny the receive
                                 debug!(log, "Denying a wl_data_offer.receive with invalid source.");
                                 let _ = ::nix::unistd::close(fd);
+                            } else if let Some(ref imported) = contents {
+                                (imported.write)(SelectionWriter::new(fd), imported.contents.to_vec());
                             } else {
                                 (&mut *callback.borrow_mut())(DataDeviceEvent::SendSelection {
                                     mime_type,
-                                    fd,
+                                    writer: SelectionWriter::new(fd),
                                 });
                             }
                         }
                     });
                     // advertize the offer to the client
                     dd.data_offer(&offer);
+                    self.offers.track(&offer);
                     for mime_type in meta.mime_types.iter().cloned() {
                         offer.offer(mime_type);
                     }
@@ -248,12 +384,150 @@ impl SeatData {
 }
 
 impl SeatData {
+    // All live data devices of the client owning the given surface
+    //
+    // A client may create several data devices for the same seat,
+    // all of them receive the drag'n'drop events.
+    fn devices_for<'a>(
+        &'a self,
+        surface: &'a wl_surface::WlSurface,
+    ) -> impl Iterator<Item = &'a wl_data_device::WlDataDevice> + 'a {
+        self.known_devices
+            .iter()
+            .filter(move |dd| dd.as_ref().is_alive() && dd.as_ref().same_client_as(surface.as_ref()))
+    }
+
+    // check if another offer can be created for the client of the device, killing it otherwise
+    fn allow_offer(&mut self, device: &wl_data_device::WlDataDevice) -> bool {
+        self.offers.allow(device, self.limits.max_offers, &self.log)
+    }
+
+    fn track_offer(&mut self, offer: &wl_data_offer::WlDataOffer) {
+        self.offers.track(offer);
+    }
+
+    // a drag'n'drop entered a surface at the given time, the enter event carries the position
+    fn dnd_enter(&mut self, time: u32) {
+        self.dnd_motion.pending = None;
+        self.dnd_motion.last_sent = Some(time);
+    }
+
+    // the drag'n'drop left its target, held back motion no longer matters
+    fn dnd_leave(&mut self) {
+        self.dnd_motion.pending = None;
+        self.dnd_motion.last_sent = None;
+    }
+
+    // send a drag'n'drop motion to the devices of the target, unless it needs to be held back
+    fn dnd_motion(&mut self, surface: &wl_surface::WlSurface, time: u32, x: f64, y: f64) {
+        let interval = self.dnd_motion.interval;
+        let throttled = self
+            .dnd_motion
+            .last_sent
+            .map(|last| time.wrapping_sub(last) < interval)
+            .unwrap_or(false);
+        if throttled {
+            self.dnd_motion.pending = Some((surface.clone(), time, x, y));
+        } else {
+            self.dnd_motion.pending = None;
+            self.dnd_motion.last_sent = Some(time);
+            for device in self.devices_for(surface) {
+                device.motion(time, x, y);
+            }
+        }
+    }
+
+    // send the motion held back, if any
+    fn flush_dnd_motion(&mut self) {
+        if let Some((surface, time, x, y)) = self.dnd_motion.pending.take() {
+            self.dnd_motion.last_sent = Some(time);
+            for device in self.devices_for(&surface) {
+                device.motion(time, x, y);
+            }
+        }
+    }
+
     fn new(log: ::slog::Logger) -> SeatData {
         SeatData {
             known_devices: Vec::new(),
             selection: Selection::Empty,
             log,
             current_focus: None,
+            limits: ResourceLimits::default(),
+            offers: Offers::default(),
+            dnd_motion: DndMotion::default(),
+        }
+    }
+
+    // Number of live data devices created through the given manager's client
+    fn devices_of_client(&self, ddm: &wl_data_device_manager::WlDataDeviceManager) -> usize {
+        self.known_devices
+            .iter()
+            .filter(|dd| dd.as_ref().is_alive() && dd.as_ref().same_client_as(ddm.as_ref()))
+            .count()
+    }
+}
+
+// Clear the selection of the seats still holding this source
+fn source_destroyed(
+    source: &wl_data_source::WlDataSource,
+    callback: &Rc<RefCell<dyn FnMut(DataDeviceEvent) + 'static>>,
+) {
+    let seats = match source.as_ref().user_data().get::<SelectionSeats>() {
+        Some(seats) => seats.0.take(),
+        None => return,
+    };
+    for seat in seats.into_iter().filter_map(|weak| weak.upgrade()) {
+        let seat = Seat { arc: seat };
+        let seat_data = match seat.user_data().get::<RefCell<SeatData>>() {
+            Some(seat_data) => seat_data,
+            None => continue,
+        };
+        let is_selection = matches!(
+            seat_data.borrow().selection,
+            Selection::Client(ref current) if current.as_ref().equals(source.as_ref())
+        );
+        if is_selection {
+            seat_data.borrow_mut().set_selection(Selection::Empty);
+            (&mut *callback.borrow_mut())(DataDeviceEvent::SelectionCleared { seat: seat.clone() });
+        }
+    }
+}
+
+/// Default maximum number of data devices a client can create for a seat
+///
+/// See [`ResourceLimits::max_data_devices`].
+pub const DEFAULT_MAX_DATA_DEVICES_PER_CLIENT: usize = 32;
+
+/// Limits on the resources each client can use through the data device manager
+///
+/// Clients exceeding any of them are killed, with a protocol error when the protocol defines one.
+/// The default limits are generous enough for any well-behaved client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Maximum number of mime types offered by a data source, defaults to 256
+    pub max_mime_types: usize,
+    /// Maximum length in bytes of a mime type offered by a data source, defaults to 1024
+    pub max_mime_type_length: usize,
+    /// Maximum number of data devices a client can create per seat, defaults to
+    /// [`DEFAULT_MAX_DATA_DEVICES_PER_CLIENT`]
+    ///
+    /// Devices destroyed by the client no longer count toward the limit.
+    pub max_data_devices: usize,
+    /// Maximum number of data offers a client can hold per seat at the same time, defaults to 256
+    ///
+    /// An offer is created for every selection and drag'n'drop sent to the client, which is
+    /// expected to destroy them when done.
+    pub max_offers: usize,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> ResourceLimits {
+        ResourceLimits {
+            max_mime_types: 256,
+            max_mime_type_length: 1024,
+            max_data_devices: DEFAULT_MAX_DATA_DEVICES_PER_CLIENT,
+            max_offers: 256,
         }
     }
 }
@@ -272,10 +546,13 @@ impl SeatData {
 /// and the second argument is the preferred action reported by the target. If no action should be
 /// chosen (and thus the drag'n'drop should abort on drop), return
 /// [`DndAction::empty()`](wayland_server::protocol::wl_data_device_manager::DndAction::empty).
+///
+/// The resources each client can use are bounded by `limits`, see [`ResourceLimits`].
 pub fn init_data_device<F, C, L>(
     display: &mut Display,
     callback: C,
     action_choice: F,
+    limits: ResourceLimits,
     logger: L,
 ) -> Global<wl_data_device_manager::WlDataDeviceManager>
 where
@@ -289,27 +566,74 @@ where
     display.create_global(
         3,
         Filter::new(move |(ddm, _version), _, _| {
-            implement_ddm(ddm, callback.clone(), action_choice.clone(), log.clone());
+            implement_ddm(ddm, callback.clone(), action_choice.clone(), limits, log.clone());
         }),
     )
 }
 
-/// Set the data device focus to a certain client for a given seat
-pub fn set_data_device_focus(seat: &Seat, client: Option<Client>) {
-    // ensure the seat user_data is ready
+// The data device state of the seat, initialized if the seat has no data device yet
+fn seat_data(seat: &Seat) -> &RefCell<SeatData> {
     // TODO: find a better way to retrieve a logger without requiring the user
     // to provide one ?
-    // This should be a rare path anyway, it is unlikely that a client gets focus
-    // before initializing its data device, which would already init the user_data.
+    // This should be a rare path anyway, it is unlikely that the compositor uses a seat
+    // before a client initialized its data device, which would already init the user_data.
     seat.user_data().insert_if_missing(|| {
         RefCell::new(SeatData::new(
             seat.arc.log.new(o!("smithay_module" => "data_device_mgr")),
         ))
     });
-    let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
+    seat.user_data().get::<RefCell<SeatData>>().unwrap()
+}
+
+/// Set the data device focus to a certain client for a given seat
+pub fn set_data_device_focus(seat: &Seat, client: Option<Client>) {
+    let seat_data = seat_data(seat);
     seat_data.borrow_mut().set_focus(client);
 }
 
+/// Returns the client of each data device created for a given seat
+///
+/// A client creating several data devices for the seat is listed once per data device. This is
+/// meant for diagnostics, like showing which clients take part in the selection and
+/// drag'n'drop of a seat.
+pub fn known_data_devices(seat: &Seat) -> Vec<Client> {
+    match seat.user_data().get::<RefCell<SeatData>>() {
+        Some(seat_data) => seat_data
+            .borrow()
+            .known_devices
+            .iter()
+            .filter(|dd| dd.as_ref().is_alive())
+            .filter_map(|dd| dd.as_ref().client())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Set the minimum interval between two drag'n'drop motion events sent to the target of a drag
+///
+/// During fast drags, a motion event is generated for every pointer event, which can flood slow
+/// clients. With a non-zero `interval`, in milliseconds, motion events following the last one
+/// sent too closely are held back, and only the latest of them is sent once the interval elapsed
+/// and the pointer moves again. Call [`flush_dnd_motion`] regularly, e.g. once per frame, for
+/// held back positions to be delivered even if the pointer stopped moving. Enter and leave events
+/// are never delayed and any held back motion is sent before a drop.
+///
+/// This applies to drag'n'drop operations started by clients and by the compositor. Defaults
+/// to `0`, sending every motion.
+pub fn set_dnd_motion_interval(seat: &Seat, interval: u32) {
+    let seat_data = seat_data(seat);
+    seat_data.borrow_mut().dnd_motion.interval = interval;
+}
+
+/// Send the drag'n'drop motion event held back for the target of a drag on this seat, if any
+///
+/// See [`set_dnd_motion_interval`].
+pub fn flush_dnd_motion(seat: &Seat) {
+    if let Some(seat_data) = seat.user_data().get::<RefCell<SeatData>>() {
+        seat_data.borrow_mut().flush_dnd_motion();
+    }
+}
+
 /// Set a compositor-provided selection for this seat
 ///
 /// You need to provide the available mime types for this selection.
@@ -317,19 +641,70 @@ pub fn set_data_device_focus(seat: &Seat, client: Option<Client>) {
 /// Whenever a client requests to read the selection, your callback will
 /// receive a [`DataDeviceEvent::SendSelection`] event.
 pub fn set_data_device_selection(seat: &Seat, mime_types: Vec<String>) {
-    // TODO: same question as in set_data_device_focus
-    seat.user_data().insert_if_missing(|| {
-        RefCell::new(SeatData::new(
-            seat.arc.log.new(o!("smithay_module" => "data_device_mgr")),
-        ))
-    });
-    let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
-    seat_data
-        .borrow_mut()
-        .set_selection(Selection::Compositor(SourceMetadata {
+    let seat_data = seat_data(seat);
+    seat_data.borrow_mut().set_selection(Selection::Compositor(
+        SourceMetadata {
+            mime_types,
+            dnd_action: DndAction::empty(),
+        },
+        None,
+    ));
+}
+
+/// Export the compositor-provided selection of this seat, e.g. to persist it across a restart
+///
+/// Returns the mime types of the selection and its contents, which are expected to be the same
+/// for all mime types. For a selection set with [`set_data_device_selection`], the contents are
+/// only known to the compositor and are retrieved by calling `contents` with the mime types. For
+/// a selection restored with [`import_selection`], the imported contents are returned.
+///
+/// Returns `None` if the seat has no selection or if the selection is owned by a client.
+pub fn export_selection<F>(seat: &Seat, contents: F) -> Option<(Vec<String>, Vec<u8>)>
+where
+    F: FnOnce(&[String]) -> Vec<u8>,
+{
+    let seat_data = seat.user_data().get::<RefCell<SeatData>>()?;
+    let (mime_types, imported) = match seat_data.borrow().selection {
+        Selection::Compositor(ref meta, ref imported) => (meta.mime_types.clone(), imported.clone()),
+        _ => return None,
+    };
+    let contents = match imported {
+        Some(imported) => imported.contents.to_vec(),
+        None => contents(&mime_types),
+    };
+    Some((mime_types, contents))
+}
+
+/// Restore a compositor-provided selection for this seat, as previously exported with [`export_selection`]
+///
+/// Unlike with [`set_data_device_selection`], the contents are known upfront: requests of clients
+/// to read the selection are served directly with `contents`, whatever the requested mime type, and
+/// do not generate [`DataDeviceEvent::SendSelection`] events. The contents are written from the
+/// event loop of `handle`, see [`SelectionWriter::write_async`].
+pub fn import_selection<Data: 'static>(
+    seat: &Seat,
+    handle: &LoopHandle<'static, Data>,
+    mime_types: Vec<String>,
+    contents: Vec<u8>,
+) {
+    let seat_data = seat_data(seat);
+    let handle = handle.clone();
+    let log = seat_data.borrow().log.clone();
+    let imported = Imported {
+        contents: contents.into(),
+        write: Rc::new(move |writer, contents| {
+            if let Err(err) = writer.write_async(&handle, contents) {
+                warn!(log, "Failed to send the imported selection: {}", err);
+            }
+        }),
+    };
+    seat_data.borrow_mut().set_selection(Selection::Compositor(
+        SourceMetadata {
             mime_types,
             dnd_action: DndAction::empty(),
-        }));
+        },
+        Some(imported),
+    ));
 }
 
 /// Start a drag'n'drop from a resource controlled by the compositor
@@ -337,6 +712,8 @@ pub fn set_data_device_selection(seat: &Seat, mime_types: Vec<String>) {
 /// You'll receive events generated by the interaction of clients with your
 /// drag'n'drop in the provided callback. See [`ServerDndEvent`] for details about
 /// which events can be generated and what response is expected from you to them.
+///
+/// The action is negotiated with the action chooser given to [`init_data_device`].
 pub fn start_dnd<C>(
     seat: &Seat,
     serial: Serial,
@@ -346,12 +723,46 @@ pub fn start_dnd<C>(
 ) where
     C: FnMut(ServerDndEvent) + 'static,
 {
-    // TODO: same question as in set_data_device_focus
-    seat.user_data().insert_if_missing(|| {
-        RefCell::new(SeatData::new(
-            seat.arc.log.new(o!("smithay_module" => "data_device_mgr")),
-        ))
-    });
+    start_server_dnd(seat, serial, start_data, metadata, None, callback)
+}
+
+/// Start a drag'n'drop from a resource controlled by the compositor, forcing its action
+///
+/// Like [`start_dnd`], but the action chooser given to [`init_data_device`] is bypassed
+/// and the action is always negotiated to `action`.
+///
+/// If `metadata` does not allow `action`, the drag'n'drop is not started and `callback`
+/// immediately receives [`ServerDndEvent::Cancelled`]. It is also cancelled with the next pointer
+/// event, once a target advertises actions not including `action`.
+pub fn start_dnd_with_forced_action<C>(
+    seat: &Seat,
+    serial: Serial,
+    start_data: PointerGrabStartData,
+    metadata: SourceMetadata,
+    action: DndAction,
+    mut callback: C,
+) where
+    C: FnMut(ServerDndEvent) + 'static,
+{
+    if !metadata.dnd_action.contains(action) {
+        callback(ServerDndEvent::Cancelled);
+        return;
+    }
+    start_server_dnd(seat, serial, start_data, metadata, Some(action), callback)
+}
+
+fn start_server_dnd<C>(
+    seat: &Seat,
+    serial: Serial,
+    start_data: PointerGrabStartData,
+    metadata: SourceMetadata,
+    forced_action: Option<DndAction>,
+    callback: C,
+) where
+    C: FnMut(ServerDndEvent) + 'static,
+{
+    // ensure the seat user_data is ready
+    seat_data(seat);
     if let Some(pointer) = seat.get_pointer() {
         pointer.set_grab(
             server_dnd_grab::ServerDnDGrab::new(
@@ -359,6 +770,7 @@ pub fn start_dnd<C>(
                 metadata,
                 seat.clone(),
                 Rc::new(RefCell::new(callback)),
+                forced_action,
             ),
             serial,
             0,
@@ -370,6 +782,7 @@ fn implement_ddm<F, C>(
     ddm: Main<wl_data_device_manager::WlDataDeviceManager>,
     callback: Rc<RefCell<C>>,
     action_choice: Rc<RefCell<F>>,
+    limits: ResourceLimits,
     log: ::slog::Logger,
 ) -> wl_data_device_manager::WlDataDeviceManager
 where
@@ -377,9 +790,9 @@ where
     C: FnMut(DataDeviceEvent) + 'static,
 {
     use self::wl_data_device_manager::Request;
-    ddm.quick_assign(move |_ddm, req, _data| match req {
+    ddm.quick_assign(move |ddm, req, _data| match req {
         Request::CreateDataSource { id } => {
-            self::data_source::implement_data_source(id);
+            self::data_source::implement_data_source(id, callback.clone(), limits);
         }
         Request::GetDataDevice { id, seat } => match Seat::from_resource(&seat) {
             Some(seat) => {
@@ -387,6 +800,19 @@ where
                 seat.user_data()
                     .insert_if_missing(|| RefCell::new(SeatData::new(log.clone())));
                 let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
+                seat_data.borrow_mut().limits = limits;
+                let max = limits.max_data_devices;
+                if seat_data.borrow().devices_of_client(&ddm) >= max {
+                    // like for data offers, the protocol has no error for this
+                    warn!(
+                        log,
+                        "Killing a client creating too many data devices, at most {} per seat.", max
+                    );
+                    if let Some(client) = ddm.as_ref().client() {
+                        client.kill();
+                    }
+                    return;
+                }
                 let data_device = implement_data_device(
                     id,
                     seat.clone(),
@@ -394,7 +820,11 @@ where
                     action_choice.clone(),
                     log.clone(),
                 );
-                seat_data.borrow_mut().known_devices.push(data_device);
+                let mut seat_data = seat_data.borrow_mut();
+                seat_data.known_devices.push(data_device.clone());
+                // the client may already have the focus, in which case the new device
+                // did not receive the current selection yet
+                seat_data.send_selection_to(Some(&data_device));
             }
             None => {
                 error!(log, "Unmanaged seat given to a data device.");
@@ -437,7 +867,12 @@ where
             /* TODO: handle the icon */
             let serial = Serial::from(serial);
             if let Some(pointer) = seat.get_pointer() {
-                if pointer.has_grab(serial) {
+                // only accept drags started in response to a recent press of the grabbing button
+                let grab_button = pointer.grab_start_data().map(|data| data.button);
+                if pointer.has_grab(serial)
+                    && grab_button.and_then(|button| pointer.last_button_serial(button)) == Some(serial)
+                    && seat.serials().validate(serial, |event| event.is_press())
+                {
                     if let Some(ref icon) = icon {
                         if compositor::give_role(icon, DND_ICON_ROLE).is_err() {
                             dd.as_ref().post_error(
@@ -446,6 +881,12 @@ where
                             );
                             return;
                         }
+                        let _ = compositor::with_states(icon, |states| {
+                            states
+                                .data_map
+                                .insert_if_missing_threadsafe(|| Mutex::new(DnDIconAttributes::default()))
+                        });
+                        compositor::add_post_commit_hook(icon, DnDIconAttributes::commit_hook);
                     }
                     // The StartDrag is in response to a pointer implicit grab, all is good
                     (&mut *callback.borrow_mut())(DataDeviceEvent::DnDStarted {
@@ -482,6 +923,11 @@ where
                 {
                     let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
                     (&mut *callback.borrow_mut())(DataDeviceEvent::NewSelection(source.clone()));
+                    if let Some(ref source) = source {
+                        if let Some(seats) = source.as_ref().user_data().get::<SelectionSeats>() {
+                            seats.0.borrow_mut().push(Rc::downgrade(&seat.arc));
+                        }
+                    }
                     // The client has kbd focus, it can set the selection
                     seat_data
                         .borrow_mut()
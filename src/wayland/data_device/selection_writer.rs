@@ -0,0 +1,118 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+};
+
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction, RegistrationToken};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+
+/// Destination of a selection requested by a client
+///
+/// This owns the fd the client provided to receive the selection contents. The selection is
+/// considered transferred once the writer is dropped, which closes the fd. It does not need to be
+/// written to from within the callback: it can be kept around (it is `Send`) to stream large
+/// contents asynchronously, without blocking the event loop.
+///
+/// Using [`IntoRawFd::into_raw_fd`], the compositor takes full ownership of the fd and is then
+/// responsible for closing it.
+#[derive(Debug)]
+pub struct SelectionWriter {
+    file: File,
+}
+
+impl SelectionWriter {
+    pub(crate) fn new(fd: RawFd) -> SelectionWriter {
+        SelectionWriter {
+            // the fd is owned by us once received from the client
+            file: unsafe { File::from_raw_fd(fd) },
+        }
+    }
+
+    /// Write `contents` from the event loop without ever blocking it
+    ///
+    /// The fd is switched to non-blocking mode and registered into the event loop of `handle`,
+    /// the contents are written each time the client is ready to receive more of them. The fd
+    /// is closed, and the source removed from the event loop, once everything has been written
+    /// or if the client stops reading by closing its end.
+    ///
+    /// A slow client only delays its own transfer: the contents not yet written are kept in
+    /// memory until the client catches up, while the rest of the event loop is dispatched as
+    /// usual. Remove the returned token from the event loop to abort the transfer.
+    ///
+    /// ```no_run
+    /// # extern crate wayland_server;
+    /// # use smithay::reexports::calloop::EventLoop;
+    /// use smithay::wayland::data_device::{
+    ///     default_action_chooser, init_data_device, DataDeviceEvent, ResourceLimits,
+    /// };
+    ///
+    /// # let mut display = wayland_server::Display::new();
+    /// let event_loop = EventLoop::<()>::try_new().unwrap();
+    /// let handle = event_loop.handle();
+    /// init_data_device(
+    ///     &mut display,
+    ///     move |event| {
+    ///         if let DataDeviceEvent::SendSelection { writer, .. } = event {
+    ///             if let Err(err) = writer.write_async(&handle, b"clipboard contents".to_vec()) {
+    ///                 eprintln!("Failed to send the selection: {}", err);
+    ///             }
+    ///         }
+    ///     },
+    ///     default_action_chooser,
+    ///     ResourceLimits::default(),
+    ///     None,
+    /// );
+    /// ```
+    pub fn write_async<Data: 'static>(
+        self,
+        handle: &LoopHandle<'_, Data>,
+        contents: Vec<u8>,
+    ) -> io::Result<RegistrationToken> {
+        let flags = OFlag::from_bits_truncate(fcntl(self.as_raw_fd(), FcntlArg::F_GETFL)?);
+        fcntl(self.as_raw_fd(), FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+        let mut written = 0;
+        handle
+            .insert_source(
+                Generic::new(self, Interest::WRITE, Mode::Level),
+                move |_, writer, _| {
+                    while written < contents.len() {
+                        match writer.write(&contents[written..]) {
+                            Ok(0) => return Ok(PostAction::Remove),
+                            Ok(n) => written += n,
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                                return Ok(PostAction::Continue)
+                            }
+                            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                            // the client closed its end, nothing else to do
+                            Err(_) => return Ok(PostAction::Remove),
+                        }
+                    }
+                    Ok(PostAction::Remove)
+                },
+            )
+            .map_err(|err| err.error)
+    }
+}
+
+impl Write for SelectionWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsRawFd for SelectionWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for SelectionWriter {
+    fn into_raw_fd(self) -> RawFd {
+        self.file.into_raw_fd()
+    }
+}
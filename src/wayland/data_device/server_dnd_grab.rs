@@ -1,4 +1,9 @@
-use std::{cell::RefCell, ops::Deref as _, os::unix::io::RawFd, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    ops::Deref as _,
+    os::unix::io::RawFd,
+    rc::Rc,
+};
 
 use wayland_server::{
     protocol::{wl_data_device_manager::DndAction, wl_data_offer, wl_pointer, wl_surface},
@@ -13,7 +18,7 @@ use crate::{
     },
 };
 
-use super::{DataDeviceData, SeatData};
+use super::{dnd_grab::OfferData, DataDeviceData, SeatData};
 
 /// Event generated by the interactions of clients with a server initiated drag'n'drop
 #[derive(Debug)]
@@ -49,6 +54,9 @@ pub(crate) struct ServerDnDGrab<C: 'static> {
     offer_data: Option<Rc<RefCell<OfferData>>>,
     seat: Seat,
     callback: Rc<RefCell<C>>,
+    forced_action: Option<DndAction>,
+    // set once a target does not support the forced action
+    action_refused: Rc<Cell<bool>>,
 }
 
 impl<C: 'static> ServerDnDGrab<C> {
@@ -57,6 +65,7 @@ impl<C: 'static> ServerDnDGrab<C> {
         metadata: super::SourceMetadata,
         seat: Seat,
         callback: Rc<RefCell<C>>,
+        forced_action: Option<DndAction>,
     ) -> ServerDnDGrab<C> {
         ServerDnDGrab {
             start_data,
@@ -66,8 +75,60 @@ impl<C: 'static> ServerDnDGrab<C> {
             offer_data: None,
             seat,
             callback,
+            forced_action,
+            action_refused: Rc::new(Cell::new(false)),
+        }
+    }
+
+    // abandon the drag'n'drop without a drop, ending the grab
+    fn cancel(&mut self, handle: &mut PointerInnerHandle<'_>, serial: Serial, time: u32) {
+        {
+            let mut seat_data = self
+                .seat
+                .user_data()
+                .get::<RefCell<SeatData>>()
+                .unwrap()
+                .borrow_mut();
+            if let Some(surface) = self.current_focus.take() {
+                for device in seat_data.devices_for(&surface) {
+                    device.leave();
+                }
+            }
+            seat_data.dnd_leave();
+        }
+        self.pending_offers.clear();
+        if let Some(offer_data) = self.offer_data.take() {
+            offer_data.borrow_mut().active = false;
         }
+        (&mut *self.callback.borrow_mut())(ServerDndEvent::Cancelled);
+        handle.unset_grab(serial, time);
     }
+
+    // actions advertised to the clients, restricted to the forced action if any
+    fn source_actions(&self) -> DndAction {
+        match self.forced_action {
+            Some(action) => self.metadata.dnd_action & action,
+            None => self.metadata.dnd_action,
+        }
+    }
+}
+
+// action chooser always negotiating to the forced action, flagging the drag'n'drop as refused
+// if the target does not support it
+fn forced_action_choice(
+    forced: DndAction,
+    refused: Rc<Cell<bool>>,
+) -> Rc<RefCell<dyn FnMut(DndAction, DndAction) -> DndAction>> {
+    Rc::new(RefCell::new(
+        move |available: DndAction, _preferred: DndAction| {
+            if available.contains(forced) {
+                forced
+            } else {
+                refused.set(true);
+                DndAction::None
+            }
+        },
+    ))
 }
 
 impl<C> PointerGrab for ServerDnDGrab<C>
@@ -76,13 +137,18 @@ where
 {
     fn motion(
         &mut self,
-        _handle: &mut PointerInnerHandle<'_>,
+        handle: &mut PointerInnerHandle<'_>,
         location: Point<f64, Logical>,
         focus: Option<(wl_surface::WlSurface, Point<i32, Logical>)>,
         serial: Serial,
         time: u32,
     ) {
-        let seat_data = self
+        if self.action_refused.get() {
+            self.cancel(handle, serial, time);
+            return;
+        }
+
+        let mut seat_data = self
             .seat
             .user_data()
             .get::<RefCell<SeatData>>()
@@ -91,11 +157,10 @@ where
         if focus.as_ref().map(|&(ref s, _)| s) != self.current_focus.as_ref() {
             // focus changed, we need to make a leave if appropriate
             if let Some(surface) = self.current_focus.take() {
-                for device in &seat_data.known_devices {
-                    if device.as_ref().same_client_as(surface.as_ref()) {
-                        device.leave();
-                    }
+                for device in seat_data.devices_for(&surface) {
+                    device.leave();
                 }
+                seat_data.dnd_leave();
                 // disable the offers
                 self.pending_offers.clear();
                 if let Some(offer_data) = self.offer_data.take() {
@@ -112,24 +177,22 @@ where
             let (x, y) = (location - surface_location.to_f64()).into();
             if self.current_focus.is_none() {
                 // We entered a new surface, send the data offer
-                let offer_data = Rc::new(RefCell::new(OfferData {
-                    active: true,
-                    dropped: false,
-                    accepted: true,
-                    chosen_action: DndAction::empty(),
-                }));
-                for device in seat_data
-                    .known_devices
-                    .iter()
-                    .filter(|d| d.as_ref().same_client_as(surface.as_ref()))
-                {
-                    let action_choice = device
-                        .as_ref()
-                        .user_data()
-                        .get::<DataDeviceData>()
-                        .unwrap()
-                        .action_choice
-                        .clone();
+                let offer_data = Rc::new(RefCell::new(OfferData::new()));
+                let devices = seat_data.devices_for(&surface).cloned().collect::<Vec<_>>();
+                for device in devices {
+                    if !seat_data.allow_offer(&device) {
+                        break;
+                    }
+                    let action_choice = match self.forced_action {
+                        Some(action) => forced_action_choice(action, self.action_refused.clone()),
+                        None => device
+                            .as_ref()
+                            .user_data()
+                            .get::<DataDeviceData>()
+                            .unwrap()
+                            .action_choice
+                            .clone(),
+                    };
                     // create a data offer
                     let offer = client
                         .create_resource::<wl_data_offer::WlDataOffer>(device.as_ref().version())
@@ -145,22 +208,20 @@ where
                         .unwrap();
                     // advertize the offer to the client
                     device.data_offer(&offer);
+                    seat_data.track_offer(&offer);
                     for mime_type in self.metadata.mime_types.iter().cloned() {
                         offer.offer(mime_type);
                     }
-                    offer.source_actions(self.metadata.dnd_action);
+                    offer.source_actions(self.source_actions());
                     device.enter(serial.into(), &surface, x, y, Some(&offer));
                     self.pending_offers.push(offer);
                 }
                 self.offer_data = Some(offer_data);
+                seat_data.dnd_enter(time);
                 self.current_focus = Some(surface);
             } else {
                 // make a move
-                for device in &seat_data.known_devices {
-                    if device.as_ref().same_client_as(surface.as_ref()) {
-                        device.motion(time, x, y);
-                    }
-                }
+                seat_data.dnd_motion(&surface, time, x, y);
             }
         }
     }
@@ -173,9 +234,13 @@ where
         serial: Serial,
         time: u32,
     ) {
+        if self.action_refused.get() {
+            self.cancel(handle, serial, time);
+            return;
+        }
         if handle.current_pressed().is_empty() {
             // the user dropped, proceed to the drop
-            let seat_data = self
+            let mut seat_data = self
                 .seat
                 .user_data()
                 .get::<RefCell<SeatData>>()
@@ -188,16 +253,17 @@ where
                 false
             };
             if let Some(ref surface) = self.current_focus {
-                for device in &seat_data.known_devices {
-                    if device.as_ref().same_client_as(surface.as_ref()) {
-                        if validated {
-                            device.drop();
-                        } else {
-                            device.leave();
-                        }
+                // the target needs to know the final position before the drop
+                seat_data.flush_dnd_motion();
+                for device in seat_data.devices_for(&surface) {
+                    if validated {
+                        device.drop();
+                    } else {
+                        device.leave();
                     }
                 }
             }
+            seat_data.dnd_leave();
             if let Some(ref offer_data) = self.offer_data {
                 let mut data = offer_data.borrow_mut();
                 if validated {
@@ -227,13 +293,6 @@ where
     }
 }
 
-struct OfferData {
-    active: bool,
-    dropped: bool,
-    accepted: bool,
-    chosen_action: DndAction,
-}
-
 fn implement_dnd_data_offer<C>(
     offer: Main<wl_data_offer::WlDataOffer>,
     metadata: super::SourceMetadata,
@@ -262,38 +321,14 @@ where
                 }
             }
             Request::Destroy => {}
-            Request::Finish => {
-                if !data.active {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that is no longer active.".into(),
-                    );
-                    return;
-                }
-                if !data.accepted {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that has not been accepted.".into(),
-                    );
-                    return;
-                }
-                if !data.dropped {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer that has not been dropped.".into(),
-                    );
-                    return;
-                }
-                if data.chosen_action.is_empty() {
-                    offer.as_ref().post_error(
-                        wl_data_offer::Error::InvalidFinish as u32,
-                        "Cannot finish a data offer with no valid action.".into(),
-                    );
-                    return;
-                }
-                (&mut *callback.borrow_mut())(ServerDndEvent::Finished);
-                data.active = false;
-            }
+            Request::Finish => match data.finish() {
+                Ok(true) => (&mut *callback.borrow_mut())(ServerDndEvent::Finished),
+                // already finished through another data device of the client
+                Ok(false) => {}
+                Err(msg) => offer
+                    .as_ref()
+                    .post_error(wl_data_offer::Error::InvalidFinish as u32, msg.into()),
+            },
             Request::SetActions {
                 dnd_actions,
                 preferred_action,
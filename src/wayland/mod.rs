@@ -54,11 +54,14 @@
 
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod color_management;
 pub mod compositor;
 pub mod data_device;
 pub mod dmabuf;
 pub mod explicit_synchronization;
 pub mod output;
+pub mod output_power_management;
+pub mod primary_selection;
 pub mod seat;
 pub mod shell;
 pub mod shm;
@@ -69,12 +72,18 @@ pub mod xdg_foreign;
 /// A global [`SerialCounter`] for use in your compositor.
 ///
 /// Is is also used internally by some parts of Smithay.
-pub static SERIAL_COUNTER: SerialCounter = SerialCounter {
-    serial: AtomicUsize::new(0),
-};
+pub static SERIAL_COUNTER: SerialCounter = SerialCounter::new();
 
 /// A serial type, whose comparison takes into account the wrapping-around behavior of the
 /// underlying counter.
+///
+/// Serials are 32-bit values and wrap around to `0` after `u32::MAX`. A serial is considered
+/// newer than another one if it is less than `u32::MAX / 2` increments ahead of it, modulo 2^32.
+/// For example `Serial::from(0) > Serial::from(u32::MAX)`, as `0` is the serial following
+/// `u32::MAX`. Serials further apart than half the range cannot be ordered reliably, which is
+/// not a concern in practice as long as old serials are not kept around for billions of events.
+///
+/// Equality is plain equality of the underlying values.
 #[derive(Debug, Copy, Clone)]
 pub struct Serial(u32);
 
@@ -129,12 +138,31 @@ pub struct SerialCounter {
 }
 
 impl SerialCounter {
+    /// Create a new counter starting at `0`
+    ///
+    /// Serials of different counters are not guaranteed to be unique, prefer the global
+    /// [`SERIAL_COUNTER`] unless you need an independent sequence, e.g. in tests.
+    pub const fn new() -> SerialCounter {
+        SerialCounter {
+            serial: AtomicUsize::new(0),
+        }
+    }
+
     /// Retrieve the next serial from the counter
+    ///
+    /// The counter wraps around to `0` after `u32::MAX`, the ordering of [`Serial`]
+    /// takes this into account.
     pub fn next_serial(&self) -> Serial {
         Serial(self.serial.fetch_add(1, Ordering::AcqRel) as u32)
     }
 }
 
+impl Default for SerialCounter {
+    fn default() -> SerialCounter {
+        SerialCounter::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,4 +213,15 @@ mod tests {
 
         assert!(serial1 < serial2);
     }
+
+    #[test]
+    fn serial_order_across_wrap_around() {
+        let counter = SerialCounter::new();
+        assert!(counter.next_serial() == 0.into());
+
+        let old = Serial::from(u32::MAX - 10);
+        assert!(Serial::from(5) > old);
+        assert!(old < Serial::from(5));
+        assert!(Serial::from(u32::MAX / 2 - 1) > Serial::from(0));
+    }
 }
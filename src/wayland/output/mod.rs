@@ -83,11 +83,36 @@ pub struct Mode {
     /// The refresh rate in millihertz
     ///
     /// `1000` is one fps (frame per second), `2000` is 2 fps, etc...
+    /// A value of `0` or less means the refresh rate is unknown, e.g. for nested backends.
     pub refresh: i32,
 }
 
+impl Mode {
+    /// Returns the refresh rate in millihertz, if it is known
+    pub fn refresh_rate(&self) -> Option<i32> {
+        if self.refresh > 0 {
+            Some(self.refresh)
+        } else {
+            None
+        }
+    }
+}
+
+/// Variable refresh rate (adaptive sync) state of an output
+///
+/// Outputs without this state, the default, are displays with a fixed refresh rate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VrrState {
+    /// Whether variable refresh rate is currently enabled
+    pub enabled: bool,
+    /// The lowest refresh rate supported by the display, in millihertz
+    pub min_refresh: i32,
+    /// The highest refresh rate supported by the display, in millihertz
+    pub max_refresh: i32,
+}
+
 /// The physical properties of an output
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PhysicalProperties {
     /// The size of the monitor, in millimeters
     pub size: Size<i32, Raw>,
@@ -105,12 +130,14 @@ pub(crate) struct Inner {
     pub(crate) log: ::slog::Logger,
     instances: Vec<WlOutput>,
     physical: PhysicalProperties,
+    description: Option<String>,
     location: Point<i32, Logical>,
     transform: Transform,
     scale: i32,
     modes: Vec<Mode>,
     current_mode: Option<Mode>,
     preferred_mode: Option<Mode>,
+    vrr: Option<VrrState>,
 
     xdg_output: Option<XdgOutput>,
 }
@@ -150,6 +177,12 @@ impl Inner {
         self.instances.push(output);
     }
 
+    fn description(&self) -> String {
+        self.description
+            .clone()
+            .unwrap_or_else(|| format!("{} - {} - {}", self.physical.make, self.physical.model, self.name))
+    }
+
     fn send_geometry(&self, output: &WlOutput) {
         output.geometry(
             self.location.x,
@@ -198,12 +231,14 @@ impl Output {
                 log,
                 instances: Vec::new(),
                 physical,
+                description: None,
                 location: (0, 0).into(),
                 transform: Transform::Normal,
                 scale: 1,
                 modes: Vec::new(),
                 current_mode: None,
                 preferred_mode: None,
+                vrr: None,
                 xdg_output: None,
             }),
             UserDataMap::default(),
@@ -265,25 +300,94 @@ impl Output {
     }
 
     /// Returns the currently advertised mode of the output
+    ///
+    /// This is `None` until a mode is set with [`Output::change_current_state`].
     pub fn current_mode(&self) -> Option<Mode> {
         self.inner.0.lock().unwrap().current_mode
     }
 
     /// Returns the currently advertised transformation of the output
+    ///
+    /// Defaults to [`Transform::Normal`].
     pub fn current_transform(&self) -> Transform {
         self.inner.0.lock().unwrap().transform
     }
 
-    /// Returns the currenly advertised scale of the output
+    /// Returns the currently advertised scale of the output
+    ///
+    /// Defaults to `1`.
     pub fn current_scale(&self) -> i32 {
         self.inner.0.lock().unwrap().scale
     }
 
+    /// Returns the currently advertised location of the output in the global compositor space
+    ///
+    /// Defaults to `(0, 0)`.
+    pub fn current_location(&self) -> Point<i32, Logical> {
+        self.inner.0.lock().unwrap().location
+    }
+
+    /// Returns the variable refresh rate state of the output
+    ///
+    /// This is `None` for displays with a fixed refresh rate, the default.
+    pub fn vrr_state(&self) -> Option<VrrState> {
+        self.inner.0.lock().unwrap().vrr
+    }
+
+    /// Sets the variable refresh rate state of the output
+    ///
+    /// Use `None` for displays with a fixed refresh rate. This state is not advertised to
+    /// clients, as none of the supported output protocols carries it, it allows the backend
+    /// and renderer to decide of the presentation timing.
+    pub fn set_vrr_state(&self, vrr: Option<VrrState>) {
+        self.inner.0.lock().unwrap().vrr = vrr;
+    }
+
+    /// Returns the preferred mode of the output, if any
+    pub fn preferred_mode(&self) -> Option<Mode> {
+        self.inner.0.lock().unwrap().preferred_mode
+    }
+
+    /// Returns all the modes known to this output
+    pub fn modes(&self) -> Vec<Mode> {
+        self.inner.0.lock().unwrap().modes.clone()
+    }
+
     /// Returns the name of the output
     pub fn name(&self) -> String {
         self.inner.0.lock().unwrap().name.clone()
     }
 
+    /// Returns the physical size of the output in millimeters
+    ///
+    /// Returns `None` if the size is unknown, e.g. for projectors or nested backends.
+    pub fn physical_size(&self) -> Option<Size<i32, Raw>> {
+        let size = self.inner.0.lock().unwrap().physical.size;
+        if size.w > 0 && size.h > 0 {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the dots per inch of the output in its current mode
+    ///
+    /// Returns `None` if the physical size of the output or its current mode is unknown.
+    pub fn dpi(&self) -> Option<f64> {
+        dpi(self.current_mode()?.size, self.physical_size()?)
+    }
+
+    /// Returns a suggested scale factor for the output in its current mode
+    ///
+    /// The scale is derived from the dpi of the output relative to a reference dpi of 96
+    /// and rounded to the nearest multiple of `0.25`, but is never smaller than `1.0`.
+    /// Use [`f64::round`] to get a suitable integer scale for [`Output::change_current_state`].
+    ///
+    /// Returns `None` if the physical size of the output or its current mode is unknown.
+    pub fn suggested_scale(&self) -> Option<f64> {
+        self.dpi().map(scale_for_dpi)
+    }
+
     /// Removes a mode from the list of known modes
     ///
     /// It will not de-advertise it from existing clients (the protocol does not
@@ -360,6 +464,60 @@ impl Output {
         }
     }
 
+    /// Returns the physical properties of the output
+    pub fn physical_properties(&self) -> PhysicalProperties {
+        self.inner.0.lock().unwrap().physical.clone()
+    }
+
+    /// Change the physical properties of this output
+    ///
+    /// This is useful if the identity of the connected monitor is only known after the output
+    /// was created, e.g. if its EDID is read asynchronously. The new geometry is sent to all
+    /// bound clients. If no custom description was set via [`Output::set_description`],
+    /// the xdg-output description derived from make and model is updated as well.
+    pub fn set_physical_properties(&self, physical: PhysicalProperties) {
+        let mut inner = self.inner.0.lock().unwrap();
+        inner.physical = physical;
+
+        // XdgOutput has to be updated before WlOutput
+        // Because WlOutput::done() has to allways be called last
+        if let Some(xdg_output) = inner.xdg_output.as_ref() {
+            xdg_output.change_description(inner.description());
+        }
+
+        for output in &inner.instances {
+            inner.send_geometry(output);
+            if output.as_ref().version() >= 2 {
+                output.done();
+            }
+        }
+    }
+
+    /// Returns the description of the output
+    ///
+    /// Unless set via [`Output::set_description`] it is derived from the make, model and name of the output.
+    pub fn description(&self) -> String {
+        self.inner.0.lock().unwrap().description()
+    }
+
+    /// Change the description of this output
+    ///
+    /// The description is advertised to clients via the xdg-output protocol and updated for
+    /// already bound clients.
+    pub fn set_description(&self, description: impl Into<String>) {
+        let mut inner = self.inner.0.lock().unwrap();
+        inner.description = Some(description.into());
+
+        if let Some(xdg_output) = inner.xdg_output.as_ref() {
+            xdg_output.change_description(inner.description());
+            for output in &inner.instances {
+                if output.as_ref().version() >= 2 {
+                    output.done();
+                }
+            }
+        }
+    }
+
     /// Check is given [`wl_output`](WlOutput) instance is managed by this [`Output`].
     pub fn owns(&self, output: &WlOutput) -> bool {
         self.inner
@@ -412,6 +570,17 @@ impl Output {
     }
 }
 
+fn dpi(mode_size: Size<i32, Physical>, physical_size: Size<i32, Raw>) -> Option<f64> {
+    if mode_size.w <= 0 || physical_size.w <= 0 {
+        return None;
+    }
+    Some(mode_size.w as f64 / (physical_size.w as f64 / 25.4))
+}
+
+fn scale_for_dpi(dpi: f64) -> f64 {
+    f64::max((dpi / 96.0 * 4.0).round() / 4.0, 1.0)
+}
+
 impl PartialEq for Output {
     fn eq(&self, other: &Self) -> bool {
         Arc::ptr_eq(&self.inner, &other.inner)
@@ -419,3 +588,65 @@ impl PartialEq for Output {
 }
 
 impl Eq for Output {}
+
+#[cfg(test)]
+mod tests {
+    use super::{dpi, scale_for_dpi, Mode, Output, PhysicalProperties, Point, Transform, VrrState};
+    use wayland_server::{protocol::wl_output::Subpixel, Display};
+
+    #[test]
+    fn current_state_getters() {
+        let mut display = Display::new();
+        let physical = PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Smithay".into(),
+            model: "Test".into(),
+        };
+        let (output, _global) = Output::new(&mut display, "test".into(), physical, None);
+        assert_eq!(output.current_mode(), None);
+        assert_eq!(output.current_transform(), Transform::Normal);
+        assert_eq!(output.current_scale(), 1);
+        assert_eq!(output.current_location(), Point::from((0, 0)));
+        assert_eq!(output.vrr_state(), None);
+
+        let mode = Mode {
+            size: (1920, 1080).into(),
+            refresh: 60_000,
+        };
+        output.change_current_state(Some(mode), Some(Transform::_90), Some(2), Some((10, 0).into()));
+        assert_eq!(output.current_mode(), Some(mode));
+        assert_eq!(output.current_transform(), Transform::_90);
+        assert_eq!(output.current_scale(), 2);
+        assert_eq!(output.current_location(), Point::from((10, 0)));
+        assert_eq!(output.modes(), vec![mode]);
+
+        let vrr = VrrState {
+            enabled: true,
+            min_refresh: 48_000,
+            max_refresh: 144_000,
+        };
+        output.set_vrr_state(Some(vrr));
+        assert_eq!(output.vrr_state(), Some(vrr));
+    }
+
+    #[test]
+    fn hidpi_laptop_scale() {
+        // 13.3" 2560x1600 panel
+        let dpi = dpi((2560, 1600).into(), (286, 179).into()).unwrap();
+        assert!((dpi - 227.4).abs() < 0.1);
+        assert_eq!(scale_for_dpi(dpi), 2.25);
+    }
+
+    #[test]
+    fn lowdpi_monitor_scale() {
+        // 24" 1920x1080 monitor
+        let dpi = dpi((1920, 1080).into(), (531, 299).into()).unwrap();
+        assert_eq!(scale_for_dpi(dpi), 1.0);
+    }
+
+    #[test]
+    fn unknown_physical_size() {
+        assert_eq!(dpi((1920, 1080).into(), (0, 0).into()), None);
+    }
+}
@@ -41,11 +41,7 @@ impl XdgOutput {
     fn new(output: &super::Inner, log: ::slog::Logger) -> Self {
         trace!(log, "Creating new xdg_output"; "name" => &output.name);
 
-        let description = format!(
-            "{} - {} - {}",
-            output.physical.make, output.physical.model, output.name
-        );
-
+        let description = output.description();
         let physical_size = output.current_mode.map(|mode| mode.size);
 
         Self {
@@ -140,6 +136,27 @@ impl XdgOutput {
             // No need for wl_output.done() here, it will be called by caller (super::Output::change_current_state)
         }
     }
+
+    pub(super) fn change_description(&self, description: String) {
+        let mut output = self.inner.lock().unwrap();
+        if output.description == description {
+            return;
+        }
+        output.description = description;
+
+        for instance in output.instances.iter() {
+            if instance.as_ref().version() >= 2 {
+                instance.description(output.description.clone());
+            }
+
+            // xdg_output.done() is deprecated since version 3
+            if instance.as_ref().version() < 3 {
+                instance.done();
+            }
+
+            // No need for wl_output.done() here, it will be called by caller
+        }
+    }
 }
 
 /// Initialize a xdg output manager global.
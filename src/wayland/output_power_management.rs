@@ -0,0 +1,222 @@
+//! Utilities for handling the `wlr-output-power-management` protocol
+//!
+//! This protocol allows privileged clients, like idle daemons, to turn outputs off and on again,
+//! e.g. to blank the screens after some time of inactivity.
+//!
+//! The compositor provides a callback that actually toggles the power of the outputs in its
+//! backend. If it fails to do so, for example because the output was unplugged, the client is
+//! notified that its power management object became invalid. Successful changes are reported to
+//! all clients controlling the same output.
+//!
+//! ```no_run
+//! # extern crate wayland_server;
+//! use smithay::wayland::output_power_management::{init_output_power_manager, OutputPowerMode};
+//!
+//! # let mut display = wayland_server::Display::new();
+//! let _global = init_output_power_manager(
+//!     &mut display,
+//!     |output, mode, _dispatch_data| {
+//!         // blank or unblank the output in your backend, returning whether it succeeded
+//!         true
+//!     },
+//!     None, // put a logger if you want
+//! );
+//! ```
+//!
+//! Power changes decided by the compositor itself should be reported with
+//! [`set_output_power_mode`], and [`disable_output_power_management`] should be called
+//! when an output goes away.
+
+use std::{cell::RefCell, ops::Deref as _, rc::Rc};
+
+use wayland_protocols::wlr::unstable::output_power_management::v1::server::{
+    zwlr_output_power_manager_v1::{self, ZwlrOutputPowerManagerV1},
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+};
+use wayland_server::{DispatchData, Display, Filter, Global, Main};
+
+use slog::{debug, o};
+
+use super::output::Output;
+
+/// Power mode of an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputPowerMode {
+    /// The output is enabled
+    On,
+    /// The output is disabled, e.g. blanked or in power saving mode
+    Off,
+}
+
+impl From<zwlr_output_power_v1::Mode> for OutputPowerMode {
+    fn from(mode: zwlr_output_power_v1::Mode) -> OutputPowerMode {
+        match mode {
+            zwlr_output_power_v1::Mode::Off => OutputPowerMode::Off,
+            _ => OutputPowerMode::On,
+        }
+    }
+}
+
+impl From<OutputPowerMode> for zwlr_output_power_v1::Mode {
+    fn from(mode: OutputPowerMode) -> zwlr_output_power_v1::Mode {
+        match mode {
+            OutputPowerMode::On => zwlr_output_power_v1::Mode::On,
+            OutputPowerMode::Off => zwlr_output_power_v1::Mode::Off,
+        }
+    }
+}
+
+// per-output state, stored in the user data of the output
+#[derive(Debug)]
+struct OutputPowerState {
+    mode: OutputPowerMode,
+    disabled: bool,
+    instances: Vec<ZwlrOutputPowerV1>,
+}
+
+impl Default for OutputPowerState {
+    fn default() -> Self {
+        OutputPowerState {
+            mode: OutputPowerMode::On,
+            disabled: false,
+            instances: Vec::new(),
+        }
+    }
+}
+
+fn with_power_state<F, T>(output: &Output, f: F) -> T
+where
+    F: FnOnce(&mut OutputPowerState) -> T,
+{
+    output
+        .user_data()
+        .insert_if_missing(|| RefCell::new(OutputPowerState::default()));
+    let state = output.user_data().get::<RefCell<OutputPowerState>>().unwrap();
+    let mut state = state.borrow_mut();
+    f(&mut *state)
+}
+
+/// Create a new `zwlr_output_power_manager_v1` global
+///
+/// `implementation` is invoked whenever a client requests a power mode change of an output.
+/// It is expected to apply the mode in the backend and to return whether this succeeded. If it
+/// did, the new mode is sent to all clients controlling the output, otherwise the power management
+/// object of the requesting client is invalidated.
+pub fn init_output_power_manager<F, L>(
+    display: &mut Display,
+    implementation: F,
+    logger: L,
+) -> Global<ZwlrOutputPowerManagerV1>
+where
+    F: FnMut(&Output, OutputPowerMode, DispatchData<'_>) -> bool + 'static,
+    L: Into<Option<::slog::Logger>>,
+{
+    let log = crate::slog_or_fallback(logger).new(o!("smithay_module" => "output_power_management"));
+    let implementation = Rc::new(RefCell::new(implementation));
+
+    display.create_global(
+        1,
+        Filter::new(
+            move |(manager, _version): (Main<ZwlrOutputPowerManagerV1>, u32), _, _| {
+                let implementation = implementation.clone();
+                let log = log.clone();
+                manager.quick_assign(move |_manager, request, _| match request {
+                    zwlr_output_power_manager_v1::Request::GetOutputPower { id, output } => {
+                        let output = match Output::from_resource(&output) {
+                            Some(output) => output,
+                            None => {
+                                debug!(log, "Power management requested for an unmanaged output");
+                                id.quick_assign(|_, _, _| {});
+                                id.failed();
+                                return;
+                            }
+                        };
+                        implement_output_power(id, output, implementation.clone(), log.clone());
+                    }
+                    zwlr_output_power_manager_v1::Request::Destroy => {}
+                    _ => unreachable!(),
+                });
+            },
+        ),
+    )
+}
+
+fn implement_output_power<F>(
+    power: Main<ZwlrOutputPowerV1>,
+    output: Output,
+    implementation: Rc<RefCell<F>>,
+    log: ::slog::Logger,
+) where
+    F: FnMut(&Output, OutputPowerMode, DispatchData<'_>) -> bool + 'static,
+{
+    let (mode, disabled) = with_power_state(&output, |state| (state.mode, state.disabled));
+    if disabled {
+        power.quick_assign(|_, _, _| {});
+        power.failed();
+        return;
+    }
+
+    let destructor_output = output.clone();
+    power.quick_assign(move |power, request, ddata| match request {
+        zwlr_output_power_v1::Request::SetMode { mode } => {
+            let mode = OutputPowerMode::from(mode);
+            let applied = !with_power_state(&output, |state| state.disabled)
+                && (&mut *implementation.borrow_mut())(&output, mode, ddata);
+            if applied {
+                set_output_power_mode(&output, mode);
+            } else {
+                debug!(log, "Failed to set the power mode of an output"; "mode" => ?mode);
+                with_power_state(&output, |state| {
+                    state.instances.retain(|p| !p.as_ref().equals(power.as_ref()))
+                });
+                power.failed();
+            }
+        }
+        zwlr_output_power_v1::Request::Destroy => {}
+        _ => unreachable!(),
+    });
+    power.assign_destructor(Filter::new(move |power: ZwlrOutputPowerV1, _, _| {
+        with_power_state(&destructor_output, |state| {
+            state.instances.retain(|p| !p.as_ref().equals(power.as_ref()))
+        });
+    }));
+
+    power.mode(mode.into());
+    with_power_state(&output, |state| state.instances.push(power.deref().clone()));
+}
+
+/// Returns the power mode of an output, as last set by a client or [`set_output_power_mode`]
+///
+/// Outputs are considered to be [`OutputPowerMode::On`] by default.
+pub fn output_power_mode(output: &Output) -> OutputPowerMode {
+    with_power_state(output, |state| state.mode)
+}
+
+/// Notify the clients about a power mode change of an output
+///
+/// Use this when the compositor changes the power of an output on its own, e.g. on user input.
+pub fn set_output_power_mode(output: &Output, mode: OutputPowerMode) {
+    with_power_state(output, |state| {
+        if state.mode == mode {
+            return;
+        }
+        state.mode = mode;
+        for power in &state.instances {
+            power.mode(mode.into());
+        }
+    })
+}
+
+/// Invalidate the power management objects of an output
+///
+/// All the clients controlling this output are notified that their power management object
+/// is no longer valid and the output can no longer be controlled by clients. Use this once the
+/// output is about to be removed.
+pub fn disable_output_power_management(output: &Output) {
+    with_power_state(output, |state| {
+        state.disabled = true;
+        for power in state.instances.drain(..) {
+            power.failed();
+        }
+    })
+}
@@ -0,0 +1,471 @@
+//! Utilities for manipulating the primary selection
+//!
+//! The primary selection is an additional clipboard, typically filled by selecting text and
+//! pasted using the middle mouse button. It is handled by the `zwp_primary_selection_v1`
+//! protocol and, like the regular selection handled by the [`data_device`](super::data_device)
+//! module, it is a per-seat notion.
+//!
+//! This module provides 2 main freestanding functions:
+//!
+//! - [`init_primary_selection`]: this function must be called
+//!   during the compositor startup to initialize the primary selection logic
+//! - [`set_primary_focus`]: this function sets
+//!   the primary selection focus for a given seat; you'd typically call it whenever the keyboard
+//!   focus changes, to follow it (for example in the focus hook of your keyboards)
+//!
+//! The compositor can also interact with the primary selection itself:
+//!
+//! - [`set_primary_selection`] sets the contents of the primary selection for your clients,
+//!   they are then requested through the callback given to [`init_primary_selection`]
+//! - [`request_primary_selection`] reads the primary selection currently set by a client
+//! - [`clear_primary_selection`] empties the primary selection
+//!
+//! These functions do nothing if [`init_primary_selection`] was never called.
+//!
+//! ## Initialization
+//!
+//! ```
+//! # extern crate wayland_server;
+//! use smithay::wayland::primary_selection::init_primary_selection;
+//!
+//! # let mut display = wayland_server::Display::new();
+//! // init the primary selection:
+//! init_primary_selection(
+//!     &mut display,            // the display
+//!     |event| { /* a callback to react to client primary selection actions */ },
+//!     None                     // insert a logger here
+//! );
+//! ```
+
+use std::{cell::RefCell, ops::Deref as _, os::unix::io::RawFd, rc::Rc};
+
+use wayland_protocols::unstable::primary_selection::v1::server::{
+    zwp_primary_selection_device_manager_v1::{self, ZwpPrimarySelectionDeviceManagerV1},
+    zwp_primary_selection_device_v1::{self, ZwpPrimarySelectionDeviceV1},
+    zwp_primary_selection_offer_v1::{self, ZwpPrimarySelectionOfferV1},
+    zwp_primary_selection_source_v1::{self, ZwpPrimarySelectionSourceV1},
+};
+use wayland_server::{Client, Display, Filter, Global, Main};
+
+use slog::{debug, error, o, warn};
+
+use crate::wayland::{
+    data_device::{SelectionWriter, SourceMetadataError},
+    seat::Seat,
+};
+
+/// Events that are generated by interactions of the clients with the primary selection
+#[derive(Debug)]
+pub enum PrimarySelectionEvent {
+    /// A client has set the primary selection
+    NewSelection(Option<ZwpPrimarySelectionSourceV1>),
+    /// A client requested to read the server-set primary selection
+    SendSelection {
+        /// the requested mime type
+        mime_type: String,
+        /// the writer to send the primary selection contents into
+        writer: SelectionWriter,
+    },
+}
+
+/// The metadata describing a primary selection source
+#[derive(Debug, Clone)]
+pub struct SourceMetadata {
+    /// The MIME types supported by this source
+    pub mime_types: Vec<String>,
+}
+
+/// Access the metadata of a primary selection source
+///
+/// Fails with [`SourceMetadataError::Dead`] if the source was destroyed, and with
+/// [`SourceMetadataError::NoMetadata`] if it was not created through Smithay.
+pub fn with_source_metadata<T, F: FnOnce(&SourceMetadata) -> T>(
+    source: &ZwpPrimarySelectionSourceV1,
+    f: F,
+) -> Result<T, SourceMetadataError> {
+    if !source.as_ref().is_alive() {
+        return Err(SourceMetadataError::Dead);
+    }
+    match source.as_ref().user_data().get::<RefCell<SourceMetadata>>() {
+        Some(data) => Ok(f(&data.borrow())),
+        None => Err(SourceMetadataError::NoMetadata),
+    }
+}
+
+/// Errors that can happen when requesting the primary selection
+#[derive(Debug, thiserror::Error)]
+pub enum SelectionRequestError {
+    /// There is no primary selection, or the primary selection global was not initialized
+    #[error("There is no primary selection")]
+    NoSelection,
+    /// The primary selection is set by the compositor itself
+    #[error("The primary selection is set by the compositor")]
+    ServerSideSelection,
+    /// The primary selection does not provide the requested mime type
+    #[error("The primary selection does not provide the requested mime type")]
+    InvalidMimetype,
+}
+
+enum Selection {
+    Empty,
+    Client(ZwpPrimarySelectionSourceV1),
+    Compositor(SourceMetadata),
+}
+
+struct SeatData {
+    known_devices: Vec<ZwpPrimarySelectionDeviceV1>,
+    selection: Selection,
+    log: ::slog::Logger,
+    current_focus: Option<Client>,
+}
+
+impl SeatData {
+    fn new(log: ::slog::Logger) -> SeatData {
+        SeatData {
+            known_devices: Vec::new(),
+            selection: Selection::Empty,
+            log,
+            current_focus: None,
+        }
+    }
+
+    fn set_selection(&mut self, new_selection: Selection) {
+        if let Selection::Client(ref old_source) = self.selection {
+            let replaced = match new_selection {
+                Selection::Client(ref new_source) => !new_source.as_ref().equals(old_source.as_ref()),
+                _ => true,
+            };
+            if replaced && old_source.as_ref().is_alive() {
+                old_source.cancelled();
+            }
+        }
+        self.selection = new_selection;
+        self.send_selection();
+    }
+
+    fn set_focus(&mut self, new_focus: Option<Client>) {
+        self.current_focus = new_focus;
+        self.send_selection();
+    }
+
+    fn send_selection(&mut self) {
+        self.send_selection_to(None);
+    }
+
+    // Send the selection to the devices of the focused client, or only to the given one
+    fn send_selection_to(&mut self, only: Option<&ZwpPrimarySelectionDeviceV1>) {
+        let client = match self.current_focus.as_ref() {
+            Some(c) => c,
+            None => return,
+        };
+        // first sanitize the selection, reseting it to null if the client holding
+        // it dropped it or its metadata is unavailable
+        let cleanup = if let Selection::Client(ref source) = self.selection {
+            match with_source_metadata(source, |_| ()) {
+                Ok(()) => false,
+                Err(err) => {
+                    debug!(self.log, "Treating the primary selection as empty: {}", err);
+                    true
+                }
+            }
+        } else {
+            false
+        };
+        if cleanup {
+            self.selection = Selection::Empty;
+        }
+        for device in &self.known_devices {
+            // skip devices not belonging to our client
+            if device
+                .as_ref()
+                .client()
+                .map(|c| !c.equals(client))
+                .unwrap_or(true)
+                || only
+                    .map(|only| !only.as_ref().equals(device.as_ref()))
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+            let mime_types = match self.selection {
+                Selection::Empty => {
+                    device.selection(None);
+                    continue;
+                }
+                Selection::Client(ref source) => {
+                    match with_source_metadata(source, |meta| meta.mime_types.clone()) {
+                        Ok(mime_types) => mime_types,
+                        Err(err) => {
+                            warn!(self.log, "Not sending the primary selection: {}", err);
+                            return;
+                        }
+                    }
+                }
+                Selection::Compositor(ref meta) => meta.mime_types.clone(),
+            };
+            let offer = client
+                .create_resource::<ZwpPrimarySelectionOfferV1>(device.as_ref().version())
+                .unwrap();
+            let log = self.log.clone();
+            match self.selection {
+                Selection::Client(ref source) => {
+                    let source = source.clone();
+                    offer.quick_assign(move |_offer, req, _| {
+                        if let zwp_primary_selection_offer_v1::Request::Receive { mime_type, fd } = req {
+                            // check if the source and associated mime type is still valid
+                            let valid =
+                                with_source_metadata(&source, |meta| meta.mime_types.contains(&mime_type))
+                                    .unwrap_or(false)
+                                    && source.as_ref().is_alive();
+                            if !valid {
+                                // deny the receive
+                                debug!(log, "Denying a primary selection receive with invalid source.");
+                            } else {
+                                source.send(mime_type, fd);
+                            }
+                            let _ = ::nix::unistd::close(fd);
+                        }
+                    });
+                }
+                _ => {
+                    let offer_mime_types = mime_types.clone();
+                    let callback = device
+                        .as_ref()
+                        .user_data()
+                        .get::<DeviceData>()
+                        .unwrap()
+                        .callback
+                        .clone();
+                    offer.quick_assign(move |_offer, req, _| {
+                        if let zwp_primary_selection_offer_v1::Request::Receive { mime_type, fd } = req {
+                            // check if the associated mime type is valid
+                            if !offer_mime_types.contains(&mime_type) {
+                                // deny the receive
+                                debug!(log, "Denying a primary selection receive with invalid source.");
+                                let _ = ::nix::unistd::close(fd);
+                            } else {
+                                (&mut *callback.borrow_mut())(PrimarySelectionEvent::SendSelection {
+                                    mime_type,
+                                    writer: SelectionWriter::new(fd),
+                                });
+                            }
+                        }
+                    });
+                }
+            }
+            // advertize the offer to the client
+            device.data_offer(&offer);
+            for mime_type in mime_types {
+                offer.offer(mime_type);
+            }
+            device.selection(Some(&offer));
+        }
+    }
+}
+
+/// Initialize the primary selection global
+///
+/// You can provide a callback to peek into the actions of your clients over the primary
+/// selection devices. See the [`PrimarySelectionEvent`] type for details about what
+/// notifications you can receive.
+pub fn init_primary_selection<C, L>(
+    display: &mut Display,
+    callback: C,
+    logger: L,
+) -> Global<ZwpPrimarySelectionDeviceManagerV1>
+where
+    C: FnMut(PrimarySelectionEvent) + 'static,
+    L: Into<Option<::slog::Logger>>,
+{
+    let log = crate::slog_or_fallback(logger).new(o!("smithay_module" => "primary_selection"));
+    let callback = Rc::new(RefCell::new(callback));
+    display.create_global(
+        1,
+        Filter::new(move |(manager, _version), _, _| {
+            implement_manager(manager, callback.clone(), log.clone());
+        }),
+    )
+}
+
+// The primary selection state of the seat, initialized if the seat has no primary
+// selection device yet
+fn seat_data(seat: &Seat) -> &RefCell<SeatData> {
+    // TODO: find a better way to retrieve a logger without requiring the user
+    // to provide one ?
+    seat.user_data().insert_if_missing(|| {
+        RefCell::new(SeatData::new(
+            seat.arc.log.new(o!("smithay_module" => "primary_selection")),
+        ))
+    });
+    seat.user_data().get::<RefCell<SeatData>>().unwrap()
+}
+
+/// Set the primary selection focus to a certain client for a given seat
+pub fn set_primary_focus(seat: &Seat, client: Option<Client>) {
+    let seat_data = seat_data(seat);
+    seat_data.borrow_mut().set_focus(client);
+}
+
+/// Set a compositor-provided primary selection for this seat
+///
+/// You need to provide the available mime types for this selection.
+///
+/// Whenever a client requests to read the primary selection, your callback will
+/// receive a [`PrimarySelectionEvent::SendSelection`] event.
+pub fn set_primary_selection(seat: &Seat, mime_types: Vec<String>) {
+    let seat_data = seat_data(seat);
+    seat_data
+        .borrow_mut()
+        .set_selection(Selection::Compositor(SourceMetadata { mime_types }));
+}
+
+/// Request the current primary selection of this seat to be written into the given fd
+///
+/// This only works if the primary selection is currently provided by a client, the given
+/// fd is closed in any case.
+///
+/// Returns [`SelectionRequestError::NoSelection`] if the primary selection global was not
+/// initialized.
+pub fn request_primary_selection(
+    seat: &Seat,
+    mime_type: String,
+    fd: RawFd,
+) -> Result<(), SelectionRequestError> {
+    let result = match seat.user_data().get::<RefCell<SeatData>>() {
+        Some(seat_data) => match seat_data.borrow().selection {
+            Selection::Client(ref source) if source.as_ref().is_alive() => {
+                let valid = with_source_metadata(source, |meta| meta.mime_types.contains(&mime_type))
+                    .unwrap_or(false);
+                if valid {
+                    source.send(mime_type, fd);
+                    Ok(())
+                } else {
+                    Err(SelectionRequestError::InvalidMimetype)
+                }
+            }
+            Selection::Compositor(_) => Err(SelectionRequestError::ServerSideSelection),
+            _ => Err(SelectionRequestError::NoSelection),
+        },
+        None => Err(SelectionRequestError::NoSelection),
+    };
+    let _ = ::nix::unistd::close(fd);
+    result
+}
+
+/// Clear the primary selection of this seat
+///
+/// If a client provided the primary selection, its source is cancelled.
+pub fn clear_primary_selection(seat: &Seat) {
+    let seat_data = seat_data(seat);
+    seat_data.borrow_mut().set_selection(Selection::Empty);
+}
+
+fn implement_manager<C>(
+    manager: Main<ZwpPrimarySelectionDeviceManagerV1>,
+    callback: Rc<RefCell<C>>,
+    log: ::slog::Logger,
+) -> ZwpPrimarySelectionDeviceManagerV1
+where
+    C: FnMut(PrimarySelectionEvent) + 'static,
+{
+    use self::zwp_primary_selection_device_manager_v1::Request;
+    manager.quick_assign(move |_manager, req, _| match req {
+        Request::CreateSource { id } => {
+            implement_source(id);
+        }
+        Request::GetDevice { id, seat } => match Seat::from_resource(&seat) {
+            Some(seat) => {
+                // ensure the seat user_data is ready
+                seat.user_data()
+                    .insert_if_missing(|| RefCell::new(SeatData::new(log.clone())));
+                let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
+                let device = implement_device(id, seat.clone(), callback.clone(), log.clone());
+                let mut seat_data = seat_data.borrow_mut();
+                seat_data.known_devices.push(device.clone());
+                // the client may already have the focus, in which case the new device
+                // did not receive the current selection yet
+                seat_data.send_selection_to(Some(&device));
+            }
+            None => {
+                error!(log, "Unmanaged seat given to a primary selection device.");
+            }
+        },
+        Request::Destroy => {}
+        _ => unreachable!(),
+    });
+
+    manager.deref().clone()
+}
+
+fn implement_source(source: Main<ZwpPrimarySelectionSourceV1>) -> ZwpPrimarySelectionSourceV1 {
+    use self::zwp_primary_selection_source_v1::Request;
+    source.quick_assign(|me, req, _| {
+        let data: &RefCell<SourceMetadata> = me.as_ref().user_data().get().unwrap();
+        match req {
+            Request::Offer { mime_type } => data.borrow_mut().mime_types.push(mime_type),
+            Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    });
+    source.as_ref().user_data().set(|| {
+        RefCell::new(SourceMetadata {
+            mime_types: Vec::new(),
+        })
+    });
+
+    source.deref().clone()
+}
+
+struct DeviceData {
+    callback: Rc<RefCell<dyn FnMut(PrimarySelectionEvent) + 'static>>,
+}
+
+fn implement_device<C>(
+    device: Main<ZwpPrimarySelectionDeviceV1>,
+    seat: Seat,
+    callback: Rc<RefCell<C>>,
+    log: ::slog::Logger,
+) -> ZwpPrimarySelectionDeviceV1
+where
+    C: FnMut(PrimarySelectionEvent) + 'static,
+{
+    use self::zwp_primary_selection_device_v1::Request;
+    let device_data = DeviceData {
+        callback: callback.clone(),
+    };
+    device.quick_assign(move |device, req, _| match req {
+        Request::SetSelection { source, .. } => {
+            if let Some(keyboard) = seat.get_keyboard() {
+                if device
+                    .as_ref()
+                    .client()
+                    .as_ref()
+                    .map(|c| keyboard.has_focus(c))
+                    .unwrap_or(false)
+                {
+                    let seat_data = seat.user_data().get::<RefCell<SeatData>>().unwrap();
+                    (&mut *callback.borrow_mut())(PrimarySelectionEvent::NewSelection(source.clone()));
+                    // The client has kbd focus, it can set the selection
+                    seat_data
+                        .borrow_mut()
+                        .set_selection(source.map(Selection::Client).unwrap_or(Selection::Empty));
+                    return;
+                }
+            }
+            debug!(log, "denying setting primary selection by a non-focused client");
+        }
+        Request::Destroy => {
+            // Clean up the known devices
+            seat.user_data()
+                .get::<RefCell<SeatData>>()
+                .unwrap()
+                .borrow_mut()
+                .known_devices
+                .retain(|d| d.as_ref().is_alive() && (!d.as_ref().equals(device.as_ref())))
+        }
+        _ => unreachable!(),
+    });
+    device.as_ref().user_data().set(|| device_data);
+
+    device.deref().clone()
+}
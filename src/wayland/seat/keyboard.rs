@@ -1,16 +1,25 @@
+use super::{
+    keymap::{compile_keymap, KeymapCache, KeymapFile},
+    SerialEvent, SerialTracker,
+};
 use crate::backend::input::KeyState;
-use crate::wayland::Serial;
+use crate::wayland::{Serial, SERIAL_COUNTER};
+use calloop::{
+    timer::{Timeout, Timer, TimerHandle},
+    LoopHandle,
+};
 use slog::{debug, info, o, trace, warn};
 use std::{
     cell::RefCell,
+    collections::HashSet,
     default::Default,
     fmt,
-    io::{Error as IoError, Seek, Write},
+    io::Error as IoError,
     ops::Deref as _,
-    os::unix::io::AsRawFd,
     rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use tempfile::tempfile;
 use thiserror::Error;
 use wayland_server::{
     protocol::{
@@ -55,6 +64,59 @@ impl ModifiersState {
         self.logo = state.mod_name_is_active(&xkb::MOD_NAME_LOGO, xkb::STATE_MODS_EFFECTIVE);
         self.num_lock = state.mod_name_is_active(&xkb::MOD_NAME_NUM, xkb::STATE_MODS_EFFECTIVE);
     }
+
+    /// Check if these modifiers match the given pattern
+    ///
+    /// Modifiers the pattern does not care about are ignored.
+    pub fn matches(&self, pattern: &ModifiersMatch) -> bool {
+        let matches = |active: bool, expected: Option<bool>| expected.map_or(true, |e| e == active);
+        matches(self.ctrl, pattern.ctrl)
+            && matches(self.alt, pattern.alt)
+            && matches(self.shift, pattern.shift)
+            && matches(self.caps_lock, pattern.caps_lock)
+            && matches(self.logo, pattern.logo)
+            && matches(self.num_lock, pattern.num_lock)
+    }
+}
+
+/// Pattern of modifiers, for example to describe the modifiers of a keybinding
+///
+/// Each field is `Some(true)` if the modifier is required to be active, `Some(false)` if it is
+/// required to be inactive and `None` if its state does not matter. The default pattern matches
+/// any modifiers.
+///
+/// See [`ModifiersState::matches`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ModifiersMatch {
+    /// The "control" key
+    pub ctrl: Option<bool>,
+    /// The "alt" key
+    pub alt: Option<bool>,
+    /// The "shift" key
+    pub shift: Option<bool>,
+    /// The "Caps lock" key
+    pub caps_lock: Option<bool>,
+    /// The "logo" key
+    pub logo: Option<bool>,
+    /// The "Num lock" key
+    pub num_lock: Option<bool>,
+}
+
+impl ModifiersMatch {
+    /// Pattern requiring exactly the given modifiers, ignoring the lock modifiers
+    ///
+    /// This is what keybindings usually expect: "Logo+Shift+Q" should trigger regardless of
+    /// caps lock or num lock being toggled, but not if control is also pressed.
+    pub fn exactly(modifiers: ModifiersState) -> ModifiersMatch {
+        ModifiersMatch {
+            ctrl: Some(modifiers.ctrl),
+            alt: Some(modifiers.alt),
+            shift: Some(modifiers.shift),
+            caps_lock: None,
+            logo: Some(modifiers.logo),
+            num_lock: None,
+        }
+    }
 }
 
 /// Configuration for xkbcommon.
@@ -103,7 +165,30 @@ struct KbdInternal {
     repeat_rate: i32,
     repeat_delay: i32,
     focus_hook: Box<dyn FnMut(Option<&WlSurface>)>,
+    // shared so that it can be called without borrowing the keyboard
+    raw_filter: Option<Rc<RefCell<dyn FnMut(u32, KeyState, u32) -> RawFilterResult>>>,
     grab: GrabStatus,
+    repeat: Option<KeyRepeat>,
+    no_repeat_keys: HashSet<u32>,
+    // layout last active on each surface, if per-window layouts are enabled
+    window_layouts: Option<Vec<(WlSurface, u32)>>,
+    serials: SerialTracker,
+}
+
+// server-side key repeat, see `KeyboardHandle::with_repeat`
+#[derive(Debug)]
+struct KeyRepeat {
+    timer: TimerHandle<u32>,
+    current: Option<RepeatingKey>,
+}
+
+#[derive(Debug)]
+struct RepeatingKey {
+    keycode: u32,
+    // time of the initial key press and when it was processed
+    time: u32,
+    pressed_at: Instant,
+    timeout: Timeout,
 }
 
 // focus_hook does not implement debug, so we have to impl Debug manually
@@ -119,6 +204,11 @@ impl fmt::Debug for KbdInternal {
             .field("repeat_rate", &self.repeat_rate)
             .field("repeat_delay", &self.repeat_delay)
             .field("focus_hook", &"...")
+            .field("raw_filter", &self.raw_filter.as_ref().map(|_| "..."))
+            .field("repeat", &self.repeat)
+            .field("no_repeat_keys", &self.no_repeat_keys)
+            .field("window_layouts", &self.window_layouts)
+            .field("serials", &self.serials)
             .finish()
     }
 }
@@ -129,30 +219,14 @@ unsafe impl Send for KbdInternal {}
 
 impl KbdInternal {
     fn new(
-        xkb_config: XkbConfig<'_>,
+        keymap: xkb::Keymap,
         repeat_rate: i32,
         repeat_delay: i32,
         focus_hook: Box<dyn FnMut(Option<&WlSurface>)>,
-    ) -> Result<KbdInternal, ()> {
-        // we create a new contex for each keyboard because libxkbcommon is actually NOT threadsafe
-        // so confining it inside the KbdInternal allows us to use Rusts mutability rules to make
-        // sure nothing goes wrong.
-        //
-        // FIXME: This is an issue with the xkbcommon-rs crate that does not reflect this
-        // non-threadsafety properly.
-        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
-        let keymap = xkb::Keymap::new_from_names(
-            &context,
-            &xkb_config.rules,
-            &xkb_config.model,
-            &xkb_config.layout,
-            &xkb_config.variant,
-            xkb_config.options,
-            xkb::KEYMAP_COMPILE_NO_FLAGS,
-        )
-        .ok_or(())?;
+        serials: SerialTracker,
+    ) -> KbdInternal {
         let state = xkb::State::new(&keymap);
-        Ok(KbdInternal {
+        KbdInternal {
             known_kbds: Vec::new(),
             focus: None,
             pending_focus: None,
@@ -163,12 +237,19 @@ impl KbdInternal {
             repeat_rate,
             repeat_delay,
             focus_hook,
+            raw_filter: None,
             grab: GrabStatus::None,
-        })
+            repeat: None,
+            no_repeat_keys: HashSet::new(),
+            window_layouts: None,
+            serials,
+        }
     }
 
     // return true if modifier state has changed
-    fn key_input(&mut self, keycode: u32, state: KeyState) -> bool {
+    //
+    // `keycode` is the evdev keycode, `xkb_keycode` the same key in X's keycode system
+    fn key_input(&mut self, keycode: u32, xkb_keycode: u32, state: KeyState) -> bool {
         // track pressed keys as xkbcommon does not seem to expose it :(
         let direction = match state {
             KeyState::Pressed => {
@@ -182,9 +263,7 @@ impl KbdInternal {
         };
 
         // update state
-        // Offset the keycode by 8, as the evdev XKB rules reflect X's
-        // broken keycode system, which starts at 8.
-        let state_components = self.state.update_key(keycode + 8, direction);
+        let state_components = self.state.update_key(xkb_keycode, direction);
 
         if state_components != 0 {
             self.mods_state.update_with(&self.state);
@@ -194,6 +273,76 @@ impl KbdInternal {
         }
     }
 
+    // (re-)arm or cancel the repeat timer, if server-side key repeat is enabled
+    fn update_repeat(&mut self, keycode: u32, xkb_keycode: u32, state: KeyState, time: u32) {
+        let repeats = self.repeat_rate > 0
+            && self.keymap.key_repeats(xkb_keycode)
+            && !self.no_repeat_keys.contains(&keycode);
+        let delay = Duration::from_millis(self.repeat_delay.max(0) as u64);
+        let repeat = match self.repeat.as_mut() {
+            Some(repeat) => repeat,
+            None => return,
+        };
+
+        match state {
+            KeyState::Pressed => {
+                // only the last pressed key is repeated
+                if let Some(current) = repeat.current.take() {
+                    repeat.timer.cancel_timeout(&current.timeout);
+                }
+                if repeats {
+                    repeat.current = Some(RepeatingKey {
+                        keycode,
+                        time,
+                        pressed_at: Instant::now(),
+                        timeout: repeat.timer.add_timeout(delay, keycode),
+                    });
+                }
+            }
+            KeyState::Released => {
+                if repeat
+                    .current
+                    .as_ref()
+                    .map(|c| c.keycode == keycode)
+                    .unwrap_or(false)
+                {
+                    let current = repeat.current.take().unwrap();
+                    repeat.timer.cancel_timeout(&current.timeout);
+                }
+            }
+        }
+    }
+
+    // remember the layout active on the current focus, if per-window layouts are enabled
+    fn save_focus_layout(&mut self) {
+        let (focus, layouts) = match (self.focus.as_ref(), self.window_layouts.as_mut()) {
+            (Some(focus), Some(layouts)) => (focus, layouts),
+            _ => return,
+        };
+        let layout = self.state.serialize_layout(xkb::STATE_LAYOUT_LOCKED);
+        layouts
+            .retain(|(surface, _)| surface.as_ref().is_alive() && !surface.as_ref().equals(focus.as_ref()));
+        if focus.as_ref().is_alive() {
+            layouts.push((focus.clone(), layout));
+        }
+    }
+
+    // switch to the layout last active on the current focus, the first one for unknown surfaces
+    fn restore_focus_layout(&mut self) {
+        let layout = match (self.focus.as_ref(), self.window_layouts.as_ref()) {
+            (Some(focus), Some(layouts)) => layouts
+                .iter()
+                .find(|(surface, _)| surface.as_ref().equals(focus.as_ref()))
+                .map(|&(_, layout)| layout)
+                .unwrap_or(0),
+            _ => return,
+        };
+        let (depressed, latched, locked, _) = self.serialize_modifiers();
+        if self.state.update_mask(depressed, latched, locked, 0, 0, layout) != 0 {
+            self.mods_state.update_with(&self.state);
+        }
+    }
+
     fn serialize_modifiers(&self) -> (u32, u32, u32, u32) {
         let mods_depressed = self.state.serialize_mods(xkb::STATE_MODS_DEPRESSED);
         let mods_latched = self.state.serialize_mods(xkb::STATE_MODS_LATCHED);
@@ -203,14 +352,13 @@ impl KbdInternal {
         (mods_depressed, mods_latched, mods_locked, layout_locked)
     }
 
+    // wl_array contents are sent as is, so the keycodes are in host byte order like the
+    // rest of the wayland wire format
     fn serialize_pressed_keys(&self) -> Vec<u8> {
-        let serialized = unsafe {
-            ::std::slice::from_raw_parts(
-                self.pressed_keys.as_ptr() as *const u8,
-                self.pressed_keys.len() * 4,
-            )
-        };
-        serialized.into()
+        self.pressed_keys
+            .iter()
+            .flat_map(|key| key.to_ne_bytes())
+            .collect()
     }
 
     fn with_focused_kbds<F>(&self, mut f: F)
@@ -226,22 +374,38 @@ impl KbdInternal {
         }
     }
 
+    // If the grab is associated with a surface that is no longer alive, discard it
+    fn drop_dead_grab(&mut self, logger: &::slog::Logger) {
+        let dead = match self.grab {
+            GrabStatus::Active(_, ref handler) => handler
+                .start_data()
+                .focus
+                .as_ref()
+                .map(|surface| !surface.as_ref().is_alive())
+                .unwrap_or(false),
+            _ => false,
+        };
+        if dead {
+            debug!(logger, "Dropping keyboard grab, its focus surface was destroyed");
+            let grab = ::std::mem::replace(&mut self.grab, GrabStatus::None);
+            if let GrabStatus::Active(_, mut handler) = grab {
+                handler.unset(&mut KeyboardInnerHandle {
+                    inner: self,
+                    logger: logger.clone(),
+                });
+            }
+        }
+    }
+
     fn with_grab<F>(&mut self, f: F, logger: ::slog::Logger)
     where
         F: FnOnce(KeyboardInnerHandle<'_>, &mut dyn KeyboardGrab),
     {
+        self.drop_dead_grab(&logger);
         let mut grab = ::std::mem::replace(&mut self.grab, GrabStatus::Borrowed);
         match grab {
             GrabStatus::Borrowed => panic!("Accessed a keyboard grab from within a keyboard grab access."),
             GrabStatus::Active(_, ref mut handler) => {
-                // If this grab is associated with a surface that is no longer alive, discard it
-                if let Some(ref surface) = handler.start_data().focus {
-                    if !surface.as_ref().is_alive() {
-                        self.grab = GrabStatus::None;
-                        f(KeyboardInnerHandle { inner: self, logger }, &mut DefaultGrab);
-                        return;
-                    }
-                }
                 f(KeyboardInnerHandle { inner: self, logger }, &mut **handler);
             }
             GrabStatus::None => {
@@ -262,8 +426,8 @@ pub enum Error {
     /// libxkbcommon could not load the specified keymap
     #[error("Libxkbcommon could not load the specified keymap")]
     BadKeymap,
-    /// Smithay could not create a tempfile to share the keymap with clients
-    #[error("Failed to create tempfile to share the keymap: {0}")]
+    /// Smithay could neither create a memfd nor a tempfile to share the keymap with clients
+    #[error("Failed to create a file to share the keymap: {0}")]
     IoError(IoError),
 }
 
@@ -273,6 +437,8 @@ pub(crate) fn create_keyboard_handler<F>(
     repeat_delay: i32,
     repeat_rate: i32,
     logger: &::slog::Logger,
+    serials: SerialTracker,
+    keymap_cache: &KeymapCache,
     focus_hook: F,
 ) -> Result<KeyboardHandle, Error>
 where
@@ -283,20 +449,26 @@ where
         "rules" => xkb_config.rules, "model" => xkb_config.model, "layout" => xkb_config.layout,
         "variant" => xkb_config.variant, "options" => &xkb_config.options
     );
-    let internal =
-        KbdInternal::new(xkb_config, repeat_rate, repeat_delay, Box::new(focus_hook)).map_err(|_| {
-            debug!(log, "Loading keymap failed");
-            Error::BadKeymap
-        })?;
-
-    info!(log, "Loaded Keymap"; "name" => internal.keymap.layouts().next());
-
-    let keymap = internal.keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
+    // we create a new contex for each keyboard because libxkbcommon is actually NOT threadsafe
+    // so confining it inside the KbdInternal allows us to use Rusts mutability rules to make
+    // sure nothing goes wrong.
+    //
+    // FIXME: This is an issue with the xkbcommon-rs crate that does not reflect this
+    // non-threadsafety properly.
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    let (keymap, keymap_file) = compile_keymap(&context, xkb_config, keymap_cache, &log).map_err(|err| {
+        debug!(log, "Loading keymap failed"; "err" => format!("{:?}", err));
+        err
+    })?;
+
+    info!(log, "Loaded Keymap"; "name" => keymap.layouts().next());
+
+    let internal = KbdInternal::new(keymap, repeat_rate, repeat_delay, Box::new(focus_hook), serials);
 
     Ok(KeyboardHandle {
         arc: Rc::new(KbdRc {
             internal: RefCell::new(internal),
-            keymap,
+            keymap: keymap_file,
             logger: log,
         }),
     })
@@ -305,7 +477,7 @@ where
 #[derive(Debug)]
 struct KbdRc {
     internal: RefCell<KbdInternal>,
-    keymap: String,
+    keymap: Arc<KeymapFile>,
     logger: ::slog::Logger,
 }
 
@@ -359,6 +531,55 @@ pub enum FilterResult<T> {
     Intercept(T),
 }
 
+/// Result of the raw key filter (see [`KeyboardHandle::set_raw_filter`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFilterResult {
+    /// Process the keystroke as is
+    Forward,
+    /// Process the keystroke with the given evdev keycode instead
+    Rewrite(u32),
+    /// Drop the keystroke entirely, it does not affect the keyboard state
+    Drop,
+}
+
+/// Keyboards notified of a repeat info change (see [`KeyboardHandle::set_repeat_info`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RepeatInfoNotified {
+    /// Number of bound `wl_keyboard`s that received the new repeat info
+    pub notified: usize,
+    /// Number of bound `wl_keyboard`s too old to receive it (version < 4)
+    ///
+    /// The clients of these keyboards repeat keys on their own with their own settings, or rely
+    /// on server-side repeat (see [`KeyboardHandle::with_repeat`]).
+    pub outdated: usize,
+}
+
+/// Outcome of a keystroke handled by [`KeyboardHandle::input_with_keysym`]
+#[derive(Debug)]
+pub struct KeyInput<T> {
+    /// State of the modifiers after the keystroke was processed
+    pub modifiers: ModifiersState,
+    /// Sym produced by the keystroke, see [`KeysymHandle::modified_sym`]
+    pub keysym: Keysym,
+    /// Value returned by the filter if it intercepted the keystroke
+    pub intercepted: Option<T>,
+    /// Whether the keystroke was dropped by the [raw filter](KeyboardHandle::set_raw_filter)
+    ///
+    /// A dropped keystroke never reaches the input filter, its `keysym` is [`keysyms::KEY_NoSymbol`].
+    pub dropped: bool,
+    /// Whether the keystroke was a press of a key already held
+    ///
+    /// Such a press leaves the keymap state untouched and is never forwarded to the clients.
+    pub repeated: bool,
+}
+
+impl<T> KeyInput<T> {
+    /// Returns true, if the keystroke was forwarded to the clients
+    pub fn forwarded(&self) -> bool {
+        !self.dropped && !self.repeated && self.intercepted.is_none()
+    }
+}
+
 /// Data about the event that started the grab.
 #[derive(Debug, Clone)]
 pub struct GrabStartData {
@@ -398,6 +619,15 @@ pub trait KeyboardGrab {
 
     /// The data about the event that started the grab.
     fn start_data(&self) -> &GrabStartData;
+
+    /// The grab is forcibly ended by the server
+    ///
+    /// This is called right before the grab is dropped, if it did not end by its own logic: because
+    /// the surface it was started on was destroyed, or because the compositor replaced or removed it
+    /// using the [`KeyboardHandle`]. Unlike the destructor, it can still interact with the keyboard.
+    ///
+    /// The default implementation does nothing.
+    fn unset(&mut self, _handle: &mut KeyboardInnerHandle<'_>) {}
 }
 
 /// An handle to a keyboard handler
@@ -421,17 +651,47 @@ impl KeyboardHandle {
     ///
     /// Overwrites any current grab.
     pub fn set_grab<G: KeyboardGrab + 'static>(&self, grab: G, serial: Serial) {
-        self.arc.internal.borrow_mut().grab = GrabStatus::Active(serial, Box::new(grab));
+        self.replace_grab(GrabStatus::Active(serial, Box::new(grab)));
+    }
+
+    /// Start a grab on this keyboard, with start data capturing its current focus
+    ///
+    /// `make_grab` is given the [`GrabStartData`] and builds the grab, which then replaces
+    /// any current grab like with [`KeyboardHandle::set_grab`].
+    pub fn start_grab_with_current_focus<G, F>(&self, serial: Serial, make_grab: F)
+    where
+        G: KeyboardGrab + 'static,
+        F: FnOnce(GrabStartData) -> G,
+    {
+        let start_data = GrabStartData {
+            focus: self.current_focus(),
+        };
+        self.set_grab(make_grab(start_data), serial);
     }
 
     /// Remove any current grab on this keyboard, resetting it to the default behavior
     pub fn unset_grab(&self) {
-        self.arc.internal.borrow_mut().grab = GrabStatus::None;
+        self.replace_grab(GrabStatus::None);
+    }
+
+    // the replaced grab is notified through `KeyboardGrab::unset`
+    fn replace_grab(&self, grab: GrabStatus) {
+        let mut guard = self.arc.internal.borrow_mut();
+        if let GrabStatus::Active(_, mut old) = ::std::mem::replace(&mut guard.grab, grab) {
+            old.unset(&mut KeyboardInnerHandle {
+                inner: &mut *guard,
+                logger: self.arc.logger.clone(),
+            });
+        }
     }
 
     /// Check if this keyboard is currently grabbed with this serial
+    ///
+    /// A grab whose focus surface was destroyed is ended by this check, like by the
+    /// following keyboard event.
     pub fn has_grab(&self, serial: Serial) -> bool {
-        let guard = self.arc.internal.borrow_mut();
+        let mut guard = self.arc.internal.borrow_mut();
+        guard.drop_dead_grab(&self.arc.logger);
         match guard.grab {
             GrabStatus::Active(s, _) => s == serial,
             _ => false,
@@ -440,13 +700,17 @@ impl KeyboardHandle {
 
     /// Check if this keyboard is currently being grabbed
     pub fn is_grabbed(&self) -> bool {
-        let guard = self.arc.internal.borrow_mut();
+        let mut guard = self.arc.internal.borrow_mut();
+        guard.drop_dead_grab(&self.arc.logger);
         !matches!(guard.grab, GrabStatus::None)
     }
 
     /// Returns the start data for the grab, if any.
+    ///
+    /// Returns `None` once the focus surface of the grab was destroyed, the grab is then ended.
     pub fn grab_start_data(&self) -> Option<GrabStartData> {
-        let guard = self.arc.internal.borrow();
+        let mut guard = self.arc.internal.borrow_mut();
+        guard.drop_dead_grab(&self.arc.logger);
         match &guard.grab {
             GrabStatus::Active(_, g) => Some(g.start_data().clone()),
             _ => None,
@@ -466,6 +730,10 @@ impl KeyboardHandle {
     ///
     /// The module [`crate::wayland::seat::keysyms`] exposes definitions of all possible keysyms
     /// to be compared against. This includes non-character keysyms, such as XF86 special keys.
+    ///
+    /// The `keycode` is expected to be an evdev keycode, as provided by the input backends. If your
+    /// input source already provides keycodes in the X keycode system (as expected by xkbcommon),
+    /// use [`KeyboardHandle::input_raw_xkb`] instead.
     pub fn input<T, F>(
         &self,
         keycode: u32,
@@ -474,28 +742,140 @@ impl KeyboardHandle {
         time: u32,
         filter: F,
     ) -> Option<T>
+    where
+        F: FnOnce(&ModifiersState, KeysymHandle<'_>) -> FilterResult<T>,
+    {
+        // Offset the keycode by 8, as the evdev XKB rules reflect X's
+        // broken keycode system, which starts at 8.
+        self.input_internal(keycode, keycode + 8, state, serial, time, filter)
+            .intercepted
+    }
+
+    /// Handle a keystroke and report the resulting keysym
+    ///
+    /// This behaves exactly like [`KeyboardHandle::input`], but the returned [`KeyInput`] always contains
+    /// the modifiers state and keysym resolved for the keystroke, regardless of whether it was
+    /// intercepted by the filter or forwarded to the clients.
+    pub fn input_with_keysym<T, F>(
+        &self,
+        keycode: u32,
+        state: KeyState,
+        serial: Serial,
+        time: u32,
+        filter: F,
+    ) -> KeyInput<T>
+    where
+        F: FnOnce(&ModifiersState, KeysymHandle<'_>) -> FilterResult<T>,
+    {
+        self.input_internal(keycode, keycode + 8, state, serial, time, filter)
+    }
+
+    /// Handle a keystroke given as an xkb keycode
+    ///
+    /// This behaves exactly like [`KeyboardHandle::input`], except that `xkb_keycode` is expected
+    /// to already be in the X keycode system used by xkbcommon (which is the evdev keycode offset
+    /// by 8). No further offset is applied before it is fed to the keymap, and the corresponding
+    /// evdev keycode is forwarded to the clients.
+    ///
+    /// Valid xkb keycodes start at 8, smaller values are ignored and return `None`.
+    pub fn input_raw_xkb<T, F>(
+        &self,
+        xkb_keycode: u32,
+        state: KeyState,
+        serial: Serial,
+        time: u32,
+        filter: F,
+    ) -> Option<T>
+    where
+        F: FnOnce(&ModifiersState, KeysymHandle<'_>) -> FilterResult<T>,
+    {
+        if xkb_keycode < 8 {
+            warn!(self.arc.logger, "Ignoring invalid xkb keycode"; "keycode" => xkb_keycode);
+            return None;
+        }
+        self.input_internal(xkb_keycode - 8, xkb_keycode, state, serial, time, filter)
+            .intercepted
+    }
+
+    fn input_internal<T, F>(
+        &self,
+        mut keycode: u32,
+        mut xkb_keycode: u32,
+        state: KeyState,
+        serial: Serial,
+        time: u32,
+        filter: F,
+    ) -> KeyInput<T>
     where
         F: FnOnce(&ModifiersState, KeysymHandle<'_>) -> FilterResult<T>,
     {
         trace!(self.arc.logger, "Handling keystroke"; "keycode" => keycode, "state" => format_args!("{:?}", state));
+        let raw_filter = self.arc.internal.borrow().raw_filter.clone();
+        if let Some(raw_filter) = raw_filter {
+            let result = (&mut *raw_filter.borrow_mut())(keycode, state, time);
+            match result {
+                RawFilterResult::Forward => {}
+                RawFilterResult::Rewrite(new_keycode) => {
+                    trace!(self.arc.logger, "Keycode rewritten by raw filter"; "keycode" => new_keycode);
+                    xkb_keycode = new_keycode + 8;
+                    keycode = new_keycode;
+                }
+                RawFilterResult::Drop => {
+                    trace!(self.arc.logger, "Input was dropped by raw filter");
+                    return KeyInput {
+                        modifiers: self.arc.internal.borrow().mods_state,
+                        keysym: keysyms::KEY_NoSymbol,
+                        intercepted: None,
+                        dropped: true,
+                        repeated: false,
+                    };
+                }
+            }
+        }
         let mut guard = self.arc.internal.borrow_mut();
-        let mods_changed = guard.key_input(keycode, state);
+        let repeated = state == KeyState::Pressed && guard.pressed_keys.contains(&keycode);
+        let mods_changed = if repeated {
+            // a repeated press of a held key does not change the keymap state
+            false
+        } else {
+            guard.update_repeat(keycode, xkb_keycode, state, time);
+            guard.key_input(keycode, xkb_keycode, state)
+        };
         let handle = KeysymHandle {
-            // Offset the keycode by 8, as the evdev XKB rules reflect X's
-            // broken keycode system, which starts at 8.
-            keycode: keycode + 8,
+            keycode: xkb_keycode,
             state: &guard.state,
             keymap: &guard.keymap,
         };
 
+        let keysym = handle.modified_sym();
+        let mods_state = guard.mods_state;
+
         trace!(self.arc.logger, "Calling input filter";
-            "mods_state" => format_args!("{:?}", guard.mods_state), "sym" => xkb::keysym_get_name(handle.modified_sym())
+            "mods_state" => format_args!("{:?}", mods_state), "sym" => xkb::keysym_get_name(keysym)
         );
 
-        if let FilterResult::Intercept(val) = filter(&guard.mods_state, handle) {
+        if let FilterResult::Intercept(val) = filter(&mods_state, handle) {
             // the filter returned false, we do not forward to client
             trace!(self.arc.logger, "Input was intercepted by filter");
-            return Some(val);
+            return KeyInput {
+                modifiers: mods_state,
+                keysym,
+                intercepted: Some(val),
+                dropped: false,
+                repeated,
+            };
+        }
+
+        if repeated {
+            // the clients already know the key is held
+            trace!(self.arc.logger, "Not forwarding the press of a held key");
+            return KeyInput {
+                modifiers: mods_state,
+                keysym,
+                intercepted: None,
+                dropped: false,
+                repeated,
+            };
         }
 
         // forward to client if no keybinding is triggered
@@ -504,10 +884,11 @@ impl KeyboardHandle {
         } else {
             None
         };
-        let wl_state = match state {
-            KeyState::Pressed => WlKeyState::Pressed,
-            KeyState::Released => WlKeyState::Released,
+        let (wl_state, event) = match state {
+            KeyState::Pressed => (WlKeyState::Pressed, SerialEvent::KeyPress),
+            KeyState::Released => (WlKeyState::Released, SerialEvent::KeyRelease),
         };
+        guard.serials.record(serial, event);
         guard.with_grab(
             move |mut handle, grab| {
                 grab.input(&mut handle, keycode, wl_state, modifiers, serial, time);
@@ -520,7 +901,103 @@ impl KeyboardHandle {
             trace!(self.arc.logger, "No client currently focused");
         }
 
-        None
+        KeyInput {
+            modifiers: mods_state,
+            keysym,
+            intercepted: None,
+            dropped: false,
+            repeated: false,
+        }
+    }
+
+    /// Handle a key press synthesized by the server-side key repeat
+    ///
+    /// This is meant to be called from the callback given to [`KeyboardHandle::with_repeat`].
+    /// `filter` is called with the keysym of the held key like for [`KeyboardHandle::input`],
+    /// but the keymap state is left untouched and the press is never forwarded to the clients,
+    /// which implement key repeat themselves.
+    ///
+    /// Returns the value of `filter` if it intercepted the press, and `None` otherwise or if
+    /// the key is not held anymore.
+    pub fn input_repeat<T, F>(&self, keycode: u32, time: u32, filter: F) -> Option<T>
+    where
+        F: FnOnce(&ModifiersState, KeysymHandle<'_>) -> FilterResult<T>,
+    {
+        let guard = self.arc.internal.borrow();
+        if !guard.pressed_keys.contains(&keycode) {
+            return None;
+        }
+        trace!(self.arc.logger, "Handling repeated keystroke"; "keycode" => keycode, "time" => time);
+        let handle = KeysymHandle {
+            keycode: keycode + 8,
+            state: &guard.state,
+            keymap: &guard.keymap,
+        };
+        match filter(&guard.mods_state, handle) {
+            FilterResult::Intercept(val) => Some(val),
+            FilterResult::Forward => None,
+        }
+    }
+
+    /// Release all keys currently held down
+    ///
+    /// A release event is sent to the focused client for every pressed key, bypassing any active
+    /// grab, and the keymap state is updated accordingly. The new modifiers are sent afterwards,
+    /// if they changed.
+    ///
+    /// Call this whenever the keyboard stops receiving events from the input backend, for example
+    /// on VT switch or session deactivation, to prevent clients from seeing keys stuck down.
+    pub fn release_all_keys(&self, serial: Serial, time: u32) {
+        let mut guard = self.arc.internal.borrow_mut();
+        let pressed_keys = guard.pressed_keys.clone();
+        if pressed_keys.is_empty() {
+            return;
+        }
+        trace!(self.arc.logger, "Releasing all pressed keys"; "keys" => format_args!("{:?}", pressed_keys));
+
+        let mut mods_changed = false;
+        for &keycode in &pressed_keys {
+            guard.update_repeat(keycode, keycode + 8, KeyState::Released, time);
+            mods_changed |= guard.key_input(keycode, keycode + 8, KeyState::Released);
+        }
+        guard.serials.record(serial, SerialEvent::KeyRelease);
+
+        let modifiers = if mods_changed {
+            Some(guard.serialize_modifiers())
+        } else {
+            None
+        };
+        guard.with_focused_kbds(|kbd, _| {
+            for &keycode in &pressed_keys {
+                kbd.key(serial.into(), time, keycode, WlKeyState::Released);
+            }
+            if let Some((dep, la, lo, gr)) = modifiers {
+                kbd.modifiers(serial.into(), dep, la, lo, gr);
+            }
+        });
+    }
+
+    /// Set the modifiers and layout of this keyboard from already serialized masks
+    ///
+    /// This is meant for backends receiving modifier state instead of key events, like remote
+    /// desktop protocols. The xkb state and [`ModifiersState`] are updated and, if they changed,
+    /// a `modifiers` event is sent to the focused client, bypassing any active grab.
+    ///
+    /// `depressed`, `latched` and `locked` are modifier masks of the current keymap, `group` is the
+    /// index of the locked layout. The pressed keys are left untouched, as no key event is involved.
+    pub fn update_modifiers(&self, depressed: u32, latched: u32, locked: u32, group: u32, serial: Serial) {
+        let mut guard = self.arc.internal.borrow_mut();
+        let guard = &mut *guard;
+        let state_components = guard.state.update_mask(depressed, latched, locked, 0, 0, group);
+        if state_components == 0 {
+            return;
+        }
+        guard.mods_state.update_with(&guard.state);
+
+        let (dep, la, lo, gr) = guard.serialize_modifiers();
+        guard.with_focused_kbds(|kbd, _| {
+            kbd.modifiers(serial.into(), dep, la, lo, gr);
+        });
     }
 
     /// Set the current focus of this keyboard
@@ -540,6 +1017,24 @@ impl KeyboardHandle {
         );
     }
 
+    /// Set the current focus of this keyboard, bypassing any active grab
+    ///
+    /// Unlike [`KeyboardHandle::set_focus`], the focus change is not passed to the current
+    /// [`KeyboardGrab`], which stays active and keeps receiving the following keystrokes.
+    ///
+    /// **Warning:** Grabs usually rely on controlling the focus, e.g. to keep it on a popup, and may
+    /// behave unexpectedly when it is moved behind their back. Only use this for focus changes that
+    /// must not be prevented by a grab, like locking the session.
+    pub fn force_set_focus(&self, focus: Option<&WlSurface>, serial: Serial) {
+        let mut guard = self.arc.internal.borrow_mut();
+        guard.pending_focus = focus.cloned();
+        KeyboardInnerHandle {
+            inner: &mut *guard,
+            logger: self.arc.logger.clone(),
+        }
+        .set_focus(focus, serial);
+    }
+
     /// Check if given client currently has keyboard focus
     pub fn has_focus(&self, client: &Client) -> bool {
         self.arc
@@ -552,11 +1047,50 @@ impl KeyboardHandle {
             .unwrap_or(false)
     }
 
+    /// Returns the client of each `wl_keyboard` bound to this keyboard
+    ///
+    /// A client binding several `wl_keyboard`s is listed once per bound object. This is meant
+    /// for diagnostics, like showing which clients listen to the keyboard.
+    pub fn known_keyboards(&self) -> Vec<Client> {
+        self.arc
+            .internal
+            .borrow()
+            .known_kbds
+            .iter()
+            .filter(|kbd| kbd.as_ref().is_alive())
+            .filter_map(|kbd| kbd.as_ref().client())
+            .collect()
+    }
+
     /// Check if keyboard has focus
     pub fn is_focused(&self) -> bool {
         self.arc.internal.borrow_mut().focus.is_some()
     }
 
+    /// Access the current focus of this keyboard
+    ///
+    /// This is the surface currently receiving the keyboard events, which may differ from the
+    /// last focus requested through [`KeyboardHandle::set_focus`] while a grab is active.
+    pub fn current_focus(&self) -> Option<WlSurface> {
+        self.arc.internal.borrow().focus.clone()
+    }
+
+    /// Access the xkb keymap and state of this keyboard
+    ///
+    /// This allows to use them with other xkbcommon-based code, e.g. for custom keysym lookups.
+    /// The state reflects all the input processed by this handle so far.
+    ///
+    /// The keyboard is borrowed while `f` runs, it must not call other methods of this handle
+    /// or of its clones. Like the rest of this handle, xkbcommon objects are not thread-safe and
+    /// may not be used from other threads.
+    pub fn with_xkb_state<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&xkb::Keymap, &xkb::State) -> T,
+    {
+        let guard = self.arc.internal.borrow();
+        f(&guard.keymap, &guard.state)
+    }
+
     /// Register a new keyboard to this handler
     ///
     /// The keymap will automatically be sent to it
@@ -565,22 +1099,14 @@ impl KeyboardHandle {
     pub(crate) fn new_kbd(&self, kbd: WlKeyboard) {
         trace!(self.arc.logger, "Sending keymap to client");
 
-        // prepare a tempfile with the keymap, to send it to the client
-        let ret = tempfile().and_then(|mut f| {
-            f.write_all(self.arc.keymap.as_bytes())?;
-            f.flush()?;
-            f.rewind()?;
-            kbd.keymap(
-                KeymapFormat::XkbV1,
-                f.as_raw_fd(),
-                self.arc.keymap.as_bytes().len() as u32,
-            );
-            Ok(())
-        });
+        let ret = self
+            .arc
+            .keymap
+            .with_fd(|fd, size| kbd.keymap(KeymapFormat::XkbV1, fd, size as u32));
 
         if let Err(e) = ret {
             warn!(self.arc.logger,
-                "Failed write keymap to client in a tempfile";
+                "Failed to write the keymap to the client";
                 "err" => format!("{:?}", e)
             );
             return;
@@ -593,14 +1119,193 @@ impl KeyboardHandle {
         guard.known_kbds.push(kbd);
     }
 
+    /// Enable server-side key repeat driven by a timer inserted into the given event loop
+    ///
+    /// Once the configured repeat delay has passed after a repeating key was pressed and until it is
+    /// released again, `callback` is invoked at the configured repeat rate with this keyboard,
+    /// the evdev keycode of the held key and the timestamp of the synthetic key press.
+    /// The callback is expected to feed the synthetic press into [`KeyboardHandle::input_repeat`],
+    /// e.g. to repeat compositor key bindings. Clients implement key repeat themselves based on the
+    /// repeat info advertised to them, so the synthetic presses are never forwarded to them.
+    ///
+    /// ```no_run
+    /// # extern crate wayland_server;
+    /// # use smithay::wayland::seat::{Seat, XkbConfig, FilterResult};
+    /// # let mut display = wayland_server::Display::new();
+    /// # let event_loop = smithay::reexports::calloop::EventLoop::<()>::try_new().unwrap();
+    /// # let (mut seat, _) = Seat::new(&mut display, "seat-0".into(), None);
+    /// let keyboard = seat
+    ///     .add_keyboard(XkbConfig::default(), 200, 25, |_, _| {})
+    ///     .expect("Failed to initialize the keyboard")
+    ///     .with_repeat(&event_loop.handle(), |keyboard, keycode, time, _data| {
+    ///         keyboard.input_repeat(keycode, time, |_, _keysym| {
+    ///             /* repeat compositor key bindings */
+    ///             FilterResult::Intercept(())
+    ///         });
+    ///     })
+    ///     .expect("Failed to initialize the repeat timer");
+    /// ```
+    pub fn with_repeat<Data, F>(
+        self,
+        loop_handle: &LoopHandle<'_, Data>,
+        mut callback: F,
+    ) -> Result<Self, Error>
+    where
+        Data: 'static,
+        F: FnMut(&KeyboardHandle, u32, u32, &mut Data) + 'static,
+    {
+        let timer = Timer::new().map_err(Error::IoError)?;
+        let timer_handle = timer.handle();
+        let weak = Rc::downgrade(&self.arc);
+        loop_handle
+            .insert_source(timer, move |keycode, _, data| {
+                let handle = match weak.upgrade() {
+                    Some(arc) => KeyboardHandle { arc },
+                    None => return,
+                };
+                let time = match handle.repeat_time(keycode, Instant::now()) {
+                    Some(time) => time,
+                    // the key was released in the meantime
+                    None => return,
+                };
+                callback(&handle, keycode, time, data);
+                handle.rearm_repeat(keycode);
+            })
+            .map_err(|err| Error::IoError(err.error))?;
+
+        self.arc.internal.borrow_mut().repeat = Some(KeyRepeat {
+            timer: timer_handle,
+            current: None,
+        });
+        Ok(self)
+    }
+
+    // timestamp of the repetition of the given key at `now`, if it is still repeating
+    fn repeat_time(&self, keycode: u32, now: Instant) -> Option<u32> {
+        let guard = self.arc.internal.borrow();
+        let current = guard.repeat.as_ref()?.current.as_ref()?;
+        if current.keycode != keycode {
+            return None;
+        }
+        let elapsed = now.saturating_duration_since(current.pressed_at);
+        Some(current.time.wrapping_add(elapsed.as_millis() as u32))
+    }
+
+    // schedule the next repetition of the given key, if it is still repeating
+    fn rearm_repeat(&self, keycode: u32) {
+        let mut guard = self.arc.internal.borrow_mut();
+        if guard.repeat_rate <= 0 {
+            return;
+        }
+        let interval = Duration::from_millis(1000 / guard.repeat_rate as u64);
+        if let Some(repeat) = guard.repeat.as_mut() {
+            if let Some(current) = repeat
+                .current
+                .as_mut()
+                .filter(|current| current.keycode == keycode)
+            {
+                current.timeout = repeat.timer.add_timeout(interval, keycode);
+            }
+        }
+    }
+
+    // cancel any grab and leave the focused surface, used when the keyboard is removed from its seat
+    pub(crate) fn release(&self, serial: Serial) {
+        // the grab is cancelled
+        self.replace_grab(GrabStatus::None);
+        let mut guard = self.arc.internal.borrow_mut();
+        if let Some(repeat) = guard.repeat.as_mut() {
+            if let Some(current) = repeat.current.take() {
+                repeat.timer.cancel_timeout(&current.timeout);
+            }
+        }
+        guard.pending_focus = None;
+        KeyboardInnerHandle {
+            inner: &mut guard,
+            logger: self.arc.logger.clone(),
+        }
+        .set_focus(None, serial);
+    }
+
+    /// Set the keys that are never repeated by the server-side key repeat
+    ///
+    /// The keys are given as evdev keycodes. They are still reported once when pressed, but the
+    /// callback given to [`KeyboardHandle::with_repeat`] is not invoked for them while they are held,
+    /// even if the keymap defines them as repeating. This is useful for keys bound to compositor
+    /// actions, like volume controls. Clients implementing key repeat themselves are not affected.
+    ///
+    /// The set is empty by default.
+    pub fn set_no_repeat_keys(&self, keycodes: HashSet<u32>) {
+        self.arc.internal.borrow_mut().no_repeat_keys = keycodes;
+    }
+
+    /// Set a filter seeing every keystroke before it is processed
+    ///
+    /// The filter is given the evdev keycode, state and time of each keystroke passed to
+    /// [`KeyboardHandle::input`] and its variants, before it updates the xkb state and reaches
+    /// their keysym-level filter. It can rewrite the keycode or drop the keystroke, in which case it
+    /// is not recorded as pressed or released and has no effect at all. This can be used to
+    /// implement key remapping or record macros.
+    ///
+    /// The keyboard is not borrowed while the filter runs, so it can use the other methods of this
+    /// handle, e.g. to read the modifiers with [`KeyboardHandle::with_xkb_state`] or to replace
+    /// itself. It must not pass keystrokes to [`KeyboardHandle::input`] and its variants though.
+    ///
+    /// Replaces any previous raw filter.
+    pub fn set_raw_filter<F>(&self, filter: F)
+    where
+        F: FnMut(u32, KeyState, u32) -> RawFilterResult + 'static,
+    {
+        self.arc.internal.borrow_mut().raw_filter = Some(Rc::new(RefCell::new(filter)));
+    }
+
+    /// Remove the raw filter of this keyboard
+    pub fn unset_raw_filter(&self) {
+        self.arc.internal.borrow_mut().raw_filter = None;
+    }
+
+    /// Enable or disable remembering the active layout per window
+    ///
+    /// When enabled, the layout active while a surface has the keyboard focus is saved when the
+    /// focus moves away from it, and restored once the surface is focused again. Surfaces focused
+    /// for the first time start with the first layout of the keymap. Only the active layout group
+    /// changes, the keymap and the other modifiers are kept, and the focused client is sent the
+    /// restored layout with its modifiers.
+    ///
+    /// This is disabled by default, disabling it forgets all saved layouts.
+    pub fn set_per_window_layout(&self, enabled: bool) {
+        let mut guard = self.arc.internal.borrow_mut();
+        match (enabled, guard.window_layouts.is_some()) {
+            (true, false) => guard.window_layouts = Some(Vec::new()),
+            (false, true) => guard.window_layouts = None,
+            _ => {}
+        }
+    }
+
     /// Change the repeat info configured for this keyboard
-    pub fn change_repeat_info(&self, rate: i32, delay: i32) {
+    ///
+    /// The new repeat info is sent to every bound `wl_keyboard` supporting it, the returned
+    /// [`RepeatInfoNotified`] tells how many were notified and how many are too old.
+    pub fn set_repeat_info(&self, rate: i32, delay: i32) -> RepeatInfoNotified {
         let mut guard = self.arc.internal.borrow_mut();
         guard.repeat_delay = delay;
         guard.repeat_rate = rate;
-        for kbd in &guard.known_kbds {
-            kbd.repeat_info(rate, delay);
+        let mut result = RepeatInfoNotified::default();
+        for kbd in guard.known_kbds.iter().filter(|kbd| kbd.as_ref().is_alive()) {
+            if kbd.as_ref().version() >= 4 {
+                kbd.repeat_info(rate, delay);
+                result.notified += 1;
+            } else {
+                result.outdated += 1;
+            }
         }
+        result
+    }
+
+    /// Change the repeat info configured for this keyboard
+    #[deprecated(note = "use `set_repeat_info`, which reports the notified keyboards")]
+    pub fn change_repeat_info(&self, rate: i32, delay: i32) {
+        self.set_repeat_info(rate, delay);
     }
 }
 
@@ -699,9 +1404,14 @@ impl<'a> KeyboardInnerHandle<'a> {
             self.inner.with_focused_kbds(|kbd, s| {
                 kbd.leave(serial.into(), s);
             });
+            self.inner.save_focus_layout();
 
             // set new focus
             self.inner.focus = focus.cloned();
+            self.inner.restore_focus_layout();
+            if focus.is_some() {
+                self.inner.serials.record(serial, SerialEvent::KeyboardEnter);
+            }
             let (dep, la, lo, gr) = self.inner.serialize_modifiers();
             let keys = self.inner.serialize_pressed_keys();
             self.inner.with_focused_kbds(|kbd, surface| {
@@ -752,3 +1462,502 @@ impl KeyboardGrab for DefaultGrab {
         unreachable!()
     }
 }
+
+/// A keyboard grab additionally forwarding all keystrokes to an observer
+///
+/// Keystrokes are delivered to the regular keyboard focus as usual, and mirrored to the given
+/// `wl_keyboard` of an observer client, which is entered on the observer surface once the first
+/// keystroke is mirrored. Mirroring is paused while the regular focus belongs to the observer client,
+/// which then receives the keystrokes anyway, and stops once the observer surface or keyboard is destroyed.
+///
+/// The observer keyboard leaves the observer surface when the grab is dropped.
+#[derive(Debug)]
+pub struct MirrorKeyboardGrab {
+    start_data: GrabStartData,
+    observer: WlKeyboard,
+    observer_surface: WlSurface,
+    entered: bool,
+}
+
+impl MirrorKeyboardGrab {
+    /// Create a grab mirroring all keystrokes to the `observer` keyboard focused on `observer_surface`
+    pub fn new(start_data: GrabStartData, observer: WlKeyboard, observer_surface: WlSurface) -> Self {
+        MirrorKeyboardGrab {
+            start_data,
+            observer,
+            observer_surface,
+            entered: false,
+        }
+    }
+
+    fn observer_alive(&self) -> bool {
+        self.observer.as_ref().is_alive() && self.observer_surface.as_ref().is_alive()
+    }
+
+    // the observer client has the regular focus and receives the keystrokes without mirroring
+    fn observer_focused(&self, focus: Option<&WlSurface>) -> bool {
+        focus
+            .map(|focus| focus.as_ref().same_client_as(self.observer.as_ref()))
+            .unwrap_or(false)
+    }
+
+    fn leave_observer(&mut self, serial: Serial) {
+        if self.entered && self.observer_alive() {
+            self.observer.leave(serial.into(), &self.observer_surface);
+        }
+        self.entered = false;
+    }
+}
+
+impl KeyboardGrab for MirrorKeyboardGrab {
+    fn input(
+        &mut self,
+        handle: &mut KeyboardInnerHandle<'_>,
+        keycode: u32,
+        key_state: WlKeyState,
+        modifiers: Option<(u32, u32, u32, u32)>,
+        serial: Serial,
+        time: u32,
+    ) {
+        handle.input(keycode, key_state, modifiers, serial, time);
+
+        if !self.observer_alive() || self.observer_focused(handle.current_focus()) {
+            self.entered = false;
+            return;
+        }
+        if !self.entered {
+            let (dep, la, lo, gr) = handle.inner.serialize_modifiers();
+            self.observer
+                .enter(serial.into(), &self.observer_surface, Vec::new());
+            self.observer.modifiers(serial.into(), dep, la, lo, gr);
+            self.entered = true;
+        } else if let Some((dep, la, lo, gr)) = modifiers {
+            self.observer.modifiers(serial.into(), dep, la, lo, gr);
+        }
+        self.observer.key(serial.into(), time, keycode, key_state);
+    }
+
+    fn set_focus(&mut self, handle: &mut KeyboardInnerHandle<'_>, focus: Option<&WlSurface>, serial: Serial) {
+        if self.observer_focused(focus) {
+            // the regular focus is about to enter the observer keyboard
+            self.leave_observer(serial);
+        }
+        handle.set_focus(focus, serial)
+    }
+
+    fn start_data(&self) -> &GrabStartData {
+        &self.start_data
+    }
+}
+
+impl Drop for MirrorKeyboardGrab {
+    fn drop(&mut self) {
+        self.leave_observer(SERIAL_COUNTER.next_serial());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_keyboard() -> KeyboardHandle {
+        create_keyboard_with_cache(&KeymapCache::default())
+    }
+
+    fn create_keyboard_with_cache(keymap_cache: &KeymapCache) -> KeyboardHandle {
+        let log = ::slog::Logger::root(::slog::Discard, ::slog::o!());
+        let config = XkbConfig {
+            layout: "us",
+            ..Default::default()
+        };
+        create_keyboard_handler(
+            config,
+            200,
+            25,
+            &log,
+            SerialTracker::default(),
+            keymap_cache,
+            |_| {},
+        )
+        .expect("Failed to create keyboard")
+    }
+
+    fn pressed_sym(keyboard: &KeyboardHandle, raw_xkb: bool, keycode: u32) -> Keysym {
+        let filter =
+            |_: &ModifiersState, handle: KeysymHandle<'_>| FilterResult::Intercept(handle.modified_sym());
+        let sym = if raw_xkb {
+            keyboard.input_raw_xkb(keycode, KeyState::Pressed, Serial::from(0), 0, filter)
+        } else {
+            keyboard.input(keycode, KeyState::Pressed, Serial::from(0), 0, filter)
+        };
+        let release = |_: &ModifiersState, _: KeysymHandle<'_>| FilterResult::Intercept(());
+        if raw_xkb {
+            keyboard.input_raw_xkb(keycode, KeyState::Released, Serial::from(1), 0, release);
+        } else {
+            keyboard.input(keycode, KeyState::Released, Serial::from(1), 0, release);
+        }
+        sym.unwrap()
+    }
+
+    #[test]
+    fn evdev_and_xkb_keycodes_resolve_same_keysym() {
+        let keyboard = create_keyboard();
+        // KEY_A in evdev, which is 38 in the X keycode system
+        let evdev = pressed_sym(&keyboard, false, 30);
+        let xkb = pressed_sym(&keyboard, true, 38);
+        assert_eq!(evdev, keysyms::KEY_a);
+        assert_eq!(evdev, xkb);
+    }
+
+    #[test]
+    fn modifiers_match_ignores_lock_modifiers() {
+        let pattern = ModifiersMatch::exactly(ModifiersState {
+            logo: true,
+            shift: true,
+            ..Default::default()
+        });
+        let mut modifiers = ModifiersState {
+            logo: true,
+            shift: true,
+            caps_lock: true,
+            num_lock: true,
+            ..Default::default()
+        };
+        assert!(modifiers.matches(&pattern));
+
+        modifiers.ctrl = true;
+        assert!(!modifiers.matches(&pattern));
+
+        let pattern = ModifiersMatch {
+            caps_lock: Some(false),
+            ..Default::default()
+        };
+        assert!(!modifiers.matches(&pattern));
+        modifiers.caps_lock = false;
+        assert!(modifiers.matches(&pattern));
+    }
+
+    #[test]
+    fn pressed_keys_are_serialized_as_u32_array() {
+        let keyboard = create_keyboard();
+        // KEY_A and KEY_B
+        for &keycode in &[30, 48] {
+            keyboard.input(keycode, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+                FilterResult::<()>::Forward
+            });
+        }
+        let serialized = keyboard.arc.internal.borrow().serialize_pressed_keys();
+        let keys = serialized
+            .chunks_exact(4)
+            .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![30, 48]);
+    }
+
+    #[test]
+    fn xkb_state_reflects_input() {
+        let keyboard = create_keyboard();
+        // KEY_LEFTSHIFT
+        keyboard.input(42, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        // KEY_A in the X keycode system
+        let sym = keyboard.with_xkb_state(|_, state| state.key_get_one_sym(38));
+        assert_eq!(sym, keysyms::KEY_A);
+    }
+
+    #[test]
+    fn identical_keymaps_are_cached() {
+        let cache = KeymapCache::default();
+        let first = create_keyboard_with_cache(&cache);
+        let second = create_keyboard_with_cache(&cache);
+        assert!(Arc::ptr_eq(&first.arc.keymap, &second.arc.keymap));
+
+        // the cache is not shared with other seats
+        let other = create_keyboard();
+        assert!(!Arc::ptr_eq(&first.arc.keymap, &other.arc.keymap));
+
+        cache.set_enabled(false);
+        let uncached = create_keyboard_with_cache(&cache);
+        assert!(!Arc::ptr_eq(&first.arc.keymap, &uncached.arc.keymap));
+    }
+
+    #[test]
+    fn forwarded_input_reports_keysym() {
+        let keyboard = create_keyboard();
+        let input = keyboard.input_with_keysym(30, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        assert!(input.forwarded());
+        assert_eq!(input.keysym, keysyms::KEY_a);
+        assert_eq!(input.modifiers, ModifiersState::default());
+    }
+
+    #[test]
+    fn raw_filter_rewrites_and_drops_keys() {
+        let keyboard = create_keyboard();
+        keyboard.set_raw_filter(|keycode, _, _| match keycode {
+            // KEY_Q is dropped, KEY_B is remapped to KEY_A
+            16 => RawFilterResult::Drop,
+            48 => RawFilterResult::Rewrite(30),
+            _ => RawFilterResult::Forward,
+        });
+
+        let input = keyboard.input_with_keysym(16, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        assert!(input.dropped);
+        assert!(!input.forwarded());
+        assert!(keyboard.arc.internal.borrow().pressed_keys.is_empty());
+
+        let input = keyboard.input_with_keysym(48, KeyState::Pressed, Serial::from(1), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        assert!(input.forwarded());
+        assert_eq!(input.keysym, keysyms::KEY_a);
+        assert_eq!(keyboard.arc.internal.borrow().pressed_keys, vec![30]);
+
+        keyboard.unset_raw_filter();
+        assert_eq!(pressed_sym(&keyboard, false, 16), keysyms::KEY_q);
+    }
+
+    #[test]
+    fn raw_filter_can_use_the_keyboard() {
+        let keyboard = create_keyboard();
+        // KEY_LEFTSHIFT
+        keyboard.input(42, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        let shift = Rc::new(std::cell::Cell::new(false));
+        let (handle, shift2) = (keyboard.clone(), shift.clone());
+        keyboard.set_raw_filter(move |_, _, _| {
+            shift2.set(handle.with_xkb_state(|_, state| {
+                state.mod_name_is_active(&xkb::MOD_NAME_SHIFT, xkb::STATE_MODS_EFFECTIVE)
+            }));
+            // only look at a single keystroke
+            handle.unset_raw_filter();
+            RawFilterResult::Forward
+        });
+
+        assert_eq!(pressed_sym(&keyboard, false, 30), keysyms::KEY_A);
+        assert!(shift.get());
+        assert!(keyboard.arc.internal.borrow().raw_filter.is_none());
+    }
+
+    #[test]
+    fn releasing_all_keys_resets_modifiers() {
+        let keyboard = create_keyboard();
+        // KEY_LEFTSHIFT and KEY_A
+        for &keycode in &[42, 30] {
+            keyboard.input(keycode, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+                FilterResult::<()>::Forward
+            });
+        }
+        assert!(keyboard.arc.internal.borrow().mods_state.shift);
+
+        keyboard.release_all_keys(Serial::from(1), 0);
+        let guard = keyboard.arc.internal.borrow();
+        assert!(guard.pressed_keys.is_empty());
+        assert_eq!(guard.mods_state, ModifiersState::default());
+    }
+
+    #[test]
+    fn modifier_masks_update_state() {
+        let keyboard = create_keyboard();
+        let shift = keyboard.with_xkb_state(|keymap, _| 1 << keymap.mod_get_index(xkb::MOD_NAME_SHIFT));
+        // KEY_A
+        keyboard.input(30, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::<()>::Forward
+        });
+
+        keyboard.update_modifiers(shift, 0, 0, 0, Serial::from(1));
+        {
+            let guard = keyboard.arc.internal.borrow();
+            assert!(guard.mods_state.shift);
+            assert_eq!(guard.serialize_modifiers(), (shift, 0, 0, 0));
+            assert_eq!(guard.pressed_keys, vec![30]);
+        }
+
+        keyboard.update_modifiers(0, 0, 0, 0, Serial::from(2));
+        assert!(!keyboard.arc.internal.borrow().mods_state.shift);
+    }
+
+    #[test]
+    fn held_key_is_repeated() {
+        let keyboard = create_keyboard();
+        // the timer is never inserted in an event loop, repetitions are driven by hand
+        let timer = Timer::<u32>::new().unwrap();
+        keyboard.arc.internal.borrow_mut().repeat = Some(KeyRepeat {
+            timer: timer.handle(),
+            current: None,
+        });
+
+        keyboard.input(30, KeyState::Pressed, Serial::from(0), 1000, |_, _| {
+            FilterResult::Intercept(())
+        });
+        let pressed_at = keyboard
+            .arc
+            .internal
+            .borrow()
+            .repeat
+            .as_ref()
+            .and_then(|r| r.current.as_ref())
+            .map(|current| current.pressed_at)
+            .expect("KEY_A is not repeating");
+        assert_eq!(
+            keyboard.repeat_time(30, pressed_at + Duration::from_millis(250)),
+            Some(1250)
+        );
+        assert_eq!(keyboard.repeat_time(48, pressed_at), None);
+
+        let keysym = keyboard.input_repeat(30, 1250, |_, keysym| {
+            FilterResult::Intercept(keysym.modified_sym())
+        });
+        assert_eq!(keysym, Some(keysyms::KEY_a));
+        // synthetic presses do not add to the held keys
+        assert_eq!(keyboard.arc.internal.borrow().pressed_keys, vec![30]);
+
+        // a duplicate press of a held key is never forwarded to the clients
+        let input = keyboard.input_with_keysym(30, KeyState::Pressed, Serial::from(1), 1300, |_, _| {
+            FilterResult::<()>::Forward
+        });
+        assert!(input.repeated);
+        assert!(!input.forwarded());
+
+        keyboard.input(30, KeyState::Released, Serial::from(2), 2000, |_, _| {
+            FilterResult::Intercept(())
+        });
+        assert_eq!(keyboard.repeat_time(30, pressed_at), None);
+        assert_eq!(
+            keyboard.input_repeat(30, 2000, |_, _| FilterResult::Intercept(())),
+            None
+        );
+        assert!(keyboard.arc.internal.borrow().pressed_keys.is_empty());
+        assert!(keyboard
+            .arc
+            .internal
+            .borrow()
+            .repeat
+            .as_ref()
+            .unwrap()
+            .current
+            .is_none());
+    }
+
+    #[test]
+    fn no_repeat_keys_are_not_repeated() {
+        let event_loop = calloop::EventLoop::<()>::try_new().unwrap();
+        let keyboard = create_keyboard()
+            .with_repeat(&event_loop.handle(), |_, _, _, _| {})
+            .unwrap();
+        let is_repeating = |keyboard: &KeyboardHandle| {
+            let guard = keyboard.arc.internal.borrow();
+            guard.repeat.as_ref().unwrap().current.is_some()
+        };
+        keyboard.set_no_repeat_keys([30].iter().copied().collect());
+
+        // KEY_A
+        keyboard.input(30, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::Intercept(())
+        });
+        assert!(!is_repeating(&keyboard));
+        keyboard.input(30, KeyState::Released, Serial::from(1), 0, |_, _| {
+            FilterResult::Intercept(())
+        });
+
+        // KEY_B
+        keyboard.input(48, KeyState::Pressed, Serial::from(2), 0, |_, _| {
+            FilterResult::Intercept(())
+        });
+        assert!(is_repeating(&keyboard));
+    }
+
+    struct NoopGrab(GrabStartData);
+
+    impl KeyboardGrab for NoopGrab {
+        fn input(
+            &mut self,
+            _: &mut KeyboardInnerHandle<'_>,
+            _: u32,
+            _: WlKeyState,
+            _: Option<(u32, u32, u32, u32)>,
+            _: Serial,
+            _: u32,
+        ) {
+        }
+        fn set_focus(&mut self, _: &mut KeyboardInnerHandle<'_>, _: Option<&WlSurface>, _: Serial) {}
+        fn start_data(&self) -> &GrabStartData {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn grab_captures_current_focus() {
+        let keyboard = create_keyboard();
+        keyboard.start_grab_with_current_focus(Serial::from(0), NoopGrab);
+        assert!(keyboard.has_grab(Serial::from(0)));
+        assert!(keyboard.grab_start_data().unwrap().focus.is_none());
+    }
+
+    #[test]
+    fn release_cancels_grab() {
+        let keyboard = create_keyboard();
+        keyboard.set_grab(NoopGrab(GrabStartData { focus: None }), Serial::from(0));
+        assert!(keyboard.is_grabbed());
+        keyboard.release(Serial::from(1));
+        assert!(!keyboard.is_grabbed());
+        assert!(keyboard.current_focus().is_none());
+    }
+
+    struct UnsetGrab(GrabStartData, Rc<RefCell<u32>>);
+
+    impl KeyboardGrab for UnsetGrab {
+        fn input(
+            &mut self,
+            _: &mut KeyboardInnerHandle<'_>,
+            _: u32,
+            _: WlKeyState,
+            _: Option<(u32, u32, u32, u32)>,
+            _: Serial,
+            _: u32,
+        ) {
+        }
+        fn set_focus(&mut self, _: &mut KeyboardInnerHandle<'_>, _: Option<&WlSurface>, _: Serial) {}
+        fn start_data(&self) -> &GrabStartData {
+            &self.0
+        }
+        fn unset(&mut self, _: &mut KeyboardInnerHandle<'_>) {
+            *self.1.borrow_mut() += 1;
+        }
+    }
+
+    #[test]
+    fn replaced_grab_is_unset() {
+        let keyboard = create_keyboard();
+        let unset = Rc::new(RefCell::new(0));
+        keyboard.set_grab(
+            UnsetGrab(GrabStartData { focus: None }, unset.clone()),
+            Serial::from(0),
+        );
+        keyboard.set_grab(NoopGrab(GrabStartData { focus: None }), Serial::from(1));
+        assert_eq!(*unset.borrow(), 1);
+
+        keyboard.set_grab(
+            UnsetGrab(GrabStartData { focus: None }, unset.clone()),
+            Serial::from(2),
+        );
+        keyboard.unset_grab();
+        assert_eq!(*unset.borrow(), 2);
+        assert!(!keyboard.is_grabbed());
+    }
+
+    #[test]
+    fn invalid_xkb_keycode_is_ignored() {
+        let keyboard = create_keyboard();
+        let ret = keyboard.input_raw_xkb(3, KeyState::Pressed, Serial::from(0), 0, |_, _| {
+            FilterResult::Intercept(())
+        });
+        assert!(ret.is_none());
+        assert!(keyboard.arc.internal.borrow().pressed_keys.is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, Write},
+    os::unix::io::{AsRawFd, RawFd},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use slog::{debug, trace};
+use tempfile::tempfile;
+use xkbcommon::xkb;
+
+use super::{KeyboardError as Error, XkbConfig};
+
+// Keymaps compiled for the keyboards of a seat
+//
+// Only the keymap strings and the files sharing them with clients are cached,
+// the xkb objects are created again for every keyboard.
+#[derive(Debug)]
+pub(super) struct KeymapCache {
+    enabled: Cell<bool>,
+    keymaps: RefCell<HashMap<KeymapNames, Arc<KeymapFile>>>,
+}
+
+impl Default for KeymapCache {
+    fn default() -> KeymapCache {
+        KeymapCache {
+            enabled: Cell::new(true),
+            keymaps: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl KeymapCache {
+    pub(super) fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    pub(super) fn clear(&self) {
+        self.keymaps.borrow_mut().clear();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct KeymapNames {
+    rules: String,
+    model: String,
+    layout: String,
+    variant: String,
+    options: Option<String>,
+}
+
+impl<'a> From<&XkbConfig<'a>> for KeymapNames {
+    fn from(config: &XkbConfig<'a>) -> KeymapNames {
+        KeymapNames {
+            rules: config.rules.into(),
+            model: config.model.into(),
+            layout: config.layout.into(),
+            variant: config.variant.into(),
+            options: config.options.clone(),
+        }
+    }
+}
+
+/// A compiled keymap and the file used to share it with clients
+#[derive(Debug)]
+pub(super) struct KeymapFile {
+    keymap: String,
+    // read-only, so it can be shared by all clients
+    file: Option<File>,
+}
+
+impl KeymapFile {
+    fn new(keymap: String, log: &::slog::Logger) -> Result<KeymapFile, Error> {
+        match sealed_memfd(&keymap) {
+            Ok(file) => {
+                return Ok(KeymapFile {
+                    keymap,
+                    file: Some(file),
+                })
+            }
+            Err(err) => debug!(log, "Cannot share the keymap in a memfd, falling back to a tempfile";
+                "err" => format!("{:?}", err)
+            ),
+        }
+
+        let mut file = tempfile().map_err(Error::IoError)?;
+        file.write_all(keymap.as_bytes()).map_err(Error::IoError)?;
+        file.flush().map_err(Error::IoError)?;
+        // reopen the file read-only, to prevent clients from modifying the keymap of other clients
+        let file = match OpenOptions::new()
+            .read(true)
+            .open(format!("/proc/self/fd/{}", file.as_raw_fd()))
+        {
+            Ok(file) => Some(file),
+            Err(err) => {
+                debug!(log, "Cannot share the keymap file, falling back to one file per client";
+                    "err" => format!("{:?}", err)
+                );
+                None
+            }
+        };
+        Ok(KeymapFile { keymap, file })
+    }
+
+    /// Run the provided closure with a file descriptor containing the keymap
+    pub(super) fn with_fd<F>(&self, f: F) -> Result<(), std::io::Error>
+    where
+        F: FnOnce(RawFd, usize),
+    {
+        if let Some(ref file) = self.file {
+            f(file.as_raw_fd(), self.keymap.len());
+        } else {
+            let mut file = tempfile()?;
+            file.write_all(self.keymap.as_bytes())?;
+            file.flush()?;
+            file.rewind()?;
+            f(file.as_raw_fd(), self.keymap.len());
+        }
+        Ok(())
+    }
+}
+
+// Write the keymap into a memfd sealed against any modification, so it can be shared by all
+// clients without touching the filesystem
+#[cfg(target_os = "linux")]
+fn sealed_memfd(keymap: &str) -> io::Result<File> {
+    use nix::{
+        fcntl::{fcntl, FcntlArg, SealFlag},
+        sys::memfd::{memfd_create, MemFdCreateFlag},
+    };
+    use std::os::unix::io::FromRawFd;
+
+    let name = std::ffi::CStr::from_bytes_with_nul(b"smithay-keymap\0").unwrap();
+    let fd = memfd_create(
+        name,
+        MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+    )?;
+    // the file takes ownership of the fd, closing it on error
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(keymap.as_bytes())?;
+    file.flush()?;
+    file.rewind()?;
+    fcntl(
+        file.as_raw_fd(),
+        FcntlArg::F_ADD_SEALS(
+            SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_WRITE | SealFlag::F_SEAL_SEAL,
+        ),
+    )?;
+    Ok(file)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sealed_memfd(_keymap: &str) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "memfd is not available on this platform",
+    ))
+}
+
+/// Compile the keymap described by the given config, reusing a cached keymap if possible
+pub(super) fn compile_keymap(
+    context: &xkb::Context,
+    config: XkbConfig<'_>,
+    cache: &KeymapCache,
+    log: &::slog::Logger,
+) -> Result<(xkb::Keymap, Arc<KeymapFile>), Error> {
+    let names = KeymapNames::from(&config);
+    let caching = cache.enabled.get();
+
+    if caching {
+        let cached = cache.keymaps.borrow().get(&names).cloned();
+        if let Some(file) = cached {
+            trace!(log, "Reusing cached keymap");
+            let keymap = xkb::Keymap::new_from_string(
+                context,
+                file.keymap.clone(),
+                xkb::KEYMAP_FORMAT_TEXT_V1,
+                xkb::KEYMAP_COMPILE_NO_FLAGS,
+            )
+            .ok_or(Error::BadKeymap)?;
+            return Ok((keymap, file));
+        }
+    }
+
+    let keymap = xkb::Keymap::new_from_names(
+        context,
+        &config.rules,
+        &config.model,
+        &config.layout,
+        &config.variant,
+        config.options,
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+    .ok_or(Error::BadKeymap)?;
+    let file = Arc::new(KeymapFile::new(
+        keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1),
+        log,
+    )?);
+    if caching {
+        cache
+            .keymaps
+            .borrow_mut()
+            .entry(names)
+            .or_insert_with(|| file.clone());
+    }
+    Ok((keymap, file))
+}
+
+/// A keyboard layout available in the xkb rules registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutInfo {
+    /// Name of the layout, as used in [`XkbConfig::layout`]
+    pub name: String,
+    /// Human readable description of the layout
+    pub description: String,
+    /// Variants available for this layout
+    pub variants: Vec<VariantInfo>,
+}
+
+/// A variant of a keyboard layout available in the xkb rules registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantInfo {
+    /// Name of the variant, as used in [`XkbConfig::variant`]
+    pub name: String,
+    /// Human readable description of the variant
+    pub description: String,
+}
+
+/// List the keyboard layouts and their variants available with the default `evdev` rules
+///
+/// The layouts are read from the rules registry in the xkb data directory, which is
+/// `$XKB_CONFIG_ROOT` if set and `/usr/share/X11/xkb` otherwise.
+pub fn available_layouts() -> io::Result<Vec<LayoutInfo>> {
+    let root = env::var_os("XKB_CONFIG_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/X11/xkb"));
+    let list = fs::read_to_string(root.join("rules").join("evdev.lst"))?;
+    Ok(parse_rules_list(&list))
+}
+
+// Parse the layout and variant sections of a `<rules>.lst` file
+//
+// Layouts are listed as `name description`, variants as `name layout: description`.
+fn parse_rules_list(list: &str) -> Vec<LayoutInfo> {
+    let mut layouts: Vec<LayoutInfo> = Vec::new();
+    let mut section = "";
+    for line in list.lines() {
+        if let Some(name) = line.strip_prefix('!') {
+            section = name.trim();
+            continue;
+        }
+        let line = line.trim();
+        let (name, description) = match line.find(char::is_whitespace) {
+            Some(idx) => (&line[..idx], line[idx..].trim()),
+            None => continue,
+        };
+        match section {
+            "layout" => layouts.push(LayoutInfo {
+                name: name.into(),
+                description: description.into(),
+                variants: Vec::new(),
+            }),
+            "variant" => {
+                let (layout, description) = match description.split_once(':') {
+                    Some((layout, description)) => (layout, description.trim()),
+                    None => continue,
+                };
+                if let Some(layout) = layouts.iter_mut().find(|l| l.name == layout) {
+                    layout.variants.push(VariantInfo {
+                        name: name.into(),
+                        description: description.into(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    layouts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_layouts_and_variants() {
+        let list = "! model
+  pc105           Generic 105-key PC
+! layout
+  us              English (US)
+  de              German
+! variant
+  chr             us: Cherokee
+  nodeadkeys      de: German (no dead keys)
+! option
+  grp                  Switching to another layout
+";
+        let layouts = parse_rules_list(list);
+        assert_eq!(layouts.len(), 2);
+        assert_eq!(layouts[0].name, "us");
+        assert_eq!(layouts[0].description, "English (US)");
+        assert_eq!(
+            layouts[1].variants,
+            vec![VariantInfo {
+                name: "nodeadkeys".into(),
+                description: "German (no dead keys)".into(),
+            }]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn keymap_memfd_is_sealed() {
+        use std::io::Read;
+
+        let mut file = sealed_memfd("xkb_keymap {};").unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "xkb_keymap {};");
+        assert!(file.write_all(b"modified").is_err());
+    }
+}
@@ -37,19 +37,27 @@
 use std::{cell::RefCell, fmt, ops::Deref as _, rc::Rc};
 
 mod keyboard;
+mod keymap;
 mod pointer;
+mod serials;
 
 pub use self::{
     keyboard::{
-        keysyms, Error as KeyboardError, FilterResult, GrabStartData as KeyboardGrabStartData, KeyboardGrab,
-        KeyboardHandle, KeyboardInnerHandle, Keysym, KeysymHandle, ModifiersState, XkbConfig,
+        keysyms, Error as KeyboardError, FilterResult, GrabStartData as KeyboardGrabStartData, KeyInput,
+        KeyboardGrab, KeyboardHandle, KeyboardInnerHandle, Keysym, KeysymHandle, MirrorKeyboardGrab,
+        ModifiersMatch, ModifiersState, RawFilterResult, RepeatInfoNotified, XkbConfig,
     },
+    keymap::{available_layouts, LayoutInfo, VariantInfo},
     pointer::{
-        AxisFrame, CursorImageAttributes, CursorImageStatus, GrabStartData as PointerGrabStartData,
-        PointerGrab, PointerHandle, PointerInnerHandle,
+        AxisFrame, AxisRelativeDirection, CursorImageAttributes, CursorImageStatus,
+        GrabStartData as PointerGrabStartData, PointerGrab, PointerHandle, PointerInnerHandle,
+        PointerMotionFilter, RelativeMotion,
     },
+    serials::{SerialEvent, SerialTracker, DEFAULT_SERIAL_HISTORY},
 };
 
+use self::keymap::KeymapCache;
+use crate::wayland::SERIAL_COUNTER;
 use wayland_server::{
     protocol::{wl_seat, wl_surface},
     Display, Filter, Global, Main, UserDataMap,
@@ -65,6 +73,8 @@ struct Inner {
 pub(crate) struct SeatRc {
     inner: RefCell<Inner>,
     user_data: UserDataMap,
+    serials: SerialTracker,
+    keymaps: KeymapCache,
     pub(crate) log: ::slog::Logger,
     name: String,
 }
@@ -75,6 +85,8 @@ impl fmt::Debug for SeatRc {
         f.debug_struct("SeatRc")
             .field("inner", &self.inner)
             .field("user_data", &"...")
+            .field("serials", &self.serials)
+            .field("keymaps", &self.keymaps)
             .field("log", &self.log)
             .field("name", &self.name)
             .finish()
@@ -139,6 +151,8 @@ impl Seat {
             log: log.new(slog::o!("smithay_module" => "seat_handler", "seat_name" => name.clone())),
             name,
             user_data: UserDataMap::new(),
+            serials: SerialTracker::default(),
+            keymaps: KeymapCache::default(),
         });
         let seat = Seat { arc: arc.clone() };
         let global = display.create_global(
@@ -170,6 +184,14 @@ impl Seat {
         &self.arc.user_data
     }
 
+    /// Access the serials recently sent to clients by this seat
+    ///
+    /// Use this to validate serials provided by clients in requests, which are only
+    /// allowed in response to a recent input event.
+    pub fn serials(&self) -> &SerialTracker {
+        &self.arc.serials
+    }
+
     /// Adds the pointer capability to this seat
     ///
     /// You are provided a [`PointerHandle`], which allows you to send input events
@@ -203,14 +225,11 @@ impl Seat {
     where
         F: FnMut(CursorImageStatus) + 'static,
     {
+        let pointer = self::pointer::create_pointer_handler(cb, self.arc.serials.clone());
+        // there may already be a pointer, remove it and notify the clients
+        // of the change
+        self.remove_pointer();
         let mut inner = self.arc.inner.borrow_mut();
-        let pointer = self::pointer::create_pointer_handler(cb);
-        if inner.pointer.is_some() {
-            // there is already a pointer, remove it and notify the clients
-            // of the change
-            inner.pointer = None;
-            inner.send_all_caps();
-        }
         inner.pointer = Some(pointer.clone());
         inner.send_all_caps();
         pointer
@@ -223,12 +242,13 @@ impl Seat {
 
     /// Remove the pointer capability from this seat
     ///
-    /// Clients will be appropriately notified.
+    /// Any active grab of the pointer is cancelled and the currently focused surface
+    /// receives a leave event, before clients are notified about the changed capabilities.
     pub fn remove_pointer(&mut self) {
-        let mut inner = self.arc.inner.borrow_mut();
-        if inner.pointer.is_some() {
-            inner.pointer = None;
-            inner.send_all_caps();
+        let pointer = self.arc.inner.borrow_mut().pointer.take();
+        if let Some(pointer) = pointer {
+            pointer.release(SERIAL_COUNTER.next_serial());
+            self.arc.inner.borrow_mut().send_all_caps();
         }
     }
 
@@ -278,25 +298,41 @@ impl Seat {
         F: FnMut(&Seat, Option<&wl_surface::WlSurface>) + 'static,
     {
         let me = self.clone();
-        let mut inner = self.arc.inner.borrow_mut();
         let keyboard = self::keyboard::create_keyboard_handler(
             xkb_config,
             repeat_delay,
             repeat_rate,
             &self.arc.log,
+            self.arc.serials.clone(),
+            &self.arc.keymaps,
             move |focus| focus_hook(&me, focus),
         )?;
-        if inner.keyboard.is_some() {
-            // there is already a keyboard, remove it and notify the clients
-            // of the change
-            inner.keyboard = None;
-            inner.send_all_caps();
-        }
+        // there may already be a keyboard, remove it and notify the clients
+        // of the change
+        self.remove_keyboard();
+        let mut inner = self.arc.inner.borrow_mut();
         inner.keyboard = Some(keyboard.clone());
         inner.send_all_caps();
         Ok(keyboard)
     }
 
+    /// Enable or disable the caching of compiled keymaps
+    ///
+    /// Keyboards added to this seat with the same RMLVO names share a single compiled keymap
+    /// and the file used to send it to the clients. Caching is enabled by default. Disabling it
+    /// does not affect the current keyboard.
+    pub fn set_keymap_caching(&self, enabled: bool) {
+        self.arc.keymaps.set_enabled(enabled);
+    }
+
+    /// Drop all keymaps cached by this seat
+    ///
+    /// Keymaps of keyboards added afterwards are compiled again, e.g. to pick up changed xkb
+    /// data files.
+    pub fn clear_keymap_cache(&self) {
+        self.arc.keymaps.clear();
+    }
+
     /// Access the keyboard of this seat if any
     pub fn get_keyboard(&self) -> Option<KeyboardHandle> {
         self.arc.inner.borrow_mut().keyboard.clone()
@@ -304,12 +340,13 @@ impl Seat {
 
     /// Remove the keyboard capability from this seat
     ///
-    /// Clients will be appropriately notified.
+    /// Any active grab of the keyboard is cancelled and the currently focused surface
+    /// receives a leave event, before clients are notified about the changed capabilities.
     pub fn remove_keyboard(&mut self) {
-        let mut inner = self.arc.inner.borrow_mut();
-        if inner.keyboard.is_some() {
-            inner.keyboard = None;
-            inner.send_all_caps();
+        let keyboard = self.arc.inner.borrow_mut().keyboard.take();
+        if let Some(keyboard) = keyboard {
+            keyboard.release(SERIAL_COUNTER.next_serial());
+            self.arc.inner.borrow_mut().send_all_caps();
         }
     }
 
@@ -5,9 +5,10 @@ use wayland_server::{
         wl_pointer::{self, Axis, AxisSource, ButtonState, Request, WlPointer},
         wl_surface::WlSurface,
     },
-    Filter, Main,
+    Client, Filter, Main,
 };
 
+use super::{SerialEvent, SerialTracker};
 use crate::{
     utils::{Logical, Point},
     wayland::{compositor, Serial},
@@ -19,9 +20,28 @@ static CURSOR_IMAGE_ROLE: &str = "cursor_image";
 #[derive(Debug, Default, Copy, Clone)]
 pub struct CursorImageAttributes {
     /// Location of the hotspot of the pointer in the surface
+    ///
+    /// It is moved by the offset of the buffers attached to the surface.
     pub hotspot: Point<i32, Logical>,
 }
 
+impl CursorImageAttributes {
+    // the offset of an attached buffer moves the hotspot in the opposite direction
+    pub(crate) fn commit_hook(surface: &WlSurface) {
+        let _ = compositor::with_states(surface, |states| {
+            let delta = states
+                .cached_state
+                .current::<compositor::SurfaceAttributes>()
+                .take_offset();
+            if let (Some(delta), Some(attributes)) =
+                (delta, states.data_map.get::<Mutex<CursorImageAttributes>>())
+            {
+                attributes.lock().unwrap().hotspot -= delta;
+            }
+        });
+    }
+}
+
 /// Possible status of a cursor as requested by clients
 #[derive(Debug, Clone, PartialEq)]
 pub enum CursorImageStatus {
@@ -30,7 +50,15 @@ pub enum CursorImageStatus {
     /// The compositor should draw its cursor
     Default,
     /// The cursor should be drawn using this surface as an image
-    Image(WlSurface),
+    Image {
+        /// The surface of the cursor image
+        surface: WlSurface,
+        /// Location of the hotspot of the pointer in the surface
+        ///
+        /// This is the hotspot given with this request, it is also stored in the
+        /// [`CursorImageAttributes`] of the surface.
+        hotspot: Point<i32, Logical>,
+    },
 }
 
 enum GrabStatus {
@@ -57,7 +85,13 @@ struct PointerInternal {
     location: Point<f64, Logical>,
     grab: GrabStatus,
     pressed_buttons: Vec<u32>,
+    // serial of the last press of each button
+    button_serials: Vec<(u32, Serial)>,
     image_callback: Box<dyn FnMut(CursorImageStatus)>,
+    motion_filter: Option<Box<dyn PointerMotionFilter>>,
+    serials: SerialTracker,
+    // high-resolution scroll not yet sent as discrete steps
+    value120_remainder: (i32, i32),
 }
 
 // image_callback does not implement debug, so we have to impl Debug manually
@@ -70,13 +104,17 @@ impl fmt::Debug for PointerInternal {
             .field("location", &self.location)
             .field("grab", &self.grab)
             .field("pressed_buttons", &self.pressed_buttons)
+            .field("button_serials", &self.button_serials)
             .field("image_callback", &"...")
+            .field("motion_filter", &self.motion_filter.as_ref().map(|_| "..."))
+            .field("serials", &self.serials)
+            .field("value120_remainder", &self.value120_remainder)
             .finish()
     }
 }
 
 impl PointerInternal {
-    fn new<F>(cb: F) -> PointerInternal
+    fn new<F>(cb: F, serials: SerialTracker) -> PointerInternal
     where
         F: FnMut(CursorImageStatus) + 'static,
     {
@@ -87,7 +125,11 @@ impl PointerInternal {
             location: (0.0, 0.0).into(),
             grab: GrabStatus::None,
             pressed_buttons: Vec::new(),
+            button_serials: Vec::new(),
             image_callback: Box::new(cb) as Box<_>,
+            motion_filter: None,
+            serials,
+            value120_remainder: (0, 0),
         }
     }
 
@@ -143,6 +185,7 @@ impl PointerInternal {
             self.focus = Some((surface, surface_location));
             let (x, y) = (location - surface_location.to_f64()).into();
             if entered {
+                self.serials.record(serial, SerialEvent::PointerEnter);
                 self.with_focused_pointers(|pointer, surface| {
                     pointer.enter(serial.into(), surface, x, y);
                     if pointer.as_ref().version() >= 5 {
@@ -227,6 +270,34 @@ impl PointerHandle {
         guard.known_pointers.push(pointer);
     }
 
+    /// Replace the callback notified of cursor image changes
+    ///
+    /// The callback given when the pointer was created (see [`Seat::add_pointer`](super::Seat::add_pointer))
+    /// is dropped. The new callback is invoked with the requested [`CursorImageStatus`] whenever a client
+    /// with the pointer focus calls `wl_pointer.set_cursor`, and with [`CursorImageStatus::Default`]
+    /// whenever the pointer leaves a surface.
+    ///
+    /// This must not be called from within the callback itself.
+    pub fn on_cursor_image_change<F>(&self, cb: F)
+    where
+        F: FnMut(CursorImageStatus) + 'static,
+    {
+        self.inner.borrow_mut().image_callback = Box::new(cb);
+    }
+
+    /// Set the filter transforming the motion reported with [`PointerHandle::absolute_motion`]
+    /// and [`PointerHandle::relative_motion`]
+    ///
+    /// Replaces any previous filter. The filter is not applied to [`PointerHandle::motion`].
+    pub fn set_motion_filter<F: PointerMotionFilter + 'static>(&self, filter: F) {
+        self.inner.borrow_mut().motion_filter = Some(Box::new(filter));
+    }
+
+    /// Remove the motion filter of this pointer, motion is then used as reported
+    pub fn unset_motion_filter(&self) {
+        self.inner.borrow_mut().motion_filter = None;
+    }
+
     /// Change the current grab on this pointer to the provided grab
     ///
     /// Overwrites any current grab.
@@ -234,6 +305,26 @@ impl PointerHandle {
         self.inner.borrow_mut().set_grab(serial, grab, time);
     }
 
+    /// Start a grab on this pointer, with start data capturing its current focus and location
+    ///
+    /// `button` is the button that initiated the grab. `make_grab` is given the [`GrabStartData`]
+    /// and builds the grab, which then replaces any current grab like with [`PointerHandle::set_grab`].
+    pub fn start_grab_with_current_focus<G, F>(&self, button: u32, serial: Serial, time: u32, make_grab: F)
+    where
+        G: PointerGrab + 'static,
+        F: FnOnce(GrabStartData) -> G,
+    {
+        let start_data = {
+            let inner = self.inner.borrow();
+            GrabStartData {
+                focus: inner.focus.clone(),
+                button,
+                location: inner.location,
+            }
+        };
+        self.set_grab(make_grab(start_data), serial, time);
+    }
+
     /// Remove any current grab on this pointer, resetting it to the default behavior
     pub fn unset_grab(&self, serial: Serial, time: u32) {
         self.inner.borrow_mut().unset_grab(serial, time);
@@ -254,6 +345,33 @@ impl PointerHandle {
         !matches!(guard.grab, GrabStatus::None)
     }
 
+    /// Returns the client of each `wl_pointer` bound to this pointer
+    ///
+    /// A client binding several `wl_pointer`s is listed once per bound object. This is meant
+    /// for diagnostics, like showing which clients listen to the pointer.
+    pub fn known_pointers(&self) -> Vec<Client> {
+        self.inner
+            .borrow()
+            .known_pointers
+            .iter()
+            .filter(|ptr| ptr.as_ref().is_alive())
+            .filter_map(|ptr| ptr.as_ref().client())
+            .collect()
+    }
+
+    /// Returns the serial of the last press of the given button, if it was ever pressed
+    ///
+    /// Requests like starting a drag'n'drop operation need to be made in response to a
+    /// button press, this allows to check the serial provided by the client.
+    pub fn last_button_serial(&self, button: u32) -> Option<Serial> {
+        self.inner
+            .borrow()
+            .button_serials
+            .iter()
+            .find(|&&(b, _)| b == button)
+            .map(|&(_, serial)| serial)
+    }
+
     /// Returns the start data for the grab, if any.
     pub fn grab_start_data(&self) -> Option<GrabStartData> {
         let guard = self.inner.borrow();
@@ -288,6 +406,95 @@ impl PointerHandle {
         });
     }
 
+    /// Notify that the pointer moved to an absolute position, e.g. on a touchscreen or tablet
+    ///
+    /// `location` is passed through the [motion filter](PointerHandle::set_motion_filter) first.
+    /// `surface_under` is then given the filtered location and returns the surface under it, in the
+    /// same form as the `focus` argument of [`PointerHandle::motion`].
+    ///
+    /// Returns the filtered location.
+    pub fn absolute_motion<F>(
+        &self,
+        location: Point<f64, Logical>,
+        surface_under: F,
+        serial: Serial,
+        time: u32,
+    ) -> Point<f64, Logical>
+    where
+        F: FnOnce(Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)>,
+    {
+        let location = match self.inner.borrow_mut().motion_filter {
+            Some(ref mut filter) => filter.filter_absolute(location, time),
+            None => location,
+        };
+        let focus = surface_under(location);
+        self.motion(location, focus, serial, time);
+        location
+    }
+
+    /// Notify that the pointer moved by a given delta, e.g. on a mouse or touchpad
+    ///
+    /// `delta` is passed through the [motion filter](PointerHandle::set_motion_filter) first and the
+    /// pointer is moved by the resulting accelerated delta. `surface_under` is then given the new
+    /// location and returns the surface under it, in the same form as the `focus` argument of
+    /// [`PointerHandle::motion`]. The compositor is responsible for clamping the location to its
+    /// outputs in `surface_under`, by returning its own location.
+    ///
+    /// Returns both the accelerated and unaccelerated deltas, as needed by the relative pointer
+    /// protocol.
+    pub fn relative_motion<F>(
+        &self,
+        delta: Point<f64, Logical>,
+        surface_under: F,
+        serial: Serial,
+        time: u32,
+    ) -> RelativeMotion
+    where
+        F: FnOnce(Point<f64, Logical>) -> (Point<f64, Logical>, Option<(WlSurface, Point<i32, Logical>)>),
+    {
+        let (motion, location) = {
+            let mut inner = self.inner.borrow_mut();
+            let motion = match inner.motion_filter {
+                Some(ref mut filter) => filter.filter_relative(delta, time),
+                None => RelativeMotion {
+                    delta,
+                    delta_unaccel: delta,
+                },
+            };
+            (motion, inner.location + motion.delta)
+        };
+        let (location, focus) = surface_under(location);
+        self.motion(location, focus, serial, time);
+        motion
+    }
+
+    /// Re-evaluate the focus of the pointer without it moving
+    ///
+    /// The stacking order or the location of the surfaces can change while the pointer does not move,
+    /// for example when a window is raised, mapped or unmapped. Call this method after such changes,
+    /// `surface_under` is given the current location of the pointer and returns the surface now under
+    /// it, in the same form as the `focus` argument of [`PointerHandle::motion`].
+    ///
+    /// If the focus changed, the appropriate leave and enter events are sent, as if the pointer had
+    /// moved. They are intercepted by the active grab, if any.
+    pub fn reevaluate_focus<F>(&self, surface_under: F, serial: Serial, time: u32)
+    where
+        F: FnOnce(Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)>,
+    {
+        let location = self.current_location();
+        let focus = surface_under(location);
+        let unchanged = match (&self.inner.borrow().pending_focus, &focus) {
+            (Some((old, old_loc)), Some((new, new_loc))) => {
+                old.as_ref().equals(new.as_ref()) && old_loc == new_loc
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.motion(location, focus, serial, time);
+        }
+    }
+
     /// Notify that a button was pressed
     ///
     /// This will internally send the appropriate button event to the client
@@ -297,9 +504,13 @@ impl PointerHandle {
         match state {
             ButtonState::Pressed => {
                 inner.pressed_buttons.push(button);
+                inner.button_serials.retain(|&(b, _)| b != button);
+                inner.button_serials.push((button, serial));
+                inner.serials.record(serial, SerialEvent::PointerButtonPress);
             }
             ButtonState::Released => {
                 inner.pressed_buttons.retain(|b| *b != button);
+                inner.serials.record(serial, SerialEvent::PointerButtonRelease);
             }
             _ => unreachable!(),
         }
@@ -321,6 +532,65 @@ impl PointerHandle {
     pub fn current_location(&self) -> Point<f64, Logical> {
         self.inner.borrow().location
     }
+
+    /// Access the surface currently focused by this pointer and the location
+    /// of the pointer relative to it
+    ///
+    /// This is the surface currently receiving the pointer events, which may differ from the
+    /// focus last provided to [`PointerHandle::motion`] while a grab is active.
+    pub fn current_focus(&self) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let inner = self.inner.borrow();
+        inner
+            .focus
+            .as_ref()
+            .map(|(surface, surface_location)| (surface.clone(), inner.location - surface_location.to_f64()))
+    }
+
+    // cancel any grab and leave the focused surface, used when the pointer is removed from its seat
+    pub(crate) fn release(&self, serial: Serial) {
+        let mut inner = self.inner.borrow_mut();
+        // dropping the grab cancels it
+        inner.grab = GrabStatus::None;
+        inner.pending_focus = None;
+        inner.pressed_buttons.clear();
+        let location = inner.location;
+        inner.motion(location, None, serial, 0);
+    }
+}
+
+/// A relative pointer motion, as returned by a [`PointerMotionFilter`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeMotion {
+    /// The delta the pointer is moved by, with acceleration applied
+    pub delta: Point<f64, Logical>,
+    /// The delta without acceleration
+    pub delta_unaccel: Point<f64, Logical>,
+}
+
+/// A stage transforming pointer motion before it is processed by the pointer
+///
+/// This allows to implement pointer acceleration or coordinate transformations, like the
+/// calibration of a touchscreen. See [`PointerHandle::set_motion_filter`].
+///
+/// Both methods default to the identity.
+pub trait PointerMotionFilter {
+    /// Transform an absolute position in the global compositor space
+    fn filter_absolute(&mut self, location: Point<f64, Logical>, time: u32) -> Point<f64, Logical> {
+        let _ = time;
+        location
+    }
+
+    /// Transform a relative motion
+    ///
+    /// The returned [`RelativeMotion::delta_unaccel`] should only have coordinate
+    /// transformations applied, but no acceleration.
+    fn filter_relative(&mut self, delta: Point<f64, Logical>, time: u32) -> RelativeMotion {
+        let _ = time;
+        RelativeMotion {
+            delta,
+            delta_unaccel: delta,
+        }
+    }
 }
 
 /// Data about the event that started the grab.
@@ -469,7 +739,16 @@ impl<'a> PointerInnerHandle<'a> {
     ///
     /// This will internally send the appropriate axis events to the client
     /// objects matching with the currently focused surface.
-    pub fn axis(&mut self, details: AxisFrame) {
+    pub fn axis(&mut self, mut details: AxisFrame) {
+        // clients bound to wl_pointer v7 or older only know about discrete steps
+        let remainder = &mut self.inner.value120_remainder;
+        if details.discrete.0 == 0 {
+            details.discrete.0 = accumulate_value120(&mut remainder.0, details.value120.0, details.stop.0);
+        }
+        if details.discrete.1 == 0 {
+            details.discrete.1 = accumulate_value120(&mut remainder.1, details.value120.1, details.stop.1);
+        }
+
         self.inner.with_focused_pointers(|pointer, _| {
             // axis
             if details.axis.0 != 0.0 {
@@ -504,6 +783,33 @@ impl<'a> PointerInnerHandle<'a> {
     }
 }
 
+// Accumulate high-resolution scroll steps and return the number of full discrete steps
+fn accumulate_value120(remainder: &mut i32, value120: i32, stop: bool) -> i32 {
+    if stop || (*remainder != 0 && remainder.signum() != value120.signum()) {
+        // scrolling stopped or changed direction
+        *remainder = 0;
+    }
+    *remainder += value120;
+    let steps = *remainder / 120;
+    *remainder %= 120;
+    steps
+}
+
+/// Direction of physical motion that caused axis events, relative to their value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AxisRelativeDirection {
+    /// The physical motion matches the axis direction
+    Identical,
+    /// The physical motion is inverted relative to the axis direction, e.g. for natural scrolling
+    Inverted,
+}
+
+impl Default for AxisRelativeDirection {
+    fn default() -> Self {
+        AxisRelativeDirection::Identical
+    }
+}
+
 /// A frame of pointer axis events.
 ///
 /// Can be used with the builder pattern, e.g.:
@@ -521,6 +827,8 @@ pub struct AxisFrame {
     time: u32,
     axis: (f64, f64),
     discrete: (i32, i32),
+    value120: (i32, i32),
+    relative_direction: (AxisRelativeDirection, AxisRelativeDirection),
     stop: (bool, bool),
 }
 
@@ -532,6 +840,8 @@ impl AxisFrame {
             time,
             axis: (0.0, 0.0),
             discrete: (0, 0),
+            value120: (0, 0),
+            relative_direction: Default::default(),
             stop: (false, false),
         }
     }
@@ -566,6 +876,52 @@ impl AxisFrame {
         self
     }
 
+    /// Specify high-resolution scrolling steps additionally to the computed value.
+    ///
+    /// A value of 120 corresponds to one discrete step of a regular scroll wheel, high-resolution
+    /// wheels may report fractions of it. The wayland protocol version currently used by smithay does
+    /// not provide the `axis_value120` event (`wl_pointer` version 8), so the steps are accumulated and
+    /// forwarded as discrete steps, unless discrete steps were given using [`AxisFrame::discrete`].
+    pub fn value120(mut self, axis: Axis, value120: i32) -> Self {
+        match axis {
+            Axis::HorizontalScroll => {
+                self.value120.0 = value120;
+            }
+            Axis::VerticalScroll => {
+                self.value120.1 = value120;
+            }
+            _ => unreachable!(),
+        };
+        self
+    }
+
+    /// Specify the direction of the physical motion relative to the scroll value
+    ///
+    /// This allows clients to adjust e.g. sliders when natural scrolling is enabled. It is not forwarded
+    /// to clients yet, as the `axis_relative_direction` event (`wl_pointer` version 9) is not provided by
+    /// the wayland protocol version currently used by smithay, but is available to pointer grabs.
+    pub fn relative_direction(mut self, axis: Axis, direction: AxisRelativeDirection) -> Self {
+        match axis {
+            Axis::HorizontalScroll => {
+                self.relative_direction.0 = direction;
+            }
+            Axis::VerticalScroll => {
+                self.relative_direction.1 = direction;
+            }
+            _ => unreachable!(),
+        };
+        self
+    }
+
+    /// Direction of the physical motion relative to the scroll value of the given axis
+    pub fn get_relative_direction(&self, axis: Axis) -> AxisRelativeDirection {
+        match axis {
+            Axis::HorizontalScroll => self.relative_direction.0,
+            Axis::VerticalScroll => self.relative_direction.1,
+            _ => unreachable!(),
+        }
+    }
+
     /// The actual scroll value. This event is the only required one, but can also
     /// be send multiple times. The values off one frame will be accumulated by the client.
     pub fn value(mut self, axis: Axis, value: f64) -> Self {
@@ -599,12 +955,12 @@ impl AxisFrame {
     }
 }
 
-pub(crate) fn create_pointer_handler<F>(cb: F) -> PointerHandle
+pub(crate) fn create_pointer_handler<F>(cb: F, serials: SerialTracker) -> PointerHandle
 where
     F: FnMut(CursorImageStatus) + 'static,
 {
     PointerHandle {
-        inner: Rc::new(RefCell::new(PointerInternal::new(cb))),
+        inner: Rc::new(RefCell::new(PointerInternal::new(cb, serials))),
     }
 }
 
@@ -631,10 +987,13 @@ pub(crate) fn implement_pointer(pointer: Main<WlPointer>, handle: Option<&Pointe
                         if focus.as_ref().same_client_as(pointer.as_ref()) {
                             match surface {
                                 Some(surface) => {
-                                    // tolerate re-using the same surface
-                                    if compositor::give_role(&surface, CURSOR_IMAGE_ROLE).is_err()
-                                        && compositor::get_role(&surface) != Some(CURSOR_IMAGE_ROLE)
-                                    {
+                                    // tolerate re-using the same surface, its hook was already added
+                                    if compositor::give_role(&surface, CURSOR_IMAGE_ROLE).is_ok() {
+                                        compositor::add_post_commit_hook(
+                                            &surface,
+                                            CursorImageAttributes::commit_hook,
+                                        );
+                                    } else if compositor::get_role(&surface) != Some(CURSOR_IMAGE_ROLE) {
                                         pointer.as_ref().post_error(
                                             wl_pointer::Error::Role as u32,
                                             "Given wl_surface has another role.".into(),
@@ -657,7 +1016,10 @@ pub(crate) fn implement_pointer(pointer: Main<WlPointer>, handle: Option<&Pointe
                                     })
                                     .unwrap();
 
-                                    image_callback(CursorImageStatus::Image(surface));
+                                    image_callback(CursorImageStatus::Image {
+                                        surface,
+                                        hotspot: (hotspot_x, hotspot_y).into(),
+                                    });
                                 }
                                 None => {
                                     image_callback(CursorImageStatus::Hidden);
@@ -777,3 +1139,61 @@ impl PointerGrab for ClickGrab {
         &self.start_data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value120_is_accumulated_to_discrete_steps() {
+        let mut remainder = 0;
+        assert_eq!(accumulate_value120(&mut remainder, 60, false), 0);
+        assert_eq!(accumulate_value120(&mut remainder, 90, false), 1);
+        assert_eq!(remainder, 30);
+        // changing direction drops the partial step
+        assert_eq!(accumulate_value120(&mut remainder, -120, false), -1);
+        assert_eq!(accumulate_value120(&mut remainder, -60, true), 0);
+        assert_eq!(remainder, -60);
+    }
+
+    #[test]
+    fn motion_filter_transforms_motion() {
+        struct Double;
+        impl PointerMotionFilter for Double {
+            fn filter_relative(&mut self, delta: Point<f64, Logical>, _time: u32) -> RelativeMotion {
+                RelativeMotion {
+                    delta: (delta.x * 2.0, delta.y * 2.0).into(),
+                    delta_unaccel: delta,
+                }
+            }
+        }
+
+        let pointer = create_pointer_handler(|_| {}, SerialTracker::default());
+        let motion = pointer.relative_motion((1.0, 2.0).into(), |loc| (loc, None), Serial::from(1), 0);
+        assert_eq!(motion.delta, motion.delta_unaccel);
+        assert_eq!(pointer.current_location(), Point::from((1.0, 2.0)));
+
+        pointer.set_motion_filter(Double);
+        let motion = pointer.relative_motion((1.0, 2.0).into(), |loc| (loc, None), Serial::from(2), 0);
+        assert_eq!(motion.delta_unaccel, Point::from((1.0, 2.0)));
+        assert_eq!(pointer.current_location(), Point::from((3.0, 6.0)));
+        // absolute motion is left untouched by default
+        let location = pointer.absolute_motion((10.0, 10.0).into(), |_| None, Serial::from(3), 0);
+        assert_eq!(location, Point::from((10.0, 10.0)));
+    }
+
+    #[test]
+    fn last_press_serial_is_tracked_per_button() {
+        let pointer = create_pointer_handler(|_| {}, SerialTracker::default());
+        assert_eq!(pointer.last_button_serial(0x110), None);
+
+        pointer.button(0x110, ButtonState::Pressed, Serial::from(1), 0);
+        pointer.button(0x111, ButtonState::Pressed, Serial::from(2), 0);
+        pointer.button(0x110, ButtonState::Released, Serial::from(3), 0);
+        assert_eq!(pointer.last_button_serial(0x110), Some(Serial::from(1)));
+        assert_eq!(pointer.last_button_serial(0x111), Some(Serial::from(2)));
+
+        pointer.button(0x110, ButtonState::Pressed, Serial::from(4), 0);
+        assert_eq!(pointer.last_button_serial(0x110), Some(Serial::from(4)));
+    }
+}
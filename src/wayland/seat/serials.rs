@@ -0,0 +1,181 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::wayland::Serial;
+
+/// Number of serials remembered by a [`SerialTracker`] by default
+///
+/// This can be changed using [`SerialTracker::set_history_size`].
+pub const DEFAULT_SERIAL_HISTORY: usize = 32;
+
+/// Kind of input event a serial was sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SerialEvent {
+    /// A pointer entered a surface
+    PointerEnter,
+    /// A pointer button was pressed
+    PointerButtonPress,
+    /// A pointer button was released
+    PointerButtonRelease,
+    /// A keyboard entered a surface
+    KeyboardEnter,
+    /// A key was pressed
+    KeyPress,
+    /// A key was released
+    KeyRelease,
+    /// A touch point went down on a surface
+    ///
+    /// The seat has no touch capability, compositors sending touch events need to
+    /// record their serials themselves.
+    TouchDown,
+    /// A tablet tool made contact with the tablet
+    TabletToolTipDown,
+    /// A button of a tablet tool was pressed
+    TabletToolButtonPress,
+    /// A button of a tablet tool was released
+    TabletToolButtonRelease,
+}
+
+impl SerialEvent {
+    /// Returns true, if the event is a button or key press, a touch down or a tablet tool
+    /// making contact
+    ///
+    /// Requests like starting a drag-and-drop operation or taking an explicit popup
+    /// grab are only valid in response to a press.
+    pub fn is_press(&self) -> bool {
+        matches!(
+            self,
+            SerialEvent::PointerButtonPress
+                | SerialEvent::KeyPress
+                | SerialEvent::TouchDown
+                | SerialEvent::TabletToolTipDown
+                | SerialEvent::TabletToolButtonPress
+        )
+    }
+}
+
+/// Tracker of the serials recently sent to clients by a seat
+///
+/// The pointer, keyboard and tablet tools of a [`Seat`](super::Seat) record the serials
+/// of their input events automatically. Serials used for events of other input devices
+/// can be added using [`SerialTracker::record`].
+///
+/// Only the last [`DEFAULT_SERIAL_HISTORY`] serials are remembered unless configured
+/// otherwise, older serials are no longer considered valid.
+///
+/// This is an handle to the inner logic, it can be cloned.
+#[derive(Debug, Clone)]
+pub struct SerialTracker {
+    inner: Rc<RefCell<SerialHistory>>,
+}
+
+#[derive(Debug)]
+struct SerialHistory {
+    events: VecDeque<(Serial, SerialEvent)>,
+    size: usize,
+}
+
+impl Default for SerialTracker {
+    fn default() -> Self {
+        SerialTracker {
+            inner: Rc::new(RefCell::new(SerialHistory {
+                events: VecDeque::with_capacity(DEFAULT_SERIAL_HISTORY),
+                size: DEFAULT_SERIAL_HISTORY,
+            })),
+        }
+    }
+}
+
+impl SerialTracker {
+    /// Record a serial sent to a client together with the kind of the event
+    pub fn record(&self, serial: Serial, event: SerialEvent) {
+        let mut history = self.inner.borrow_mut();
+        if history.size == 0 {
+            return;
+        }
+        while history.events.len() >= history.size {
+            history.events.pop_front();
+        }
+        history.events.push_back((serial, event));
+    }
+
+    /// Change the number of serials remembered by this tracker
+    ///
+    /// If the history is shrunk, the oldest serials are forgotten.
+    pub fn set_history_size(&self, size: usize) {
+        let mut history = self.inner.borrow_mut();
+        while history.events.len() > size {
+            history.events.pop_front();
+        }
+        history.size = size;
+    }
+
+    /// The number of serials remembered by this tracker
+    pub fn history_size(&self) -> usize {
+        self.inner.borrow().size
+    }
+
+    /// Returns the kind of event the given serial was recently sent with
+    ///
+    /// Returns `None` if the serial is unknown or too old.
+    pub fn event(&self, serial: Serial) -> Option<SerialEvent> {
+        self.inner
+            .borrow()
+            .events
+            .iter()
+            .rev()
+            .find(|(s, _)| *s == serial)
+            .map(|(_, event)| *event)
+    }
+
+    /// Check if the given serial was recently sent with an event matching the predicate
+    pub fn validate<F>(&self, serial: Serial, predicate: F) -> bool
+    where
+        F: FnOnce(SerialEvent) -> bool,
+    {
+        self.event(serial).map(predicate).unwrap_or(false)
+    }
+
+    /// Forget about all recorded serials
+    pub fn clear(&self) {
+        self.inner.borrow_mut().events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_serials_are_forgotten() {
+        let tracker = SerialTracker::default();
+
+        let first = Serial::from(0);
+        tracker.record(first, SerialEvent::PointerButtonPress);
+        assert!(tracker.validate(first, |event| event.is_press()));
+
+        for i in 1..=DEFAULT_SERIAL_HISTORY {
+            tracker.record(Serial::from(i as u32), SerialEvent::KeyRelease);
+        }
+        assert_eq!(tracker.event(first), None);
+        assert!(!tracker.validate(first, |event| event.is_press()));
+    }
+
+    #[test]
+    fn history_size_is_configurable() {
+        let tracker = SerialTracker::default();
+        for i in 0..4 {
+            tracker.record(Serial::from(i), SerialEvent::TouchDown);
+        }
+
+        // shrinking the history forgets the oldest serials
+        tracker.set_history_size(2);
+        assert_eq!(tracker.event(Serial::from(1)), None);
+        assert_eq!(tracker.event(Serial::from(2)), Some(SerialEvent::TouchDown));
+
+        tracker.set_history_size(DEFAULT_SERIAL_HISTORY * 2);
+        for i in 4..(4 + DEFAULT_SERIAL_HISTORY as u32) {
+            tracker.record(Serial::from(i), SerialEvent::TabletToolTipDown);
+        }
+        assert!(tracker.validate(Serial::from(3), |event| event.is_press()));
+    }
+}
@@ -0,0 +1,431 @@
+//! Utilities for handling surfaces with the `fullscreen_shell` protocol
+//!
+//! This minimal shell lets a client present a single surface per output, which is then displayed
+//! fullscreen. It is mostly useful for kiosk or appliance compositors, that do not need the window
+//! management of a full desktop shell.
+//!
+//! ### Initialization
+//!
+//! To initialize this handler, use the [`fullscreen_shell_init`] function provided in this module.
+//! Requests presenting a surface on a specific output are handled by this module: the presented surface
+//! of the output is replaced and can be retrieved using [`presented_surface`]. You need to provide a
+//! closure that is invoked for every request, which allows you to handle requests leaving the choice of
+//! the output to the compositor and to attempt the mode switches requested by clients.
+//!
+//! ```no_run
+//! # extern crate wayland_server;
+//! #
+//! use smithay::wayland::shell::fullscreen::{fullscreen_shell_init, set_presented_surface, FullscreenShellRequest};
+//!
+//! # let mut display = wayland_server::Display::new();
+//! # let output: smithay::wayland::output::Output = unimplemented!();
+//! let _global = fullscreen_shell_init(
+//!     &mut display,
+//!     &[],
+//!     // your implementation
+//!     move |request, _dispatch_data| match request {
+//!         FullscreenShellRequest::PresentSurface { surface, method, output: None } => {
+//!             // the client left the choice of the output to the compositor
+//!             set_presented_surface(&output, surface.map(|surface| (surface, method)));
+//!         }
+//!         FullscreenShellRequest::PresentSurfaceForMode { feedback, .. } => {
+//!             // try to switch the mode of the output to match the surface
+//!             feedback.mode_failed();
+//!         }
+//!         _ => {}
+//!     },
+//!     None  // put a logger if you want
+//! );
+//! ```
+//!
+//! The presented surfaces can be drawn by adding a
+//! [`FullscreenShellElement`](crate::desktop::space::FullscreenShellElement) to
+//! [`Space::render_output`](crate::desktop::space::Space::render_output).
+
+use std::{cell::RefCell, rc::Rc};
+
+use wayland_protocols::unstable::fullscreen_shell::v1::server::{
+    zwp_fullscreen_shell_mode_feedback_v1::ZwpFullscreenShellModeFeedbackV1,
+    zwp_fullscreen_shell_v1::{self, ZwpFullscreenShellV1},
+};
+use wayland_server::{protocol::wl_surface::WlSurface, DispatchData, Display, Filter, Global, Main};
+
+use crate::{
+    utils::{Logical, Point, Size},
+    wayland::{compositor, output::Output},
+};
+
+pub use wayland_protocols::unstable::fullscreen_shell::v1::server::zwp_fullscreen_shell_v1::Capability;
+
+/// The role of a surface presented via the fullscreen shell
+pub const FULLSCREEN_SHELL_SURFACE_ROLE: &str = "zwp_fullscreen_shell_surface_v1";
+
+/// Method to present a surface, whose size does not match the size of its output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PresentMethod {
+    /// No preference, centers the surface if it fits on the output and zooms it otherwise
+    Default,
+    /// Center the surface on the output
+    Center,
+    /// Scale the surface preserving its aspect ratio to the largest size fitting on the output
+    Zoom,
+    /// Scale the surface preserving its aspect ratio to fully cover the output, cropping if needed
+    ZoomCrop,
+    /// Scale the surface to the size of the output ignoring its aspect ratio
+    Stretch,
+}
+
+impl PresentMethod {
+    // the method requested by a client, `None` if it is not known
+    fn from_request(method: zwp_fullscreen_shell_v1::PresentMethod) -> Option<PresentMethod> {
+        match method {
+            zwp_fullscreen_shell_v1::PresentMethod::Default => Some(PresentMethod::Default),
+            zwp_fullscreen_shell_v1::PresentMethod::Center => Some(PresentMethod::Center),
+            zwp_fullscreen_shell_v1::PresentMethod::Zoom => Some(PresentMethod::Zoom),
+            zwp_fullscreen_shell_v1::PresentMethod::ZoomCrop => Some(PresentMethod::ZoomCrop),
+            zwp_fullscreen_shell_v1::PresentMethod::Stretch => Some(PresentMethod::Stretch),
+            _ => None,
+        }
+    }
+
+    /// Computes the location and the horizontal and vertical scale factors of a surface of the
+    /// given size presented on an output of the given size with this method.
+    ///
+    /// The location is relative to the output and may be negative, if the surface is cropped.
+    /// Both factors are equal, except for [`PresentMethod::Stretch`].
+    pub fn placement(
+        self,
+        surface_size: Size<i32, Logical>,
+        output_size: Size<i32, Logical>,
+    ) -> (Point<i32, Logical>, (f64, f64)) {
+        if surface_size.w <= 0 || surface_size.h <= 0 {
+            return ((0, 0).into(), (1.0, 1.0));
+        }
+
+        let fits = surface_size.w <= output_size.w && surface_size.h <= output_size.h;
+        let scale_x = output_size.w as f64 / surface_size.w as f64;
+        let scale_y = output_size.h as f64 / surface_size.h as f64;
+        let scale = match self {
+            PresentMethod::Default if fits => (1.0, 1.0),
+            PresentMethod::Center => (1.0, 1.0),
+            PresentMethod::Default | PresentMethod::Zoom => {
+                let scale = f64::min(scale_x, scale_y);
+                (scale, scale)
+            }
+            PresentMethod::ZoomCrop => {
+                let scale = f64::max(scale_x, scale_y);
+                (scale, scale)
+            }
+            PresentMethod::Stretch => (scale_x, scale_y),
+        };
+
+        let size = (
+            (surface_size.w as f64 * scale.0).round() as i32,
+            (surface_size.h as f64 * scale.1).round() as i32,
+        );
+        let location = ((output_size.w - size.0) / 2, (output_size.h - size.1) / 2).into();
+        (location, scale)
+    }
+}
+
+/// Feedback for a requested mode switch of [`FullscreenShellRequest::PresentSurfaceForMode`]
+///
+/// Exactly one of the methods should be called once the mode switch was attempted. If another
+/// surface is presented on the same output before that, the client is notified, that the
+/// request was cancelled.
+#[derive(Debug, Clone)]
+pub struct ModeFeedback {
+    feedback: ZwpFullscreenShellModeFeedbackV1,
+    surface: WlSurface,
+    output: Output,
+}
+
+impl ModeFeedback {
+    /// The mode was switched successfully, the surface will be presented on the output
+    pub fn mode_successful(self) {
+        if self.take_pending() {
+            set_presented_surface(&self.output, Some((self.surface.clone(), PresentMethod::Center)));
+            self.feedback.mode_successful();
+        }
+    }
+
+    /// The mode could not be switched, the previously presented surface stays on the output
+    pub fn mode_failed(self) {
+        if self.take_pending() {
+            self.feedback.mode_failed();
+        }
+    }
+
+    // returns true, if this was the pending feedback of the output and it was not yet resolved
+    fn take_pending(&self) -> bool {
+        let state = output_state(&self.output);
+        let mut state = state.borrow_mut();
+        if state.pending_feedback.as_ref() == Some(&self.feedback) {
+            state.pending_feedback = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Requests of the fullscreen shell, that require the compositor to take action
+#[derive(Debug)]
+pub enum FullscreenShellRequest {
+    /// A client presented a surface
+    ///
+    /// If an output was given, the surface has already been presented on the output
+    /// replacing the previously presented surface.
+    ///
+    /// If no output was given, the compositor is expected to choose the output(s) to present
+    /// the surface on using [`set_presented_surface`], replacing any previously presented surfaces.
+    PresentSurface {
+        /// The presented surface, `None` to remove the presented surface
+        surface: Option<WlSurface>,
+        /// The method to present the surface
+        method: PresentMethod,
+        /// The output to present the surface on, if any
+        output: Option<Output>,
+    },
+    /// A client presented a surface requesting to switch the mode of the output to match its size
+    ///
+    /// The compositor is expected to attempt the mode switch and report the result using `feedback`.
+    PresentSurfaceForMode {
+        /// The presented surface
+        surface: WlSurface,
+        /// The output to present the surface on
+        output: Output,
+        /// The requested refresh rate in mHz, `0` if the client has no preference
+        framerate: i32,
+        /// The feedback to report the result of the mode switch
+        feedback: ModeFeedback,
+    },
+}
+
+#[derive(Debug, Default)]
+struct FullscreenOutputState {
+    presented: Option<(WlSurface, PresentMethod)>,
+    pending_feedback: Option<ZwpFullscreenShellModeFeedbackV1>,
+}
+
+impl FullscreenOutputState {
+    fn cancel_pending(&mut self) {
+        if let Some(feedback) = self.pending_feedback.take() {
+            feedback.present_cancelled();
+        }
+    }
+}
+
+fn output_state(output: &Output) -> &RefCell<FullscreenOutputState> {
+    output
+        .user_data()
+        .insert_if_missing(|| RefCell::new(FullscreenOutputState::default()));
+    output
+        .user_data()
+        .get::<RefCell<FullscreenOutputState>>()
+        .unwrap()
+}
+
+/// Returns the surface currently presented on an output and the method to present it
+pub fn presented_surface(output: &Output) -> Option<(WlSurface, PresentMethod)> {
+    let mut state = output_state(output).borrow_mut();
+    if matches!(state.presented, Some((ref surface, _)) if !surface.as_ref().is_alive()) {
+        state.presented = None;
+    }
+    state.presented.clone()
+}
+
+// The output a surface is presented on
+#[derive(Default)]
+struct PresentedOn(RefCell<Option<Output>>);
+
+/// Replace the surface presented on an output
+///
+/// A surface is only presented on a single output, if it was presented on another output before,
+/// it is removed from it. Any pending mode switch for this output is cancelled.
+pub fn set_presented_surface(output: &Output, presented: Option<(WlSurface, PresentMethod)>) {
+    if let Some((ref surface, _)) = presented {
+        let previous = compositor::with_states(surface, |states| {
+            states.data_map.insert_if_missing(PresentedOn::default);
+            let presented_on = states.data_map.get::<PresentedOn>().unwrap();
+            presented_on.0.replace(Some(output.clone()))
+        })
+        .ok()
+        .flatten();
+        if let Some(previous) = previous.filter(|previous| previous != output) {
+            let mut state = output_state(&previous).borrow_mut();
+            if matches!(state.presented, Some((ref other, _)) if other == surface) {
+                state.presented = None;
+            }
+        }
+    }
+    let mut state = output_state(output).borrow_mut();
+    state.cancel_pending();
+    state.presented = presented;
+}
+
+#[derive(Clone)]
+struct ShellUserData {
+    log: ::slog::Logger,
+    user_impl: Rc<RefCell<dyn FnMut(FullscreenShellRequest, DispatchData<'_>)>>,
+}
+
+/// Create a new `zwp_fullscreen_shell_v1` global
+///
+/// The given capabilities are advertised to clients binding the global.
+pub fn fullscreen_shell_init<L, Impl>(
+    display: &mut Display,
+    capabilities: &[Capability],
+    implementation: Impl,
+    logger: L,
+) -> Global<ZwpFullscreenShellV1>
+where
+    L: Into<Option<::slog::Logger>>,
+    Impl: FnMut(FullscreenShellRequest, DispatchData<'_>) + 'static,
+{
+    let log = crate::slog_or_fallback(logger);
+    let shell_data = ShellUserData {
+        log: log.new(slog::o!("smithay_module" => "fullscreen_shell_handler")),
+        user_impl: Rc::new(RefCell::new(implementation)),
+    };
+    let capabilities = capabilities.to_vec();
+
+    display.create_global(
+        1,
+        Filter::new(move |(shell, _version): (Main<ZwpFullscreenShellV1>, _), _, _| {
+            shell.quick_assign(fullscreen_shell_implementation);
+            shell.as_ref().user_data().set({
+                let shell_data = shell_data.clone();
+                move || shell_data
+            });
+            for &capability in &capabilities {
+                shell.capability(capability);
+            }
+        }),
+    )
+}
+
+fn give_role(shell: &ZwpFullscreenShellV1, surface: &WlSurface) -> bool {
+    if compositor::give_role(surface, FULLSCREEN_SHELL_SURFACE_ROLE).is_err()
+        && compositor::get_role(surface) != Some(FULLSCREEN_SHELL_SURFACE_ROLE)
+    {
+        shell.as_ref().post_error(
+            zwp_fullscreen_shell_v1::Error::Role as u32,
+            "Surface already has a role.".into(),
+        );
+        return false;
+    }
+    true
+}
+
+fn fullscreen_shell_implementation(
+    shell: Main<ZwpFullscreenShellV1>,
+    request: zwp_fullscreen_shell_v1::Request,
+    dispatch_data: DispatchData<'_>,
+) {
+    let data = shell.as_ref().user_data().get::<ShellUserData>().unwrap();
+    let request = match request {
+        zwp_fullscreen_shell_v1::Request::Release => return,
+        zwp_fullscreen_shell_v1::Request::PresentSurface {
+            surface,
+            method,
+            output,
+        } => {
+            if let Some(surface) = surface.as_ref() {
+                if !give_role(&shell, surface) {
+                    return;
+                }
+            }
+            let method = match PresentMethod::from_request(method) {
+                Some(method) => method,
+                None => {
+                    shell.as_ref().post_error(
+                        zwp_fullscreen_shell_v1::Error::InvalidMethod as u32,
+                        "Unknown present method.".into(),
+                    );
+                    return;
+                }
+            };
+            let output = output.as_ref().and_then(Output::from_resource);
+            if let Some(output) = output.as_ref() {
+                slog::trace!(data.log, "Presenting surface on output"; "output" => output.name());
+                set_presented_surface(output, surface.clone().map(|surface| (surface, method)));
+            }
+            FullscreenShellRequest::PresentSurface {
+                surface,
+                method,
+                output,
+            }
+        }
+        zwp_fullscreen_shell_v1::Request::PresentSurfaceForMode {
+            surface,
+            output,
+            framerate,
+            feedback,
+        } => {
+            feedback.quick_assign(|_, _, _| {});
+            if !give_role(&shell, &surface) {
+                return;
+            }
+            let output = match Output::from_resource(&output) {
+                Some(output) => output,
+                None => {
+                    feedback.mode_failed();
+                    return;
+                }
+            };
+
+            {
+                let mut state = output_state(&output).borrow_mut();
+                state.cancel_pending();
+                state.pending_feedback = Some((*feedback).clone());
+            }
+            FullscreenShellRequest::PresentSurfaceForMode {
+                surface: surface.clone(),
+                output: output.clone(),
+                framerate,
+                feedback: ModeFeedback {
+                    feedback: (*feedback).clone(),
+                    surface,
+                    output,
+                },
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    (&mut *data.user_impl.borrow_mut())(request, dispatch_data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PresentMethod;
+
+    #[test]
+    fn center_smaller_surface() {
+        let (location, scale) = PresentMethod::Default.placement((800, 600).into(), (1920, 1080).into());
+        assert_eq!(location, (560, 240).into());
+        assert_eq!(scale, (1.0, 1.0));
+    }
+
+    #[test]
+    fn zoom_larger_surface() {
+        let (location, scale) = PresentMethod::Zoom.placement((3840, 1600).into(), (1920, 1080).into());
+        assert_eq!(location, (0, 140).into());
+        assert_eq!(scale, (0.5, 0.5));
+    }
+
+    #[test]
+    fn zoom_crop_surface() {
+        let (location, scale) = PresentMethod::ZoomCrop.placement((1000, 1000).into(), (1920, 1080).into());
+        assert_eq!(location, (0, -420).into());
+        assert_eq!(scale, (1.92, 1.92));
+    }
+
+    #[test]
+    fn stretch_surface() {
+        let (location, scale) = PresentMethod::Stretch.placement((1000, 1000).into(), (1920, 1080).into());
+        assert_eq!(location, (0, 0).into());
+        assert_eq!(scale, (1.92, 1.08));
+    }
+}
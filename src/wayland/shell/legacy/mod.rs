@@ -74,6 +74,8 @@ pub struct ShellSurfaceAttributes {
     /// Class of the surface
     pub class: String,
     pending_ping: Option<Serial>,
+    // parent and location of the surface if it is a popup
+    popup: Option<(wl_surface::WlSurface, Point<i32, Logical>)>,
 }
 
 /// A handle to a shell surface
@@ -146,6 +148,28 @@ impl ShellSurface {
     pub fn send_popup_done(&self) {
         self.shell_surface.popup_done()
     }
+
+    /// Returns the parent surface and the location relative to it, if this surface is a popup
+    pub fn popup_placement(&self) -> Option<(wl_surface::WlSurface, Point<i32, Logical>)> {
+        self.get_surface().and_then(popup_placement)
+    }
+}
+
+// placement of a `wl_shell` popup given its surface, `None` for any other surface
+pub(crate) fn popup_placement(
+    surface: &wl_surface::WlSurface,
+) -> Option<(wl_surface::WlSurface, Point<i32, Logical>)> {
+    if compositor::get_role(surface) != Some(WL_SHELL_SURFACE_ROLE) {
+        return None;
+    }
+    compositor::with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<ShellSurfaceAttributes>>()
+            .and_then(|attributes| attributes.lock().unwrap().popup.clone())
+    })
+    .ok()
+    .flatten()
 }
 
 /// Possible kinds of shell surface of the `wl_shell` protocol
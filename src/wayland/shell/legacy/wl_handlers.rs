@@ -40,6 +40,7 @@ pub(crate) fn implement_shell<Impl>(
                     title: "".into(),
                     class: "".into(),
                     pending_ping: None,
+                    popup: None,
                 })
             })
         })
@@ -94,6 +95,27 @@ where
             .get::<ShellSurfaceUserData>()
             .unwrap();
         let mut user_impl = implementation.borrow_mut();
+        // remember the placement of popups, the other kinds are not placed relative to a parent
+        let popup = match req {
+            Request::SetPopup { ref parent, x, y, .. } => Some(Some((parent.clone(), (x, y).into()))),
+            Request::SetToplevel
+            | Request::SetTransient { .. }
+            | Request::SetFullscreen { .. }
+            | Request::SetMaximized { .. } => Some(None),
+            _ => None,
+        };
+        if let Some(popup) = popup {
+            compositor::with_states(&data.surface, |states| {
+                states
+                    .data_map
+                    .get::<Mutex<ShellSurfaceAttributes>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .popup = popup;
+            })
+            .unwrap();
+        }
         match req {
             Request::Pong { serial } => {
                 let serial = Serial::from(serial);
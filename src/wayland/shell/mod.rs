@@ -7,18 +7,21 @@
 //! The shell protocols thus define what kind of interactions a client can have with
 //! the compositor to properly display its contents on the screen.
 //!
-//! Smithay currently provides two of them:
+//! Smithay currently provides three of them:
 //!
 //! - The [`xdg`](xdg/index.html) module provides handlers for the `xdg_shell` protocol, which is
 //!   the current standard for desktop apps
 //! - The [`legacy`](legacy/index.html) module provides handlers for the `wl_shell` protocol, which
 //!   is now deprecated. You only need it if you want to support apps predating `xdg_shell`.
+//! - The [`fullscreen`](fullscreen/index.html) module provides handlers for the `fullscreen_shell`
+//!   protocol, which presents a single surface per output, e.g. for kiosk compositors.
 
 use super::Serial;
 use crate::wayland::compositor;
 use thiserror::Error;
 use wayland_server::protocol::wl_surface::WlSurface;
 
+pub mod fullscreen;
 pub mod legacy;
 pub mod xdg;
 
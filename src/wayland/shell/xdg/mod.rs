@@ -252,6 +252,12 @@ macro_rules! xdg_role {
             pub fn has_pending_changes(&self) -> bool {
                 self.server_pending.as_ref().map(|s| s != self.current_server_state()).unwrap_or(false)
             }
+
+            /// The serial of the last configure sent to the client, if it was not
+            /// acknowledged yet
+            pub fn pending_configure_serial(&self) -> Option<Serial> {
+                self.pending_configures.last().map(|c| c.serial)
+            }
         }
 
         impl Default for $attributes_name {
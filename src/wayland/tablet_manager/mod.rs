@@ -55,7 +55,7 @@ pub trait TabletSeatTrait {
 impl TabletSeatTrait for Seat {
     fn tablet_seat(&self) -> TabletSeatHandle {
         let user_data = self.user_data();
-        user_data.insert_if_missing(TabletSeatHandle::default);
+        user_data.insert_if_missing(|| TabletSeatHandle::new(self.serials().clone()));
         user_data.get::<TabletSeatHandle>().unwrap().clone()
     }
 }
@@ -71,7 +71,7 @@ pub fn init_tablet_manager_global(display: &mut Display) -> Global<ZwpTabletMana
                         let seat = Seat::from_resource(&seat).unwrap();
 
                         let user_data = seat.user_data();
-                        user_data.insert_if_missing(TabletSeatHandle::default);
+                        user_data.insert_if_missing(|| TabletSeatHandle::new(self.serials().clone()));
 
                         let instance = tablet_seat;
                         let tablet_seat = user_data.get::<TabletSeatHandle>().unwrap();
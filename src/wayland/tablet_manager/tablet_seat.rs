@@ -2,7 +2,7 @@ use wayland_protocols::unstable::tablet::v2::server::zwp_tablet_seat_v2::ZwpTabl
 use wayland_server::{Filter, Main};
 
 use crate::backend::input::TabletToolDescriptor;
-use crate::wayland::seat::CursorImageStatus;
+use crate::wayland::seat::{CursorImageStatus, SerialTracker};
 
 use super::tablet::{TabletDescriptor, TabletHandle};
 use super::tablet_tool::TabletToolHandle;
@@ -19,6 +19,8 @@ struct TabletSeat {
     tools: HashMap<TabletToolDescriptor, TabletToolHandle>,
 
     cursor_callback: Option<Box<dyn FnMut(&TabletToolDescriptor, CursorImageStatus)>>,
+
+    serials: SerialTracker,
 }
 
 impl fmt::Debug for TabletSeat {
@@ -27,6 +29,7 @@ impl fmt::Debug for TabletSeat {
             .field("instances", &self.instances)
             .field("tablets", &self.tablets)
             .field("tools", &self.tools)
+            .field("serials", &self.serials)
             .field(
                 "cursor_callback",
                 if self.cursor_callback.is_some() {
@@ -50,6 +53,15 @@ pub struct TabletSeatHandle {
 }
 
 impl TabletSeatHandle {
+    pub(super) fn new(serials: SerialTracker) -> TabletSeatHandle {
+        TabletSeatHandle {
+            inner: Rc::new(RefCell::new(TabletSeat {
+                serials,
+                ..Default::default()
+            })),
+        }
+    }
+
     pub(super) fn add_instance(&self, seat: Main<ZwpTabletSeatV2>) {
         let mut inner = self.inner.borrow_mut();
 
@@ -145,9 +157,10 @@ impl TabletSeatHandle {
 
         let tools = &mut inner.tools;
         let instances = &inner.instances;
+        let serials = &inner.serials;
 
         let tool = tools.entry(tool_desc.clone()).or_insert_with(|| {
-            let mut tool = TabletToolHandle::default();
+            let mut tool = TabletToolHandle::with_serials(serials.clone());
             // Create new tool instance for every seat instance
             for seat in instances.iter() {
                 let inner = self.inner.clone();
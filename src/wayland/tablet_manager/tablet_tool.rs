@@ -4,7 +4,7 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::backend::input::{ButtonState, TabletToolCapabilitys, TabletToolDescriptor, TabletToolType};
 use crate::utils::{Logical, Point};
-use crate::wayland::seat::{CursorImageAttributes, CursorImageStatus};
+use crate::wayland::seat::{CursorImageAttributes, CursorImageStatus, SerialEvent, SerialTracker};
 use wayland_protocols::unstable::tablet::v2::server::{
     zwp_tablet_seat_v2::ZwpTabletSeatV2,
     zwp_tablet_tool_v2::{self, ZwpTabletToolV2},
@@ -31,6 +31,8 @@ struct TabletTool {
     pending_slider: Option<f64>,
     pending_rotation: Option<f64>,
     pending_wheel: Option<(f64, i32)>,
+
+    serials: SerialTracker,
 }
 
 impl TabletTool {
@@ -88,6 +90,7 @@ impl TabletTool {
                 .find(|i| i.as_ref().same_client_as(focus.as_ref()))
             {
                 if !self.is_down {
+                    self.serials.record(serial, SerialEvent::TabletToolTipDown);
                     wl_tool.down(serial.into());
                     wl_tool.frame(time);
                 }
@@ -207,6 +210,11 @@ impl TabletTool {
                 .iter()
                 .find(|i| i.as_ref().same_client_as(focus.as_ref()))
             {
+                let event = match state {
+                    ButtonState::Pressed => SerialEvent::TabletToolButtonPress,
+                    ButtonState::Released => SerialEvent::TabletToolButtonRelease,
+                };
+                self.serials.record(serial, event);
                 wl_tool.button(serial.into(), button, state.into());
                 wl_tool.frame(time);
             }
@@ -234,6 +242,23 @@ pub struct TabletToolHandle {
 }
 
 impl TabletToolHandle {
+    pub(super) fn with_serials(serials: SerialTracker) -> TabletToolHandle {
+        TabletToolHandle {
+            inner: Rc::new(RefCell::new(TabletTool {
+                instances: Vec::new(),
+                focus: None,
+                is_down: false,
+                pending_pressure: None,
+                pending_distance: None,
+                pending_tilt: None,
+                pending_slider: None,
+                pending_rotation: None,
+                pending_wheel: None,
+                serials,
+            })),
+        }
+    }
+
     pub(super) fn new_instance<F>(&mut self, seat: &ZwpTabletSeatV2, tool: &TabletToolDescriptor, mut cb: F)
     where
         F: FnMut(&TabletToolDescriptor, CursorImageStatus) + 'static,
@@ -259,10 +284,13 @@ impl TabletToolHandle {
                         if let Some(ref focus) = inner.focus {
                             if focus.as_ref().same_client_as(tool.as_ref()) {
                                 if let Some(surface) = surface {
-                                    // tolerate re-using the same surface
-                                    if compositor::give_role(&surface, CURSOR_IMAGE_ROLE).is_err()
-                                        && compositor::get_role(&surface) != Some(CURSOR_IMAGE_ROLE)
-                                    {
+                                    // tolerate re-using the same surface, its hook was already added
+                                    if compositor::give_role(&surface, CURSOR_IMAGE_ROLE).is_ok() {
+                                        compositor::add_post_commit_hook(
+                                            &surface,
+                                            CursorImageAttributes::commit_hook,
+                                        );
+                                    } else if compositor::get_role(&surface) != Some(CURSOR_IMAGE_ROLE) {
                                         tool.as_ref().post_error(
                                             zwp_tablet_tool_v2::Error::Role as u32,
                                             "Given wl_surface has another role.".into(),
@@ -286,7 +314,13 @@ impl TabletToolHandle {
                                     })
                                     .unwrap();
 
-                                    cb(&desc, CursorImageStatus::Image(surface));
+                                    cb(
+                                        &desc,
+                                        CursorImageStatus::Image {
+                                            surface,
+                                            hotspot: (hotspot_x, hotspot_y).into(),
+                                        },
+                                    );
                                 } else {
                                     cb(&desc, CursorImageStatus::Hidden);
                                 };
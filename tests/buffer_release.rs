@@ -0,0 +1,232 @@
+//! Release of the buffers of a double-buffering client, relative to the frames using them
+
+mod helpers;
+
+use std::{cell::RefCell, ffi::CStr, os::unix::io::RawFd, rc::Rc};
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::{
+        allocator::{Format, Fourcc, Modifier},
+        renderer::{
+            dummy::DummyRenderer,
+            utils::{
+                draw_surface_tree, on_commit_buffer_handler_with_delayed_release, release_presented_buffers,
+            },
+            Renderer,
+        },
+    },
+    utils::{Rectangle, Transform},
+    wayland::{dmabuf::init_dmabuf_global, shm::init_shm_global},
+};
+use wayland_client::{
+    protocol::{
+        wl_buffer::{self, WlBuffer},
+        wl_compositor::WlCompositor,
+        wl_shm::{self, WlShm},
+    },
+    Main,
+};
+use wayland_protocols::unstable::linux_dmabuf::v1::client::{
+    zwp_linux_buffer_params_v1::Flags, zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use helpers::{roundtrip, TestClient, TestServer};
+
+fn draw(renderer: &mut DummyRenderer, surface: &WlSurface) {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    let damage = [Rectangle::from_loc_and_size((0, 0), (4, 4))];
+    renderer
+        .render((4, 4).into(), Transform::Normal, |renderer, frame| {
+            draw_surface_tree(renderer, frame, surface, 1.0, (0, 0).into(), &damage, 1.0, &log)
+        })
+        .unwrap()
+        .unwrap();
+}
+
+fn memfd(size: i64) -> RawFd {
+    let name = CStr::from_bytes_with_nul(b"buffers\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, size).unwrap();
+    fd
+}
+
+fn init_dmabuf(server: &mut TestServer) {
+    let format = Format {
+        code: Fourcc::Argb8888,
+        modifier: Modifier::Linear,
+    };
+    init_dmabuf_global(&mut server.display, vec![format], |_, _| true, None);
+}
+
+fn dma_buffers(client: &TestClient) -> Vec<Main<WlBuffer>> {
+    let dmabuf = client.globals.instantiate_exact::<ZwpLinuxDmabufV1>(3).unwrap();
+    (0..2)
+        .map(|_| {
+            let params = dmabuf.create_params();
+            params.add(memfd(4 * 4 * 4), 0, 0, 4 * 4, 0, 0);
+            params.create_immed(4, 4, Fourcc::Argb8888 as u32, Flags::empty())
+        })
+        .collect()
+}
+
+// record the index of each buffer released
+fn track_releases(buffers: Vec<Main<WlBuffer>>, releases: &Rc<RefCell<Vec<usize>>>) -> Vec<WlBuffer> {
+    buffers
+        .into_iter()
+        .enumerate()
+        .map(|(i, buffer)| {
+            let releases = releases.clone();
+            buffer.quick_assign(move |_, event, _| {
+                if let wl_buffer::Event::Release = event {
+                    releases.borrow_mut().push(i);
+                }
+            });
+            buffer.detach()
+        })
+        .collect()
+}
+
+// attach buffers alternately, drawing a frame each time which is not presented before the swap
+fn swap_buffers(
+    client: &mut TestClient,
+    server: &mut TestServer,
+    committed: &Rc<RefCell<Vec<WlSurface>>>,
+    buffers: &[WlBuffer],
+    releases: &Rc<RefCell<Vec<usize>>>,
+    delayed: bool,
+) {
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let surface = compositor.create_surface();
+    surface.attach(Some(&buffers[0]), 0, 0);
+    surface.commit();
+    roundtrip(client, server);
+    let server_surface = committed.borrow().last().unwrap().clone();
+
+    let mut renderer = DummyRenderer::new();
+    for swap in 1..5 {
+        draw(&mut renderer, &server_surface);
+        surface.attach(Some(&buffers[swap % 2]), 0, 0);
+        surface.commit();
+        roundtrip(client, server);
+        if delayed {
+            // the frame showing the previous buffer was not presented yet
+            assert_eq!(releases.borrow().len(), swap - 1);
+            release_presented_buffers(&server_surface);
+            roundtrip(client, server);
+        }
+        assert_eq!(releases.borrow().len(), swap);
+        assert_eq!(*releases.borrow().last().unwrap(), (swap - 1) % 2);
+    }
+
+    // a buffer never drawn is released right away when replaced
+    surface.attach(Some(&buffers[1]), 0, 0);
+    surface.commit();
+    roundtrip(client, server);
+    assert_eq!(*releases.borrow(), vec![0, 1, 0, 1, 0]);
+}
+
+#[test]
+fn dmabufs_are_released_once_presented() {
+    let (mut server, committed) =
+        TestServer::with_commit_handler(on_commit_buffer_handler_with_delayed_release);
+    init_dmabuf(&mut server);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let releases = Rc::new(RefCell::new(Vec::new()));
+    let buffers = track_releases(dma_buffers(&client), &releases);
+
+    swap_buffers(&mut client, &mut server, &committed, &buffers, &releases, true);
+}
+
+#[test]
+fn shm_buffers_are_released_right_away() {
+    // their contents are copied when imported, nothing needs to wait for the frame
+    let (mut server, committed) =
+        TestServer::with_commit_handler(on_commit_buffer_handler_with_delayed_release);
+    init_shm_global(&mut server.display, Vec::new(), None);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let pool = shm.create_pool(memfd(2 * 4 * 4 * 4), 2 * 4 * 4 * 4);
+    let buffers = (0..2)
+        .map(|i| pool.create_buffer(i * 4 * 4 * 4, 4, 4, 4 * 4, wl_shm::Format::Argb8888))
+        .collect::<Vec<_>>();
+    let releases = Rc::new(RefCell::new(Vec::new()));
+    let buffers = track_releases(buffers, &releases);
+
+    swap_buffers(&mut client, &mut server, &committed, &buffers, &releases, false);
+}
+
+#[test]
+fn buffers_are_released_right_away_by_default() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_dmabuf(&mut server);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let releases = Rc::new(RefCell::new(Vec::new()));
+    let buffers = track_releases(dma_buffers(&client), &releases);
+
+    swap_buffers(&mut client, &mut server, &committed, &buffers, &releases, false);
+}
+
+#[test]
+fn cycled_dmabufs_are_freed() {
+    let (mut server, committed) = TestServer::with_compositor();
+    // renderers key their texture cache by the imported dmabufs
+    let imported = Rc::new(RefCell::new(Vec::new()));
+    let imported2 = imported.clone();
+    let format = Format {
+        code: Fourcc::Argb8888,
+        modifier: Modifier::Linear,
+    };
+    init_dmabuf_global(
+        &mut server.display,
+        vec![format],
+        move |dmabuf, _| {
+            imported2.borrow_mut().push(dmabuf.weak());
+            true
+        },
+        None,
+    );
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let dmabuf = client.globals.instantiate_exact::<ZwpLinuxDmabufV1>(3).unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+    let server_surface = committed.borrow()[0].clone();
+
+    let mut renderer = DummyRenderer::new();
+    for _ in 0..20 {
+        // a client allocating a new buffer every frame, destroying the old one once released
+        let params = dmabuf.create_params();
+        params.add(memfd(4 * 4 * 4), 0, 0, 4 * 4, 0, 0);
+        let buffer = params.create_immed(4, 4, Fourcc::Argb8888 as u32, Flags::empty());
+        params.destroy();
+        buffer.quick_assign(|buffer, event, _| {
+            if let wl_buffer::Event::Release = event {
+                buffer.destroy();
+            }
+        });
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+        roundtrip(&mut client, &mut server);
+        draw(&mut renderer, &server_surface);
+        roundtrip(&mut client, &mut server);
+
+        // only the attached buffer is still alive, its predecessors were released and freed
+        let alive = imported
+            .borrow()
+            .iter()
+            .filter(|dmabuf| dmabuf.upgrade().is_some())
+            .count();
+        assert_eq!(alive, 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! Color metadata of surfaces passed to the frame while rendering
+
+mod helpers;
+
+use std::ffi::CStr;
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::renderer::dummy::{DrawCommand, DummyRenderer},
+    desktop::{
+        space::{DynamicRenderElements, SurfaceTree},
+        Space,
+    },
+    utils::{Physical, Point},
+    wayland::{
+        color_management::{
+            set_color_metadata, ColorMetadata, MasteringLuminance, Primaries, TransferFunction,
+        },
+        output::{Mode, Output, PhysicalProperties},
+        shm::init_shm_global,
+    },
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_shm::{self, WlShm},
+};
+use wayland_server::protocol::wl_output::Subpixel;
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn metadata_of_rendered_surfaces_reaches_the_frame() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let physical = PhysicalProperties {
+        size: (0, 0).into(),
+        subpixel: Subpixel::Unknown,
+        make: "Smithay".into(),
+        model: "Test".into(),
+    };
+    let (output, _global) = Output::new(&mut server.display, "test".into(), physical, None);
+    let mode = Mode {
+        size: (800, 600).into(),
+        refresh: 60_000,
+    };
+    output.change_current_state(Some(mode), None, None, None);
+    let mut space = Space::new(None);
+    space.map_output(&output, 1.0, (0, 0));
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let name = CStr::from_bytes_with_nul(b"buffer\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 10 * 10 * 4).unwrap();
+    let pool = shm.create_pool(fd, 10 * 10 * 4);
+    let buffer = pool.create_buffer(0, 10, 10, 10 * 4, wl_shm::Format::Argb8888);
+
+    let hdr = compositor.create_surface();
+    hdr.attach(Some(&buffer), 0, 0);
+    hdr.commit();
+    let sdr = compositor.create_surface();
+    sdr.attach(Some(&buffer), 0, 0);
+    sdr.commit();
+    roundtrip(&mut client, &mut server);
+    let server_hdr = committed.borrow()[0].clone();
+    let server_sdr = committed.borrow()[1].clone();
+
+    // the metadata is double-buffered
+    let metadata = ColorMetadata {
+        primaries: Primaries::Bt2020,
+        transfer_function: TransferFunction::Pq,
+        mastering_luminance: Some(MasteringLuminance {
+            min: 0.005,
+            max: 1000.0,
+        }),
+        ..Default::default()
+    };
+    set_color_metadata(&server_hdr, Some(metadata));
+    hdr.commit();
+    roundtrip(&mut client, &mut server);
+
+    let elements: Vec<DynamicRenderElements<DummyRenderer>> = vec![
+        Box::new(SurfaceTree {
+            surface: server_hdr,
+            position: (0, 0).into(),
+        }),
+        Box::new(SurfaceTree {
+            surface: server_sdr,
+            position: (100, 0).into(),
+        }),
+    ];
+    let mut renderer = DummyRenderer::new();
+    space
+        .render_output(&mut renderer, &output, 0, [0.0; 4], &elements)
+        .unwrap();
+
+    // metadata in effect for every drawn texture
+    let mut current = None;
+    let mut drawn = Vec::<(Option<ColorMetadata>, Point<f64, Physical>)>::new();
+    for command in renderer.take_commands() {
+        match command {
+            DrawCommand::ColorMetadata(metadata) => current = metadata,
+            DrawCommand::RenderTexture { dst, .. } => drawn.push((current, dst.loc)),
+            _ => {}
+        }
+    }
+    assert_eq!(
+        drawn,
+        vec![(Some(metadata), (0.0, 0.0).into()), (None, (100.0, 0.0).into()),]
+    );
+}
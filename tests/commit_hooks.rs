@@ -0,0 +1,88 @@
+//! Order of the commit hooks and of the compositor callback on surface commit
+
+mod helpers;
+
+use std::{cell::RefCell, rc::Rc};
+
+use smithay::wayland::compositor::{add_commit_hook, add_post_commit_hook, with_states, SurfaceAttributes};
+use wayland_client::protocol::wl_compositor::WlCompositor;
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use helpers::{roundtrip, TestServer};
+
+// the buffer scale seen by each step of the commit
+type CommitLog = RefCell<Vec<(&'static str, i32)>>;
+
+fn log_step(surface: &WlSurface, step: &'static str) {
+    with_states(surface, |states| {
+        states.data_map.insert_if_missing(CommitLog::default);
+        let scale = states.cached_state.current::<SurfaceAttributes>().buffer_scale;
+        states
+            .data_map
+            .get::<CommitLog>()
+            .unwrap()
+            .borrow_mut()
+            .push((step, scale));
+    })
+    .unwrap();
+}
+
+#[test]
+fn post_commit_hooks_see_the_applied_state() {
+    let (mut server, committed) = TestServer::with_commit_handler(|surface| log_step(surface, "callback"));
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    let server_surface = committed.borrow()[0].clone();
+    add_commit_hook(&server_surface, |surface| log_step(surface, "pre"));
+    add_post_commit_hook(&server_surface, |surface| log_step(surface, "post"));
+
+    surface.set_buffer_scale(2);
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    let log = with_states(&server_surface, |states| {
+        states.data_map.get::<CommitLog>().unwrap().borrow().clone()
+    })
+    .unwrap();
+    assert_eq!(
+        log,
+        vec![("callback", 1), ("pre", 1), ("post", 2), ("callback", 2)]
+    );
+}
+
+#[test]
+fn hooks_can_capture_their_environment() {
+    let (mut server, committed) = TestServer::with_compositor();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    let server_surface = committed.borrow()[0].clone();
+    let scales = Rc::new(RefCell::new(Vec::new()));
+    let hook_scales = scales.clone();
+    add_post_commit_hook(&server_surface, move |surface| {
+        let scale = with_states(surface, |states| {
+            states.cached_state.current::<SurfaceAttributes>().buffer_scale
+        })
+        .unwrap();
+        hook_scales.borrow_mut().push(scale);
+    });
+
+    surface.set_buffer_scale(2);
+    surface.commit();
+    surface.set_buffer_scale(3);
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    assert_eq!(*scales.borrow(), vec![2, 3]);
+}
@@ -0,0 +1,87 @@
+//! Visibility of cursor surfaces on the outputs of a space
+
+mod helpers;
+
+use std::ffi::CStr;
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    desktop::{space::CursorImage, Space},
+    utils::Point,
+    wayland::{
+        output::{Mode, Output, PhysicalProperties},
+        seat::CursorImageStatus,
+        shm::init_shm_global,
+    },
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_shm::{self, WlShm},
+};
+use wayland_server::protocol::wl_output::Subpixel;
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn cursor_surface_is_visible_while_overlapping_the_output() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let physical = PhysicalProperties {
+        size: (0, 0).into(),
+        subpixel: Subpixel::Unknown,
+        make: "Smithay".into(),
+        model: "Test".into(),
+    };
+    let (output, _global) = Output::new(&mut server.display, "test".into(), physical, None);
+    let mode = Mode {
+        size: (800, 600).into(),
+        refresh: 60_000,
+    };
+    output.change_current_state(Some(mode), None, None, None);
+    let mut space = Space::new(None);
+    space.map_output(&output, 1.0, (800, 0));
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let name = CStr::from_bytes_with_nul(b"cursor\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 32 * 32 * 4).unwrap();
+    let pool = shm.create_pool(fd, 32 * 32 * 4);
+    let buffer = pool.create_buffer(0, 32, 32, 32 * 4, wl_shm::Format::Argb8888);
+    let cursor = compositor.create_surface();
+    cursor.attach(Some(&buffer), 0, 0);
+    cursor.commit();
+    roundtrip(&mut client, &mut server);
+    let surface = committed.borrow()[0].clone();
+    let status = CursorImageStatus::Image {
+        surface: surface.clone(),
+        hotspot: (4, 4).into(),
+    };
+
+    // the pointer is on the output left of this one, but the image reaches into it
+    let cursor = space
+        .cursor_for_output(&output, &status, (790.0, 10.0).into())
+        .unwrap();
+    assert_eq!(
+        cursor.image,
+        CursorImage::Surface {
+            surface,
+            hotspot: (4, 4).into(),
+        }
+    );
+    assert_eq!(cursor.location, Point::from((-10, 10)));
+    assert_eq!(cursor.surface_element().unwrap().position, Point::from((786, 6)));
+
+    // the image ends before the output
+    assert_eq!(
+        space.cursor_for_output(&output, &status, (700.0, 10.0).into()),
+        None
+    );
+    // other images are only visible on the output the pointer is on
+    assert_eq!(
+        space.cursor_for_output(&output, &CursorImageStatus::Default, (790.0, 10.0).into()),
+        None
+    );
+}
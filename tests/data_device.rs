@@ -0,0 +1,814 @@
+//! Drag'n'drop sessions between clients
+
+mod helpers;
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::Read,
+    os::unix::io::FromRawFd,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use smithay::reexports::calloop::EventLoop;
+use smithay::wayland::{
+    compositor::with_states,
+    data_device::{
+        default_action_chooser, export_selection, flush_dnd_motion, import_selection, init_data_device,
+        known_data_devices, set_data_device_focus, set_data_device_selection, set_dnd_motion_interval,
+        start_dnd_with_forced_action, with_source_metadata, DataDeviceEvent, DnDIconAttributes,
+        ResourceLimits, ServerDndEvent, SourceMetadata, SourceMetadataError,
+    },
+    seat::{PointerGrabStartData, Seat, XkbConfig},
+    SERIAL_COUNTER,
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_data_device,
+    wl_data_device_manager::{DndAction, WlDataDeviceManager},
+    wl_seat::WlSeat,
+};
+use wayland_server::protocol::{
+    wl_data_device_manager::DndAction as ServerDndAction, wl_data_source::WlDataSource,
+    wl_pointer::ButtonState,
+};
+
+use helpers::{roundtrip, TestServer};
+
+#[derive(Debug, PartialEq)]
+enum DnDEvent {
+    Started,
+    Dropped,
+}
+
+#[test]
+fn destroyed_source_cancels_drag() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let dnd_events = Rc::new(RefCell::new(Vec::new()));
+    let dnd_events2 = dnd_events.clone();
+    init_data_device(
+        &mut server.display,
+        move |event| match event {
+            DataDeviceEvent::DnDStarted { .. } => dnd_events2.borrow_mut().push(DnDEvent::Started),
+            DataDeviceEvent::DnDDropped { .. } => dnd_events2.borrow_mut().push(DnDEvent::Dropped),
+            _ => {}
+        },
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    let device = manager.get_data_device(&wl_seat);
+    let source = manager.create_data_source();
+    source.offer("text/plain".into());
+    roundtrip(&mut client, &mut server);
+
+    // press a button on the surface of the client
+    let server_surface = committed.borrow()[0].clone();
+    let focus = Some((server_surface, (0, 0).into()));
+    pointer.motion(
+        (10.0, 10.0).into(),
+        focus.clone(),
+        SERIAL_COUNTER.next_serial(),
+        0,
+    );
+    let serial = SERIAL_COUNTER.next_serial();
+    pointer.button(0x110, ButtonState::Pressed, serial, 0);
+
+    device.start_drag(Some(&*source), &surface, None, serial.into());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*dnd_events.borrow(), vec![DnDEvent::Started]);
+    assert!(pointer.is_grabbed());
+
+    // the grab ends on the next event after the source is gone
+    source.destroy();
+    roundtrip(&mut client, &mut server);
+    pointer.motion((20.0, 20.0).into(), focus, SERIAL_COUNTER.next_serial(), 1);
+    assert_eq!(*dnd_events.borrow(), vec![DnDEvent::Started, DnDEvent::Dropped]);
+    assert!(!pointer.is_grabbed());
+}
+
+#[test]
+fn dnd_icon_offset_follows_its_commits() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(5).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    let icon = compositor.create_surface();
+    icon.commit();
+    let device = manager.get_data_device(&wl_seat);
+    roundtrip(&mut client, &mut server);
+    let (server_surface, server_icon) = {
+        let committed = committed.borrow();
+        (committed[0].clone(), committed[1].clone())
+    };
+
+    pointer.motion(
+        (10.0, 10.0).into(),
+        Some((server_surface, (0, 0).into())),
+        SERIAL_COUNTER.next_serial(),
+        0,
+    );
+    let serial = SERIAL_COUNTER.next_serial();
+    pointer.button(0x110, ButtonState::Pressed, serial, 0);
+    device.start_drag(None, &surface, Some(&icon), serial.into());
+    roundtrip(&mut client, &mut server);
+
+    // offsets of successive commits add up
+    icon.offset(3, -2);
+    icon.commit();
+    icon.offset(1, 1);
+    icon.commit();
+    roundtrip(&mut client, &mut server);
+    let offset = with_states(&server_icon, |states| {
+        states
+            .data_map
+            .get::<Mutex<DnDIconAttributes>>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .offset
+    })
+    .unwrap();
+    assert_eq!(offset, (4, -1).into());
+}
+
+#[test]
+fn stale_button_serial_does_not_start_drag() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let dnd_events = Rc::new(RefCell::new(Vec::new()));
+    let dnd_events2 = dnd_events.clone();
+    init_data_device(
+        &mut server.display,
+        move |event| {
+            if let DataDeviceEvent::DnDStarted { .. } = event {
+                dnd_events2.borrow_mut().push(DnDEvent::Started);
+            }
+        },
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    let device = manager.get_data_device(&wl_seat);
+    let source = manager.create_data_source();
+    roundtrip(&mut client, &mut server);
+
+    // click once, then press the same button again
+    let server_surface = committed.borrow()[0].clone();
+    pointer.motion(
+        (10.0, 10.0).into(),
+        Some((server_surface, (0, 0).into())),
+        SERIAL_COUNTER.next_serial(),
+        0,
+    );
+    let stale = SERIAL_COUNTER.next_serial();
+    pointer.button(0x110, ButtonState::Pressed, stale, 0);
+    pointer.button(0x110, ButtonState::Released, SERIAL_COUNTER.next_serial(), 1);
+    let serial = SERIAL_COUNTER.next_serial();
+    pointer.button(0x110, ButtonState::Pressed, serial, 2);
+    assert!(pointer.is_grabbed());
+
+    // the serial of the first click is rejected while the grab of the second one is active
+    device.start_drag(Some(&*source), &surface, None, stale.into());
+    roundtrip(&mut client, &mut server);
+    assert!(dnd_events.borrow().is_empty());
+
+    device.start_drag(Some(&*source), &surface, None, serial.into());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*dnd_events.borrow(), vec![DnDEvent::Started]);
+}
+
+#[test]
+fn data_devices_per_client_are_capped() {
+    let mut server = TestServer::new();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits {
+            max_data_devices: 2,
+            ..ResourceLimits::default()
+        },
+        None,
+    );
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let selections = Rc::new(RefCell::new(0));
+    for _ in 0..2 {
+        let selections = selections.clone();
+        manager
+            .get_data_device(&wl_seat)
+            .quick_assign(move |_, event, _| {
+                if let wl_data_device::Event::Selection { .. } = event {
+                    *selections.borrow_mut() += 1;
+                }
+            });
+    }
+    roundtrip(&mut client, &mut server);
+    let known = known_data_devices(&seat);
+    assert_eq!(known.len(), 2);
+    assert!(known.iter().all(|c| c.equals(&server_client)));
+
+    // the devices below the cap all receive the selection
+    set_data_device_focus(&seat, Some(server_client.clone()));
+    set_data_device_selection(&seat, vec!["text/plain".into()]);
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*selections.borrow(), 4);
+
+    // one more device kills the client
+    manager.get_data_device(&wl_seat);
+    client.display.flush().unwrap();
+    server.answer();
+    assert!(!server_client.alive());
+}
+
+#[test]
+fn resource_limits_kill_the_client() {
+    let limits = ResourceLimits {
+        max_mime_types: 2,
+        max_offers: 1,
+        ..ResourceLimits::default()
+    };
+    let mut server = TestServer::new();
+    init_data_device(&mut server.display, |_| {}, default_action_chooser, limits, None);
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    manager.get_data_device(&wl_seat);
+    roundtrip(&mut client, &mut server);
+
+    // the first offer is kept alive, so a second one exceeds the limit
+    set_data_device_focus(&seat, Some(server_client.clone()));
+    set_data_device_selection(&seat, vec!["text/plain".into()]);
+    roundtrip(&mut client, &mut server);
+    assert!(server_client.alive());
+    set_data_device_selection(&seat, vec!["text/plain".into()]);
+    server.answer();
+    assert!(!server_client.alive());
+
+    // a source offering too many mime types
+    let (_server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let source = manager.create_data_source();
+    for mime_type in &["text/plain", "text/html", "image/png"] {
+        source.offer(mime_type.to_string());
+    }
+    client.display.flush().unwrap();
+    server.answer();
+    if let Some(guard) = client.event_queue.prepare_read() {
+        let _ = guard.read_events();
+    }
+    let error = client.display.protocol_error().unwrap();
+    assert_eq!(error.object_interface, "wl_data_source");
+}
+
+#[test]
+fn device_created_after_focus_receives_selection() {
+    let mut server = TestServer::new();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    roundtrip(&mut client, &mut server);
+
+    // the client gets the focus before it has any data device
+    set_data_device_selection(&seat, vec!["text/plain".into()]);
+    set_data_device_focus(&seat, Some(server_client));
+
+    let offers = Rc::new(RefCell::new(Vec::new()));
+    let offers2 = offers.clone();
+    manager
+        .get_data_device(&wl_seat)
+        .quick_assign(move |_, event, _| {
+            if let wl_data_device::Event::Selection { id } = event {
+                offers2.borrow_mut().push(id.is_some());
+            }
+        });
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*offers.borrow(), vec![true]);
+}
+
+#[test]
+fn imported_selection_is_served_and_exported() {
+    let mut server = TestServer::new();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    assert_eq!(export_selection(&seat, |_| unreachable!()), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let offers = Rc::new(RefCell::new(Vec::new()));
+    let offers2 = offers.clone();
+    manager
+        .get_data_device(&wl_seat)
+        .quick_assign(move |_, event, _| {
+            if let wl_data_device::Event::Selection { id: Some(offer) } = event {
+                offers2.borrow_mut().push(offer);
+            }
+        });
+    roundtrip(&mut client, &mut server);
+
+    let mut event_loop = EventLoop::<()>::try_new().unwrap();
+    import_selection(
+        &seat,
+        &event_loop.handle(),
+        vec!["text/plain".into()],
+        b"restored".to_vec(),
+    );
+    set_data_device_focus(&seat, Some(server_client));
+    roundtrip(&mut client, &mut server);
+
+    let (read_fd, write_fd) = nix::unistd::pipe().unwrap();
+    offers.borrow()[0].receive("text/plain".into(), write_fd);
+    roundtrip(&mut client, &mut server);
+    nix::unistd::close(write_fd).unwrap();
+    // the contents are written, and the fd closed, by the event loop
+    event_loop
+        .dispatch(Some(Duration::from_millis(10)), &mut ())
+        .unwrap();
+    let mut contents = Vec::new();
+    unsafe { File::from_raw_fd(read_fd) }
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(contents, b"restored");
+
+    assert_eq!(
+        export_selection(&seat, |_| unreachable!()),
+        Some((vec!["text/plain".to_string()], b"restored".to_vec()))
+    );
+}
+
+#[test]
+fn selection_is_written_from_the_event_loop() {
+    let mut server = TestServer::new();
+    let writers = Rc::new(RefCell::new(Vec::new()));
+    let writers2 = writers.clone();
+    init_data_device(
+        &mut server.display,
+        move |event| {
+            if let DataDeviceEvent::SendSelection { writer, .. } = event {
+                writers2.borrow_mut().push(writer);
+            }
+        },
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let offers = Rc::new(RefCell::new(Vec::new()));
+    let offers2 = offers.clone();
+    manager
+        .get_data_device(&wl_seat)
+        .quick_assign(move |_, event, _| {
+            if let wl_data_device::Event::DataOffer { id } = event {
+                offers2.borrow_mut().push(id);
+            }
+        });
+    roundtrip(&mut client, &mut server);
+    set_data_device_selection(&seat, vec!["text/plain".into()]);
+    set_data_device_focus(&seat, Some(server_client));
+    roundtrip(&mut client, &mut server);
+
+    let (read_fd, write_fd) = nix::unistd::pipe().unwrap();
+    offers.borrow()[0].receive("text/plain".into(), write_fd);
+    roundtrip(&mut client, &mut server);
+    nix::unistd::close(write_fd).unwrap();
+
+    // much more than the pipe can hold at once
+    let contents = (0..1 << 20).map(|i| i as u8).collect::<Vec<u8>>();
+    let mut event_loop = EventLoop::<()>::try_new().unwrap();
+    let writer = writers.borrow_mut().pop().unwrap();
+    writer
+        .write_async(&event_loop.handle(), contents.clone())
+        .unwrap();
+    // the reader only gets EOF once everything was written and the fd closed
+    let done = Arc::new(AtomicBool::new(false));
+    let done2 = done.clone();
+    let reader = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        unsafe { File::from_raw_fd(read_fd) }
+            .read_to_end(&mut received)
+            .unwrap();
+        done2.store(true, Ordering::SeqCst);
+        received
+    });
+    while !done.load(Ordering::SeqCst) {
+        event_loop
+            .dispatch(Some(Duration::from_millis(10)), &mut ())
+            .unwrap();
+    }
+    assert_eq!(reader.join().unwrap(), contents);
+}
+
+#[test]
+fn dead_selection_source_is_treated_as_empty() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let sources = Rc::new(RefCell::new(Vec::new()));
+    let sources2 = sources.clone();
+    init_data_device(
+        &mut server.display,
+        move |event| {
+            if let DataDeviceEvent::NewSelection(Some(source)) = event {
+                sources2.borrow_mut().push(source);
+            }
+        },
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let keyboard = seat
+        .add_keyboard(XkbConfig::default(), 200, 25, |_, _| {})
+        .unwrap();
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    let offers = Rc::new(RefCell::new(Vec::new()));
+    let offers2 = offers.clone();
+    let device = manager.get_data_device(&wl_seat);
+    device.quick_assign(move |_, event, _| {
+        if let wl_data_device::Event::Selection { id } = event {
+            offers2.borrow_mut().push(id.is_some());
+        }
+    });
+    roundtrip(&mut client, &mut server);
+
+    // the focused client sets the selection
+    let server_surface = committed.borrow()[0].clone();
+    keyboard.set_focus(Some(&server_surface), SERIAL_COUNTER.next_serial());
+    let source = manager.create_data_source();
+    source.offer("text/plain".into());
+    device.set_selection(Some(&source), 0);
+    roundtrip(&mut client, &mut server);
+    let server_source = sources.borrow()[0].clone();
+    assert!(with_source_metadata(&server_source, |meta| meta.mime_types.clone()).is_ok());
+
+    source.destroy();
+    roundtrip(&mut client, &mut server);
+    assert!(matches!(
+        with_source_metadata(&server_source, |_| ()),
+        Err(SourceMetadataError::Dead)
+    ));
+
+    // refocusing the client must not panic and offers an empty selection
+    offers.borrow_mut().clear();
+    set_data_device_focus(&seat, Some(server_client));
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*offers.borrow(), vec![false]);
+}
+
+#[test]
+fn dnd_motion_is_coalesced() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+    set_dnd_motion_interval(&seat, 10);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events2 = events.clone();
+    let device = manager.get_data_device(&wl_seat);
+    device.quick_assign(move |_, event, _| match event {
+        wl_data_device::Event::Enter { x, y, .. } => events2.borrow_mut().push(("enter", x, y)),
+        wl_data_device::Event::Motion { x, y, .. } => events2.borrow_mut().push(("motion", x, y)),
+        wl_data_device::Event::Leave => events2.borrow_mut().push(("leave", 0.0, 0.0)),
+        _ => {}
+    });
+    let source = manager.create_data_source();
+    source.offer("text/plain".into());
+    roundtrip(&mut client, &mut server);
+
+    let server_surface = committed.borrow()[0].clone();
+    let focus = Some((server_surface, (0, 0).into()));
+    pointer.motion((1.0, 1.0).into(), focus.clone(), SERIAL_COUNTER.next_serial(), 0);
+    let serial = SERIAL_COUNTER.next_serial();
+    pointer.button(0x110, ButtonState::Pressed, serial, 0);
+    device.start_drag(Some(&*source), &surface, None, serial.into());
+    roundtrip(&mut client, &mut server);
+
+    // only the latest position is sent once flushed
+    pointer.motion((2.0, 2.0).into(), focus.clone(), SERIAL_COUNTER.next_serial(), 1);
+    pointer.motion((3.0, 3.0).into(), focus.clone(), SERIAL_COUNTER.next_serial(), 2);
+    flush_dnd_motion(&seat);
+    // held back until the drop
+    pointer.motion((4.0, 4.0).into(), focus.clone(), SERIAL_COUNTER.next_serial(), 5);
+    pointer.button(0x110, ButtonState::Released, SERIAL_COUNTER.next_serial(), 6);
+    roundtrip(&mut client, &mut server);
+
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            ("enter", 1.0, 1.0),
+            ("motion", 3.0, 3.0),
+            ("motion", 4.0, 4.0),
+            ("leave", 0.0, 0.0)
+        ]
+    );
+}
+
+#[test]
+fn foreign_source_has_no_metadata() {
+    let mut server = TestServer::new();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    // a data source implemented outside of smithay, e.g. by another global of the compositor
+    let source = server_client.create_resource::<WlDataSource>(3).unwrap();
+    source.quick_assign(|_, _, _| {});
+    assert!(matches!(
+        with_source_metadata(&source, |_| ()),
+        Err(SourceMetadataError::NoMetadata)
+    ));
+}
+
+fn server_dnd_event(event: ServerDndEvent) -> &'static str {
+    match event {
+        ServerDndEvent::Action(action) if action.is_empty() => "no action",
+        ServerDndEvent::Action(_) => "action",
+        ServerDndEvent::Dropped => "dropped",
+        ServerDndEvent::Cancelled => "cancelled",
+        ServerDndEvent::Send { .. } => "send",
+        ServerDndEvent::Finished => "finished",
+    }
+}
+
+#[test]
+fn forced_action_refused_by_target_cancels_drag() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_data_device(
+        &mut server.display,
+        |_| {},
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    // the target only supports moving the data
+    manager.get_data_device(&wl_seat).quick_assign(|_, event, _| {
+        if let wl_data_device::Event::Enter { id: Some(offer), .. } = event {
+            offer.set_actions(DndAction::Move, DndAction::Move);
+        }
+    });
+    roundtrip(&mut client, &mut server);
+    let server_surface = committed.borrow()[0].clone();
+
+    let metadata = SourceMetadata {
+        mime_types: vec!["text/plain".into()],
+        dnd_action: ServerDndAction::Copy | ServerDndAction::Move,
+    };
+    let start_data = PointerGrabStartData {
+        focus: None,
+        button: 0x110,
+        location: (0.0, 0.0).into(),
+    };
+    let events = Rc::new(RefCell::new(Vec::new()));
+
+    // a source not allowing the forced action is refused right away
+    let events2 = events.clone();
+    start_dnd_with_forced_action(
+        &seat,
+        SERIAL_COUNTER.next_serial(),
+        start_data.clone(),
+        SourceMetadata {
+            dnd_action: ServerDndAction::Move,
+            ..metadata.clone()
+        },
+        ServerDndAction::Copy,
+        move |event| events2.borrow_mut().push(server_dnd_event(event)),
+    );
+    assert_eq!(*events.borrow(), vec!["cancelled"]);
+    assert!(!pointer.is_grabbed());
+
+    events.borrow_mut().clear();
+    let events2 = events.clone();
+    start_dnd_with_forced_action(
+        &seat,
+        SERIAL_COUNTER.next_serial(),
+        start_data,
+        metadata,
+        ServerDndAction::Copy,
+        move |event| events2.borrow_mut().push(server_dnd_event(event)),
+    );
+    assert!(pointer.is_grabbed());
+    let focus = Some((server_surface, (0, 0).into()));
+    pointer.motion(
+        (10.0, 10.0).into(),
+        focus.clone(),
+        SERIAL_COUNTER.next_serial(),
+        0,
+    );
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*events.borrow(), vec!["no action"]);
+
+    // the drag'n'drop is cancelled with the next event, without waiting for the drop
+    pointer.motion((20.0, 20.0).into(), focus, SERIAL_COUNTER.next_serial(), 1);
+    assert_eq!(*events.borrow(), vec!["no action", "cancelled"]);
+    assert!(!pointer.is_grabbed());
+}
+
+#[test]
+fn selection_shared_by_two_seats_is_cleared_once_per_seat() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let cleared = Rc::new(RefCell::new(Vec::new()));
+    let cleared2 = cleared.clone();
+    init_data_device(
+        &mut server.display,
+        move |event| {
+            if let DataDeviceEvent::SelectionCleared { seat } = event {
+                cleared2.borrow_mut().push(seat);
+            }
+        },
+        default_action_chooser,
+        ResourceLimits::default(),
+        None,
+    );
+    let seats = (0..2)
+        .map(|i| {
+            let (mut seat, _seat_global) = Seat::new(&mut server.display, format!("seat-{}", i), None);
+            let keyboard = seat
+                .add_keyboard(XkbConfig::default(), 200, 25, |_, _| {})
+                .unwrap();
+            (seat, keyboard)
+        })
+        .collect::<Vec<_>>();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<WlDataDeviceManager>(3)
+        .unwrap();
+    let registry = client.display_proxy.get_registry();
+    let devices = client
+        .globals
+        .list()
+        .into_iter()
+        .filter(|(_, interface, _)| interface == "wl_seat")
+        .map(|(id, _, _)| manager.get_data_device(&registry.bind::<WlSeat>(5, id)))
+        .collect::<Vec<_>>();
+    assert_eq!(devices.len(), 2);
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    // the same source becomes the selection of both seats, twice for the first one
+    let server_surface = committed.borrow()[0].clone();
+    for (_, keyboard) in &seats {
+        keyboard.set_focus(Some(&server_surface), SERIAL_COUNTER.next_serial());
+    }
+    let source = manager.create_data_source();
+    source.offer("text/plain".into());
+    for device in devices.iter().chain(devices.first()) {
+        device.set_selection(Some(&source), 0);
+    }
+    roundtrip(&mut client, &mut server);
+    assert!(cleared.borrow().is_empty());
+
+    source.destroy();
+    roundtrip(&mut client, &mut server);
+    let cleared = cleared.borrow();
+    assert_eq!(cleared.len(), 2);
+    assert!(seats.iter().all(|(seat, _)| cleared.contains(seat)));
+}
@@ -0,0 +1,136 @@
+//! Drawing of cursor surfaces set by clients
+
+mod helpers;
+
+use std::{cell::RefCell, ffi::CStr, rc::Rc};
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::renderer::{
+        dummy::{DrawCommand, DummyRenderer},
+        utils::draw_cursor,
+        Renderer,
+    },
+    utils::{Logical, Physical, Point, Rectangle, Transform},
+    wayland::{seat::Seat, shm::init_shm_global, SERIAL_COUNTER},
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_pointer,
+    wl_seat::WlSeat,
+    wl_shm::{self, WlShm},
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use helpers::{roundtrip, TestServer};
+
+// where the textures were drawn
+fn draw(
+    surface: &WlSurface,
+    location: Point<i32, Logical>,
+    default_cursor: bool,
+) -> Vec<Rectangle<f64, Physical>> {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    let mut renderer = DummyRenderer::new();
+    let default_cursor = if default_cursor {
+        Some(renderer.create_texture((8, 8).into()))
+    } else {
+        None
+    };
+    renderer
+        .render((50, 50).into(), Transform::Normal, |renderer, frame| {
+            draw_cursor(
+                renderer,
+                frame,
+                surface,
+                location,
+                (50, 50).into(),
+                1.0,
+                default_cursor.as_ref(),
+                &log,
+            )
+        })
+        .unwrap()
+        .unwrap();
+    renderer
+        .take_commands()
+        .into_iter()
+        .filter_map(|command| match command {
+            DrawCommand::RenderTexture { dst, .. } => Some(dst),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn cursor_is_drawn_at_its_hotspot_or_replaced_by_the_default() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let pointer = seat.add_pointer(|_| {});
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let name = CStr::from_bytes_with_nul(b"cursor\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 4 * 4 * 4).unwrap();
+    let pool = shm.create_pool(fd, 4 * 4 * 4);
+    let buffer = pool.create_buffer(0, 4, 4, 4 * 4, wl_shm::Format::Argb8888);
+
+    let surface = compositor.create_surface();
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    let cursor = compositor.create_surface();
+    cursor.attach(Some(&buffer), 0, 0);
+    cursor.commit();
+    let enter_serial = Rc::new(RefCell::new(None));
+    let enter_serial2 = enter_serial.clone();
+    let wl_pointer = wl_seat.get_pointer();
+    wl_pointer.quick_assign(move |_, event, _| {
+        if let wl_pointer::Event::Enter { serial, .. } = event {
+            *enter_serial2.borrow_mut() = Some(serial);
+        }
+    });
+    roundtrip(&mut client, &mut server);
+    let (server_surface, server_cursor) = {
+        let committed = committed.borrow();
+        (committed[0].clone(), committed[1].clone())
+    };
+
+    // the client sets its cursor once the pointer entered its surface
+    pointer.motion(
+        (10.0, 10.0).into(),
+        Some((server_surface.clone(), (0, 0).into())),
+        SERIAL_COUNTER.next_serial(),
+        0,
+    );
+    roundtrip(&mut client, &mut server);
+    let serial = enter_serial.borrow().unwrap();
+    wl_pointer.set_cursor(serial, Some(&cursor), 3, 4);
+    roundtrip(&mut client, &mut server);
+
+    // the pointer location is clamped to the output before subtracting the hotspot
+    assert_eq!(
+        draw(&server_cursor, (100, -5).into(), true),
+        vec![Rectangle::from_loc_and_size((46.0, -4.0), (4.0, 4.0))]
+    );
+
+    // attaching a buffer with an offset moves the hotspot the other way
+    cursor.attach(Some(&buffer), 1, 2);
+    cursor.commit();
+    roundtrip(&mut client, &mut server);
+    assert_eq!(
+        draw(&server_cursor, (100, -5).into(), true),
+        vec![Rectangle::from_loc_and_size((47.0, -2.0), (4.0, 4.0))]
+    );
+
+    // a surface without the cursor role is replaced by the default cursor
+    assert_eq!(
+        draw(&server_surface, (20, 20).into(), true),
+        vec![Rectangle::from_loc_and_size((20.0, 20.0), (8.0, 8.0))]
+    );
+    assert!(draw(&server_surface, (20, 20).into(), false).is_empty());
+}
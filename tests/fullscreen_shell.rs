@@ -0,0 +1,47 @@
+//! Surfaces presented on outputs via the fullscreen shell
+
+mod helpers;
+
+use smithay::wayland::{
+    output::{Output, PhysicalProperties},
+    shell::fullscreen::{presented_surface, set_presented_surface, PresentMethod},
+};
+use wayland_client::protocol::wl_compositor::WlCompositor;
+use wayland_server::{protocol::wl_output::Subpixel, Display};
+
+use helpers::{roundtrip, TestServer};
+
+fn output(display: &mut Display, name: &str) -> Output {
+    let physical = PhysicalProperties {
+        size: (0, 0).into(),
+        subpixel: Subpixel::Unknown,
+        make: "Smithay".into(),
+        model: "Test".into(),
+    };
+    Output::new(display, name.into(), physical, None).0
+}
+
+#[test]
+fn surface_is_moved_between_outputs() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let first = output(&mut server.display, "first");
+    let second = output(&mut server.display, "second");
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    compositor.create_surface().commit();
+    roundtrip(&mut client, &mut server);
+    let surface = committed.borrow()[0].clone();
+
+    set_presented_surface(&first, Some((surface.clone(), PresentMethod::Center)));
+    assert_eq!(
+        presented_surface(&first),
+        Some((surface.clone(), PresentMethod::Center))
+    );
+
+    // presenting the surface on another output removes it from the first one
+    set_presented_surface(&second, Some((surface.clone(), PresentMethod::Zoom)));
+    assert_eq!(presented_surface(&first), None);
+    assert_eq!(presented_surface(&second), Some((surface, PresentMethod::Zoom)));
+}
@@ -0,0 +1,125 @@
+//! Harness connecting a wayland client to a smithay server in the same process
+//!
+//! Both ends run on the current thread, [`roundtrip`] dispatches them alternately until the
+//! client received all the events the server sent in response to its requests.
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    io,
+    os::unix::io::IntoRawFd,
+    os::unix::net::UnixStream,
+    rc::Rc,
+    time::Duration,
+};
+
+use smithay::{backend::renderer::utils::on_commit_buffer_handler, wayland::compositor::compositor_init};
+use wayland_client::{protocol::wl_display::WlDisplay, Attached, EventQueue, GlobalManager};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+/// Surfaces committed by the clients of a [`TestServer`], in the order of their commits
+pub type CommittedSurfaces = Rc<RefCell<Vec<WlSurface>>>;
+
+pub struct TestServer {
+    pub display: wayland_server::Display,
+}
+
+impl TestServer {
+    pub fn new() -> TestServer {
+        TestServer {
+            display: wayland_server::Display::new(),
+        }
+    }
+
+    /// Create a server with a compositor global, letting smithay manage the buffers of the surfaces
+    pub fn with_compositor() -> (TestServer, CommittedSurfaces) {
+        TestServer::with_commit_handler(on_commit_buffer_handler)
+    }
+
+    /// Create a server with a compositor global, calling `handler` for every commit
+    pub fn with_commit_handler<F>(mut handler: F) -> (TestServer, CommittedSurfaces)
+    where
+        F: FnMut(&WlSurface) + 'static,
+    {
+        let mut server = TestServer::new();
+        let committed = CommittedSurfaces::default();
+        let committed2 = committed.clone();
+        compositor_init(
+            &mut server.display,
+            move |surface, _| {
+                handler(&surface);
+                committed2.borrow_mut().push(surface);
+            },
+            None,
+        );
+        (server, committed)
+    }
+
+    /// Connect a new client to this server
+    pub fn add_client(&mut self) -> (wayland_server::Client, TestClient) {
+        let (server_socket, client_socket) = UnixStream::pair().unwrap();
+        client_socket.set_nonblocking(true).unwrap();
+        let client = unsafe { self.display.create_client(server_socket.into_raw_fd(), &mut ()) };
+        (client, TestClient::new(client_socket))
+    }
+
+    /// Process the pending requests of all clients and send them the resulting events
+    pub fn answer(&mut self) {
+        self.display.dispatch(Duration::from_millis(10), &mut ()).unwrap();
+        self.display.flush_clients(&mut ());
+    }
+}
+
+pub struct TestClient {
+    pub display: wayland_client::Display,
+    pub display_proxy: Attached<WlDisplay>,
+    pub event_queue: EventQueue,
+    pub globals: GlobalManager,
+}
+
+impl TestClient {
+    fn new(socket: UnixStream) -> TestClient {
+        let display = unsafe { wayland_client::Display::from_fd(socket.into_raw_fd()) }.unwrap();
+        let event_queue = display.create_event_queue();
+        let display_proxy = display.attach(event_queue.token());
+        let globals = GlobalManager::new(&display_proxy);
+        TestClient {
+            display,
+            display_proxy,
+            event_queue,
+            globals,
+        }
+    }
+}
+
+/// Exchange messages between the client and the server until the server processed all
+/// requests of the client and the client received all resulting events
+pub fn roundtrip(client: &mut TestClient, server: &mut TestServer) {
+    let done = Rc::new(Cell::new(false));
+    let done2 = done.clone();
+    client
+        .display_proxy
+        .sync()
+        .quick_assign(move |_, _, _| done2.set(true));
+    while !done.get() {
+        match client.display.flush() {
+            Err(e) if e.kind() != io::ErrorKind::WouldBlock => panic!("Failed to flush the client: {}", e),
+            _ => {}
+        }
+        server.answer();
+        if let Some(guard) = client.event_queue.prepare_read() {
+            match guard.read_events() {
+                Err(e) if e.kind() != io::ErrorKind::WouldBlock => {
+                    panic!("Failed to read the server events: {}", e)
+                }
+                _ => {}
+            }
+        }
+        client
+            .event_queue
+            .dispatch_pending(&mut (), |_, _, _| {})
+            .unwrap();
+    }
+}
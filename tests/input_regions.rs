@@ -0,0 +1,186 @@
+//! Hit-testing of overlapping windows and layer surfaces with input regions
+
+mod helpers;
+
+use std::{cell::RefCell, ffi::CStr, rc::Rc};
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    desktop::{layer_map_for_output, Kind, LayerSurface, Space, Window},
+    wayland::{
+        output::{Mode, Output, PhysicalProperties},
+        shell::{
+            legacy::{wl_shell_init, ShellRequest},
+            wlr_layer::{wlr_layer_shell_init, Layer, LayerShellRequest},
+        },
+        shm::init_shm_global,
+    },
+};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_shell::WlShell,
+        wl_shm::{self, WlShm},
+        wl_surface,
+    },
+    Main,
+};
+use wayland_protocols::wlr::unstable::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1,
+};
+use wayland_server::protocol::wl_output::Subpixel;
+
+use helpers::{roundtrip, TestServer};
+
+// a 100x100 buffer shared by all surfaces
+fn create_buffer(shm: &Main<WlShm>) -> Main<WlBuffer> {
+    let name = CStr::from_bytes_with_nul(b"buffer\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 100 * 100 * 4).unwrap();
+    let pool = shm.create_pool(fd, 100 * 100 * 4);
+    pool.create_buffer(0, 100, 100, 100 * 4, wl_shm::Format::Argb8888)
+}
+
+// only accept input in a 20x20 square at (10, 10) of the surface
+fn set_small_input_region(compositor: &Main<WlCompositor>, surface: &wl_surface::WlSurface) {
+    let region = compositor.create_region();
+    region.add(10, 10, 20, 20);
+    surface.set_input_region(Some(&region));
+}
+
+#[test]
+fn window_under_falls_through_input_regions() {
+    let (mut server, _committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let shell_surfaces = Rc::new(RefCell::new(Vec::new()));
+    let shell_surfaces2 = shell_surfaces.clone();
+    wl_shell_init(
+        &mut server.display,
+        move |request, _| {
+            if let ShellRequest::NewShellSurface { surface } = request {
+                shell_surfaces2.borrow_mut().push(surface);
+            }
+        },
+        None,
+    );
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(1).unwrap();
+    let wl_shell = client.globals.instantiate_exact::<WlShell>(1).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let buffer = create_buffer(&shm);
+
+    let lower = compositor.create_surface();
+    wl_shell.get_shell_surface(&lower).set_toplevel();
+    let upper = compositor.create_surface();
+    wl_shell.get_shell_surface(&upper).set_toplevel();
+    set_small_input_region(&compositor, &upper);
+    for surface in &[&lower, &upper] {
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+    }
+    roundtrip(&mut client, &mut server);
+
+    let mut space = Space::new(None);
+    let windows = shell_surfaces
+        .borrow()
+        .iter()
+        .map(|surface| Window::new(Kind::Wl(surface.clone())))
+        .collect::<Vec<_>>();
+    for window in &windows {
+        window.refresh();
+        space.map_window(window, (0, 0), false);
+    }
+    let (lower, upper) = (&windows[0], &windows[1]);
+
+    // inside the input region of the upper window
+    assert_eq!(space.window_under((15.0, 15.0)), Some(upper));
+    // inside the buffer of the upper window, but outside of its input region
+    assert_eq!(space.window_under((50.0, 50.0)), Some(lower));
+    assert_eq!(space.window_under((150.0, 150.0)), None);
+}
+
+#[test]
+fn layer_under_falls_through_input_regions() {
+    let (mut server, _committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let layer_surfaces = Rc::new(RefCell::new(Vec::new()));
+    let layer_surfaces2 = layer_surfaces.clone();
+    wlr_layer_shell_init(
+        &mut server.display,
+        move |request, _| {
+            if let LayerShellRequest::NewLayerSurface {
+                surface, namespace, ..
+            } = request
+            {
+                layer_surfaces2
+                    .borrow_mut()
+                    .push(LayerSurface::new(surface, namespace));
+            }
+        },
+        None,
+    );
+    let physical = PhysicalProperties {
+        size: (0, 0).into(),
+        subpixel: Subpixel::Unknown,
+        make: "Smithay".into(),
+        model: "Test".into(),
+    };
+    let (output, _global) = Output::new(&mut server.display, "test".into(), physical, None);
+    let mode = Mode {
+        size: (800, 600).into(),
+        refresh: 60_000,
+    };
+    output.change_current_state(Some(mode), None, None, None);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(1).unwrap();
+    let layer_shell = client.globals.instantiate_exact::<ZwlrLayerShellV1>(4).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let buffer = create_buffer(&shm);
+
+    let surfaces = (0..2)
+        .map(|_| {
+            let surface = compositor.create_surface();
+            let layer_surface =
+                layer_shell.get_layer_surface(&surface, None, zwlr_layer_shell_v1::Layer::Top, "test".into());
+            layer_surface.set_size(100, 100);
+            layer_surface
+                .set_anchor(zwlr_layer_surface_v1::Anchor::Top | zwlr_layer_surface_v1::Anchor::Left);
+            layer_surface.quick_assign(|layer_surface, event, _| {
+                if let zwlr_layer_surface_v1::Event::Configure { serial, .. } = event {
+                    layer_surface.ack_configure(serial);
+                }
+            });
+            surface.commit();
+            surface
+        })
+        .collect::<Vec<_>>();
+    roundtrip(&mut client, &mut server);
+
+    // mapping the layer surfaces sends their initial configure
+    for layer in layer_surfaces.borrow().iter() {
+        layer_map_for_output(&output).map_layer(layer).unwrap();
+    }
+    roundtrip(&mut client, &mut server);
+    set_small_input_region(&compositor, &surfaces[1]);
+    for surface in &surfaces {
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+    }
+    roundtrip(&mut client, &mut server);
+
+    let layer_surfaces = layer_surfaces.borrow();
+    let (lower, upper) = (&layer_surfaces[0], &layer_surfaces[1]);
+    let map = layer_map_for_output(&output);
+    // inside the input region of the upper layer surface
+    assert_eq!(map.layer_under(Layer::Top, (15.0, 15.0)), Some(upper));
+    // inside the buffer of the upper layer surface, but outside of its input region
+    assert_eq!(map.layer_under(Layer::Top, (50.0, 50.0)), Some(lower));
+    assert_eq!(map.layer_under(Layer::Top, (150.0, 150.0)), None);
+    assert_eq!(map.layer_under(Layer::Bottom, (50.0, 50.0)), None);
+}
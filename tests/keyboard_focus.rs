@@ -0,0 +1,253 @@
+//! Events received by the keyboard of a client while the keyboard focus moves between its surfaces
+
+mod helpers;
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use smithay::wayland::{
+    seat::{KeyboardGrab, KeyboardGrabStartData, KeyboardInnerHandle, RepeatInfoNotified, Seat, XkbConfig},
+    Serial, SERIAL_COUNTER,
+};
+use wayland_client::protocol::{wl_compositor::WlCompositor, wl_keyboard, wl_seat::WlSeat};
+use wayland_server::protocol::{wl_keyboard::KeyState, wl_surface::WlSurface};
+use xkbcommon::xkb;
+
+use helpers::{roundtrip, TestServer};
+
+#[derive(Debug, PartialEq)]
+enum KeyboardEvent {
+    Keymap,
+    RepeatInfo,
+    Enter(u32),
+    Leave(u32),
+    Modifiers,
+    Key,
+}
+
+#[test]
+fn focus_change_sequence() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let config = XkbConfig {
+        layout: "us",
+        ..Default::default()
+    };
+    let keyboard = seat.add_keyboard(config, 200, 25, |_, _| {}).unwrap();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let events2 = events.clone();
+    wl_seat.get_keyboard().quick_assign(move |_, event, _| {
+        let event = match event {
+            wl_keyboard::Event::Keymap { .. } => KeyboardEvent::Keymap,
+            wl_keyboard::Event::RepeatInfo { .. } => KeyboardEvent::RepeatInfo,
+            wl_keyboard::Event::Enter { surface, .. } => KeyboardEvent::Enter(surface.as_ref().id()),
+            wl_keyboard::Event::Leave { surface, .. } => KeyboardEvent::Leave(surface.as_ref().id()),
+            wl_keyboard::Event::Modifiers { .. } => KeyboardEvent::Modifiers,
+            wl_keyboard::Event::Key { .. } => KeyboardEvent::Key,
+            _ => unreachable!(),
+        };
+        events2.borrow_mut().push(event);
+    });
+    let first = compositor.create_surface();
+    let second = compositor.create_surface();
+    first.commit();
+    second.commit();
+    roundtrip(&mut client, &mut server);
+
+    // a new keyboard is sent the keymap and the repeat info
+    assert_eq!(
+        events.borrow_mut().drain(..).collect::<Vec<_>>(),
+        vec![KeyboardEvent::Keymap, KeyboardEvent::RepeatInfo]
+    );
+
+    let surfaces = committed.borrow().clone();
+    assert_eq!(surfaces.len(), 2);
+    let (first_id, second_id) = (first.as_ref().id(), second.as_ref().id());
+
+    // the modifiers follow the enter event
+    keyboard.set_focus(Some(&surfaces[0]), SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(
+        events.borrow_mut().drain(..).collect::<Vec<_>>(),
+        vec![KeyboardEvent::Enter(first_id), KeyboardEvent::Modifiers]
+    );
+
+    // the old surface is left before the new one is entered
+    keyboard.set_focus(Some(&surfaces[1]), SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(
+        events.borrow_mut().drain(..).collect::<Vec<_>>(),
+        vec![
+            KeyboardEvent::Leave(first_id),
+            KeyboardEvent::Enter(second_id),
+            KeyboardEvent::Modifiers
+        ]
+    );
+
+    // focusing the same surface again sends nothing
+    keyboard.set_focus(Some(&surfaces[1]), SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert!(events.borrow().is_empty());
+
+    keyboard.set_focus(None, SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(
+        events.borrow_mut().drain(..).collect::<Vec<_>>(),
+        vec![KeyboardEvent::Leave(second_id)]
+    );
+}
+
+struct NotifiedGrab {
+    start_data: KeyboardGrabStartData,
+    unset: Rc<Cell<bool>>,
+}
+
+impl KeyboardGrab for NotifiedGrab {
+    fn input(
+        &mut self,
+        _: &mut KeyboardInnerHandle<'_>,
+        _: u32,
+        _: KeyState,
+        _: Option<(u32, u32, u32, u32)>,
+        _: Serial,
+        _: u32,
+    ) {
+    }
+
+    fn set_focus(&mut self, _: &mut KeyboardInnerHandle<'_>, _: Option<&WlSurface>, _: Serial) {}
+
+    fn start_data(&self) -> &KeyboardGrabStartData {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _: &mut KeyboardInnerHandle<'_>) {
+        self.unset.set(true);
+    }
+}
+
+#[test]
+fn grab_ends_when_its_focus_dies() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let keyboard = seat.add_keyboard(Default::default(), 200, 25, |_, _| {}).unwrap();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+
+    let server_surface = committed.borrow()[0].clone();
+    keyboard.set_focus(Some(&server_surface), SERIAL_COUNTER.next_serial());
+    let unset = Rc::new(Cell::new(false));
+    let unset2 = unset.clone();
+    keyboard.start_grab_with_current_focus(SERIAL_COUNTER.next_serial(), move |start_data| NotifiedGrab {
+        start_data,
+        unset: unset2,
+    });
+    assert!(keyboard.grab_start_data().is_some());
+
+    // the surface dies between two keyboard events
+    surface.destroy();
+    roundtrip(&mut client, &mut server);
+    assert!(keyboard.grab_start_data().is_none());
+    assert!(unset.get());
+    assert!(!keyboard.is_grabbed());
+}
+
+#[test]
+fn layout_is_restored_per_window() {
+    let (mut server, committed) = TestServer::with_compositor();
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let config = XkbConfig {
+        layout: "us,de",
+        ..Default::default()
+    };
+    let keyboard = seat.add_keyboard(config, 200, 25, |_, _| {}).unwrap();
+    keyboard.set_per_window_layout(true);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let groups = Rc::new(RefCell::new(Vec::new()));
+    let groups2 = groups.clone();
+    wl_seat.get_keyboard().quick_assign(move |_, event, _| {
+        if let wl_keyboard::Event::Modifiers { group, .. } = event {
+            groups2.borrow_mut().push(group);
+        }
+    });
+    let first = compositor.create_surface();
+    let second = compositor.create_surface();
+    first.commit();
+    second.commit();
+    roundtrip(&mut client, &mut server);
+    let surfaces = committed.borrow().clone();
+
+    // switch the first window to the second layout
+    keyboard.set_focus(Some(&surfaces[0]), SERIAL_COUNTER.next_serial());
+    keyboard.update_modifiers(0, 0, 0, 1, SERIAL_COUNTER.next_serial());
+    // a window focused for the first time starts with the first layout
+    keyboard.set_focus(Some(&surfaces[1]), SERIAL_COUNTER.next_serial());
+    assert_eq!(
+        keyboard.with_xkb_state(|_, state| state.serialize_layout(xkb::STATE_LAYOUT_LOCKED)),
+        0
+    );
+    keyboard.set_focus(Some(&surfaces[0]), SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*groups.borrow(), vec![0, 1, 0, 1]);
+
+    // without per-window layouts, the layout is kept across focus changes
+    keyboard.set_per_window_layout(false);
+    keyboard.set_focus(Some(&surfaces[1]), SERIAL_COUNTER.next_serial());
+    roundtrip(&mut client, &mut server);
+    assert_eq!(groups.borrow().last(), Some(&1));
+}
+
+#[test]
+fn repeat_info_reaches_recent_keyboards_only() {
+    let mut server = TestServer::new();
+    let (mut seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+    let keyboard = seat
+        .add_keyboard(XkbConfig::default(), 200, 25, |_, _| {})
+        .unwrap();
+
+    let repeat_infos = Rc::new(RefCell::new(Vec::new()));
+    let mut clients = Vec::new();
+    for version in &[3, 5] {
+        let (_server_client, mut client) = server.add_client();
+        roundtrip(&mut client, &mut server);
+        let wl_seat = client.globals.instantiate_exact::<WlSeat>(*version).unwrap();
+        let repeat_infos = repeat_infos.clone();
+        wl_seat.get_keyboard().quick_assign(move |_, event, _| {
+            if let wl_keyboard::Event::RepeatInfo { rate, delay } = event {
+                repeat_infos.borrow_mut().push((rate, delay));
+            }
+        });
+        roundtrip(&mut client, &mut server);
+        clients.push(client);
+    }
+    repeat_infos.borrow_mut().clear();
+
+    let notified = keyboard.set_repeat_info(30, 300);
+    assert_eq!(
+        notified,
+        RepeatInfoNotified {
+            notified: 1,
+            outdated: 1,
+        }
+    );
+    for client in &mut clients {
+        roundtrip(client, &mut server);
+    }
+    assert_eq!(*repeat_infos.borrow(), vec![(30, 300)]);
+}
@@ -0,0 +1,104 @@
+//! Power modes of outputs set by clients
+
+mod helpers;
+
+use std::{cell::RefCell, rc::Rc};
+
+use smithay::wayland::{
+    output::{Output, PhysicalProperties},
+    output_power_management::{
+        init_output_power_manager, output_power_mode, set_output_power_mode, OutputPowerMode,
+    },
+};
+use wayland_client::{protocol::wl_output::WlOutput, Main};
+use wayland_protocols::wlr::unstable::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+    zwlr_output_power_v1::{self, Mode, ZwlrOutputPowerV1},
+};
+use wayland_server::{
+    protocol::wl_output::{Subpixel, WlOutput as WlOutputServer},
+    Global,
+};
+
+use helpers::{roundtrip, TestClient, TestServer};
+
+// `Some(mode)` for every `mode` event received by the power object, `None` for `failed`
+type PowerEvents = Rc<RefCell<Vec<Option<Mode>>>>;
+
+fn new_output(server: &mut TestServer) -> (Output, Global<WlOutputServer>) {
+    let physical = PhysicalProperties {
+        size: (0, 0).into(),
+        subpixel: Subpixel::Unknown,
+        make: "Smithay".into(),
+        model: "Test".into(),
+    };
+    Output::new(&mut server.display, "test".into(), physical, None)
+}
+
+fn get_output_power(
+    client: &mut TestClient,
+    server: &mut TestServer,
+) -> (Main<ZwlrOutputPowerV1>, PowerEvents) {
+    roundtrip(client, server);
+    let output = client.globals.instantiate_exact::<WlOutput>(3).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<ZwlrOutputPowerManagerV1>(1)
+        .unwrap();
+    let events = PowerEvents::default();
+    let events2 = events.clone();
+    let power = manager.get_output_power(&output);
+    power.quick_assign(move |_, event, _| match event {
+        zwlr_output_power_v1::Event::Mode { mode } => events2.borrow_mut().push(Some(mode)),
+        zwlr_output_power_v1::Event::Failed => events2.borrow_mut().push(None),
+        _ => unreachable!(),
+    });
+    roundtrip(client, server);
+    (power, events)
+}
+
+#[test]
+fn mode_changes_are_sent_to_all_clients() {
+    let mut server = TestServer::new();
+    let (output, _output_global) = new_output(&mut server);
+    let _global = init_output_power_manager(&mut server.display, |_, _, _| true, None);
+
+    let (_client1, mut client1) = server.add_client();
+    let (power1, events1) = get_output_power(&mut client1, &mut server);
+    let (_client2, mut client2) = server.add_client();
+    let (_power2, events2) = get_output_power(&mut client2, &mut server);
+    // both receive the initial mode
+    assert_eq!(*events1.borrow(), vec![Some(Mode::On)]);
+    assert_eq!(*events2.borrow(), vec![Some(Mode::On)]);
+
+    power1.set_mode(Mode::Off);
+    roundtrip(&mut client1, &mut server);
+    roundtrip(&mut client2, &mut server);
+    assert_eq!(output_power_mode(&output), OutputPowerMode::Off);
+    assert_eq!(*events1.borrow(), vec![Some(Mode::On), Some(Mode::Off)]);
+    assert_eq!(*events2.borrow(), vec![Some(Mode::On), Some(Mode::Off)]);
+}
+
+#[test]
+fn failed_mode_change_invalidates_the_power_object() {
+    let mut server = TestServer::new();
+    let (output, _output_global) = new_output(&mut server);
+    let _global = init_output_power_manager(&mut server.display, |_, _, _| false, None);
+
+    let (_client1, mut client1) = server.add_client();
+    let (power1, events1) = get_output_power(&mut client1, &mut server);
+    let (_client2, mut client2) = server.add_client();
+    let (_power2, events2) = get_output_power(&mut client2, &mut server);
+
+    power1.set_mode(Mode::Off);
+    roundtrip(&mut client1, &mut server);
+    assert_eq!(output_power_mode(&output), OutputPowerMode::On);
+    assert_eq!(*events1.borrow(), vec![Some(Mode::On), None]);
+
+    // the failed object was dropped from the instances notified about later changes
+    set_output_power_mode(&output, OutputPowerMode::Off);
+    roundtrip(&mut client1, &mut server);
+    roundtrip(&mut client2, &mut server);
+    assert_eq!(*events1.borrow(), vec![Some(Mode::On), None]);
+    assert_eq!(*events2.borrow(), vec![Some(Mode::On), Some(Mode::Off)]);
+}
@@ -0,0 +1,71 @@
+//! Primary selection state set by the compositor
+
+mod helpers;
+
+use std::{cell::RefCell, rc::Rc};
+
+use smithay::wayland::{
+    data_device::SourceMetadataError,
+    primary_selection::{
+        init_primary_selection, set_primary_focus, set_primary_selection, with_source_metadata,
+    },
+    seat::Seat,
+};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_protocols::unstable::primary_selection::v1::{
+    client::{
+        zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+        zwp_primary_selection_device_v1,
+    },
+    server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+};
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn device_created_after_focus_receives_selection() {
+    let mut server = TestServer::new();
+    init_primary_selection(&mut server.display, |_| {}, None);
+    let (seat, _seat_global) = Seat::new(&mut server.display, "seat-0".into(), None);
+
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let wl_seat = client.globals.instantiate_exact::<WlSeat>(5).unwrap();
+    let manager = client
+        .globals
+        .instantiate_exact::<ZwpPrimarySelectionDeviceManagerV1>(1)
+        .unwrap();
+    roundtrip(&mut client, &mut server);
+
+    // the selection and focus are set before any primary selection device exists
+    set_primary_selection(&seat, vec!["text/plain".into()]);
+    set_primary_focus(&seat, Some(server_client));
+
+    let offers = Rc::new(RefCell::new(Vec::new()));
+    let offers2 = offers.clone();
+    manager.get_device(&wl_seat).quick_assign(move |_, event, _| {
+        if let zwp_primary_selection_device_v1::Event::Selection { id } = event {
+            offers2.borrow_mut().push(id.is_some());
+        }
+    });
+    roundtrip(&mut client, &mut server);
+    assert_eq!(*offers.borrow(), vec![true]);
+}
+
+#[test]
+fn foreign_source_has_no_metadata() {
+    let mut server = TestServer::new();
+    init_primary_selection(&mut server.display, |_| {}, None);
+    let (server_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+
+    // a source implemented outside of smithay, e.g. by another global of the compositor
+    let source = server_client
+        .create_resource::<ZwpPrimarySelectionSourceV1>(1)
+        .unwrap();
+    source.quick_assign(|_, _, _| {});
+    assert!(matches!(
+        with_source_metadata(&source, |_| ()),
+        Err(SourceMetadataError::NoMetadata)
+    ));
+}
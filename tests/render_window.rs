@@ -0,0 +1,104 @@
+//! Windows rendered into textures by the space
+
+mod helpers;
+
+use std::{cell::RefCell, ffi::CStr, rc::Rc};
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::renderer::dummy::{DrawCommand, DummyRenderer},
+    desktop::{space::RenderError, Kind, Space, Window},
+    utils::{Physical, Point, Size, Transform},
+    wayland::{
+        shell::legacy::{wl_shell_init, ShellRequest},
+        shm::init_shm_global,
+    },
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_shell::WlShell,
+    wl_shm::{self, WlShm},
+    wl_subcompositor::WlSubcompositor,
+};
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn window_is_rendered_at_the_texture_origin() {
+    let (mut server, _committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+    let shell_surfaces = Rc::new(RefCell::new(Vec::new()));
+    let shell_surfaces2 = shell_surfaces.clone();
+    wl_shell_init(
+        &mut server.display,
+        move |request, _| {
+            if let ShellRequest::NewShellSurface { surface } = request {
+                shell_surfaces2.borrow_mut().push(surface);
+            }
+        },
+        None,
+    );
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(1).unwrap();
+    let subcompositor = client.globals.instantiate_exact::<WlSubcompositor>(1).unwrap();
+    let wl_shell = client.globals.instantiate_exact::<WlShell>(1).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+    let name = CStr::from_bytes_with_nul(b"buffer\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 100 * 100 * 4).unwrap();
+    let pool = shm.create_pool(fd, 100 * 100 * 4);
+    let buffer = pool.create_buffer(0, 100, 100, 100 * 4, wl_shm::Format::Argb8888);
+
+    // a toplevel with a subsurface reaching out of its top left corner
+    let toplevel = compositor.create_surface();
+    wl_shell.get_shell_surface(&toplevel).set_toplevel();
+    let child = compositor.create_surface();
+    let subsurface = subcompositor.get_subsurface(&child, &toplevel);
+    subsurface.set_position(-20, -10);
+    child.attach(Some(&buffer), 0, 0);
+    child.commit();
+    toplevel.attach(Some(&buffer), 0, 0);
+    toplevel.commit();
+    roundtrip(&mut client, &mut server);
+
+    let window = Window::new(Kind::Wl(shell_surfaces.borrow()[0].clone()));
+    window.refresh();
+    let mut space = Space::new(None);
+    let mut renderer = DummyRenderer::new();
+    assert!(matches!(
+        space.render_window_to_texture(&mut renderer, &window, 1.0),
+        Err(RenderError::UnmappedWindow)
+    ));
+    assert!(renderer.commands().is_empty());
+
+    // the location in the space does not matter
+    space.map_window(&window, (300, 200), false);
+    space
+        .render_window_to_texture(&mut renderer, &window, 1.0)
+        .unwrap();
+    let commands = renderer.take_commands();
+    assert_eq!(
+        commands[0],
+        DrawCommand::Render {
+            size: (120, 110).into(),
+            transform: Transform::Normal,
+        }
+    );
+    let positions = commands
+        .into_iter()
+        .filter_map(|command| match command {
+            DrawCommand::RenderTexture { dst, .. } => Some((dst.loc, dst.size)),
+            _ => None,
+        })
+        .collect::<Vec<(Point<f64, Physical>, Size<f64, Physical>)>>();
+    // the window geometry starts at the subsurface, moving the toplevel to the bottom right
+    assert_eq!(
+        positions,
+        vec![
+            ((20.0, 10.0).into(), (100.0, 100.0).into()),
+            ((0.0, 0.0).into(), (100.0, 100.0).into()),
+        ]
+    );
+}
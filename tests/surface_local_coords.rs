@@ -0,0 +1,53 @@
+//! Conversion of global positions into surface-local coordinates
+
+mod helpers;
+
+use smithay::{desktop::utils::surface_local_coords, utils::Point};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor, wl_output::Transform, wl_subcompositor::WlSubcompositor,
+};
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn subsurface_offsets_are_applied() {
+    let (mut server, committed) = TestServer::with_compositor();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let subcompositor = client.globals.instantiate_exact::<WlSubcompositor>(1).unwrap();
+
+    let root = compositor.create_surface();
+    let child = compositor.create_surface();
+    let grandchild = compositor.create_surface();
+    let child_sub = subcompositor.get_subsurface(&child, &root);
+    child_sub.set_position(10, 20);
+    let grandchild_sub = subcompositor.get_subsurface(&grandchild, &child);
+    grandchild_sub.set_position(-5, 5);
+    // neither the scale nor the transform change the surface-local coordinate space
+    grandchild.set_buffer_scale(2);
+    grandchild.set_buffer_transform(Transform::_90);
+    grandchild.commit();
+    child.commit();
+    root.commit();
+    roundtrip(&mut client, &mut server);
+
+    let (grandchild, child, root) = {
+        let committed = committed.borrow();
+        (committed[0].clone(), committed[1].clone(), committed[2].clone())
+    };
+    let point = (150.0, 100.0).into();
+    assert_eq!(
+        surface_local_coords(&root, point, (100, 50)),
+        Point::from((50.0, 50.0))
+    );
+    assert_eq!(
+        surface_local_coords(&child, point, (100, 50)),
+        Point::from((40.0, 30.0))
+    );
+    assert_eq!(
+        surface_local_coords(&grandchild, point, (100, 50)),
+        Point::from((45.0, 25.0))
+    );
+}
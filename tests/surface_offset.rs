@@ -0,0 +1,113 @@
+//! Surfaces moved by their client with `wl_surface.offset`
+
+mod helpers;
+
+use std::ffi::CStr;
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::renderer::{
+        dummy::{DrawCommand, DummyRenderer},
+        utils::{draw_surface_tree, take_buffer_delta},
+        Renderer,
+    },
+    desktop::{
+        utils::{bbox_from_surface_tree, under_from_surface_tree},
+        WindowSurfaceType,
+    },
+    utils::{Physical, Point, Rectangle, Transform},
+    wayland::shm::init_shm_global,
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_shm::{self, WlShm},
+    wl_subcompositor::WlSubcompositor,
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use helpers::{roundtrip, TestServer};
+
+// positions the textures of the surface tree are drawn at, with the root at the origin
+fn draw_positions(surface: &WlSurface) -> Vec<Point<f64, Physical>> {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    let damage = [Rectangle::from_loc_and_size((0, 0), (100, 100))];
+    let mut renderer = DummyRenderer::new();
+    renderer
+        .render((100, 100).into(), Transform::Normal, |renderer, frame| {
+            draw_surface_tree(renderer, frame, surface, 1.0, (0, 0).into(), &damage, 1.0, &log)
+        })
+        .unwrap()
+        .unwrap();
+    renderer
+        .take_commands()
+        .into_iter()
+        .filter_map(|command| match command {
+            DrawCommand::RenderTexture { dst, .. } => Some(dst.loc),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn offsets_move_subsurfaces_and_are_reported_for_roots() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(5).unwrap();
+    let subcompositor = client.globals.instantiate_exact::<WlSubcompositor>(1).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+
+    let name = CStr::from_bytes_with_nul(b"buffer\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 4 * 4 * 4).unwrap();
+    let pool = shm.create_pool(fd, 4 * 4 * 4);
+    let buffer = pool.create_buffer(0, 4, 4, 4 * 4, wl_shm::Format::Argb8888);
+
+    let root = compositor.create_surface();
+    let child = compositor.create_surface();
+    let child_sub = subcompositor.get_subsurface(&child, &root);
+    child_sub.set_position(10, 20);
+    child.attach(Some(&buffer), 0, 0);
+    child.commit();
+    root.attach(Some(&buffer), 0, 0);
+    root.commit();
+    roundtrip(&mut client, &mut server);
+    let server_root = committed.borrow().last().unwrap().clone();
+    assert_eq!(
+        draw_positions(&server_root),
+        vec![Point::from((0.0, 0.0)), Point::from((10.0, 20.0))]
+    );
+
+    // the offset of a subsurface moves it, for drawing as well as for input
+    child.offset(5, 7);
+    child.commit();
+    root.commit();
+    roundtrip(&mut client, &mut server);
+    let server_child = committed.borrow()[0].clone();
+    assert_eq!(take_buffer_delta(&server_child), None);
+    assert_eq!(
+        draw_positions(&server_root),
+        vec![Point::from((0.0, 0.0)), Point::from((15.0, 27.0))]
+    );
+    assert_eq!(
+        under_from_surface_tree(&server_root, (16.0, 28.0).into(), (0, 0), WindowSurfaceType::ALL),
+        Some((server_child, Point::from((15, 27))))
+    );
+    assert_eq!(
+        bbox_from_surface_tree(&server_root, (0, 0)),
+        Rectangle::from_loc_and_size((0, 0), (19, 31))
+    );
+
+    // the offset of a root surface is left to the compositor, once
+    root.offset(-3, 4);
+    root.commit();
+    roundtrip(&mut client, &mut server);
+    assert_eq!(take_buffer_delta(&server_root), Some(Point::from((-3, 4))));
+    assert_eq!(take_buffer_delta(&server_root), None);
+    assert_eq!(
+        draw_positions(&server_root),
+        vec![Point::from((0.0, 0.0)), Point::from((15.0, 27.0))]
+    );
+}
@@ -0,0 +1,44 @@
+//! Roles given to surfaces and released again
+
+mod helpers;
+
+use smithay::wayland::compositor::{clear_role, get_role, give_role, WrongRole};
+use wayland_client::protocol::wl_compositor::WlCompositor;
+
+use helpers::{roundtrip, TestServer};
+
+#[test]
+fn cleared_role_can_be_replaced() {
+    let (mut server, committed) = TestServer::with_compositor();
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let surface = compositor.create_surface();
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+    let server_surface = committed.borrow()[0].clone();
+
+    give_role(&server_surface, "dnd_icon").unwrap();
+    assert!(give_role(&server_surface, "cursor_image").is_err());
+
+    // only the current role can be cleared
+    assert!(matches!(
+        clear_role(&server_surface, "cursor_image"),
+        Err(WrongRole)
+    ));
+    assert_eq!(get_role(&server_surface), Some("dnd_icon"));
+
+    clear_role(&server_surface, "dnd_icon").unwrap();
+    assert_eq!(get_role(&server_surface), None);
+    // a surface without a role has no role to clear
+    assert!(matches!(clear_role(&server_surface, "dnd_icon"), Err(WrongRole)));
+
+    give_role(&server_surface, "cursor_image").unwrap();
+    assert_eq!(get_role(&server_surface), Some("cursor_image"));
+
+    // clearing the role of a destroyed surface is a no-op
+    surface.destroy();
+    roundtrip(&mut client, &mut server);
+    assert!(clear_role(&server_surface, "dnd_icon").is_ok());
+}
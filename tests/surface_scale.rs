@@ -0,0 +1,109 @@
+//! Surfaces drawn with the buffer scale and transform committed by their client
+
+mod helpers;
+
+use std::ffi::CStr;
+
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay::{
+    backend::renderer::{
+        dummy::{DrawCommand, DummyRenderer},
+        utils::draw_surface_tree,
+        Renderer,
+    },
+    utils::{Buffer, Physical, Rectangle, Transform},
+    wayland::shm::init_shm_global,
+};
+use wayland_client::protocol::{
+    wl_compositor::WlCompositor,
+    wl_output,
+    wl_shm::{self, WlShm},
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use helpers::{roundtrip, TestServer};
+
+// source, destination, damage and transform of the textures drawn for the surface tree
+fn draw_textures(
+    surface: &WlSurface,
+) -> Vec<(
+    Rectangle<i32, Buffer>,
+    Rectangle<f64, Physical>,
+    Vec<Rectangle<i32, Buffer>>,
+    Transform,
+)> {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    let damage = [Rectangle::from_loc_and_size((0, 0), (400, 400))];
+    let mut renderer = DummyRenderer::new();
+    renderer
+        .render((400, 400).into(), Transform::Normal, |renderer, frame| {
+            draw_surface_tree(renderer, frame, surface, 1.0, (0, 0).into(), &damage, 1.0, &log)
+        })
+        .unwrap()
+        .unwrap();
+    renderer
+        .take_commands()
+        .into_iter()
+        .filter_map(|command| match command {
+            DrawCommand::RenderTexture {
+                src,
+                dst,
+                damage,
+                src_transform,
+                ..
+            } => Some((src, dst, damage, src_transform)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn buffer_scale_and_transform_are_applied() {
+    let (mut server, committed) = TestServer::with_compositor();
+    init_shm_global(&mut server.display, Vec::new(), None);
+
+    let (_client, mut client) = server.add_client();
+    roundtrip(&mut client, &mut server);
+    let compositor = client.globals.instantiate_exact::<WlCompositor>(4).unwrap();
+    let shm = client.globals.instantiate_exact::<WlShm>(1).unwrap();
+
+    let name = CStr::from_bytes_with_nul(b"buffer\0").unwrap();
+    let fd = memfd_create(name, MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+    nix::unistd::ftruncate(fd, 200 * 100 * 4).unwrap();
+    let pool = shm.create_pool(fd, 200 * 100 * 4);
+    let buffer = pool.create_buffer(0, 200, 100, 200 * 4, wl_shm::Format::Argb8888);
+
+    let surface = compositor.create_surface();
+    surface.attach(Some(&buffer), 0, 0);
+    surface.set_buffer_scale(2);
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+    let server_surface = committed.borrow()[0].clone();
+
+    // the whole buffer covers a surface of half its size
+    let full_buffer = Rectangle::from_loc_and_size((0, 0), (200, 100));
+    assert_eq!(
+        draw_textures(&server_surface),
+        vec![(
+            full_buffer,
+            Rectangle::from_loc_and_size((0.0, 0.0), (100.0, 50.0)),
+            vec![full_buffer],
+            Transform::Normal,
+        )]
+    );
+
+    // a rotated buffer swaps the dimensions of the surface
+    surface.attach(Some(&buffer), 0, 0);
+    surface.set_buffer_transform(wl_output::Transform::_90);
+    surface.commit();
+    roundtrip(&mut client, &mut server);
+    assert_eq!(
+        draw_textures(&server_surface),
+        vec![(
+            full_buffer,
+            Rectangle::from_loc_and_size((0.0, 0.0), (50.0, 100.0)),
+            vec![full_buffer],
+            Transform::_90,
+        )]
+    );
+}
@@ -140,7 +140,7 @@ pub fn run(channel: Channel<WlcsEvent>) {
                         let mut guard = state.cursor_status.lock().unwrap();
                         // reset the cursor if the surface is no longer alive
                         let mut reset = false;
-                        if let CursorImageStatus::Image(ref surface) = *guard {
+                        if let CursorImageStatus::Image { ref surface, .. } = *guard {
                             reset = !surface.as_ref().is_alive();
                         }
                         if reset {
@@ -148,7 +148,7 @@ pub fn run(channel: Channel<WlcsEvent>) {
                         }
 
                         // draw as relevant
-                        if let CursorImageStatus::Image(ref surface) = *guard {
+                        if let CursorImageStatus::Image { ref surface, .. } = *guard {
                             draw_cursor(
                                 renderer,
                                 frame,